@@ -0,0 +1,157 @@
+//! Derive macros for the leveldb crate
+//!
+//! Use them through the `derive` feature of `leveldb`, which re-exports
+//! them. The macros parse their input by hand, so the crate has no
+//! dependencies besides `proc_macro`.
+
+#![crate_type = "proc-macro"]
+#![crate_name = "leveldb_derive"]
+#![deny(missing_docs)]
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+enum Fields {
+    Named(Vec<String>),
+    Unnamed(usize),
+    Unit,
+}
+
+/// Implement `Key` for a struct, encoding its fields in order
+///
+/// The fields must implement `leveldb::keys::KeyComponent`: integers, byte
+/// arrays, `Vec<u8>` and `String`. Keys sort by their first field, then
+/// the second, and so on, so the bytewise order matches a derived `Ord`.
+/// Decoding a key that doesn't hold all fields panics.
+#[proc_macro_derive(DbKey)]
+pub fn derive_db_key(input: TokenStream) -> TokenStream {
+    let generated = match parse_struct(input) {
+        Ok((name, fields)) => implement_key(&name, &fields),
+        Err(message) => format!("compile_error!({:?});", message),
+    };
+    generated.parse().unwrap()
+}
+
+fn is_punct(token: &TokenTree, c: char) -> bool {
+    match *token {
+        TokenTree::Punct(ref p) => p.as_char() == c,
+        _ => false,
+    }
+}
+
+fn is_ident(token: &TokenTree, name: &str) -> bool {
+    match *token {
+        TokenTree::Ident(ref i) => i.to_string() == name,
+        _ => false,
+    }
+}
+
+// skip attributes and a visibility
+fn skip_prefix(tokens: &[TokenTree]) -> &[TokenTree] {
+    let mut tokens = tokens;
+    loop {
+        match tokens {
+            [hash, TokenTree::Group(group), rest @ ..]
+                if is_punct(hash, '#') && group.delimiter() == Delimiter::Bracket => tokens = rest,
+            [vis, TokenTree::Group(group), rest @ ..]
+                if is_ident(vis, "pub") && group.delimiter() == Delimiter::Parenthesis => tokens = rest,
+            [vis, rest @ ..] if is_ident(vis, "pub") => tokens = rest,
+            _ => return tokens,
+        }
+    }
+}
+
+// split at commas outside of angle brackets, dropping empty parts
+fn split_fields(stream: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut fields = vec![];
+    let mut field = vec![];
+    let mut depth = 0;
+    for token in stream {
+        if is_punct(&token, '<') {
+            depth += 1;
+        } else if is_punct(&token, '>') {
+            depth -= 1;
+        } else if depth == 0 && is_punct(&token, ',') {
+            fields.push(std::mem::take(&mut field));
+            continue;
+        }
+        field.push(token);
+    }
+    fields.push(field);
+    fields.retain(|field| !field.is_empty());
+    fields
+}
+
+fn parse_struct(input: TokenStream) -> Result<(String, Fields), String> {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let (name, rest) = match skip_prefix(&tokens) {
+        [keyword, TokenTree::Ident(name), rest @ ..] if is_ident(keyword, "struct") => (name.to_string(), rest),
+        _ => return Err("DbKey can only be derived for structs".to_string()),
+    };
+    match rest.first() {
+        Some(token) if is_punct(token, '<') => {
+            Err(format!("DbKey can't be derived for the generic struct {}", name))
+        }
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+            let mut names = vec![];
+            for field in split_fields(group.stream()) {
+                match skip_prefix(&field) {
+                    [TokenTree::Ident(field), colon, ..] if is_punct(colon, ':') => names.push(field.to_string()),
+                    _ => return Err(format!("unexpected field in struct {}", name)),
+                }
+            }
+            Ok((name, Fields::Named(names)))
+        }
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+            let count = split_fields(group.stream()).len();
+            Ok((name, Fields::Unnamed(count)))
+        }
+        Some(token) if is_punct(token, ';') => Ok((name, Fields::Unit)),
+        _ => Err(format!("unexpected tokens in struct {}", name)),
+    }
+}
+
+fn implement_key(name: &str, fields: &Fields) -> String {
+    let accessors: Vec<String> = match *fields {
+        Fields::Named(ref names) => names.clone(),
+        Fields::Unnamed(count) => (0..count).map(|i| i.to_string()).collect(),
+        Fields::Unit => vec![],
+    };
+    let read = "::leveldb::keys::KeyComponent::read_from(&mut reader)\
+                .expect(\"key too short for its type\")";
+    let construct = match *fields {
+        Fields::Named(ref names) => {
+            let fields: Vec<String> = names.iter().map(|n| format!("{}: {}", n, read)).collect();
+            format!("{} {{ {} }}", name, fields.join(", "))
+        }
+        Fields::Unnamed(count) => {
+            let fields: Vec<&str> = (0..count).map(|_| read).collect();
+            format!("{}({})", name, fields.join(", "))
+        }
+        Fields::Unit => name.to_string(),
+    };
+    let append: String = accessors.iter()
+        .map(|field| format!("let key = ::leveldb::keys::KeyComponent::append_to(&self.{}, key);", field))
+        .collect();
+    format!("
+        impl ::leveldb::keys::Key for {name} {{
+            fn from_u8(key: &[u8]) -> {name} {{
+                let key = <::leveldb::keys::CompositeKey as ::leveldb::keys::Key>::from_u8(key);
+                #[allow(unused_mut)]
+                let mut reader = key.reader();
+                let value = {construct};
+                assert!(reader.is_empty(), \"key too long for its type\");
+                value
+            }}
+
+            fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {{
+                let key = ::leveldb::keys::CompositeKey::new();
+                {append}
+                f(key.as_bytes())
+            }}
+        }}",
+        name = name,
+        construct = construct,
+        append = append)
+}
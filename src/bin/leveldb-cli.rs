@@ -0,0 +1,230 @@
+//! A command line tool to inspect and modify leveldb databases
+//!
+//! Keys and values are given as UTF-8 strings, or as hex when prefixed
+//! with `0x`. Bytes that aren't printable ASCII are printed escaped
+//! as `\xNN`.
+extern crate leveldb;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+use leveldb::compaction::Compaction;
+use leveldb::database::Database;
+use leveldb::error::{Error, ErrorKind};
+use leveldb::iterator::{Iterable, LevelDBIterator};
+use leveldb::keys::BinaryKey;
+use leveldb::kv::KV;
+use leveldb::management;
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+use leveldb::properties::Properties;
+
+const USAGE: &str = "usage: leveldb-cli <database> <command> [arguments]
+
+commands:
+    get <key>                 print the value of a key
+    put <key> <value>         set the value of a key, creating the database if missing
+    delete <key>              delete a key
+    scan [--prefix <prefix>] [--from <key>] [--to <key>] [--limit <n>]
+                              print the entries in order, keys and values separated by a tab
+    stats                     print the leveldb.stats property
+    compact                   compact the whole database
+    repair                    repair a damaged database
+    destroy                   delete the database
+    export <file>             write a dump of all entries, - for stdout
+    import <file>             put all entries of a dump, - for stdin";
+
+enum Failure {
+    Usage(String),
+    Database(Error),
+    Io(io::Error),
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Failure {
+        Failure::Database(error)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Failure {
+        Failure::Io(error)
+    }
+}
+
+fn usage<T>(message: &str) -> Result<T, Failure> {
+    Err(Failure::Usage(message.to_string()))
+}
+
+/// Decode an argument, hex if prefixed with `0x`
+fn parse_bytes(arg: &str) -> Result<Vec<u8>, Failure> {
+    if !arg.starts_with("0x") {
+        return Ok(arg.as_bytes().to_vec());
+    }
+    let hex = &arg[2..];
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return usage(&format!("invalid hex in {}", arg));
+    }
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    escaped
+}
+
+fn open(path: &Path, create_if_missing: bool) -> Result<Database<BinaryKey>, Failure> {
+    let mut options = Options::new();
+    options.create_if_missing = create_if_missing;
+    Ok(Database::open(path, options)?)
+}
+
+fn expect_args(args: &[String], count: usize, command: &str) -> Result<(), Failure> {
+    if args.len() != count {
+        return usage(&format!("{} takes {} argument(s)", command, count));
+    }
+    Ok(())
+}
+
+fn scan(path: &Path, args: &[String]) -> Result<(), Failure> {
+    let mut prefix = None;
+    let mut from = None;
+    let mut to = None;
+    let mut limit = None;
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = match args.next() {
+            Some(value) => value,
+            None => return usage(&format!("{} needs a value", flag)),
+        };
+        match flag.as_str() {
+            "--prefix" => prefix = Some(parse_bytes(value)?),
+            "--from" => from = Some(BinaryKey(parse_bytes(value)?)),
+            "--to" => to = Some(BinaryKey(parse_bytes(value)?)),
+            "--limit" => {
+                limit = Some(value.parse::<usize>().or_else(|_| usage("--limit needs a number"))?)
+            }
+            _ => return usage(&format!("unknown option {}", flag)),
+        }
+    }
+
+    let database = open(path, false)?;
+    let mut iter = database.iter(ReadOptions::new());
+    if let Some(ref prefix) = prefix {
+        iter = iter.prefix(prefix);
+    }
+    if let Some(ref from) = from {
+        iter = iter.from(from);
+    }
+    if let Some(ref to) = to {
+        iter = iter.to(to);
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (key, value) in iter.by_ref().take(limit.unwrap_or(usize::MAX)) {
+        writeln!(out, "{}\t{}", escape(&key.0), escape(&value))?;
+    }
+    Ok(iter.status()?)
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    if args.len() < 2 {
+        return usage("missing database or command");
+    }
+    let path = Path::new(&args[0]);
+    let command = args[1].as_str();
+    let args = &args[2..];
+    match command {
+        "get" => {
+            expect_args(args, 1, command)?;
+            let database = open(path, false)?;
+            match database.get(ReadOptions::new(), BinaryKey(parse_bytes(&args[0])?))? {
+                Some(value) => println!("{}", escape(&value)),
+                None => {
+                    return Err(Failure::Database(Error::with_kind(ErrorKind::NotFound, args[0].to_string())));
+                }
+            }
+        }
+        "put" => {
+            expect_args(args, 2, command)?;
+            let database = open(path, true)?;
+            database.put(WriteOptions::new(), BinaryKey(parse_bytes(&args[0])?), &parse_bytes(&args[1])?)?;
+        }
+        "delete" => {
+            expect_args(args, 1, command)?;
+            let database = open(path, false)?;
+            database.delete(WriteOptions::new(), BinaryKey(parse_bytes(&args[0])?))?;
+        }
+        "scan" => scan(path, args)?,
+        "stats" => {
+            expect_args(args, 0, command)?;
+            let database = open(path, false)?;
+            print!("{}", database.property_value("leveldb.stats").unwrap_or_default());
+        }
+        "compact" => {
+            expect_args(args, 0, command)?;
+            open(path, false)?.compact_all();
+        }
+        "repair" => {
+            expect_args(args, 0, command)?;
+            management::repair(path, Options::new())?;
+        }
+        "destroy" => {
+            expect_args(args, 0, command)?;
+            management::destroy(path, Options::new())?;
+        }
+        "export" => {
+            expect_args(args, 1, command)?;
+            let database = open(path, false)?;
+            let count = if args[0] == "-" {
+                database.export_to(io::stdout().lock())?
+            } else {
+                database.export_to(File::create(&args[0])?)?
+            };
+            eprintln!("exported {} entries", count);
+        }
+        "import" => {
+            expect_args(args, 1, command)?;
+            let database = open(path, true)?;
+            let count = if args[0] == "-" {
+                database.import_from(io::stdin().lock())?
+            } else {
+                database.import_from(File::open(&args[0])?)?
+            };
+            eprintln!("imported {} entries", count);
+        }
+        _ => return usage(&format!("unknown command {}", command)),
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => {}
+        Err(Failure::Usage(message)) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+        Err(Failure::Database(error)) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        Err(Failure::Io(error)) => {
+            eprintln!("IO error: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Asynchronous access to the database
+//!
+//! leveldb only offers blocking calls. `AsyncDatabase` runs every call on
+//! a separate thread and hands back a future resolving to its result.
+//! The futures only rely on `std::task`, so they can be driven by any
+//! executor.
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::Error;
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::compaction::Compaction;
+use super::iterator::{OwnedIterable, OwnedIterator};
+
+/// Number of entries an `EntryStream` reads ahead of its consumer.
+const STREAM_BUFFER: usize = 64;
+
+struct TaskState<T> {
+    result: Option<T>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// A future resolving to the result of a blocking database call.
+///
+/// Panics when polled if the call itself panicked.
+pub struct Task<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+// marks the task as done even if the blocking call panics
+struct Completion<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+fn spawn_blocking<T, F>(f: F) -> Task<T>
+    where T: Send + 'static,
+          F: FnOnce() -> T + Send + 'static
+{
+    let state = Arc::new(Mutex::new(TaskState {
+        result: None,
+        done: false,
+        waker: None,
+    }));
+    let completion = Completion { state: state.clone() };
+    thread::spawn(move || {
+        let result = f();
+        completion.state.lock().unwrap_or_else(|e| e.into_inner()).result = Some(result);
+    });
+    Task { state }
+}
+
+impl<T> Future for Task<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.done {
+            match state.result.take() {
+                Some(result) => Poll::Ready(result),
+                None => panic!("blocking database call panicked"),
+            }
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// A database handle whose operations return futures.
+///
+/// Cloning is cheap, all clones share the same database.
+pub struct AsyncDatabase<K: Key> {
+    database: Database<K>,
+}
+
+impl<K: Key> Clone for AsyncDatabase<K> {
+    fn clone(&self) -> AsyncDatabase<K> {
+        AsyncDatabase { database: self.database.clone() }
+    }
+}
+
+impl<K: Key> From<Database<K>> for AsyncDatabase<K> {
+    fn from(database: Database<K>) -> AsyncDatabase<K> {
+        AsyncDatabase { database }
+    }
+}
+
+impl<K: Key + Send + Sync + 'static> AsyncDatabase<K> {
+    /// Wrap a database for asynchronous access.
+    pub fn new(database: Database<K>) -> AsyncDatabase<K> {
+        AsyncDatabase { database }
+    }
+
+    /// Return the underlying blocking database handle.
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// get a value from the database, using the default read options.
+    pub fn get(&self, key: K) -> Task<Result<Option<Vec<u8>>, Error>> {
+        let database = self.database.clone();
+        spawn_blocking(move || database.get(ReadOptions::new(), key))
+    }
+
+    /// put a binary value into the database.
+    pub fn put(&self, options: WriteOptions, key: K, value: Vec<u8>) -> Task<Result<(), Error>> {
+        let database = self.database.clone();
+        spawn_blocking(move || database.put(options, key, &value))
+    }
+
+    /// delete a value from the database.
+    pub fn delete(&self, options: WriteOptions, key: K) -> Task<Result<(), Error>> {
+        let database = self.database.clone();
+        spawn_blocking(move || database.delete(options, key))
+    }
+
+    /// Write a batch to the database, ensuring success for all items or an error
+    pub fn write(&self, options: WriteOptions, batch: Writebatch<K>) -> Task<Result<(), Error>> {
+        let database = self.database.clone();
+        spawn_blocking(move || database.write(options, &batch))
+    }
+
+    /// Compact the given range, `None` meaning the respective end of the keyspace.
+    pub fn compact_range(&self, start: Option<K>, limit: Option<K>) -> Task<()> {
+        let database = self.database.clone();
+        spawn_blocking(move || database.compact_range(start.as_ref(), limit.as_ref()))
+    }
+
+    /// Compact the whole database.
+    pub fn compact_all(&self) -> Task<()> {
+        self.compact_range(None, None)
+    }
+
+    /// Stream all (Key,Value) pairs, using the default read options.
+    ///
+    /// For ranges, prefixes or reverse order, configure an `OwnedIterator`
+    /// and convert it using `EntryStream::from`.
+    pub fn iter(&self) -> EntryStream<K> {
+        let iter = Arc::new(self.database.clone()).owned_iter(ReadOptions::new());
+        EntryStream::from(iter)
+    }
+}
+
+struct StreamState<K> {
+    buffer: VecDeque<(K, Vec<u8>)>,
+    done: bool,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+struct StreamShared<K> {
+    state: Mutex<StreamState<K>>,
+    space: Condvar,
+}
+
+// ends the stream even if reading from the iterator panics
+struct Producer<K> {
+    shared: Arc<StreamShared<K>>,
+}
+
+impl<K> Drop for Producer<K> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// An asynchronous stream of (Key,Value) pairs.
+///
+/// The entries are read on a background thread, which stays a bounded
+/// number of entries ahead of the consumer. `poll_next` has the shape of
+/// the common `Stream` trait, so adapting it to one is a one-liner.
+pub struct EntryStream<K> {
+    shared: Arc<StreamShared<K>>,
+}
+
+impl<K: Key + Send + Sync + 'static> From<OwnedIterator<K>> for EntryStream<K> {
+    fn from(iter: OwnedIterator<K>) -> EntryStream<K> {
+        let shared = Arc::new(StreamShared {
+            state: Mutex::new(StreamState {
+                buffer: VecDeque::new(),
+                done: false,
+                closed: false,
+                waker: None,
+            }),
+            space: Condvar::new(),
+        });
+        let producer = Producer { shared: shared.clone() };
+        thread::spawn(move || {
+            for entry in iter {
+                let shared = &producer.shared;
+                let mut state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+                while state.buffer.len() >= STREAM_BUFFER && !state.closed {
+                    state = shared.space.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                if state.closed {
+                    return;
+                }
+                state.buffer.push_back(entry);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+        EntryStream { shared }
+    }
+}
+
+impl<K> EntryStream<K> {
+    /// Attempt to pull out the next entry, registering the current task
+    /// for wakeup if none is available yet.
+    pub fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<(K, Vec<u8>)>> {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.buffer.pop_front() {
+            Some(entry) => {
+                self.shared.space.notify_one();
+                Poll::Ready(Some(entry))
+            }
+            None if state.done => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Return a future resolving to the next entry, or `None` at the end.
+    pub fn next_entry<'a>(&'a mut self) -> Next<'a, K> {
+        Next { stream: self }
+    }
+}
+
+impl<K> Drop for EntryStream<K> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.closed = true;
+        self.shared.space.notify_one();
+    }
+}
+
+/// Future returned by `EntryStream::next_entry`.
+pub struct Next<'a, K: 'a> {
+    stream: &'a mut EntryStream<K>,
+}
+
+impl<'a, K> Future for Next<'a, K> {
+    type Output = Option<(K, Vec<u8>)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.stream.poll_next(cx)
+    }
+}
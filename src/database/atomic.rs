@@ -0,0 +1,294 @@
+//! Atomic read-modify-write operations
+//!
+//! leveldb has no conditional writes. These operations read, decide and
+//! write while holding a lock on the key, taken from a fixed set of
+//! stripes shared by all clones of a database. They are atomic with
+//! respect to each other, plain writes to the same key are not locked.
+//! `Database::entry` hands the lock to the caller, as an `Entry` that reads
+//! and writes the key in the style of `HashMap::entry`.
+//!
+//! A thread holds at most one lock at a time, of any database. Atomic
+//! operations started while it holds one, in the closure passed to
+//! `update` or while holding an `Entry`, fail with `ErrorKind::Conflict`
+//! instead of waiting. Two threads can't each hold a stripe and wait for
+//! the other's, and an `Entry` can't be changed under its feet by atomic
+//! operations of its own thread. **Waiting on another thread while
+//! holding a lock can still deadlock**, as that thread may need the
+//! stripe held by the first.
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::{Condvar, Mutex};
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+
+const STRIPES: usize = 64;
+
+thread_local! {
+    // whether the thread holds a key lock
+    static HOLDING: Cell<bool> = const { Cell::new(false) };
+}
+
+struct Stripe {
+    locked: Mutex<bool>,
+    released: Condvar,
+}
+
+pub(crate) struct KeyLocks {
+    stripes: Vec<Stripe>,
+}
+
+/// Holds the lock on a stripe until dropped, on the thread that took it.
+pub(crate) struct KeyGuard<'a> {
+    stripe: &'a Stripe,
+    // the lock belongs to the thread
+    _thread: PhantomData<*const ()>,
+}
+
+impl KeyLocks {
+    pub(crate) fn new() -> KeyLocks {
+        let stripes = (0..STRIPES)
+            .map(|_| Stripe { locked: Mutex::new(false), released: Condvar::new() })
+            .collect();
+        KeyLocks { stripes }
+    }
+
+    /// Lock the stripe of `key`, failing with `Conflict` if the thread
+    /// already holds a lock
+    pub(crate) fn lock(&self, key: &[u8]) -> Result<KeyGuard<'_>, Error> {
+        if HOLDING.with(|holding| holding.get()) {
+            return Err(Error::with_kind(ErrorKind::Conflict, "the thread already holds a key lock".to_string()));
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let stripe = &self.stripes[hasher.finish() as usize % STRIPES];
+        // the flag is set in a single step, a panic can't leave it
+        // inconsistent
+        let mut locked = stripe.locked.lock().unwrap_or_else(|e| e.into_inner());
+        while *locked {
+            locked = stripe.released.wait(locked).unwrap_or_else(|e| e.into_inner());
+        }
+        *locked = true;
+        HOLDING.with(|holding| holding.set(true));
+        Ok(KeyGuard { stripe, _thread: PhantomData })
+    }
+}
+
+impl<'a> Drop for KeyGuard<'a> {
+    fn drop(&mut self) {
+        *self.stripe.locked.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        HOLDING.with(|holding| holding.set(false));
+        self.stripe.released.notify_one();
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Replace the value of `key` by `new`, if it currently is `expected`
+    ///
+    /// `None` stands for a missing key, as `expected` value as well as `new`
+    /// value, which deletes the key. On mismatch, nothing is written and
+    /// the current value is returned as `Err`.
+    pub fn compare_and_swap(&self,
+                            options: WriteOptions,
+                            key: K,
+                            expected: Option<&[u8]>,
+                            new: Option<&[u8]>)
+                            -> Result<Result<(), Option<Vec<u8>>>, Error> {
+        let bytes = key.as_slice(|k| k.to_vec());
+        let _guard = self.database.locks.lock(&bytes)?;
+        let current = self.get(ReadOptions::new(), &key)?;
+        if current.as_deref() != expected {
+            return Ok(Err(current));
+        }
+        match new {
+            Some(value) => self.put(options, &key, value)?,
+            None => self.delete(options, &key)?,
+        }
+        Ok(Ok(()))
+    }
+
+    /// Write `batch` if the value of `guard_key` currently is `expected`
+    ///
+    /// `None` stands for a missing key. The check and the write happen
+    /// under the lock of `guard_key`, so they are atomic with respect to
+    /// the other atomic operations on it, and to other guarded writes.
+    /// Fails with `ErrorKind::ConditionFailed` without writing anything if
+    /// the value differs.
+    pub fn write_if(&self,
+                    options: WriteOptions,
+                    batch: &Writebatch<K>,
+                    guard_key: K,
+                    expected: Option<&[u8]>)
+                    -> Result<(), Error> {
+        let bytes = guard_key.as_slice(|k| k.to_vec());
+        let _guard = self.database.locks.lock(&bytes)?;
+        let current = self.get_bytes(ReadOptions::new(), &guard_key)?;
+        if current.as_ref().map(|value| value.as_ref()) != expected {
+            return Err(Error::with_kind(ErrorKind::ConditionFailed, "the guard key doesn't hold the expected value".to_string()));
+        }
+        self.write(options, batch)
+    }
+
+    /// Replace the value of `key` by the result of `f`, applied to the
+    /// current value, returning the new value
+    ///
+    /// `None` stands for a missing key, returning `None` from `f` deletes
+    /// the key. This can stand in for a merge operator, for counters,
+    /// appending or set-union updates. `f` runs under the lock of the key,
+    /// atomic operations in it fail with `ErrorKind::Conflict`, and **it
+    /// must not wait for other threads**, see the module docs.
+    pub fn update<F>(&self, options: WriteOptions, key: K, f: F) -> Result<Option<Vec<u8>>, Error>
+        where F: FnOnce(Option<&[u8]>) -> Option<Vec<u8>>
+    {
+        let bytes = key.as_slice(|k| k.to_vec());
+        let _guard = self.database.locks.lock(&bytes)?;
+        let current = self.get_bytes(ReadOptions::new(), &key)?;
+        let new = f(current.as_ref().map(|value| value.as_ref()));
+        match new {
+            Some(ref value) => self.put(options, &key, value)?,
+            None if current.is_some() => self.delete(options, &key)?,
+            None => {}
+        }
+        Ok(new)
+    }
+
+    /// Lock `key` and look up its value, for updating it in place
+    ///
+    /// The lock is held until the `Entry` is dropped, as with the other
+    /// atomic operations. Atomic operations of the same thread fail with
+    /// `ErrorKind::Conflict` meanwhile, use the entry itself instead.
+    /// **Don't wait for other threads while holding an entry**, they may
+    /// wait for its lock.
+    pub fn entry(&self, key: K) -> Result<Entry<'_, K>, Error> {
+        let guard = key.as_slice(|k| self.database.locks.lock(k))?;
+        let entry = match self.get(ReadOptions::new(), &key)? {
+            Some(value) => {
+                Entry::Occupied(OccupiedEntry {
+                    database: self,
+                    key,
+                    value,
+                    _guard: guard,
+                })
+            }
+            None => {
+                Entry::Vacant(VacantEntry {
+                    database: self,
+                    key,
+                    _guard: guard,
+                })
+            }
+        };
+        Ok(entry)
+    }
+}
+
+/// A locked key, see `Database::entry`.
+pub enum Entry<'a, K: Key + 'a> {
+    /// The key has a value
+    Occupied(OccupiedEntry<'a, K>),
+    /// The key is missing
+    Vacant(VacantEntry<'a, K>),
+}
+
+/// A locked key that has a value.
+pub struct OccupiedEntry<'a, K: Key + 'a> {
+    database: &'a Database<K>,
+    key: K,
+    value: Vec<u8>,
+    _guard: KeyGuard<'a>,
+}
+
+/// A locked key that is missing.
+pub struct VacantEntry<'a, K: Key + 'a> {
+    database: &'a Database<K>,
+    key: K,
+    _guard: KeyGuard<'a>,
+}
+
+impl<'a, K: Key + 'a> Entry<'a, K> {
+    /// Return the key
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref entry) => &entry.key,
+            Entry::Vacant(ref entry) => &entry.key,
+        }
+    }
+
+    /// Return the value, inserting the result of `f` if the key is missing
+    pub fn or_insert_with<F>(self, options: WriteOptions, f: F) -> Result<Vec<u8>, Error>
+        where F: FnOnce() -> Vec<u8>
+    {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.value),
+            Entry::Vacant(entry) => {
+                let value = f();
+                entry.insert(options, &value)?;
+                Ok(value)
+            }
+        }
+    }
+
+    /// Apply `f` to the value and write the result, if the key has a value
+    pub fn and_modify<F>(self, options: WriteOptions, f: F) -> Result<Entry<'a, K>, Error>
+        where F: FnOnce(&mut Vec<u8>)
+    {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(&mut entry.value);
+                entry.database.put(options, &entry.key, &entry.value)?;
+                Ok(Entry::Occupied(entry))
+            }
+            vacant => Ok(vacant),
+        }
+    }
+
+    /// Delete the key, returning its value
+    pub fn remove(self, options: WriteOptions) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            Entry::Occupied(entry) => entry.remove(options).map(Some),
+            Entry::Vacant(_) => Ok(None),
+        }
+    }
+}
+
+impl<'a, K: Key + 'a> OccupiedEntry<'a, K> {
+    /// Return the key
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Return the value
+    pub fn get(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// Replace the value, returning the previous one
+    pub fn insert(&mut self, options: WriteOptions, value: &[u8]) -> Result<Vec<u8>, Error> {
+        self.database.put(options, &self.key, value)?;
+        Ok(::std::mem::replace(&mut self.value, value.to_vec()))
+    }
+
+    /// Delete the key, returning its value
+    pub fn remove(self, options: WriteOptions) -> Result<Vec<u8>, Error> {
+        self.database.delete(options, &self.key)?;
+        Ok(self.value)
+    }
+}
+
+impl<'a, K: Key + 'a> VacantEntry<'a, K> {
+    /// Return the key
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Set the value of the key
+    pub fn insert(self, options: WriteOptions, value: &[u8]) -> Result<(), Error> {
+        self.database.put(options, &self.key, value)
+    }
+}
@@ -0,0 +1,206 @@
+//! Background compaction
+//!
+//! leveldb compacts on its own as tables fill up, but deleted entries
+//! stay on disk until a compaction happens to cover them, and level-0
+//! files pile up under heavy writes. With `Options::auto_compaction` set,
+//! a background thread polls the database every `interval` and compacts:
+//!
+//! * the range of keys deleted since the last compaction, once there were
+//!   `deletes` of them
+//! * everything, once level 0 holds `level0_files` table files
+//!
+//! The thread stops when the database is closed. It can be paused, e.g.
+//! during bulk loads, with `Database::pause_auto_compaction`.
+use std::cmp::Ordering;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use database::key::Key;
+use super::{Database, RawDB};
+use super::compaction::compact_raw;
+use super::properties::property_value_raw;
+
+/// When to compact in the background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoCompaction {
+    /// How often to check the database
+    pub interval: Duration,
+    /// Compact everything once level 0 holds this many files
+    pub level0_files: usize,
+    /// Compact the deleted range once this many keys were deleted
+    pub deletes: u64,
+}
+
+impl AutoCompaction {
+    /// Check every minute, compacting at 8 level-0 files or 100000 deletes
+    pub fn new() -> AutoCompaction {
+        AutoCompaction {
+            interval: Duration::from_secs(60),
+            level0_files: 8,
+            deletes: 100_000,
+        }
+    }
+}
+
+impl Default for AutoCompaction {
+    fn default() -> AutoCompaction {
+        AutoCompaction::new()
+    }
+}
+
+struct State {
+    stopped: bool,
+    paused: bool,
+    deletes: u64,
+    // the smallest and largest key deleted since the last compaction
+    deleted: Option<(Vec<u8>, Vec<u8>)>,
+    compactions: u64,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+pub(crate) struct Scheduler {
+    state: Shared,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Scheduler {
+    pub(crate) fn new() -> Scheduler {
+        let state = State {
+            stopped: false,
+            paused: false,
+            deletes: 0,
+            deleted: None,
+            compactions: 0,
+        };
+        Scheduler {
+            state: Arc::new((Mutex::new(state), Condvar::new())),
+            thread: Mutex::new(None),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start polling `database`, which holds this scheduler
+    pub(crate) fn start(&self, database: Weak<RawDB>, config: AutoCompaction) {
+        let state = self.state.clone();
+        let thread = thread::spawn(move || run(&state, &database, config));
+        *self.thread.lock().unwrap_or_else(|e| e.into_inner()) = Some(thread);
+    }
+
+    /// Record the deletion of `key`
+    pub(crate) fn deleted<C>(&self, key: &[u8], compare: C)
+        where C: Fn(&[u8], &[u8]) -> Ordering
+    {
+        let mut state = self.lock();
+        state.deletes += 1;
+        let range = match state.deleted.take() {
+            Some((mut start, mut end)) => {
+                if compare(key, &start) == Ordering::Less {
+                    start = key.to_vec();
+                }
+                if compare(key, &end) == Ordering::Greater {
+                    end = key.to_vec();
+                }
+                (start, end)
+            }
+            None => (key.to_vec(), key.to_vec()),
+        };
+        state.deleted = Some(range);
+    }
+
+    /// Stop the thread, waiting for it unless it is the one closing the
+    /// database
+    pub(crate) fn stop(&self) {
+        self.lock().stopped = true;
+        self.state.1.notify_all();
+        if let Some(thread) = self.thread.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+fn run(shared: &Shared, database: &Weak<RawDB>, config: AutoCompaction) {
+    let (ref state, ref condvar) = **shared;
+    let lock = || state.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        {
+            let guard = lock();
+            if guard.stopped {
+                return;
+            }
+            let guard = condvar.wait_timeout(guard, config.interval).unwrap_or_else(|e| e.into_inner()).0;
+            if guard.stopped {
+                return;
+            }
+            if guard.paused {
+                continue;
+            }
+        }
+        // the handle is dropped at the end of the round, without holding the
+        // lock, as dropping the last one closes the database and stops this
+        let database = match database.upgrade() {
+            Some(database) => database,
+            None => return,
+        };
+        let level0_files = property_value_raw(database.ptr, "leveldb.num-files-at-level0")
+            .and_then(|files| files.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        let plan = {
+            let mut guard = lock();
+            if level0_files >= config.level0_files {
+                guard.deletes = 0;
+                guard.deleted = None;
+                Some(None)
+            } else if guard.deletes > 0 && guard.deletes >= config.deletes {
+                guard.deletes = 0;
+                Some(guard.deleted.take())
+            } else {
+                None
+            }
+        };
+        if let Some(range) = plan {
+            match range {
+                Some((ref start, ref end)) => compact_raw(database.ptr, Some(start), Some(end)),
+                None => compact_raw(database.ptr, None, None),
+            }
+            lock().compactions += 1;
+        }
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Record the deletion of `key`, if background compaction is enabled
+    pub(crate) fn record_delete(&self, key: &[u8]) {
+        if let Some(ref scheduler) = self.database.auto_compaction {
+            scheduler.deleted(key, |a, b| self.compare_keys(a, b));
+        }
+    }
+
+    /// Pause background compaction, if it is enabled
+    pub fn pause_auto_compaction(&self) {
+        if let Some(ref scheduler) = self.database.auto_compaction {
+            scheduler.lock().paused = true;
+        }
+    }
+
+    /// Resume background compaction, if it is enabled
+    pub fn resume_auto_compaction(&self) {
+        if let Some(ref scheduler) = self.database.auto_compaction {
+            scheduler.lock().paused = false;
+        }
+    }
+
+    /// The number of background compactions run so far
+    pub fn auto_compactions(&self) -> u64 {
+        match self.database.auto_compaction {
+            Some(ref scheduler) => scheduler.lock().compactions,
+            None => 0,
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Storage backends
+//!
+//! `Backend` is a byte-keyed interface to an ordered key-value store, for
+//! code that should not depend on leveldb itself. `Database` implements it
+//! through leveldb, `MemoryBackend` keeps entries in memory, in pure Rust,
+//! for tests and targets leveldb can't be built for, such as wasm.
+//!
+//! Keys are the encoded keys. A `Database` orders them by its comparator,
+//! `MemoryBackend` bytewise, like leveldb's default comparator. Iteration
+//! yields `Err` as its last item if reading fails.
+use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::iter;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use database::key::{Key, from_u8};
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::Error;
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::compaction::compact_raw;
+use super::iterator::{self, Iterable, LevelDBIterator};
+use super::snapshots::{Snapshot, Snapshots};
+
+/// A change applied by `Backend::write`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Store a value under a key
+    Put(Vec<u8>, Vec<u8>),
+    /// Delete a key
+    Delete(Vec<u8>),
+}
+
+/// An iterator over entries of a backend, in key order.
+pub type Entries<'a> = Box<dyn iter::Iterator<Item = Result<(Vec<u8>, Vec<u8>), Error>> + 'a>;
+
+/// Reading from a backend or a snapshot of it.
+pub trait ReadBackend {
+    /// Return the value of `key`
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Iterate over the entries from `from`, inclusive, to `to`, exclusive;
+    /// `None` leaves that end open
+    fn range<'a>(&'a self, from: Option<&[u8]>, to: Option<&[u8]>) -> Entries<'a>;
+
+    /// Iterate over all entries
+    fn entries(&self) -> Entries<'_> {
+        self.range(None, None)
+    }
+}
+
+/// An ordered key-value store.
+pub trait Backend: ReadBackend {
+    /// Apply `operations` atomically
+    fn write(&self, options: WriteOptions, operations: &[Operation]) -> Result<(), Error>;
+
+    /// Return a consistent view of the current entries
+    fn snapshot(&self) -> Box<dyn ReadBackend + '_>;
+
+    /// Reclaim the space of deleted and overwritten entries from `from` to
+    /// `to`, if the backend keeps any
+    fn compact(&self, _from: Option<&[u8]>, _to: Option<&[u8]>) {}
+
+    /// Store `value` under `key`
+    fn put(&self, options: WriteOptions, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.write(options, &[Operation::Put(key.to_vec(), value.to_vec())])
+    }
+
+    /// Delete `key`
+    fn delete(&self, options: WriteOptions, key: &[u8]) -> Result<(), Error> {
+        self.write(options, &[Operation::Delete(key.to_vec())])
+    }
+}
+
+// entries of a leveldb iterator up to an exclusive end, compared by the
+// comparator of the database
+struct Range<'a, K: Key + 'a> {
+    database: &'a Database<K>,
+    iter: iterator::Iterator<'a, K>,
+    to: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<'a, K: Key + 'a> Range<'a, K> {
+    fn new(mut iter: iterator::Iterator<'a, K>, from: Option<&[u8]>, to: Option<&[u8]>) -> Range<'a, K> {
+        if let Some(from) = from {
+            iter.seek(&from_u8(from));
+        }
+        Range {
+            database: iter.database(),
+            iter,
+            to: to.map(|to| to.to_vec()),
+            done: false,
+        }
+    }
+}
+
+impl<'a, K: Key + 'a> iter::Iterator for Range<'a, K> {
+    type Item = Result<(Vec<u8>, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if !self.iter.advance() {
+            self.done = true;
+            return self.iter.status().err().map(Err);
+        }
+        let key = self.iter.key().as_slice(|k| k.to_vec());
+        if let Some(ref to) = self.to {
+            if self.database.compare_keys(&key, to) != Ordering::Less {
+                self.done = true;
+                return None;
+            }
+        }
+        Some(Ok((key, self.iter.value())))
+    }
+}
+
+impl<K: Key> ReadBackend for Database<K> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        KV::get(self, ReadOptions::new(), from_u8::<K>(key))
+    }
+
+    fn range<'a>(&'a self, from: Option<&[u8]>, to: Option<&[u8]>) -> Entries<'a> {
+        Box::new(Range::new(self.iter(ReadOptions::new()), from, to))
+    }
+}
+
+impl<K: Key> Backend for Database<K> {
+    fn write(&self, options: WriteOptions, operations: &[Operation]) -> Result<(), Error> {
+        let mut batch = Writebatch::new();
+        for operation in operations {
+            match *operation {
+                Operation::Put(ref key, ref value) => batch.put(from_u8::<K>(key), value),
+                Operation::Delete(ref key) => batch.delete(from_u8::<K>(key)),
+            }
+        }
+        Batch::write(self, options, &batch)
+    }
+
+    fn snapshot(&self) -> Box<dyn ReadBackend + '_> {
+        Box::new(Snapshots::snapshot(self))
+    }
+
+    fn compact(&self, from: Option<&[u8]>, to: Option<&[u8]>) {
+        compact_raw(self.database.ptr, from, to)
+    }
+}
+
+impl<'s, K: Key + 's> ReadBackend for Snapshot<'s, K> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Snapshot::get(self, ReadOptions::new(), from_u8::<K>(key))
+    }
+
+    fn range<'a>(&'a self, from: Option<&[u8]>, to: Option<&[u8]>) -> Entries<'a> {
+        Box::new(Range::new(self.iter(ReadOptions::new()), from, to))
+    }
+}
+
+/// A backend keeping its entries in memory.
+///
+/// Clones share the entries. Snapshots copy them, which is fine for tests
+/// but not for large stores.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    entries: Arc<RwLock<BTreeMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl MemoryBackend {
+    /// An empty backend
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.entries.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_lock(&self) -> RwLockWriteGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.entries.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+}
+
+fn map_range<'a>(entries: &BTreeMap<Vec<u8>, Vec<u8>>, from: Option<&[u8]>, to: Option<&[u8]>) -> Entries<'a> {
+    let start = from.map_or(Bound::Unbounded, |from| Bound::Included(from.to_vec()));
+    let end = to.map_or(Bound::Unbounded, |to| Bound::Excluded(to.to_vec()));
+    if let (Bound::Included(ref start), Bound::Excluded(ref end)) = (&start, &end) {
+        // `BTreeMap::range` panics on reversed bounds
+        if start > end {
+            return Box::new(iter::empty());
+        }
+    }
+    // copied, so the lock isn't held while iterating
+    let entries: Vec<_> = entries.range((start, end))
+        .map(|(key, value)| Ok((key.clone(), value.clone())))
+        .collect();
+    Box::new(entries.into_iter())
+}
+
+impl ReadBackend for MemoryBackend {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.read().get(key).cloned())
+    }
+
+    fn range<'a>(&'a self, from: Option<&[u8]>, to: Option<&[u8]>) -> Entries<'a> {
+        map_range(&self.read(), from, to)
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn write(&self, _options: WriteOptions, operations: &[Operation]) -> Result<(), Error> {
+        let mut entries = self.write_lock();
+        for operation in operations {
+            match *operation {
+                Operation::Put(ref key, ref value) => {
+                    entries.insert(key.clone(), value.clone());
+                }
+                Operation::Delete(ref key) => {
+                    entries.remove(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Box<dyn ReadBackend + '_> {
+        Box::new(MemorySnapshot { entries: self.read().clone() })
+    }
+}
+
+struct MemorySnapshot {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl ReadBackend for MemorySnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn range<'a>(&'a self, from: Option<&[u8]>, to: Option<&[u8]>) -> Entries<'a> {
+        map_range(&self.entries, from, to)
+    }
+}
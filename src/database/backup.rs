@@ -0,0 +1,491 @@
+//! Online backups
+//!
+//! `Database::checkpoint` copies a consistent state of a live database
+//! into a new one while writes carry on. The C API gives no access to the
+//! table files, so rather than linking them, the checkpoint scans a
+//! snapshot and writes its entries into a fresh database, written with the
+//! same options and comparator as the original one. The copy comes out
+//! compacted, which makes it smaller, but means the time taken grows with
+//! the number of entries.
+//!
+//! `Backup` restores such copies and verifies them, reading every entry
+//! with checksum verification turned on.
+//!
+//! `Database::export_to` writes the entries into a dump instead, which
+//! doesn't depend on the storage format and can be loaded into any
+//! database with `Database::import_from`. A dump starts with a magic
+//! number, followed by a record per entry: the key length as a big-endian
+//! `u32`, the key, the value length and the value. It ends with a length
+//! of `u32::MAX` and the number of records as a big-endian `u64`, so a
+//! truncated dump is detected.
+//!
+//! `Snapshot::export` writes a snapshot stream, for serving state to nodes
+//! that sync from scratch and load it with `Database::import_snapshot`. It
+//! is laid out like a dump with its own magic number, followed by the
+//! CRC-32 of everything before it as a big-endian `u32`. Entries come in
+//! key order, so a snapshot of the same state always exports to the same
+//! bytes and the checksum identifies it.
+//! Unlike dumps, snapshot streams include the metadata of `Database::meta`.
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::panic;
+use std::path::Path;
+use std::ptr;
+
+use leveldb_sys::*;
+use database::key::{Key, from_u8};
+use options::{Options, ReadOptions, WriteOptions, c_options, c_readoptions};
+use super::Database;
+use super::error::{Error, ErrorKind, Operation};
+use super::batch::{Batch, Writebatch};
+use super::comparator::Comparator;
+use super::iterator::{RawIterator, raw_key, raw_status, raw_valid, raw_value};
+use super::keys::BinaryKey;
+use super::meta::{is_reserved, is_reserved_or_end};
+use super::checksum::Crc32;
+use super::snapshots::{Snapshot, Snapshots};
+
+// size of the batches the entries are copied and imported in
+const BATCH_BYTES: usize = 1 << 20;
+
+const DUMP_MAGIC: &[u8; 8] = b"LDBDUMP1";
+const SNAPSHOT_MAGIC: &[u8; 8] = b"LDBSNAP1";
+// stands in for the key length after the last record
+const DUMP_END: u32 = u32::MAX;
+
+impl<K: Key> Database<K> {
+    /// Write a consistent copy of the database to the new database `path`
+    ///
+    /// The copy holds the state at the time of the call, including the
+    /// metadata of `Database::meta`, writes done while it runs are not
+    /// included. Fails if a database exists at `path`.
+    pub fn checkpoint(&self, path: &Path) -> Result<(), Error> {
+        let target = self.open_checkpoint(path)?;
+        let snapshot = self.snapshot();
+        let mut options = ReadOptions::new();
+        options.snapshot = Some(&snapshot);
+        // raw, so the copy keeps the reserved entries as well
+        let iter = raw_iter(self, &options);
+        let mut batch = Writebatch::new();
+        while raw_valid(iter.ptr) {
+            unsafe { batch.put_raw(raw_key(iter.ptr), raw_value(iter.ptr)) };
+            if batch.approximate_size_bytes() >= BATCH_BYTES {
+                target.write(WriteOptions::new(), &batch)?;
+                batch.clear();
+            }
+            unsafe { leveldb_iter_next(iter.ptr) };
+        }
+        raw_status(iter.ptr)?;
+        // syncing the last write syncs everything written before it
+        let mut options = WriteOptions::new();
+        options.sync = true;
+        target.write(options, &batch)
+    }
+
+    // open a new database at `path`, configured like this one
+    fn open_checkpoint(&self, path: &Path) -> Result<Database<BinaryKey>, Error> {
+        let name = path.to_str()
+            .and_then(|name| CString::new(name).ok())
+            .ok_or_else(|| Error::with_kind(ErrorKind::InvalidArgument, "invalid checkpoint path".to_string()))?;
+        let comparator = self.database.comparator.as_ref().map(|comparator| comparator.ptr);
+        let mut error = ptr::null_mut();
+        unsafe {
+            // the cache, filter policy, env and comparator are owned by this
+            // database, which outlives the checkpoint
+            let c_options = c_options(&self.database.options, comparator);
+            leveldb_options_set_create_if_missing(c_options, 1);
+            leveldb_options_set_error_if_exists(c_options, 1);
+            let db = leveldb_open(c_options as *const leveldb_options_t,
+                                  name.as_bytes_with_nul().as_ptr() as *const i8,
+                                  &mut error);
+            leveldb_options_destroy(c_options);
+
+            if error.is_null() {
+                Ok(Database::new(db, path, Options::new(), None))
+            } else {
+                Err(Error::new_from_i8(error).with_operation(Operation::Open).with_path(path))
+            }
+        }
+    }
+
+    /// Write all entries into `writer` as a dump, returning their number
+    ///
+    /// The entries are read from a snapshot, writes can carry on meanwhile.
+    pub fn export_to<W: Write>(&self, writer: W) -> Result<u64, Error> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(DUMP_MAGIC).map_err(io_error)?;
+        let snapshot = self.snapshot();
+        let mut options = ReadOptions::new();
+        options.snapshot = Some(&snapshot);
+        // raw, so keys are copied as they are stored without decoding them
+        let iter = raw_iter(self, &options);
+        let mut count: u64 = 0;
+        while raw_valid(iter.ptr) {
+            let (key, value) = unsafe { (raw_key(iter.ptr), raw_value(iter.ptr)) };
+            if !is_reserved(key) {
+                write_field(&mut writer, key)?;
+                write_field(&mut writer, value)?;
+                count += 1;
+            }
+            unsafe { leveldb_iter_next(iter.ptr) };
+        }
+        raw_status(iter.ptr)?;
+        writer.write_all(&DUMP_END.to_be_bytes()).map_err(io_error)?;
+        writer.write_all(&count.to_be_bytes()).map_err(io_error)?;
+        writer.flush().map_err(io_error)?;
+        Ok(count)
+    }
+
+    /// Put all entries of the dump read from `reader` into the database,
+    /// returning their number
+    ///
+    /// Existing entries with the same keys are overwritten. The entries are
+    /// written in several batches, a dump that turns out to be damaged,
+    /// or holds keys that don't decode as `K`, fails with `Corruption` and
+    /// may have been imported partially.
+    pub fn import_from<R: Read>(&self, reader: R) -> Result<u64, Error> {
+        let mut reader = BufReader::new(reader);
+        let mut magic = [0; 8];
+        read_exact(&mut reader, &mut magic)?;
+        if &magic != DUMP_MAGIC {
+            return Err(Error::with_kind(ErrorKind::Corruption, "not a dump".to_string()));
+        }
+        let mut batch = Writebatch::new();
+        let mut count: u64 = 0;
+        loop {
+            let length = read_u32(&mut reader)?;
+            if length == DUMP_END {
+                break;
+            }
+            let key = read_bytes(&mut reader, length)?;
+            let length = read_u32(&mut reader)?;
+            let value = read_bytes(&mut reader, length)?;
+            batch.put(decode_key::<K>(&key)?, &value);
+            count += 1;
+            if batch.approximate_size_bytes() >= BATCH_BYTES {
+                self.write(WriteOptions::new(), &batch)?;
+                batch.clear();
+            }
+        }
+        let mut expected = [0; 8];
+        read_exact(&mut reader, &mut expected)?;
+        if u64::from_be_bytes(expected) != count {
+            return Err(Error::with_kind(ErrorKind::Corruption, "dump has a wrong number of records".to_string()));
+        }
+        self.write(WriteOptions::new(), &batch)?;
+        Ok(count)
+    }
+
+    /// Load a snapshot stream written by `Snapshot::export` from `reader`
+    /// into the database, which must be empty, returning the number of
+    /// entries
+    ///
+    /// The checksum is only known at the end of the stream, a damaged
+    /// stream fails with `Corruption` and leaves the database empty again.
+    pub fn import_snapshot<R: Read>(&self, reader: R) -> Result<u64, Error> {
+        let iter = raw_iter(self, &ReadOptions::new());
+        let empty = !raw_valid(iter.ptr);
+        drop(iter);
+        if !empty {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, "database is not empty".to_string()));
+        }
+        let result = self.load_snapshot(reader);
+        if result.is_err() {
+            self.clear_all()?;
+        }
+        result
+    }
+
+    /// Delete every entry, including the reserved ones, returning their
+    /// number
+    pub(crate) fn clear_all(&self) -> Result<u64, Error> {
+        let iter = raw_iter(self, &ReadOptions::new());
+        let mut batch: Writebatch<K> = Writebatch::new();
+        let mut count = 0;
+        while raw_valid(iter.ptr) {
+            batch.delete_raw(unsafe { raw_key(iter.ptr) });
+            count += 1;
+            if batch.approximate_size_bytes() >= BATCH_BYTES {
+                self.write(WriteOptions::new(), &batch)?;
+                batch.clear();
+            }
+            unsafe { leveldb_iter_next(iter.ptr) };
+        }
+        raw_status(iter.ptr)?;
+        self.write(WriteOptions::new(), &batch)?;
+        Ok(count)
+    }
+
+    fn load_snapshot<R: Read>(&self, reader: R) -> Result<u64, Error> {
+        let mut reader = Checksummed::new(BufReader::new(reader));
+        let mut magic = [0; 8];
+        read_exact(&mut reader, &mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(Error::with_kind(ErrorKind::Corruption, "not a snapshot stream".to_string()));
+        }
+        let mut batch = Writebatch::new();
+        let mut count: u64 = 0;
+        loop {
+            let length = read_u32(&mut reader)?;
+            if length == DUMP_END {
+                break;
+            }
+            let key = read_bytes(&mut reader, length)?;
+            let length = read_u32(&mut reader)?;
+            let value = read_bytes(&mut reader, length)?;
+            batch.put_raw(&key, &value);
+            count += 1;
+            if batch.approximate_size_bytes() >= BATCH_BYTES {
+                self.write(WriteOptions::new(), &batch)?;
+                batch.clear();
+            }
+        }
+        let mut expected = [0; 8];
+        read_exact(&mut reader, &mut expected)?;
+        if u64::from_be_bytes(expected) != count {
+            return Err(Error::with_kind(ErrorKind::Corruption, "snapshot stream has a wrong number of records".to_string()));
+        }
+        let checksum = reader.crc.finish();
+        if read_u32(&mut reader.inner)? != checksum {
+            return Err(Error::with_kind(ErrorKind::Corruption, "snapshot stream checksum mismatch".to_string()));
+        }
+        // syncing the last write syncs everything written before it
+        let mut options = WriteOptions::new();
+        options.sync = true;
+        self.write(options, &batch)?;
+        Ok(count)
+    }
+}
+
+impl<'a, K: Key + 'a> Snapshot<'a, K> {
+    /// Write the entries of the snapshot into `writer` as a snapshot
+    /// stream, returning their number
+    ///
+    /// The stream only depends on the entries, so nodes holding the same
+    /// state export the same bytes.
+    pub fn export<W: Write>(&'a self, writer: W) -> Result<u64, Error> {
+        let mut writer = Checksummed::new(BufWriter::new(writer));
+        writer.write_all(SNAPSHOT_MAGIC).map_err(io_error)?;
+        let mut options = ReadOptions::new();
+        options.snapshot = Some(self);
+        // raw, as the stream carries the reserved entries as well
+        let iter = raw_iter(self.database(), &options);
+        let mut count: u64 = 0;
+        while raw_valid(iter.ptr) {
+            let (key, value) = unsafe { (raw_key(iter.ptr), raw_value(iter.ptr)) };
+            write_field(&mut writer, key)?;
+            write_field(&mut writer, value)?;
+            count += 1;
+            unsafe { leveldb_iter_next(iter.ptr) };
+        }
+        raw_status(iter.ptr)?;
+        writer.write_all(&DUMP_END.to_be_bytes()).map_err(io_error)?;
+        writer.write_all(&count.to_be_bytes()).map_err(io_error)?;
+        let checksum = writer.crc.finish();
+        writer.inner.write_all(&checksum.to_be_bytes()).map_err(io_error)?;
+        writer.inner.flush().map_err(io_error)?;
+        Ok(count)
+    }
+}
+
+// an iterator over the encoded entries, positioned at the first one
+fn raw_iter<'a, K: Key>(database: &Database<K>, options: &ReadOptions<'a, K>) -> RawIterator {
+    unsafe {
+        let c_readoptions = database.database.c_options.read(options);
+        let ptr = leveldb_create_iterator(database.database.ptr, c_readoptions.ptr);
+        leveldb_iter_seek_to_first(ptr);
+        RawIterator { ptr }
+    }
+}
+
+// a reader or writer keeping the checksum of the bytes passing through
+struct Checksummed<T> {
+    inner: T,
+    crc: Crc32,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Checksummed<T> {
+        Checksummed {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// decode a key read from a dump, which may come from a database with
+// other keys, or hold reserved ones
+fn decode_key<K: Key>(key: &[u8]) -> Result<K, Error> {
+    if is_reserved_or_end(key) {
+        return Err(Error::with_kind(ErrorKind::Corruption, "dump holds a reserved key".to_string()));
+    }
+    // `Key::from_u8` has no way to fail but panicking
+    panic::catch_unwind(|| from_u8::<K>(key))
+        .map_err(|_| Error::with_kind(ErrorKind::Corruption, "dump holds a key that doesn't decode".to_string()))
+}
+
+fn write_field<W: Write>(writer: &mut W, field: &[u8]) -> Result<(), Error> {
+    if field.len() >= DUMP_END as usize {
+        return Err(Error::with_kind(ErrorKind::InvalidArgument, "entry too large to dump".to_string()));
+    }
+    writer.write_all(&(field.len() as u32).to_be_bytes()).map_err(io_error)?;
+    writer.write_all(field).map_err(io_error)
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::with_kind(ErrorKind::Corruption, "truncated dump".to_string()),
+        _ => io_error(e),
+    })
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut bytes = [0; 4];
+    read_exact(reader, &mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, length: u32) -> Result<Vec<u8>, Error> {
+    // read through `take`, so a damaged length can't allocate huge buffers
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes).map_err(io_error)?;
+    if bytes.len() != length as usize {
+        return Err(Error::with_kind(ErrorKind::Corruption, "truncated dump".to_string()));
+    }
+    Ok(bytes)
+}
+
+fn io_error(error: io::Error) -> Error {
+    Error::with_kind(ErrorKind::IOError, format!("{}", error))
+}
+
+/// The result of verifying a database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupReport {
+    /// Number of files in the database directory
+    pub files: usize,
+    /// Total size of those files, in bytes
+    pub file_bytes: u64,
+    /// Number of entries read
+    pub entries: u64,
+    /// Total size of the keys and values read, in bytes
+    pub data_bytes: u64,
+}
+
+/// Restoring and verifying backups.
+///
+/// A backup is any closed database directory, usually one written by
+/// `Database::checkpoint`. Databases using a custom comparator have to be
+/// opened with it, through the `_with_comparator` variants.
+pub struct Backup;
+
+impl Backup {
+    /// Verify the database at `path`
+    ///
+    /// The database is opened with paranoid checks and all of its entries
+    /// are read, verifying the checksums of every block. Damage is reported
+    /// as an error of kind `Corruption`.
+    pub fn verify(path: &Path) -> Result<BackupReport, Error> {
+        Backup::verify_opened::<BinaryKey, _>(path, |options| Database::open(path, options))
+    }
+
+    /// Verify the database at `path`, which uses `comparator`
+    pub fn verify_with_comparator<C: Comparator>(path: &Path, comparator: C) -> Result<BackupReport, Error> {
+        Backup::verify_opened(path, |options| Database::open_with_comparator(path, options, comparator))
+    }
+
+    /// Copy the backup `src` to `dst` and verify the copy
+    ///
+    /// `dst` must not exist or be an empty directory. If verification
+    /// fails, the copy is left in place for inspection.
+    pub fn restore(src: &Path, dst: &Path) -> Result<BackupReport, Error> {
+        Backup::copy(src, dst)?;
+        Backup::verify(dst)
+    }
+
+    /// Copy the backup `src`, which uses `comparator`, to `dst` and verify
+    /// the copy
+    pub fn restore_with_comparator<C: Comparator>(src: &Path,
+                                                  dst: &Path,
+                                                  comparator: C)
+                                                  -> Result<BackupReport, Error> {
+        Backup::copy(src, dst)?;
+        Backup::verify_with_comparator(dst, comparator)
+    }
+
+    fn copy(src: &Path, dst: &Path) -> Result<(), Error> {
+        if dst.exists() && fs::read_dir(dst).map_err(io_error)?.next().is_some() {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, format!("{} is not empty", dst.display())));
+        }
+        fs::create_dir_all(dst).map_err(io_error)?;
+        for entry in fs::read_dir(src).map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+            // the lock belongs to whoever has the database open
+            if entry.file_type().map_err(io_error)?.is_file() && entry.file_name() != "LOCK" {
+                fs::copy(entry.path(), dst.join(entry.file_name())).map_err(io_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_opened<K, F>(path: &Path, open: F) -> Result<BackupReport, Error>
+        where K: Key,
+              F: FnOnce(Options) -> Result<Database<K>, Error>
+    {
+        let mut report = BackupReport {
+            files: 0,
+            file_bytes: 0,
+            entries: 0,
+            data_bytes: 0,
+        };
+        // counted before opening, which adds files of its own
+        for entry in fs::read_dir(path).map_err(io_error)? {
+            let metadata = entry.map_err(io_error)?.metadata().map_err(io_error)?;
+            if metadata.is_file() {
+                report.files += 1;
+                report.file_bytes += metadata.len();
+            }
+        }
+        let mut options = Options::new();
+        options.paranoid_checks = true;
+        let database = open(options)?;
+        let mut read_options: ReadOptions<K> = ReadOptions::new();
+        read_options.verify_checksums = true;
+        read_options.fill_cache = false;
+        unsafe {
+            let c_readoptions = c_readoptions(&read_options);
+            let iter = RawIterator { ptr: leveldb_create_iterator(database.database.ptr, c_readoptions) };
+            leveldb_readoptions_destroy(c_readoptions);
+            leveldb_iter_seek_to_first(iter.ptr);
+            while leveldb_iter_valid(iter.ptr) != 0 {
+                report.entries += 1;
+                report.data_bytes += (raw_key(iter.ptr).len() + raw_value(iter.ptr).len()) as u64;
+                leveldb_iter_next(iter.ptr);
+            }
+            // a damaged block ends the iteration early, leaving an error
+            raw_status(iter.ptr)?;
+        }
+        Ok(report)
+    }
+}
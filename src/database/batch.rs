@@ -6,16 +6,48 @@ use std::marker::PhantomData;
 use database::key::Key;
 use database::key::from_u8;
 use std::slice;
-use options::{WriteOptions, c_writeoptions};
-use super::error::Error;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use options::WriteOptions;
+use super::error::{Error, ErrorKind};
 use std::ptr;
 use super::Database;
+use super::metrics::OperationKind;
+use super::trace::Timer;
+use super::compaction::Compaction;
+use super::iterator::{Iterable, LevelDBIterator};
+use super::options::ReadOptions;
+use super::snapshots::Snapshots;
+use super::meta::{is_reserved_or_end, reserved_key_error};
+
+// size of the batches `delete_range` deletes in
+const DELETE_BATCH_BYTES: usize = 1 << 20;
+// size of the batches `bulk_load` writes in
+const BULK_BATCH_BYTES: usize = 4 << 20;
+
+extern "C" {
+    // part of the C API of the bundled leveldb, but not exported by leveldb-sys
+    fn leveldb_writebatch_append(destination: *mut leveldb_writebatch_t,
+                                 source: *const leveldb_writebatch_t);
+}
+
+// layout of the serialized batch, see leveldb's `write_batch.cc`:
+// 8 byte sequence number, 4 byte count, followed by the records
+const HEADER_SIZE: usize = 12;
+const TYPE_DELETION: u8 = 0;
+const TYPE_VALUE: u8 = 1;
+
+/// An operation on an encoded key, `None` standing for a delete
+pub(crate) type Operation = (Vec<u8>, Option<Vec<u8>>);
 
 #[allow(missing_docs)]
 struct RawWritebatch {
     ptr: *mut leveldb_writebatch_t,
 }
 
+// a batch is not shared with leveldb, so it can be moved across threads
+unsafe impl Send for RawWritebatch {}
+
 impl Drop for RawWritebatch {
     fn drop(&mut self) {
         unsafe {
@@ -28,6 +60,16 @@ impl Drop for RawWritebatch {
 pub struct Writebatch<K: Key> {
     #[allow(dead_code)]
     writebatch: RawWritebatch,
+    // the number of operations and `reserved` at each savepoint, oldest
+    // first
+    savepoints: Vec<(usize, bool)>,
+    // leveldb's C API doesn't tell, so the batch keeps count of its
+    // operations and the size of its encoding
+    count: usize,
+    size: usize,
+    // whether `put` or `delete` were passed a key in the reserved
+    // namespace, which fails writing the batch
+    reserved: bool,
     marker: PhantomData<K>,
 }
 
@@ -39,63 +81,448 @@ pub trait Batch<K: Key> {
 
 impl<K: Key> Batch<K> for Database<K> {
     fn write(&self, options: WriteOptions, batch: &Writebatch<K>) -> Result<(), Error> {
-        unsafe {
+        if batch.reserved {
+            return Err(self.with_context(reserved_key_error(), super::error::Operation::Write, None));
+        }
+        let write = || unsafe {
             let mut error = ptr::null_mut();
-            let c_writeoptions = c_writeoptions(options);
+            let c_writeoptions = self.database.c_options.write(options);
 
             leveldb_write(self.database.ptr,
                           c_writeoptions,
                           batch.writebatch.ptr,
                           &mut error);
-            leveldb_writeoptions_destroy(c_writeoptions);
 
-            if error == ptr::null_mut() {
+            if error.is_null() {
                 Ok(())
             } else {
-                Err(Error::new_from_i8(error))
+                Err(self.with_context(Error::new_from_i8(error), super::error::Operation::Write, None))
+            }
+        };
+        let mut timer = Timer::start("write");
+        timer.entries(batch.len() as u64);
+        timer.finish(self.database.metrics.measure(OperationKind::Write, || {
+            let operations = || batch.operations();
+            self.database.hooks.run(|| self.database.watchers.notify(write, operations), operations)
+        }))?;
+        if self.database.auto_compaction.is_some() {
+            for (key, value) in batch.operations() {
+                if value.is_none() {
+                    self.record_delete(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Put all `entries`, in batches of about `chunk_size` bytes
+    ///
+    /// Each batch is atomic, but they are written one after another: if
+    /// one fails, the batches before it have been written. `options` apply
+    /// to the last batch only, the others are written without sync. With
+    /// `options.sync` set, syncing the last batch syncs all of them.
+    /// Returns the number of entries written.
+    pub fn write_all<I>(&self, options: WriteOptions, entries: I, chunk_size: usize) -> Result<u64, Error>
+        where I: IntoIterator<Item = (K, Vec<u8>)>
+    {
+        let mut batch = Writebatch::new();
+        let mut count = 0;
+        for (key, value) in entries {
+            batch.put(key, &value);
+            count += 1;
+            if batch.approximate_size_bytes() >= chunk_size {
+                self.write(WriteOptions::new(), &batch)?;
+                batch.clear();
+            }
+        }
+        self.write(options, &batch)?;
+        Ok(count)
+    }
+
+    /// Load `entries` sorted by key, as fast as leveldb takes them
+    ///
+    /// The entries are written in batches of 4 MiB without sync, with
+    /// background compaction paused, and only the last batch is synced.
+    /// The whole keyspace is compacted afterwards, so the loaded data ends
+    /// up in sorted tables instead of overlapping level-0 files.
+    ///
+    /// `entries` must be sorted by the comparator of the database and free
+    /// of duplicates, so memtable flushes produce non-overlapping files.
+    /// Debug builds panic on unsorted input. The write buffer size is fixed
+    /// when the database is opened: open it with a larger
+    /// `write_buffer_size`, e.g. 64 MiB, for the loading session to flush
+    /// fewer, larger files. Returns the number of entries written.
+    pub fn bulk_load<I>(&self, entries: I) -> Result<u64, Error>
+        where I: IntoIterator<Item = (K, Vec<u8>)>
+    {
+        self.pause_auto_compaction();
+        let result = self.load_sorted(entries);
+        self.resume_auto_compaction();
+        let count = result?;
+        self.compact_all();
+        Ok(count)
+    }
+
+    fn load_sorted<I>(&self, entries: I) -> Result<u64, Error>
+        where I: IntoIterator<Item = (K, Vec<u8>)>
+    {
+        let mut batch = Writebatch::new();
+        let mut count = 0;
+        let mut previous: Option<Vec<u8>> = None;
+        for (key, value) in entries {
+            if cfg!(debug_assertions) {
+                let key = key.as_slice(|k| k.to_vec());
+                if let Some(ref previous) = previous {
+                    assert!(self.compare_keys(previous, &key) == Ordering::Less,
+                            "bulk_load: keys not sorted, {:?} after {:?}", key, previous);
+                }
+                previous = Some(key);
+            }
+            batch.put(key, &value);
+            count += 1;
+            if batch.approximate_size_bytes() >= BULK_BATCH_BYTES {
+                self.write(WriteOptions::new(), &batch)?;
+                batch.clear();
             }
         }
+        let mut options = WriteOptions::new();
+        options.sync = true;
+        self.write(options, &batch)?;
+        Ok(count)
+    }
+
+    /// Delete all keys from `start` up to, but not including, `end`
+    ///
+    /// The keys are read from a snapshot and deleted in batches, which
+    /// isn't atomic: keys added to the range while this runs are kept, but
+    /// keys of the snapshot are deleted even if they were overwritten
+    /// meanwhile. `options` apply to every batch. With `compact`, the range is compacted afterwards to
+    /// reclaim the space of the deleted entries. Returns the number of
+    /// keys deleted.
+    pub fn delete_range(&self, options: WriteOptions, start: &K, end: &K, compact: bool) -> Result<u64, Error> {
+        let snapshot = self.snapshot();
+        let count = self.delete_keys(options, snapshot.iter(ReadOptions::new()).from(start).until(end))?;
+        if compact {
+            self.compact(start, end);
+        }
+        Ok(count)
+    }
+
+    /// Delete all keys, and compact the database to reclaim their space
+    ///
+    /// Works like `delete_range` over the whole keyspace, keeping the
+    /// metadata of `Database::meta`. The database is not destroyed and
+    /// reopened, as clones, iterators and snapshots may share its handle.
+    /// Returns the number of keys deleted.
+    pub fn clear(&self, options: WriteOptions) -> Result<u64, Error> {
+        let snapshot = self.snapshot();
+        let count = self.delete_keys(options, snapshot.iter(ReadOptions::new()))?;
+        self.compact_all();
+        Ok(count)
+    }
+
+    // delete the keys `iter` passes, which skips the reserved ones, as
+    // they are stored, so keys that don't decode as `K` go as well
+    fn delete_keys<'a, I: LevelDBIterator<'a, K>>(&self, options: WriteOptions, mut iter: I) -> Result<u64, Error> {
+        let mut batch: Writebatch<K> = Writebatch::new();
+        let mut count = 0;
+        while iter.advance() {
+            batch.delete_raw(iter.key_bytes());
+            count += 1;
+            if batch.approximate_size_bytes() >= DELETE_BATCH_BYTES {
+                self.write(options, &batch)?;
+                batch.clear();
+            }
+        }
+        iter.status()?;
+        if !batch.is_empty() {
+            self.write(options, &batch)?;
+        }
+        Ok(count)
+    }
+}
+
+// the size of the varint32 length prefix of `data`
+fn length_prefix_size(data: &[u8]) -> usize {
+    let mut len = data.len() >> 7;
+    let mut size = 1;
+    while len > 0 {
+        len >>= 7;
+        size += 1;
     }
+    size
+}
+
+// put into `batch`, returning the size of the record
+fn raw_put(batch: *mut leveldb_writebatch_t, key: &[u8], value: &[u8]) -> usize {
+    unsafe {
+        leveldb_writebatch_put(batch,
+                               key.as_ptr() as *mut c_char,
+                               key.len() as size_t,
+                               value.as_ptr() as *mut c_char,
+                               value.len() as size_t);
+    }
+    1 + length_prefix_size(key) + key.len() + length_prefix_size(value) + value.len()
+}
+
+// delete from `batch`, returning the size of the record
+fn raw_delete(batch: *mut leveldb_writebatch_t, key: &[u8]) -> usize {
+    unsafe {
+        leveldb_writebatch_delete(batch,
+                                  key.as_ptr() as *mut c_char,
+                                  key.len() as size_t);
+    }
+    1 + length_prefix_size(key) + key.len()
 }
 
 impl<K: Key> Writebatch<K> {
     /// Create a new writebatch
     pub fn new() -> Writebatch<K> {
         let ptr = unsafe { leveldb_writebatch_create() };
-        let raw = RawWritebatch { ptr: ptr };
+        let raw = RawWritebatch { ptr };
         Writebatch {
             writebatch: raw,
+            savepoints: vec![],
+            count: 0,
+            size: HEADER_SIZE,
+            reserved: false,
             marker: PhantomData,
         }
     }
 
-    /// Clear the writebatch
+    /// Clear the writebatch, and its savepoints
     pub fn clear(&mut self) {
+        self.truncate();
+        self.savepoints.clear();
+        self.reserved = false;
+    }
+
+    // drop all operations, keeping the savepoints
+    fn truncate(&mut self) {
         unsafe { leveldb_writebatch_clear(self.writebatch.ptr) };
+        self.count = 0;
+        self.size = HEADER_SIZE;
+    }
+
+    /// Remember the current end of the batch, to roll back to later
+    ///
+    /// Savepoints nest, `rollback_to_savepoint` returns to the most recent
+    /// one.
+    pub fn set_savepoint(&mut self) {
+        let len = self.len();
+        self.savepoints.push((len, self.reserved));
+    }
+
+    /// Drop the operations added since the most recent savepoint, and the
+    /// savepoint itself
+    ///
+    /// leveldb can't truncate a batch, so the remaining operations are
+    /// copied into a fresh one. Fails with `NotFound` if there is no
+    /// savepoint.
+    pub fn rollback_to_savepoint(&mut self) -> Result<(), Error> {
+        let (len, reserved) = self.savepoints.pop()
+            .ok_or_else(|| Error::with_kind(ErrorKind::NotFound, "no savepoint set".to_string()))?;
+        self.reserved = reserved;
+        if self.len() == len {
+            return Ok(());
+        }
+        let operations = self.operations();
+        self.truncate();
+        for (key, value) in operations.into_iter().take(len) {
+            match value {
+                Some(value) => self.put_raw(&key, &value),
+                None => self.delete_raw(&key),
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget the most recent savepoint, keeping the operations after it
+    ///
+    /// Fails with `NotFound` if there is no savepoint.
+    pub fn pop_savepoint(&mut self) -> Result<(), Error> {
+        self.savepoints.pop()
+            .map(|_| ())
+            .ok_or_else(|| Error::with_kind(ErrorKind::NotFound, "no savepoint set".to_string()))
     }
 
     /// Batch a put operation
+    ///
+    /// Writing the batch fails with `InvalidArgument` if `key` lies in the
+    /// reserved namespace of `meta`.
     pub fn put(&mut self, key: K, value: &[u8]) {
-        unsafe {
-            key.as_slice(|k| {
-                leveldb_writebatch_put(self.writebatch.ptr,
-                                       k.as_ptr() as *mut c_char,
-                                       k.len() as size_t,
-                                       value.as_ptr() as *mut c_char,
-                                       value.len() as size_t);
-            })
-        }
+        let ptr = self.writebatch.ptr;
+        let (size, reserved) = key.as_slice(|k| (raw_put(ptr, k, value), is_reserved_or_end(k)));
+        self.size += size;
+        self.reserved |= reserved;
+        self.count += 1;
+    }
+
+    /// Batch a put of an encoded key
+    pub(crate) fn put_raw(&mut self, key: &[u8], value: &[u8]) {
+        self.size += raw_put(self.writebatch.ptr, key, value);
+        self.count += 1;
     }
 
     /// Batch a delete operation
+    ///
+    /// Writing the batch fails with `InvalidArgument` if `key` lies in the
+    /// reserved namespace of `meta`.
     pub fn delete(&mut self, key: K) {
+        let ptr = self.writebatch.ptr;
+        let (size, reserved) = key.as_slice(|k| (raw_delete(ptr, k), is_reserved_or_end(k)));
+        self.size += size;
+        self.reserved |= reserved;
+        self.count += 1;
+    }
+
+    /// Batch a delete of an encoded key
+    pub(crate) fn delete_raw(&mut self, key: &[u8]) {
+        self.size += raw_delete(self.writebatch.ptr, key);
+        self.count += 1;
+    }
+
+    /// Collapse the operations on each key to the last one, returning the
+    /// number of operations dropped
+    ///
+    /// Writing the batch has the same effect afterwards, with less log and
+    /// memtable space. The remaining operations keep their order. If any
+    /// are dropped, so are the savepoints, as the operations before them
+    /// change.
+    pub fn dedup(&mut self) -> usize {
+        let operations = self.operations();
+        let mut last = HashMap::with_capacity(operations.len());
+        for (index, (key, _)) in operations.iter().enumerate() {
+            last.insert(key.as_slice(), index);
+        }
+        let dropped = operations.len() - last.len();
+        if dropped == 0 {
+            return 0;
+        }
+        let keep: Vec<bool> = (0..operations.len())
+            .map(|index| last[operations[index].0.as_slice()] == index)
+            .collect();
+        let reserved = self.reserved;
+        self.clear();
+        self.reserved = reserved;
+        for ((key, value), keep) in operations.iter().zip(keep) {
+            if !keep {
+                continue;
+            }
+            match *value {
+                Some(ref value) => self.put_raw(key, value),
+                None => self.delete_raw(key),
+            }
+        }
+        dropped
+    }
+
+    /// Append all operations of `other` to this batch, keeping their order
+    ///
+    /// `other` is left unchanged.
+    pub fn append(&mut self, other: &Writebatch<K>) {
+        unsafe { leveldb_writebatch_append(self.writebatch.ptr, other.writebatch.ptr) };
+        self.count += other.count;
+        self.size += other.size - HEADER_SIZE;
+        self.reserved |= other.reserved;
+    }
+
+    /// Number of put and delete operations in the batch
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether the batch contains no operations
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size of the encoded batch in bytes, including its fixed header
+    pub fn approximate_size_bytes(&self) -> usize {
+        self.size
+    }
+
+    /// Serialize the batch into leveldb's own batch encoding
+    ///
+    /// The result can be shipped to another process and turned back into
+    /// a batch using `from_bytes`. The sequence number is always written as 0,
+    /// leveldb assigns a new one on write.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder {
+            bytes: vec![0; HEADER_SIZE],
+            count: 0,
+        };
+        unsafe {
+            leveldb_writebatch_iterate(self.writebatch.ptr,
+                                       &mut encoder as *mut Encoder as *mut c_void,
+                                       encode_put_callback,
+                                       encode_deleted_callback);
+        }
+        let count = encoder.count.to_le_bytes();
+        encoder.bytes[8..HEADER_SIZE].copy_from_slice(&count);
+        encoder.bytes
+    }
+
+    /// Rebuild a batch serialized by `to_bytes`
+    ///
+    /// Fails if the bytes are not a well-formed batch.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Writebatch<K>, Error> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::with_kind(ErrorKind::Corruption, "malformed writebatch: too small".to_string()));
+        }
+        let mut count_bytes = [0; 4];
+        count_bytes.copy_from_slice(&bytes[8..HEADER_SIZE]);
+        let expected = u32::from_le_bytes(count_bytes);
+
+        let mut batch = Writebatch::new();
+        let mut rest = &bytes[HEADER_SIZE..];
+        let mut found = 0u32;
+        while let Some((&tag, tail)) = rest.split_first() {
+            rest = tail;
+            let key = read_length_prefixed(&mut rest)?;
+            match tag {
+                TYPE_VALUE => {
+                    let value = read_length_prefixed(&mut rest)?;
+                    batch.put_raw(key, value);
+                }
+                TYPE_DELETION => batch.delete_raw(key),
+                _ => return Err(Error::with_kind(ErrorKind::Corruption, "malformed writebatch: unknown record tag".to_string())),
+            }
+            found += 1;
+        }
+        if found != expected {
+            return Err(Error::with_kind(ErrorKind::Corruption, "malformed writebatch: wrong count".to_string()));
+        }
+        Ok(batch)
+    }
+
+    /// Copy all operations into a new batch, rewriting every encoded key with `f`
+    pub(crate) fn map_keys<K2: Key, F: Fn(&[u8]) -> Vec<u8>>(&self, f: F) -> Writebatch<K2> {
+        let mut mapper = KeyMapper {
+            batch: Writebatch::new(),
+            f: &f,
+        };
         unsafe {
-            key.as_slice(|k| {
-                leveldb_writebatch_delete(self.writebatch.ptr,
-                                          k.as_ptr() as *mut c_char,
-                                          k.len() as size_t);
-            })
+            leveldb_writebatch_iterate(self.writebatch.ptr,
+                                       &mut mapper as *mut KeyMapper<K2> as *mut c_void,
+                                       map_put_callback::<K2>,
+                                       map_deleted_callback::<K2>);
+        }
+        mapper.batch
+    }
+
+    /// Return the operations of the batch on encoded keys, in order
+    pub(crate) fn operations(&self) -> Vec<Operation> {
+        let mut operations: Vec<Operation> = Vec::new();
+        unsafe {
+            leveldb_writebatch_iterate(self.writebatch.ptr,
+                                       &mut operations as *mut Vec<Operation> as *mut c_void,
+                                       collect_put_callback,
+                                       collect_deleted_callback);
         }
+        operations
     }
 
     /// Iterate over the writebatch, returning the resulting iterator
@@ -111,6 +538,12 @@ impl<K: Key> Writebatch<K> {
     }
 }
 
+impl<K: Key> Default for Writebatch<K> {
+    fn default() -> Writebatch<K> {
+        Writebatch::new()
+    }
+}
+
 /// A trait for iterators to iterate over written batches and check their validity.
 pub trait WritebatchIterator {
     /// The database key type this iterates over
@@ -130,8 +563,8 @@ extern "C" fn put_callback<K: Key, T: WritebatchIterator<K = K>>(state: *mut c_v
                                                                  vallen: size_t) {
     unsafe {
         let iter: &mut T = &mut *(state as *mut T);
-        let key_slice = slice::from_raw_parts::<u8>(key as *const u8, keylen as usize);
-        let val_slice = slice::from_raw_parts::<u8>(val as *const u8, vallen as usize);
+        let key_slice = slice::from_raw_parts::<u8>(key as *const u8, keylen);
+        let val_slice = slice::from_raw_parts::<u8>(val as *const u8, vallen);
         let k = from_u8::<<T as WritebatchIterator>::K>(key_slice);
         iter.put(k, val_slice);
     }
@@ -142,8 +575,119 @@ extern "C" fn deleted_callback<K: Key, T: WritebatchIterator<K = K>>(state: *mut
                                                                      keylen: size_t) {
     unsafe {
         let iter: &mut T = &mut *(state as *mut T);
-        let key_slice = slice::from_raw_parts::<u8>(key as *const u8, keylen as usize);
+        let key_slice = slice::from_raw_parts::<u8>(key as *const u8, keylen);
         let k = from_u8::<<T as WritebatchIterator>::K>(key_slice);
         iter.deleted(k);
     }
 }
+
+struct KeyMapper<'f, K: Key> {
+    batch: Writebatch<K>,
+    f: &'f dyn Fn(&[u8]) -> Vec<u8>,
+}
+
+extern "C" fn map_put_callback<K: Key>(state: *mut c_void,
+                                       key: *const i8,
+                                       keylen: size_t,
+                                       val: *const i8,
+                                       vallen: size_t) {
+    unsafe {
+        let mapper: &mut KeyMapper<K> = &mut *(state as *mut KeyMapper<K>);
+        let key = (mapper.f)(slice::from_raw_parts(key as *const u8, keylen));
+        mapper.batch.put_raw(&key, slice::from_raw_parts(val as *const u8, vallen));
+    }
+}
+
+extern "C" fn map_deleted_callback<K: Key>(state: *mut c_void, key: *const i8, keylen: size_t) {
+    unsafe {
+        let mapper: &mut KeyMapper<K> = &mut *(state as *mut KeyMapper<K>);
+        let key = (mapper.f)(slice::from_raw_parts(key as *const u8, keylen));
+        mapper.batch.delete_raw(&key);
+    }
+}
+
+extern "C" fn collect_put_callback(state: *mut c_void,
+                                   key: *const i8,
+                                   keylen: size_t,
+                                   val: *const i8,
+                                   vallen: size_t) {
+    unsafe {
+        let operations: &mut Vec<Operation> = &mut *(state as *mut Vec<Operation>);
+        let key = slice::from_raw_parts(key as *const u8, keylen).to_vec();
+        let value = slice::from_raw_parts(val as *const u8, vallen).to_vec();
+        operations.push((key, Some(value)));
+    }
+}
+
+extern "C" fn collect_deleted_callback(state: *mut c_void, key: *const i8, keylen: size_t) {
+    unsafe {
+        let operations: &mut Vec<Operation> = &mut *(state as *mut Vec<Operation>);
+        let key = slice::from_raw_parts(key as *const u8, keylen).to_vec();
+        operations.push((key, None));
+    }
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+    count: u32,
+}
+
+impl Encoder {
+    fn push_length_prefixed(&mut self, data: &[u8]) {
+        let mut len = data.len() as u32;
+        while len >= 0x80 {
+            self.bytes.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        self.bytes.push(len as u8);
+        self.bytes.extend_from_slice(data);
+    }
+}
+
+extern "C" fn encode_put_callback(state: *mut c_void,
+                                  key: *const i8,
+                                  keylen: size_t,
+                                  val: *const i8,
+                                  vallen: size_t) {
+    unsafe {
+        let encoder: &mut Encoder = &mut *(state as *mut Encoder);
+        encoder.bytes.push(TYPE_VALUE);
+        encoder.push_length_prefixed(slice::from_raw_parts(key as *const u8, keylen));
+        encoder.push_length_prefixed(slice::from_raw_parts(val as *const u8, vallen));
+        encoder.count += 1;
+    }
+}
+
+extern "C" fn encode_deleted_callback(state: *mut c_void, key: *const i8, keylen: size_t) {
+    unsafe {
+        let encoder: &mut Encoder = &mut *(state as *mut Encoder);
+        encoder.bytes.push(TYPE_DELETION);
+        encoder.push_length_prefixed(slice::from_raw_parts(key as *const u8, keylen));
+        encoder.count += 1;
+    }
+}
+
+/// Split a varint32 length prefixed slice off the front of `input`.
+fn read_length_prefixed<'a>(input: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let malformed = || Error::with_kind(ErrorKind::Corruption, "malformed writebatch: truncated record".to_string());
+    let mut len = 0usize;
+    let mut shift = 0;
+    loop {
+        let (&byte, tail) = input.split_first().ok_or_else(malformed)?;
+        *input = tail;
+        len |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 28 {
+            return Err(malformed());
+        }
+    }
+    if input.len() < len {
+        return Err(malformed());
+    }
+    let (data, tail) = input.split_at(len);
+    *input = tail;
+    Ok(data)
+}
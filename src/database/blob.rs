@@ -0,0 +1,491 @@
+//! Values stored outside of leveldb
+//!
+//! leveldb rewrites values every time a compaction moves them down a level,
+//! which for large values costs far more than the writes themselves. A
+//! `BlobStore` appends values of at least `BlobOptions::min_blob_size`
+//! bytes to blob files next to the database, and stores only a pointer to
+//! them in leveldb, the design of WiscKey. Smaller values stay in leveldb.
+//!
+//! Overwritten and deleted values stay in their blob files until
+//! `collect_garbage` rewrites the live values of files that are mostly
+//! garbage and removes them, either called by hand or from a background
+//! `Collector`.
+//!
+//! Garbage collection doesn't know about snapshots: reading a value through
+//! a snapshot taken before its blob file was collected fails with an
+//! `ErrorKind::IOError`, as the snapshot still points into the removed file.
+//! Don't collect garbage while such snapshots are in use.
+//!
+//! Blob files are named by increasing numbers, the store always appends to
+//! a new one after opening. A database should only be accessed through its
+//! blob store, and always with the same blob directory.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::collections::HashMap;
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::checksum::Crc32;
+use super::iterator::{Iterable, LevelDBIterator};
+
+// first byte of the values stored in leveldb
+const INLINE: u8 = 0;
+const POINTER: u8 = 1;
+const POINTER_SIZE: usize = 1 + 4 + 8 + 4;
+
+// a record starts with the checksum of the rest of it, and the lengths of
+// the key and value following it
+const RECORD_HEADER_SIZE: usize = 12;
+
+const EXTENSION: &str = "blob";
+
+/// How values are split between leveldb and blob files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobOptions {
+    /// Store values of at least this many bytes in blob files
+    pub min_blob_size: usize,
+    /// Start a new blob file once the current one holds this many bytes
+    pub max_file_size: u64,
+}
+
+impl BlobOptions {
+    /// Store values of 4 KiB and more, in blob files of up to 64 MiB
+    pub fn new() -> BlobOptions {
+        BlobOptions {
+            min_blob_size: 4096,
+            max_file_size: 64 << 20,
+        }
+    }
+}
+
+impl Default for BlobOptions {
+    fn default() -> BlobOptions {
+        BlobOptions::new()
+    }
+}
+
+/// What a run of `collect_garbage` did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// The number of blob files removed
+    pub files_removed: u64,
+    /// The number of live values moved to the current blob file
+    pub blobs_moved: u64,
+    /// The number of bytes freed on disk
+    pub bytes_reclaimed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pointer {
+    file: u32,
+    offset: u64,
+    length: u32,
+}
+
+impl Pointer {
+    fn encode(&self) -> Vec<u8> {
+        let mut stored = Vec::with_capacity(POINTER_SIZE);
+        stored.push(POINTER);
+        stored.extend_from_slice(&self.file.to_be_bytes());
+        stored.extend_from_slice(&self.offset.to_be_bytes());
+        stored.extend_from_slice(&self.length.to_be_bytes());
+        stored
+    }
+
+    fn decode(stored: &[u8]) -> Option<Pointer> {
+        if stored.len() != POINTER_SIZE || stored[0] != POINTER {
+            return None;
+        }
+        let mut file = [0; 4];
+        file.copy_from_slice(&stored[1..5]);
+        let mut offset = [0; 8];
+        offset.copy_from_slice(&stored[5..13]);
+        let mut length = [0; 4];
+        length.copy_from_slice(&stored[13..17]);
+        Some(Pointer {
+            file: u32::from_be_bytes(file),
+            offset: u64::from_be_bytes(offset),
+            length: u32::from_be_bytes(length),
+        })
+    }
+}
+
+fn record_size(key: usize, value: usize) -> u64 {
+    (RECORD_HEADER_SIZE + key + value) as u64
+}
+
+fn io_error(error: io::Error) -> Error {
+    Error::with_kind(ErrorKind::IOError, format!("{}", error))
+}
+
+fn blob_path(dir: &Path, file: u32) -> PathBuf {
+    dir.join(format!("{:08}.{}", file, EXTENSION))
+}
+
+// the numbers of the blob files in `dir`, in increasing order
+fn blob_files(dir: &Path) -> Result<Vec<u32>, Error> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+            continue;
+        }
+        if let Some(file) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// read the record at the current position, `None` at the end of the file
+// or at a torn record left by a crash
+fn read_record<R: Read>(reader: &mut R) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut header = [0; RECORD_HEADER_SIZE];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut word = [0; 4];
+    word.copy_from_slice(&header[0..4]);
+    let checksum = u32::from_be_bytes(word);
+    word.copy_from_slice(&header[4..8]);
+    let key_length = u32::from_be_bytes(word) as usize;
+    word.copy_from_slice(&header[8..12]);
+    let value_length = u32::from_be_bytes(word) as usize;
+
+    // the lengths aren't checked yet, so don't allocate by them up front
+    let expected = (key_length + value_length) as u64;
+    let mut key = vec![];
+    if reader.by_ref().take(expected).read_to_end(&mut key)? as u64 != expected {
+        return Ok(None);
+    }
+    let value = key.split_off(key_length);
+    let mut crc = Crc32::new();
+    crc.update(&header[4..]);
+    crc.update(&key);
+    crc.update(&value);
+    if crc.finish() != checksum {
+        return Ok(None);
+    }
+    Ok(Some((key, value)))
+}
+
+struct Writer {
+    file: u32,
+    handle: File,
+    size: u64,
+}
+
+impl Writer {
+    fn create(dir: &Path, file: u32) -> Result<Writer, Error> {
+        let handle = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(blob_path(dir, file))
+            .map_err(io_error)?;
+        Ok(Writer {
+            file,
+            handle,
+            size: 0,
+        })
+    }
+}
+
+struct Shared {
+    dir: PathBuf,
+    options: BlobOptions,
+    writer: Mutex<Writer>,
+}
+
+/// A database keeping large values in blob files.
+pub struct BlobStore<K: Key> {
+    database: Database<K>,
+    shared: Arc<Shared>,
+}
+
+impl<K: Key> Clone for BlobStore<K> {
+    fn clone(&self) -> BlobStore<K> {
+        BlobStore {
+            database: self.database.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K: Key> BlobStore<K> {
+    /// Open the blob files in `dir` for `database`, creating the directory
+    /// if it doesn't exist
+    ///
+    /// A store should only be opened once per database, clones share it.
+    pub fn open<P: AsRef<Path>>(database: Database<K>, dir: P, options: BlobOptions) -> Result<BlobStore<K>, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(io_error)?;
+        let next = blob_files(&dir)?.last().map_or(0, |last| last + 1);
+        let writer = Writer::create(&dir, next)?;
+        Ok(BlobStore {
+            database,
+            shared: Arc::new(Shared {
+                dir,
+                options,
+                writer: Mutex::new(writer),
+            }),
+        })
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// The directory of the blob files
+    pub fn dir(&self) -> &Path {
+        &self.shared.dir
+    }
+
+    fn writer(&self) -> MutexGuard<'_, Writer> {
+        self.shared.writer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // append a record to the current blob file, starting a new one first if
+    // it is full
+    fn append(&self, key: &[u8], value: &[u8], sync: bool) -> Result<Pointer, Error> {
+        if key.len() > u32::MAX as usize || value.len() > u32::MAX as usize {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, "blob too large".to_string()));
+        }
+        let mut writer = self.writer();
+        if writer.size >= self.shared.options.max_file_size {
+            *writer = Writer::create(&self.shared.dir, writer.file + 1)?;
+        }
+        let mut record = Vec::with_capacity(record_size(key.len(), value.len()) as usize);
+        record.extend_from_slice(&[0; 4]);
+        record.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        record.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+        let mut crc = Crc32::new();
+        crc.update(&record[4..]);
+        record[0..4].copy_from_slice(&crc.finish().to_be_bytes());
+
+        let written = writer.handle.write_all(&record).and_then(|_| {
+            if sync {
+                writer.handle.sync_data()
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(error) = written {
+            // drop a partial record, later ones are found by their offset
+            let size = writer.size;
+            let _ = writer.handle.set_len(size);
+            return Err(io_error(error));
+        }
+        let pointer = Pointer {
+            file: writer.file,
+            offset: writer.size,
+            length: value.len() as u32,
+        };
+        writer.size += record.len() as u64;
+        Ok(pointer)
+    }
+
+    fn read_blob(&self, pointer: Pointer, key: &[u8]) -> io::Result<Vec<u8>> {
+        let mut file = File::open(blob_path(&self.shared.dir, pointer.file))?;
+        file.seek(SeekFrom::Start(pointer.offset))?;
+        match read_record(&mut file)? {
+            Some((stored_key, value)) if stored_key == key && value.len() == pointer.length as usize => Ok(value),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "blob record doesn't match its pointer")),
+        }
+    }
+
+    /// Store `value` under `key`, in a blob file if it is large enough
+    ///
+    /// With `options.sync`, the blob file is synced before the pointer is
+    /// written.
+    pub fn put(&self, options: WriteOptions, key: K, value: &[u8]) -> Result<(), Error> {
+        let encoded = key.as_slice(|k| k.to_vec());
+        let _guard = self.database.database.locks.lock(&encoded)?;
+        let stored = if value.len() >= self.shared.options.min_blob_size {
+            self.append(&encoded, value, options.sync)?.encode()
+        } else {
+            let mut stored = Vec::with_capacity(1 + value.len());
+            stored.push(INLINE);
+            stored.extend_from_slice(value);
+            stored
+        };
+        self.database.put(options, &key, &stored)
+    }
+
+    /// get a value, reading it from its blob file if needed
+    ///
+    /// Fails if `options.snapshot` was taken before garbage collection
+    /// removed the blob file of the value.
+    pub fn get(&self, options: ReadOptions<K>, key: K) -> Result<Option<Vec<u8>>, Error> {
+        let encoded = key.as_slice(|k| k.to_vec());
+        let mut previous = None;
+        loop {
+            let reread = ReadOptions {
+                verify_checksums: options.verify_checksums,
+                fill_cache: options.fill_cache,
+                snapshot: options.snapshot,
+                iterate_lower_bound: None,
+                iterate_upper_bound: None,
+            };
+            let stored = match self.database.get_bytes(reread, &key)? {
+                Some(stored) => stored,
+                None => return Ok(None),
+            };
+            match stored.first() {
+                Some(&INLINE) => return Ok(Some(stored[1..].to_vec())),
+                Some(&POINTER) => {}
+                _ => return Err(Error::with_kind(ErrorKind::Corruption, "malformed blob pointer".to_string())),
+            }
+            let pointer = Pointer::decode(&stored)
+                .ok_or_else(|| Error::with_kind(ErrorKind::Corruption, "malformed blob pointer".to_string()))?;
+            match self.read_blob(pointer, &encoded) {
+                Ok(value) => return Ok(Some(value)),
+                // garbage collection moved the value and removed its file
+                // since the pointer was read, read it again. A snapshot
+                // keeps returning the old pointer, so don't bother.
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound
+                              && options.snapshot.is_none()
+                              && previous != Some(pointer) => {
+                    previous = Some(pointer);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                    return Err(Error::with_kind(ErrorKind::Corruption, format!("{}", e)));
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+    }
+
+    /// delete a value, leaving its blob for garbage collection
+    pub fn delete(&self, options: WriteOptions, key: K) -> Result<(), Error> {
+        let _guard = key.as_slice(|k| self.database.database.locks.lock(k))?;
+        self.database.delete(options, &key)
+    }
+
+    /// Remove the blob files of which at least `min_garbage`, a fraction
+    /// between 0 and 1, is taken up by overwritten or deleted values
+    ///
+    /// Their live values are first appended to the current blob file. The
+    /// current file itself is never collected. Snapshots taken before can
+    /// no longer read the values of removed files.
+    pub fn collect_garbage(&self, min_garbage: f64) -> Result<GcStats, Error> {
+        let current = self.writer().file;
+
+        // the bytes of live records in each blob file
+        let mut live: HashMap<u32, u64> = HashMap::new();
+        let mut iter = self.database.iter(ReadOptions::new());
+        while iter.advance() {
+            if let Some(pointer) = Pointer::decode(&iter.value()) {
+                let key_length = iter.key().as_slice(|k| k.len());
+                *live.entry(pointer.file).or_insert(0) += record_size(key_length, pointer.length as usize);
+            }
+        }
+        iter.status()?;
+        drop(iter);
+
+        let mut stats = GcStats::default();
+        for file in blob_files(&self.shared.dir)? {
+            if file >= current {
+                continue;
+            }
+            let path = blob_path(&self.shared.dir, file);
+            let size = fs::metadata(&path).map_err(io_error)?.len();
+            let garbage = size.saturating_sub(live.get(&file).cloned().unwrap_or(0));
+            if size > 0 && (garbage as f64) < min_garbage * size as f64 {
+                continue;
+            }
+            stats.blobs_moved += self.relocate(file)?;
+            // make the moved values and their new pointers durable before
+            // the old copies go away
+            self.writer().handle.sync_data().map_err(io_error)?;
+            self.database.write(WriteOptions { sync: true }, &Writebatch::new())?;
+            fs::remove_file(&path).map_err(io_error)?;
+            stats.files_removed += 1;
+            stats.bytes_reclaimed += garbage;
+        }
+        Ok(stats)
+    }
+
+    // move the live values of `file` to the current blob file, returning
+    // their number
+    fn relocate(&self, file: u32) -> Result<u64, Error> {
+        let mut reader = BufReader::new(File::open(blob_path(&self.shared.dir, file)).map_err(io_error)?);
+        let mut offset = 0;
+        let mut moved = 0;
+        while let Some((key, value)) = read_record(&mut reader).map_err(io_error)? {
+            let pointer = Pointer {
+                file,
+                offset,
+                length: value.len() as u32,
+            };
+            offset += record_size(key.len(), value.len());
+            let _guard = self.database.database.locks.lock(&key)?;
+            let user_key = K::from_u8(&key);
+            let stored = self.database.get_bytes(ReadOptions::new(), &user_key)?;
+            if stored.as_ref().and_then(|stored| Pointer::decode(stored)) == Some(pointer) {
+                let relocated = self.append(&key, &value, false)?;
+                self.database.put(WriteOptions::new(), &user_key, &relocated.encode())?;
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+}
+
+impl<K: Key + Send + Sync + 'static> BlobStore<K> {
+    /// Start a background thread calling `collect_garbage(min_garbage)`
+    /// every `interval`
+    ///
+    /// The thread stops when the returned `Collector` is dropped.
+    pub fn spawn_collector(&self, interval: Duration, min_garbage: f64) -> Collector {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let store = self.clone();
+        let signal = stop.clone();
+        let thread = thread::spawn(move || {
+            let (ref stopped, ref condvar) = *signal;
+            let mut stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+            while !*stopped {
+                let (guard, timeout) = condvar.wait_timeout(stopped, interval)
+                    .unwrap_or_else(|e| e.into_inner());
+                stopped = guard;
+                if timeout.timed_out() && !*stopped {
+                    let _ = store.collect_garbage(min_garbage);
+                }
+            }
+        });
+        Collector {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Handle to a background garbage collection thread, stopping it on drop.
+pub struct Collector {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        let (ref stopped, ref condvar) = *self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -17,23 +17,32 @@ impl Bytes {
     /// Creates instance of `Bytes` from leveldb-allocated data.
     ///
     /// Returns `None` if `ptr` is `null`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be allocated by leveldb and point to at least `size` bytes.
+    /// Ownership is transferred, the buffer is freed with `leveldb_free`.
     pub unsafe fn from_raw(ptr: *mut u8, size: usize) -> Option<Self> {
         if ptr.is_null() {
             None
         } else {
             Some(Bytes {
                 bytes: &mut *ptr,
-                size: size,
+                size,
                 _marker: Default::default(),
             })
         }
     }
 
     /// Creates instance of `Bytes` from leveldb-allocated data without null checking.
+    ///
+    /// # Safety
+    ///
+    /// Same as `from_raw`, additionally `ptr` must not be `null`.
     pub unsafe fn from_raw_unchecked(ptr: *mut u8, size: usize) -> Self {
         Bytes {
             bytes: &mut *ptr,
-            size: size,
+            size,
             _marker: Default::default(),
         }
     }
@@ -69,25 +78,25 @@ impl ::std::ops::DerefMut for Bytes {
 
 impl ::std::borrow::Borrow<[u8]> for Bytes {
     fn borrow(&self) -> &[u8] {
-        &*self
+        self
     }
 }
 
 impl ::std::borrow::BorrowMut<[u8]> for Bytes {
     fn borrow_mut(&mut self) -> &mut [u8] {
-        &mut *self
+        self
     }
 }
 
 impl AsRef<[u8]> for Bytes {
     fn as_ref(&self) -> &[u8] {
-        &*self
+        self
     }
 }
 
 impl AsMut<[u8]> for Bytes {
     fn as_mut(&mut self) -> &mut [u8] {
-        &mut *self
+        self
     }
 }
 
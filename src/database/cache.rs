@@ -1,6 +1,7 @@
 //! Structs and traits to work with the leveldb cache.
 use leveldb_sys::{leveldb_cache_t, leveldb_cache_create_lru, leveldb_cache_destroy};
 use libc::size_t;
+use std::sync::Arc;
 
 #[allow(missing_docs)]
 struct RawCache {
@@ -15,16 +16,25 @@ impl Drop for RawCache {
     }
 }
 
+// leveldb caches synchronise internally
+unsafe impl Send for RawCache {}
+unsafe impl Sync for RawCache {}
+
 /// Represents a leveldb cache
+///
+/// Cloning a `Cache` returns a handle to the same cache. Passing clones
+/// to multiple databases makes them share a single memory budget. The
+/// cache is destroyed once the last handle and database using it are gone.
+#[derive(Clone)]
 pub struct Cache {
-    raw: RawCache,
+    raw: Arc<RawCache>,
 }
 
 impl Cache {
     /// Create a leveldb LRU cache of a given size
     pub fn new(size: size_t) -> Cache {
         let cache = unsafe { leveldb_cache_create_lru(size) };
-        Cache { raw: RawCache { ptr: cache } }
+        Cache { raw: Arc::new(RawCache { ptr: cache }) }
     }
 
     #[allow(missing_docs)]
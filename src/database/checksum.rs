@@ -0,0 +1,59 @@
+//! CRC-32 checksums of stored data
+//!
+//! The IEEE polynomial, as used by zlib and gzip, so checksums can be
+//! checked with common tools, and CRC-32C, which leveldb uses for its own
+//! files.
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+// Castagnoli
+const POLYNOMIAL_C: u32 = 0x82F6_3B78;
+
+const fn table(polynomial: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ polynomial } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const TABLE: [u32; 256] = table(POLYNOMIAL);
+const TABLE_C: [u32; 256] = table(POLYNOMIAL_C);
+
+fn update(table: &[u32; 256], mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc = table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// A running CRC-32 over data written in pieces.
+#[derive(Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.0 = update(&TABLE, self.0, data);
+    }
+
+    pub(crate) fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// The CRC-32C of `data`, masked the way leveldb stores it in its logs.
+pub(crate) fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = !update(&TABLE_C, !0, data);
+    crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
@@ -0,0 +1,332 @@
+//! Large values split into chunks
+//!
+//! leveldb handles values of a few kilobytes best: multi-megabyte values
+//! bloat the write-ahead log and memtable, and are rewritten by every
+//! compaction they take part in. `ChunkedStore` stores values larger than
+//! its chunk size as a header and a series of chunks under keys of their
+//! own, so no single entry exceeds the chunk size. Chunk sizes of 64 to
+//! 256 KiB work well, smaller values are stored in one entry as usual.
+//!
+//! Values can be written from a `Read` and read back as a `ChunkedValue`
+//! implementing `Read`, so they never need to fit in memory at once.
+//!
+//! Replacing a value writes the new chunks under a new generation before
+//! switching the header over, so readers see either the old or the new
+//! value. The store namespaces its entries, a database should only be
+//! accessed through it.
+use std::io::{self, Read};
+
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::keys::BinaryKey;
+use super::snapshots::{Snapshot, Snapshots};
+
+// first byte of the keys of headers and chunks
+const HEADER: u8 = 0;
+const CHUNK: u8 = 1;
+
+// first byte of a header: the value follows, or it is split into chunks
+const INLINE: u8 = 0;
+const CHUNKED: u8 = 1;
+const CHUNKED_HEADER_SIZE: usize = 1 + 8 + 8 + 4;
+
+// size of the batches chunks are written in
+const BATCH_BYTES: usize = 1 << 20;
+
+fn header_key(key: &[u8]) -> BinaryKey {
+    let mut header = Vec::with_capacity(1 + key.len());
+    header.push(HEADER);
+    header.extend_from_slice(key);
+    BinaryKey(header)
+}
+
+// all chunk keys of a key have the same length, so they can't collide with
+// those of keys of a different length
+fn chunk_key(key: &[u8], generation: u64, index: u32) -> BinaryKey {
+    let mut chunk = Vec::with_capacity(1 + key.len() + 12);
+    chunk.push(CHUNK);
+    chunk.extend_from_slice(key);
+    chunk.extend_from_slice(&generation.to_be_bytes());
+    chunk.extend_from_slice(&index.to_be_bytes());
+    BinaryKey(chunk)
+}
+
+fn io_error(error: io::Error) -> Error {
+    Error::with_kind(ErrorKind::IOError, format!("{}", error))
+}
+
+enum Header {
+    Inline(Vec<u8>),
+    Chunked {
+        generation: u64,
+        length: u64,
+        chunks: u32,
+    },
+}
+
+impl Header {
+    fn decode(mut stored: Vec<u8>) -> Result<Header, Error> {
+        match stored.first() {
+            Some(&INLINE) => {
+                stored.remove(0);
+                Ok(Header::Inline(stored))
+            }
+            Some(&CHUNKED) if stored.len() == CHUNKED_HEADER_SIZE => {
+                let mut generation = [0; 8];
+                generation.copy_from_slice(&stored[1..9]);
+                let mut length = [0; 8];
+                length.copy_from_slice(&stored[9..17]);
+                let mut chunks = [0; 4];
+                chunks.copy_from_slice(&stored[17..21]);
+                Ok(Header::Chunked {
+                    generation: u64::from_be_bytes(generation),
+                    length: u64::from_be_bytes(length),
+                    chunks: u32::from_be_bytes(chunks),
+                })
+            }
+            _ => Err(Error::with_kind(ErrorKind::Corruption, "malformed chunked value header".to_string())),
+        }
+    }
+
+    fn encode_chunked(generation: u64, length: u64, chunks: u32) -> Vec<u8> {
+        let mut header = Vec::with_capacity(CHUNKED_HEADER_SIZE);
+        header.push(CHUNKED);
+        header.extend_from_slice(&generation.to_be_bytes());
+        header.extend_from_slice(&length.to_be_bytes());
+        header.extend_from_slice(&chunks.to_be_bytes());
+        header
+    }
+}
+
+// fill `buffer` from `reader`, returning how many bytes were read
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+    Ok(filled)
+}
+
+/// A store for values of any size, split into chunks.
+pub struct ChunkedStore {
+    database: Database<BinaryKey>,
+    chunk_size: usize,
+}
+
+impl Clone for ChunkedStore {
+    fn clone(&self) -> ChunkedStore {
+        ChunkedStore {
+            database: self.database.clone(),
+            chunk_size: self.chunk_size,
+        }
+    }
+}
+
+impl ChunkedStore {
+    /// Store values in `database`, split into chunks of `chunk_size` bytes
+    /// if they are larger
+    ///
+    /// The chunk size may be changed between uses, values keep the size
+    /// they were written with.
+    pub fn new(database: Database<BinaryKey>, chunk_size: usize) -> ChunkedStore {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        ChunkedStore {
+            database,
+            chunk_size,
+        }
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<BinaryKey> {
+        &self.database
+    }
+
+    fn header(&self, options: ReadOptions<BinaryKey>, key: &[u8]) -> Result<Option<Header>, Error> {
+        match self.database.get(options, header_key(key))? {
+            Some(stored) => Header::decode(stored).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Store `value` under `key`
+    pub fn put(&self, options: WriteOptions, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.put_reader(options, key, value).map(|_| ())
+    }
+
+    /// Store the bytes read from `reader` under `key`, returning their
+    /// number
+    ///
+    /// If reading or writing fails, the previous value is kept.
+    pub fn put_reader<R: Read>(&self, options: WriteOptions, key: &[u8], mut reader: R) -> Result<u64, Error> {
+        let _guard = self.database.database.locks.lock(&header_key(key).0)?;
+        let previous = self.header(ReadOptions::new(), key)?;
+        let generation = match previous {
+            Some(Header::Chunked { generation, .. }) => generation.wrapping_add(1),
+            _ => 0,
+        };
+
+        let mut buffer = vec![0; self.chunk_size];
+        let mut batch = Writebatch::new();
+        let mut filled = read_chunk(&mut reader, &mut buffer)?;
+        let mut length = filled as u64;
+        if filled < self.chunk_size {
+            buffer.truncate(filled);
+            let mut stored = vec![INLINE];
+            stored.extend_from_slice(&buffer);
+            batch.put(header_key(key), &stored);
+        } else {
+            let mut chunks: u32 = 0;
+            let written = (|| {
+                while filled > 0 {
+                    batch.put(chunk_key(key, generation, chunks), &buffer[..filled]);
+                    chunks = chunks.checked_add(1)
+                        .ok_or_else(|| Error::with_kind(ErrorKind::InvalidArgument, "value has too many chunks".to_string()))?;
+                    if batch.approximate_size_bytes() >= BATCH_BYTES {
+                        self.database.write(WriteOptions::new(), &batch)?;
+                        batch.clear();
+                    }
+                    filled = read_chunk(&mut reader, &mut buffer)?;
+                    length += filled as u64;
+                }
+                Ok(())
+            })();
+            if let Err(error) = written {
+                self.delete_chunks(generation, key, chunks);
+                return Err(error);
+            }
+            batch.put(header_key(key), &Header::encode_chunked(generation, length, chunks));
+        }
+        if let Some(Header::Chunked { generation, chunks, .. }) = previous {
+            for index in 0..chunks {
+                batch.delete(chunk_key(key, generation, index));
+            }
+        }
+        self.database.write(options, &batch)?;
+        Ok(length)
+    }
+
+    // remove chunks written before a failure, on a best effort basis
+    fn delete_chunks(&self, generation: u64, key: &[u8], chunks: u32) {
+        let mut batch = Writebatch::new();
+        for index in 0..chunks {
+            batch.delete(chunk_key(key, generation, index));
+        }
+        let _ = self.database.write(WriteOptions::new(), &batch);
+    }
+
+    /// Read the value of `key`
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut value = match self.open(key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let mut bytes = Vec::with_capacity(value.len() as usize);
+        value.read_to_end(&mut bytes).map_err(io_error)?;
+        Ok(Some(bytes))
+    }
+
+    /// Open the value of `key` for reading
+    ///
+    /// The value is read from a snapshot, later writes don't affect it.
+    pub fn open(&self, key: &[u8]) -> Result<Option<ChunkedValue<'_>>, Error> {
+        let snapshot = self.database.snapshot();
+        let header = {
+            let mut options = ReadOptions::new();
+            options.snapshot = Some(&snapshot);
+            self.header(options, key)?
+        };
+        let (buffer, chunked) = match header {
+            None => return Ok(None),
+            Some(Header::Inline(value)) => (value, None),
+            Some(Header::Chunked { generation, length, chunks }) => (vec![], Some((generation, length, chunks))),
+        };
+        let length = chunked.map_or(buffer.len() as u64, |(_, length, _)| length);
+        Ok(Some(ChunkedValue {
+            snapshot,
+            key: key.to_vec(),
+            chunked,
+            next_chunk: 0,
+            buffer,
+            position: 0,
+            length,
+        }))
+    }
+
+    /// Delete the value of `key`
+    pub fn delete(&self, options: WriteOptions, key: &[u8]) -> Result<(), Error> {
+        let _guard = self.database.database.locks.lock(&header_key(key).0)?;
+        let mut batch = Writebatch::new();
+        if let Some(Header::Chunked { generation, chunks, .. }) = self.header(ReadOptions::new(), key)? {
+            for index in 0..chunks {
+                batch.delete(chunk_key(key, generation, index));
+            }
+        }
+        batch.delete(header_key(key));
+        self.database.write(options, &batch)
+    }
+}
+
+/// A value of a `ChunkedStore`, read chunk by chunk.
+pub struct ChunkedValue<'a> {
+    snapshot: Snapshot<'a, BinaryKey>,
+    key: Vec<u8>,
+    // generation, length and number of chunks, `None` for inline values
+    chunked: Option<(u64, u64, u32)>,
+    next_chunk: u32,
+    // the current chunk, or the whole inline value
+    buffer: Vec<u8>,
+    position: usize,
+    length: u64,
+}
+
+impl<'a> ChunkedValue<'a> {
+    /// The length of the value in bytes
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Whether the value is empty
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn next_buffer(&mut self) -> Result<bool, Error> {
+        let (generation, _, chunks) = match self.chunked {
+            Some(chunked) => chunked,
+            None => return Ok(false),
+        };
+        if self.next_chunk == chunks {
+            return Ok(false);
+        }
+        let chunk = self.snapshot.get(ReadOptions::new(), chunk_key(&self.key, generation, self.next_chunk))?;
+        self.buffer = chunk.ok_or_else(|| {
+            Error::with_kind(ErrorKind::Corruption, format!("chunk {} of a value is missing", self.next_chunk))
+        })?;
+        self.next_chunk += 1;
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<'a> Read for ChunkedValue<'a> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            let more = self.next_buffer().map_err(|e| io::Error::other(e.to_string()))?;
+            if !more {
+                return Ok(0);
+            }
+        }
+        let count = out.len().min(self.buffer.len() - self.position);
+        out[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
@@ -0,0 +1,248 @@
+//! Group commit
+//!
+//! A synced write costs an fsync, which limits durable writes to a few
+//! hundred per second on most disks. A `WriteCoalescer` collects the puts
+//! and deletes of many threads into one batch, committed with `sync` by a
+//! background thread once the oldest waits for `max_delay` or the batch
+//! reaches `max_bytes`. Each call returns once its batch is durable, with
+//! the result of that commit, so callers trade a little latency for a
+//! single fsync per batch.
+//!
+//! Writes of one call are applied in order. Calls of different threads
+//! are applied in the order they reached the coalescer.
+use std::mem;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use database::key::{Key, from_u8};
+use options::WriteOptions;
+use super::Database;
+use super::error::Error;
+use super::batch::{Batch, Operation, Writebatch};
+
+/// When a `WriteCoalescer` commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoalescerConfig {
+    /// Commit once the oldest pending write waited this long
+    pub max_delay: Duration,
+    /// Commit once the pending writes hold this many bytes
+    pub max_bytes: usize,
+}
+
+impl CoalescerConfig {
+    /// Commit after 2ms or 1 MiB
+    pub fn new() -> CoalescerConfig {
+        CoalescerConfig {
+            max_delay: Duration::from_millis(2),
+            max_bytes: 1 << 20,
+        }
+    }
+}
+
+impl Default for CoalescerConfig {
+    fn default() -> CoalescerConfig {
+        CoalescerConfig::new()
+    }
+}
+
+struct State {
+    pending: Vec<Operation>,
+    bytes: usize,
+    // when the oldest pending write arrived
+    since: Option<Instant>,
+    // the number of the batch being collected
+    batch: u64,
+    // the number of batches committed, all before it are
+    committed: u64,
+    // the errors of failed batches, with the number of callers yet to
+    // collect them
+    failures: HashMap<u64, (String, usize)>,
+    waiting: HashMap<u64, usize>,
+    stopped: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // signals pending writes to the committer
+    work: Condvar,
+    // signals committed batches to the callers
+    committed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Commits the writes of many callers in shared synced batches.
+///
+/// Calls block until their batch is committed, share a coalescer between
+/// threads through an `Arc`. Its thread stops once it is dropped.
+pub struct WriteCoalescer<K: Key> {
+    database: Database<K>,
+    config: CoalescerConfig,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<K: Key + Send + Sync + 'static> WriteCoalescer<K> {
+    /// Coalesce writes to `database`
+    pub fn new(database: Database<K>, config: CoalescerConfig) -> WriteCoalescer<K> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                pending: vec![],
+                bytes: 0,
+                since: None,
+                batch: 0,
+                committed: 0,
+                failures: HashMap::new(),
+                waiting: HashMap::new(),
+                stopped: false,
+            }),
+            work: Condvar::new(),
+            committed: Condvar::new(),
+        });
+        let thread = {
+            let database = database.clone();
+            let shared = shared.clone();
+            thread::spawn(move || commit_loop(&database, &shared, config))
+        };
+        WriteCoalescer {
+            database,
+            config,
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<K: Key> WriteCoalescer<K> {
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// Durably put `value` under `key`, with other callers' writes
+    pub fn put(&self, key: &K, value: &[u8]) -> Result<(), Error> {
+        self.write_all(vec![(key.as_slice(|k| k.to_vec()), Some(value.to_vec()))])
+    }
+
+    /// Durably delete `key`, with other callers' writes
+    pub fn delete(&self, key: &K) -> Result<(), Error> {
+        self.write_all(vec![(key.as_slice(|k| k.to_vec()), None)])
+    }
+
+    /// Durably apply `puts` and `deletes` atomically, with other callers'
+    /// writes
+    pub fn write(&self, puts: &[(K, &[u8])], deletes: &[K]) -> Result<(), Error> {
+        let mut operations = Vec::with_capacity(puts.len() + deletes.len());
+        for &(ref key, value) in puts {
+            operations.push((key.as_slice(|k| k.to_vec()), Some(value.to_vec())));
+        }
+        for key in deletes {
+            operations.push((key.as_slice(|k| k.to_vec()), None));
+        }
+        self.write_all(operations)
+    }
+
+    /// The number of batches committed so far
+    pub fn batches(&self) -> u64 {
+        self.shared.lock().committed
+    }
+
+    fn write_all(&self, operations: Vec<Operation>) -> Result<(), Error> {
+        let mut state = self.shared.lock();
+        let batch = state.batch;
+        state.bytes += operations.iter()
+            .map(|(key, value)| key.len() + value.as_ref().map_or(0, |v| v.len()))
+            .sum::<usize>();
+        state.pending.extend(operations);
+        state.since.get_or_insert_with(Instant::now);
+        *state.waiting.entry(batch).or_insert(0) += 1;
+        if state.bytes >= self.config.max_bytes || state.pending.len() == 1 {
+            self.shared.work.notify_one();
+        }
+        while state.committed <= batch {
+            state = self.shared.committed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        let failure = match state.failures.get_mut(&batch) {
+            Some(&mut (ref message, ref mut callers)) => {
+                *callers -= 1;
+                Some((message.clone(), *callers == 0))
+            }
+            None => None,
+        };
+        match failure {
+            Some((message, last)) => {
+                if last {
+                    state.failures.remove(&batch);
+                }
+                Err(Error::new(message))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+fn commit_loop<K: Key>(database: &Database<K>, shared: &Shared, config: CoalescerConfig) {
+    let mut state = shared.lock();
+    loop {
+        // wait for a full batch or the delay of the oldest write
+        loop {
+            if state.bytes >= config.max_bytes {
+                break;
+            }
+            match state.since {
+                Some(since) => {
+                    let elapsed = since.elapsed();
+                    if elapsed >= config.max_delay {
+                        break;
+                    }
+                    state = shared.work.wait_timeout(state, config.max_delay - elapsed)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+                None if state.stopped => return,
+                None => state = shared.work.wait(state).unwrap_or_else(|e| e.into_inner()),
+            }
+        }
+        let operations = mem::take(&mut state.pending);
+        let number = state.batch;
+        state.batch += 1;
+        state.bytes = 0;
+        state.since = None;
+        drop(state);
+
+        let mut batch = Writebatch::new();
+        for (key, value) in operations {
+            match value {
+                Some(value) => batch.put(from_u8::<K>(&key), &value),
+                None => batch.delete(from_u8::<K>(&key)),
+            }
+        }
+        let mut options = WriteOptions::new();
+        options.sync = true;
+        let result = database.write(options, &batch);
+
+        state = shared.lock();
+        let callers = state.waiting.remove(&number).unwrap_or(0);
+        if let Err(error) = result {
+            state.failures.insert(number, (error.message().to_string(), callers));
+        }
+        state.committed = number + 1;
+        shared.committed.notify_all();
+    }
+}
+
+impl<K: Key> Drop for WriteCoalescer<K> {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.work.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
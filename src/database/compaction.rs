@@ -1,25 +1,49 @@
 //! Compaction
 use super::Database;
 use super::key::Key;
-use leveldb_sys::leveldb_compact_range;
+use super::trace::Timer;
+use leveldb_sys::{leveldb_t, leveldb_compact_range};
 use libc::{c_char, size_t};
+use std::ptr;
 
 pub trait Compaction<'a, K: Key + 'a> {
     fn compact(&self, start: &'a K, limit: &'a K);
+
+    /// Compact the range between `start` and `limit`.
+    ///
+    /// `None` is treated as a key before (`start`) or after (`limit`)
+    /// all keys in the database.
+    fn compact_range(&self, start: Option<&'a K>, limit: Option<&'a K>);
+
+    /// Compact the whole keyspace.
+    fn compact_all(&self) {
+        self.compact_range(None, None)
+    }
 }
 
 impl<'a, K: Key + 'a> Compaction<'a, K> for Database<K> {
     fn compact(&self, start: &'a K, limit: &'a K) {
-        unsafe {
-            start.as_slice(|s| {
-                limit.as_slice(|l| {
-                    leveldb_compact_range(self.database.ptr,
-                                          s.as_ptr() as *mut c_char,
-                                          s.len() as size_t,
-                                          l.as_ptr() as *mut c_char,
-                                          l.len() as size_t);
-                });
-            });
-        }
+        self.compact_range(Some(start), Some(limit))
+    }
+
+    fn compact_range(&self, start: Option<&'a K>, limit: Option<&'a K>) {
+        let start = start.map(|k| k.as_slice(|s| s.to_vec()));
+        let limit = limit.map(|k| k.as_slice(|l| l.to_vec()));
+        let _timer = Timer::start("compact");
+        compact_raw(self.database.ptr, start.as_deref(), limit.as_deref());
+    }
+}
+
+pub(crate) fn compact_raw(database: *mut leveldb_t, start: Option<&[u8]>, limit: Option<&[u8]>) {
+    let (s_ptr, s_len) = match start {
+        Some(s) => (s.as_ptr() as *const c_char, s.len() as size_t),
+        None => (ptr::null(), 0),
+    };
+    let (l_ptr, l_len) = match limit {
+        Some(l) => (l.as_ptr() as *const c_char, l.len() as size_t),
+        None => (ptr::null(), 0),
+    };
+    unsafe {
+        leveldb_compact_range(database, s_ptr, s_len, l_ptr, l_len);
     }
 }
@@ -4,13 +4,18 @@
 //! Comparators allow to override this comparison.
 //! The ordering of keys introduced by the compartor influences iteration order.
 //! Databases written with one Comparator cannot be opened with another.
+//!
+//! Keys in the reserved namespace of this crate, see `meta`, never reach a
+//! comparator: they are compared bytewise and sort after all other keys.
 use leveldb_sys::*;
 use libc::{size_t, c_void, c_char};
 use std::slice;
 use std::cmp::Ordering;
 use database::key::Key;
 use database::key::from_u8;
+use database::meta::is_reserved_or_end;
 use std::marker::PhantomData;
+use std::ffi::CString;
 
 /// A comparator has two important functions:
 ///
@@ -22,7 +27,10 @@ pub trait Comparator {
     type K: Key;
 
     /// Return the name of the Comparator
-    fn name(&self) -> *const c_char;
+    ///
+    /// The name is copied when the comparator is handed to leveldb, it
+    /// must not contain NUL bytes.
+    fn name(&self) -> &str;
     /// compare two keys. This must implement a total ordering.
     fn compare(&self, a: &Self::K, b: &Self::K) -> Ordering;
     /// whether the comparator is the `DefaultComparator`
@@ -51,11 +59,22 @@ impl<K: Key + Ord> OrdComparator<K> {
 #[derive(Copy,Clone)]
 pub struct DefaultComparator;
 
+/// The state handed to leveldb, keeping the name alive for
+/// as long as the comparator is in use.
+struct ComparatorState<C: Comparator> {
+    name: CString,
+    comparator: Box<C>,
+}
+
+/// # Safety
+///
+/// The callbacks receive the boxed `ComparatorState` as `state` and must only be
+/// handed to leveldb through `create_comparator`.
 unsafe trait InternalComparator : Comparator where Self: Sized {
 
     extern "C" fn name(state: *mut c_void) -> *const c_char {
-        let x = unsafe { &*(state as *mut Self) };
-        x.name()
+        let x = unsafe { &*(state as *mut ComparatorState<Self>) };
+        x.name.as_ptr()
     }
 
     extern "C" fn compare(state: *mut c_void,
@@ -65,12 +84,20 @@ unsafe trait InternalComparator : Comparator where Self: Sized {
                           b_len: size_t)
                           -> i32 {
         unsafe {
-            let a_slice = slice::from_raw_parts::<u8>(a as *const u8, a_len as usize);
-            let b_slice = slice::from_raw_parts::<u8>(b as *const u8, b_len as usize);
-            let x = &*(state as *mut Self);
-            let a_key = from_u8::<<Self as Comparator>::K>(a_slice);
-            let b_key = from_u8::<<Self as Comparator>::K>(b_slice);
-            match x.compare(&a_key, &b_key) {
+            let a_slice = slice::from_raw_parts::<u8>(a as *const u8, a_len);
+            let b_slice = slice::from_raw_parts::<u8>(b as *const u8, b_len);
+            let ordering = match (is_reserved_or_end(a_slice), is_reserved_or_end(b_slice)) {
+                (true, true) => a_slice.cmp(b_slice),
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => {
+                    let x = &(*(state as *mut ComparatorState<Self>)).comparator;
+                    let a_key = from_u8::<<Self as Comparator>::K>(a_slice);
+                    let b_key = from_u8::<<Self as Comparator>::K>(b_slice);
+                    x.compare(&a_key, &b_key)
+                }
+            };
+            match ordering {
                 Ordering::Less => -1,
                 Ordering::Equal => 0,
                 Ordering::Greater => 1,
@@ -79,29 +106,49 @@ unsafe trait InternalComparator : Comparator where Self: Sized {
     }
 
     extern "C" fn destructor(state: *mut c_void) {
-        let _x: Box<Self> = unsafe { Box::from_raw(state as *mut Self) };
+        let _x: Box<ComparatorState<Self>> =
+            unsafe { Box::from_raw(state as *mut ComparatorState<Self>) };
          // let the Box fall out of scope and run the T's destructor
     }
 }
 
 unsafe impl<C: Comparator> InternalComparator for C {}
 
+/// The signature of the comparison callback handed to leveldb.
+pub type CompareFn = extern "C" fn(*mut c_void, *const i8, size_t, *const i8, size_t) -> i32;
+
 #[allow(missing_docs)]
 pub fn create_comparator<T: Comparator>(x: Box<T>) -> *mut leveldb_comparator_t {
-    unsafe {
-        leveldb_comparator_create(Box::into_raw(x) as *mut c_void,
+    create_raw_comparator(x).0
+}
+
+/// Like `create_comparator`, but additionally returns the state and
+/// the comparison callback handed to leveldb, so keys can be compared
+/// by the same ordering from Rust.
+///
+/// The state stays valid until the comparator is destroyed.
+pub fn create_raw_comparator<T: Comparator>(x: Box<T>)
+                                            -> (*mut leveldb_comparator_t, *mut c_void, CompareFn) {
+    let name = x.name().split('\0').next().unwrap_or("").to_string();
+    let state = Box::new(ComparatorState {
+        name: CString::new(name).unwrap(),
+        comparator: x,
+    });
+    let state = Box::into_raw(state) as *mut c_void;
+    let ptr = unsafe {
+        leveldb_comparator_create(state,
                                   <T as InternalComparator>::destructor,
                                   <T as InternalComparator>::compare,
                                   <T as InternalComparator>::name)
-    }
+    };
+    (ptr, state, <T as InternalComparator>::compare)
 }
 
 impl<K: Key + Ord> Comparator for OrdComparator<K> {
   type K = K;
 
-    fn name(&self) -> *const c_char {
-        let slice: &str = self.name.as_ref();
-        slice.as_ptr() as *const c_char
+    fn name(&self) -> &str {
+        &self.name
     }
 
     fn compare(&self, a: &K, b: &K) -> Ordering {
@@ -112,8 +159,8 @@ impl<K: Key + Ord> Comparator for OrdComparator<K> {
 impl Comparator for DefaultComparator {
   type K = i32;
 
-    fn name(&self) -> *const c_char {
-        "default_comparator".as_ptr() as *const c_char
+    fn name(&self) -> &str {
+        "default_comparator"
     }
 
     fn compare(&self, _a: &i32, _b: &i32) -> Ordering {
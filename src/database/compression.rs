@@ -0,0 +1,257 @@
+//! Value compression
+//!
+//! `Compressed` is a `Codec` for the typed layer that compresses encoded
+//! values of at least a threshold size with a `Compressor`, leaving smaller
+//! ones as they are. Every stored value starts with a one byte tag naming
+//! the compressor used, `0` for none, so the threshold may be changed at any
+//! time.
+//!
+//! With the `compression-lz4` feature, `Lz4` compresses into the LZ4 block
+//! format. Other formats are plugged in by implementing `Compressor`.
+use super::error::{Error, ErrorKind};
+use super::typed::Codec;
+
+/// Tag of values stored uncompressed.
+pub const UNCOMPRESSED: u8 = 0;
+
+/// A compression format.
+pub trait Compressor {
+    /// The tag stored in front of values compressed with this format,
+    /// must not be `UNCOMPRESSED`
+    fn tag(&self) -> u8;
+    /// Compress `bytes`, `None` if the format can't hold them, which
+    /// stores them uncompressed
+    fn compress(&self, bytes: &[u8]) -> Option<Vec<u8>>;
+    /// Decompress `bytes`, failing on malformed input
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// Compresses the values encoded by the codec `C` with `Z`.
+#[derive(Debug, Clone)]
+pub struct Compressed<C, Z> {
+    codec: C,
+    compressor: Z,
+    threshold: usize,
+}
+
+impl<C, Z: Compressor> Compressed<C, Z> {
+    /// Compress the values encoded by `codec` that are at least
+    /// `threshold` bytes long
+    pub fn new(codec: C, compressor: Z, threshold: usize) -> Compressed<C, Z> {
+        Compressed {
+            codec,
+            compressor,
+            threshold,
+        }
+    }
+
+    /// Return the codec encoding the values
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+}
+
+impl<V, C: Codec<V>, Z: Compressor> Codec<V> for Compressed<C, Z> {
+    fn encode(&self, value: &V) -> Vec<u8> {
+        let encoded = self.codec.encode(value);
+        if encoded.len() >= self.threshold {
+            // incompressible values are kept as they are
+            if let Some(compressed) = self.compressor.compress(&encoded) {
+                if compressed.len() < encoded.len() {
+                    let mut stored = Vec::with_capacity(1 + compressed.len());
+                    stored.push(self.compressor.tag());
+                    stored.extend_from_slice(&compressed);
+                    return stored;
+                }
+            }
+        }
+        let mut stored = Vec::with_capacity(1 + encoded.len());
+        stored.push(UNCOMPRESSED);
+        stored.extend_from_slice(&encoded);
+        stored
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<V, Error> {
+        match bytes.split_first() {
+            Some((&UNCOMPRESSED, encoded)) => self.codec.decode(encoded),
+            Some((&tag, compressed)) if tag == self.compressor.tag() => {
+                self.codec.decode(&self.compressor.decompress(compressed)?)
+            }
+            Some((&tag, _)) => Err(Error::with_kind(ErrorKind::Corruption, format!("unknown compression tag {}", tag))),
+            None => Err(Error::with_kind(ErrorKind::Corruption, "value without compression tag".to_string())),
+        }
+    }
+}
+
+/// The LZ4 block format, prefixed with the uncompressed length.
+#[cfg(feature = "compression-lz4")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lz4;
+
+#[cfg(feature = "compression-lz4")]
+mod lz4 {
+    use super::super::error::{Error, ErrorKind};
+
+    const MIN_MATCH: usize = 4;
+    // the last match must start this many bytes before the end of the input
+    const MATCH_LIMIT: usize = 12;
+    // and the last bytes are always literals
+    const LAST_LITERALS: usize = 5;
+    const MAX_OFFSET: usize = 65535;
+    const HASH_BITS: u32 = 12;
+    // the output reserved up front, as a multiple of the input
+    const RESERVE_RATIO: usize = 4;
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn hash(sequence: u32) -> usize {
+        (sequence.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn push_length(out: &mut Vec<u8>, mut length: usize) {
+        while length >= 255 {
+            out.push(255);
+            length -= 255;
+        }
+        out.push(length as u8);
+    }
+
+    fn push_sequence(out: &mut Vec<u8>, literals: &[u8], lengths: Option<(usize, usize)>) {
+        let literal_nibble = literals.len().min(15);
+        let match_nibble = lengths.map_or(0, |(_, length)| (length - MIN_MATCH).min(15));
+        out.push((literal_nibble << 4 | match_nibble) as u8);
+        if literals.len() >= 15 {
+            push_length(out, literals.len() - 15);
+        }
+        out.extend_from_slice(literals);
+        if let Some((offset, length)) = lengths {
+            out.extend_from_slice(&(offset as u16).to_le_bytes());
+            if length - MIN_MATCH >= 15 {
+                push_length(out, length - MIN_MATCH - 15);
+            }
+        }
+    }
+
+    pub(super) fn compress(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(input.len() / 2 + 16);
+        let mut table = vec![0usize; 1 << HASH_BITS];
+        let mut anchor = 0;
+        let mut position = 0;
+        if input.len() > MATCH_LIMIT {
+            let limit = input.len() - MATCH_LIMIT;
+            while position < limit {
+                let sequence = read_u32(input, position);
+                let slot = hash(sequence);
+                // positions are stored plus one, zero marks an empty slot
+                let candidate = table[slot];
+                table[slot] = position + 1;
+                if candidate > 0 {
+                    let candidate = candidate - 1;
+                    if position - candidate <= MAX_OFFSET && read_u32(input, candidate) == sequence {
+                        let longest = input.len() - LAST_LITERALS - position;
+                        let mut length = MIN_MATCH;
+                        while length < longest && input[candidate + length] == input[position + length] {
+                            length += 1;
+                        }
+                        push_sequence(&mut out, &input[anchor..position], Some((position - candidate, length)));
+                        position += length;
+                        anchor = position;
+                        continue;
+                    }
+                }
+                position += 1;
+            }
+        }
+        push_sequence(&mut out, &input[anchor..], None);
+        out
+    }
+
+    fn malformed() -> Error {
+        Error::with_kind(ErrorKind::Corruption, "malformed LZ4 block".to_string())
+    }
+
+    fn read_length(input: &[u8], position: &mut usize, mut length: usize) -> Result<usize, Error> {
+        loop {
+            let byte = *input.get(*position).ok_or_else(malformed)?;
+            *position += 1;
+            length += byte as usize;
+            if byte != 255 {
+                return Ok(length);
+            }
+        }
+    }
+
+    pub(super) fn decompress(input: &[u8], length: usize) -> Result<Vec<u8>, Error> {
+        // the length comes from the stored value, don't trust it to reserve
+        // more than the input plausibly expands to, the vector grows beyond
+        let mut out = Vec::with_capacity(length.min(input.len().saturating_mul(RESERVE_RATIO)));
+        let mut position = 0;
+        loop {
+            let token = *input.get(position).ok_or_else(malformed)?;
+            position += 1;
+            let mut literals = (token >> 4) as usize;
+            if literals == 15 {
+                literals = read_length(input, &mut position, literals)?;
+            }
+            if input.len() - position < literals || length - out.len() < literals {
+                return Err(malformed());
+            }
+            out.extend_from_slice(&input[position..position + literals]);
+            position += literals;
+            if position == input.len() {
+                break;
+            }
+            if input.len() - position < 2 {
+                return Err(malformed());
+            }
+            let offset = u16::from_le_bytes([input[position], input[position + 1]]) as usize;
+            position += 2;
+            let mut matched = (token & 15) as usize;
+            if matched == 15 {
+                matched = read_length(input, &mut position, matched)?;
+            }
+            matched += MIN_MATCH;
+            if offset == 0 || offset > out.len() || length - out.len() < matched {
+                return Err(malformed());
+            }
+            // the match may overlap the bytes it produces
+            let start = out.len() - offset;
+            for i in 0..matched {
+                let byte = out[start + i];
+                out.push(byte);
+            }
+        }
+        if out.len() != length {
+            return Err(malformed());
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(feature = "compression-lz4")]
+impl Compressor for Lz4 {
+    fn tag(&self) -> u8 {
+        1
+    }
+
+    /// Compress `bytes`, `None` from 4 GiB on, as the length is stored as
+    /// a `u32`
+    fn compress(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        use std::convert::TryFrom;
+
+        let length = u32::try_from(bytes.len()).ok()?;
+        let mut compressed = length.to_be_bytes().to_vec();
+        compressed.extend_from_slice(&lz4::compress(bytes));
+        Some(compressed)
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        if bytes.len() < 4 {
+            return Err(Error::with_kind(ErrorKind::Corruption, "malformed LZ4 block".to_string()));
+        }
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        lz4::decompress(&bytes[4..], length)
+    }
+}
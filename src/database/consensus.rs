@@ -0,0 +1,124 @@
+//! Applying the log of a consensus engine
+//!
+//! Consensus engines like Raft agree on a log of commands and apply each
+//! committed entry to a state machine, once and in log order. A
+//! `StateMachine` applies entries carrying a writebatch to the database,
+//! and stores the index of the last applied entry in the same atomic write
+//! as the batch. After a crash, the engine resumes applying from
+//! `last_applied_index() + 1`, and entries it hands in again are skipped.
+//!
+//! Applying a batch only writes what it contains, so nodes applying the
+//! same log hold the same state, as long as the batches don't depend on
+//! anything local to a node. The snapshots of that state, exported with
+//! `export_snapshot` into the snapshot stream of `Snapshot::export`, are
+//! the same bytes on every node. They carry the last applied index, which
+//! `install_snapshot` restores on a lagging node together with the state.
+//!
+//! The index is the metadata `LAST_APPLIED_INDEX`, see `Database::meta`.
+use std::io::{Read, Write};
+
+use leveldb_sys::leveldb_readoptions_destroy;
+use database::key::Key;
+use options::{ReadOptions, WriteOptions, c_readoptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::batch::{Batch, Writebatch};
+use super::kv::get_raw_bytes;
+use super::meta::meta_key;
+use super::snapshots::Snapshots;
+
+/// The name of the metadata holding the index of the last applied entry
+pub const LAST_APPLIED_INDEX: &str = "leveldb.consensus.last-applied-index";
+
+fn decode_index(value: Option<Vec<u8>>) -> Result<u64, Error> {
+    match value {
+        Some(value) => {
+            if value.len() != 8 {
+                return Err(Error::with_kind(ErrorKind::Corruption, "malformed last applied index".to_string()));
+            }
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&value);
+            Ok(u64::from_be_bytes(bytes))
+        }
+        None => Ok(0),
+    }
+}
+
+/// Applies the committed entries of a consensus log to a database.
+pub struct StateMachine<K: Key> {
+    database: Database<K>,
+}
+
+impl<K: Key> StateMachine<K> {
+    /// Apply entries to `database`
+    pub fn new(database: Database<K>) -> StateMachine<K> {
+        StateMachine { database }
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// The index of the last applied entry, 0 if there is none
+    pub fn last_applied_index(&self) -> Result<u64, Error> {
+        decode_index(self.database.meta().get(LAST_APPLIED_INDEX)?)
+    }
+
+    /// Apply `batch`, the entry committed at `index`, returning whether it
+    /// was applied
+    ///
+    /// Entries up to the last applied index are skipped. Fails with
+    /// `InvalidArgument` if `index` is not the next one, so no entry is
+    /// applied out of order.
+    pub fn apply_committed(&self, index: u64, batch: &Writebatch<K>) -> Result<bool, Error> {
+        // serialises applying to clones of the database
+        let _guard = self.database.database.locks.lock(&meta_key(LAST_APPLIED_INDEX))?;
+        let applied = self.last_applied_index()?;
+        if index <= applied {
+            return Ok(false);
+        }
+        if index != applied + 1 {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument,
+                                        format!("expected log index {}, got {}", applied + 1, index)));
+        }
+        let mut applying = Writebatch::new();
+        applying.append(batch);
+        self.database.meta().put_in(&mut applying, LAST_APPLIED_INDEX, &index.to_be_bytes());
+        self.database.write(WriteOptions::new(), &applying)?;
+        Ok(true)
+    }
+
+    /// Write a snapshot stream of the current state into `writer`,
+    /// returning the last applied index it includes
+    ///
+    /// Entries can be applied meanwhile, they are not part of the stream.
+    pub fn export_snapshot<W: Write>(&self, writer: W) -> Result<u64, Error> {
+        let snapshot = self.database.snapshot();
+        let mut options = ReadOptions::new();
+        options.snapshot = Some(&snapshot);
+        let index = unsafe {
+            let c_readoptions = c_readoptions(&options);
+            let value = get_raw_bytes(self.database.database.ptr,
+                                      c_readoptions,
+                                      &meta_key(LAST_APPLIED_INDEX));
+            leveldb_readoptions_destroy(c_readoptions);
+            decode_index(value?.map(Into::into))?
+        };
+        snapshot.export(writer)?;
+        Ok(index)
+    }
+
+    /// Replace the state with the snapshot stream read from `reader`,
+    /// returning the last applied index it restored
+    ///
+    /// Everything in the database is deleted first, including its
+    /// metadata. A damaged stream fails with `Corruption` and leaves the
+    /// database empty, to be installed again.
+    pub fn install_snapshot<R: Read>(&self, reader: R) -> Result<u64, Error> {
+        let _guard = self.database.database.locks.lock(&meta_key(LAST_APPLIED_INDEX))?;
+        self.database.clear_all()?;
+        self.database.import_snapshot(reader)?;
+        self.last_applied_index()
+    }
+}
@@ -0,0 +1,245 @@
+//! Resumable iteration
+//!
+//! A `Cursor` reads a range of keys page by page, remembering the last key
+//! it returned. Its position and bounds can be saved as an opaque token,
+//! and a cursor restored from the token continues after that key, in a
+//! later process or on a newer snapshot. Keys written or deleted in between
+//! are seen if they lie ahead of the cursor.
+use std::marker::PhantomData;
+
+use database::key::{Key, from_u8};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::iterator::{Iterable, LevelDBIterator};
+use super::options::ReadOptions;
+
+const TOKEN_VERSION: u8 = 1;
+
+const REVERSE: u8 = 1;
+const TO_EXCLUSIVE: u8 = 2;
+const DONE: u8 = 4;
+
+/// A position in a range of keys, that can be saved and resumed.
+pub struct Cursor<K: Key> {
+    from: Option<Vec<u8>>,
+    to: Option<Vec<u8>>,
+    to_exclusive: bool,
+    prefix: Option<Vec<u8>>,
+    reverse: bool,
+    // the encoding of the last key returned
+    last: Option<Vec<u8>>,
+    done: bool,
+    marker: PhantomData<K>,
+}
+
+impl<K: Key> Clone for Cursor<K> {
+    fn clone(&self) -> Cursor<K> {
+        Cursor {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            to_exclusive: self.to_exclusive,
+            prefix: self.prefix.clone(),
+            reverse: self.reverse,
+            last: self.last.clone(),
+            done: self.done,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K: Key> Default for Cursor<K> {
+    fn default() -> Cursor<K> {
+        Cursor::new()
+    }
+}
+
+fn encode<K: Key>(key: &K) -> Vec<u8> {
+    key.as_slice(|k| k.to_vec())
+}
+
+fn malformed() -> Error {
+    Error::with_kind(ErrorKind::InvalidArgument, "malformed cursor token".to_string())
+}
+
+#[allow(clippy::wrong_self_convention)]
+impl<K: Key> Cursor<K> {
+    /// Create a cursor over all keys, in ascending order
+    pub fn new() -> Cursor<K> {
+        Cursor {
+            from: None,
+            to: None,
+            to_exclusive: false,
+            prefix: None,
+            reverse: false,
+            last: None,
+            done: false,
+            marker: PhantomData,
+        }
+    }
+
+    /// Only return keys at or after `key`
+    pub fn from(mut self, key: &K) -> Cursor<K> {
+        self.from = Some(encode(key));
+        self
+    }
+
+    /// Only return keys at or before `key`
+    pub fn to(mut self, key: &K) -> Cursor<K> {
+        self.to = Some(encode(key));
+        self.to_exclusive = false;
+        self
+    }
+
+    /// Only return keys before `key`
+    pub fn until(mut self, key: &K) -> Cursor<K> {
+        self.to = Some(encode(key));
+        self.to_exclusive = true;
+        self
+    }
+
+    /// Only return keys whose encoding starts with `prefix`
+    pub fn prefix(mut self, prefix: &[u8]) -> Cursor<K> {
+        self.prefix = Some(prefix.to_vec());
+        self
+    }
+
+    /// Return the keys in descending order
+    pub fn reverse(mut self) -> Cursor<K> {
+        self.reverse = true;
+        self
+    }
+
+    /// The last key returned, `None` before the first page
+    pub fn last_key(&self) -> Option<K> {
+        self.last.as_ref().map(|key| from_u8(key))
+    }
+
+    /// Whether the end of the range was reached
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Read up to `limit` entries following the position of the cursor
+    ///
+    /// Reads from the snapshot in `options`, if there is one. Returns
+    /// fewer than `limit` entries only at the end of the range, after which
+    /// the cursor is done and returns no more entries.
+    pub fn next_page(&mut self, database: &Database<K>, options: ReadOptions<K>, limit: usize) -> Vec<(K, Vec<u8>)> {
+        if self.done || limit == 0 {
+            return vec![];
+        }
+        // resuming narrows the bound in the direction of travel to the last key
+        let (lower, upper, upper_exclusive) = match self.last {
+            Some(ref last) if self.reverse => (self.from.clone(), Some(last.clone()), true),
+            Some(ref last) => (Some(last.clone()), self.to.clone(), self.to_exclusive),
+            None => (self.from.clone(), self.to.clone(), self.to_exclusive),
+        };
+        let lower: Option<K> = lower.map(|key| from_u8(&key));
+        let upper: Option<K> = upper.map(|key| from_u8(&key));
+
+        let mut iter = database.iter(options);
+        if let Some(ref prefix) = self.prefix {
+            iter = iter.prefix(prefix);
+        }
+        if let Some(ref lower) = lower {
+            iter = iter.from(lower);
+        }
+        if let Some(ref upper) = upper {
+            iter = if upper_exclusive { iter.until(upper) } else { iter.to(upper) };
+        }
+        if self.reverse {
+            iter = iter.reverse();
+        }
+
+        let mut page = Vec::with_capacity(limit);
+        for (key, value) in iter {
+            let encoded = encode(&key);
+            if !self.reverse && self.last.as_ref() == Some(&encoded) {
+                continue;
+            }
+            self.last = Some(encoded);
+            page.push((key, value));
+            if page.len() == limit {
+                return page;
+            }
+        }
+        self.done = true;
+        page
+    }
+
+    /// Save the bounds and position of the cursor as a token
+    pub fn token(&self) -> String {
+        let mut flags = 0;
+        if self.reverse {
+            flags |= REVERSE;
+        }
+        if self.to_exclusive {
+            flags |= TO_EXCLUSIVE;
+        }
+        if self.done {
+            flags |= DONE;
+        }
+        let mut bytes = vec![TOKEN_VERSION, flags];
+        for field in [&self.from, &self.to, &self.prefix, &self.last].iter() {
+            match **field {
+                Some(ref value) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                    bytes.extend_from_slice(value);
+                }
+                None => bytes.push(0),
+            }
+        }
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Restore a cursor saved with `token`
+    ///
+    /// Fails with `InvalidArgument` if the token is malformed.
+    pub fn from_token(token: &str) -> Result<Cursor<K>, Error> {
+        if token.len() % 2 != 0 || !token.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(malformed());
+        }
+        let bytes: Vec<u8> = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&token[i..i + 2], 16).unwrap())
+            .collect();
+        if bytes.len() < 2 || bytes[0] != TOKEN_VERSION {
+            return Err(malformed());
+        }
+        let flags = bytes[1];
+        let mut rest = &bytes[2..];
+        let mut fields = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let (&present, tail) = rest.split_first().ok_or_else(malformed)?;
+            rest = tail;
+            if present == 0 {
+                fields.push(None);
+                continue;
+            }
+            if rest.len() < 4 {
+                return Err(malformed());
+            }
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            if rest.len() - 4 < length {
+                return Err(malformed());
+            }
+            fields.push(Some(rest[4..4 + length].to_vec()));
+            rest = &rest[4 + length..];
+        }
+        if !rest.is_empty() {
+            return Err(malformed());
+        }
+        let mut fields = fields.into_iter();
+        Ok(Cursor {
+            from: fields.next().unwrap(),
+            to: fields.next().unwrap(),
+            prefix: fields.next().unwrap(),
+            last: fields.next().unwrap(),
+            to_exclusive: flags & TO_EXCLUSIVE != 0,
+            reverse: flags & REVERSE != 0,
+            done: flags & DONE != 0,
+            marker: PhantomData,
+        })
+    }
+}
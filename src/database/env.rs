@@ -0,0 +1,88 @@
+//! Structs to work with leveldb environments.
+//!
+//! An environment is used by leveldb to access the file system,
+//! schedule background work and similar.
+//!
+//! `Env::memory` needs the `memory-env` feature. leveldb's C API has no
+//! in-memory environment, so it reaches into the bundled leveldb: it relies
+//! on the private layout of `leveldb_env_t` and on the C++ symbol of
+//! `leveldb::NewMemEnv`, as mangled by the Itanium ABI. It only builds for
+//! linux-gnu targets, and must be checked again when leveldb-sys updates the
+//! bundled leveldb.
+use leveldb_sys::{leveldb_env_t, leveldb_create_default_env, leveldb_env_destroy};
+#[cfg(feature = "memory-env")]
+use libc::c_void;
+use std::sync::Arc;
+
+#[cfg(all(feature = "memory-env", not(all(target_os = "linux", target_env = "gnu"))))]
+compile_error!("the `memory-env` feature is only supported on linux-gnu targets");
+
+// mirrors `struct leveldb_env_t` in leveldb's `db/c.cc`
+#[cfg(feature = "memory-env")]
+#[repr(C)]
+struct EnvRep {
+    rep: *mut c_void,
+    is_default: bool,
+}
+
+#[cfg(feature = "memory-env")]
+extern "C" {
+    // `leveldb::NewMemEnv(leveldb::Env*)`, compiled into the bundled
+    // leveldb but not part of its C API.
+    #[link_name = "_ZN7leveldb9NewMemEnvEPNS_3EnvE"]
+    fn leveldb_new_mem_env(base: *mut c_void) -> *mut c_void;
+}
+
+#[allow(missing_docs)]
+struct RawEnv {
+    ptr: *mut leveldb_env_t,
+}
+
+impl Drop for RawEnv {
+    fn drop(&mut self) {
+        unsafe {
+            leveldb_env_destroy(self.ptr);
+        }
+    }
+}
+
+// leveldb environments synchronise internally
+unsafe impl Send for RawEnv {}
+unsafe impl Sync for RawEnv {}
+
+/// Represents a leveldb environment
+///
+/// Cloning an `Env` returns a handle to the same environment.
+#[derive(Clone)]
+pub struct Env {
+    raw: Arc<RawEnv>,
+}
+
+impl Env {
+    /// The default environment, backed by the file system.
+    pub fn default_env() -> Env {
+        let env = unsafe { leveldb_create_default_env() };
+        Env { raw: Arc::new(RawEnv { ptr: env }) }
+    }
+
+    /// An environment keeping all files in memory.
+    ///
+    /// Databases opened in this environment are lost once the
+    /// environment is dropped. Useful for tests.
+    #[cfg(feature = "memory-env")]
+    pub fn memory() -> Env {
+        unsafe {
+            let env = leveldb_create_default_env();
+            let rep = env as *mut EnvRep;
+            // leveldb_env_destroy deletes `rep` for non-default environments
+            (*rep).rep = leveldb_new_mem_env((*rep).rep);
+            (*rep).is_default = false;
+            Env { raw: Arc::new(RawEnv { ptr: env }) }
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn raw_ptr(&self) -> *mut leveldb_env_t {
+        self.raw.ptr
+    }
+}
@@ -3,23 +3,194 @@
 use libc::c_void;
 use leveldb_sys::leveldb_free;
 use std;
+use std::path::{Path, PathBuf};
 
-/// A leveldb error, just containing the error string
-/// provided by leveldb.
+/// The category of a leveldb error, as reported by leveldb's status.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum ErrorKind {
+    /// The requested entity was not found
+    NotFound,
+    /// The database files are damaged or malformed
+    Corruption,
+    /// The operation is not supported
+    NotSupported,
+    /// An argument passed to leveldb was rejected
+    InvalidArgument,
+    /// An error reading or writing the underlying files
+    IOError,
+    /// A transaction conflicted with a concurrent commit and can be retried
+    Conflict,
+    /// The guard of a conditional write didn't hold
+    ConditionFailed,
+    /// Any message without a known status prefix
+    Other(String),
+}
+
+// status prefixes as written by `leveldb::Status::ToString`
+// and the prefixes used for conflicts and failed conditions detected by
+// this crate
+const PREFIXES: [(&str, ErrorKind); 7] = [("NotFound: ", ErrorKind::NotFound),
+                                          ("Corruption: ", ErrorKind::Corruption),
+                                          ("Not implemented: ", ErrorKind::NotSupported),
+                                          ("Invalid argument: ", ErrorKind::InvalidArgument),
+                                          ("IO error: ", ErrorKind::IOError),
+                                          ("Conflict: ", ErrorKind::Conflict),
+                                          ("Condition failed: ", ErrorKind::ConditionFailed)];
+
+// the number of key bytes kept in an error
+const MAX_KEY_BYTES: usize = 32;
+
+/// The operation an error occurred in.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Operation {
+    /// Reading a key
+    Get,
+    /// Putting a key
+    Put,
+    /// Deleting a key
+    Delete,
+    /// Writing a batch
+    Write,
+    /// Opening a database
+    Open,
+    /// Compacting a range
+    Compact,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Get => "get",
+            Operation::Put => "put",
+            Operation::Delete => "delete",
+            Operation::Write => "write",
+            Operation::Open => "open",
+            Operation::Compact => "compact",
+        }
+    }
+}
+
+/// A leveldb error, containing the error string provided by leveldb
+/// and the kind parsed from it.
+///
+/// Errors returned by the database also record the operation that failed,
+/// the path of the database and, for single keys, the key, which appear in
+/// the `Display` output.
 #[derive(Debug)]
 pub struct Error {
+    kind: ErrorKind,
     message: String,
+    operation: Option<Operation>,
+    path: Option<PathBuf>,
+    // hex, truncated to `MAX_KEY_BYTES`
+    key: Option<String>,
 }
 
 impl Error {
     /// create a new Error, using the String provided
+    ///
+    /// The kind is derived from a leveldb status prefix, like `"Corruption: "`.
     pub fn new(message: String) -> Error {
-        Error { message: message }
+        let kind = PREFIXES.iter()
+            .find(|&&(prefix, _)| message.starts_with(prefix))
+            .map(|(_, kind)| kind.clone())
+            .unwrap_or_else(|| ErrorKind::Other(message.clone()));
+        Error {
+            kind,
+            message,
+            operation: None,
+            path: None,
+            key: None,
+        }
+    }
+
+    /// create a new Error of `kind`
+    ///
+    /// The message is prefixed like a leveldb status of that kind, so it
+    /// reads the same as errors reported by leveldb. An `Other` kind takes
+    /// the message as is.
+    pub fn with_kind(kind: ErrorKind, message: String) -> Error {
+        let (kind, message) = match PREFIXES.iter().find(|(_, prefixed)| *prefixed == kind) {
+            Some(&(prefix, _)) => (kind, format!("{}{}", prefix, message)),
+            None => (ErrorKind::Other(message.clone()), message),
+        };
+        Error {
+            kind,
+            message,
+            operation: None,
+            path: None,
+            key: None,
+        }
+    }
+
+    /// Add `context` to the message, keeping the kind, the operation, the
+    /// path and the key
+    pub fn context<C: std::fmt::Display>(mut self, context: C) -> Error {
+        self.message = format!("{} ({})", self.message, context);
+        if let ErrorKind::Other(ref mut message) = self.kind {
+            *message = self.message.clone();
+        }
+        self
+    }
+
+    /// Record the operation the error occurred in
+    pub fn with_operation(mut self, operation: Operation) -> Error {
+        self.operation = Some(operation);
+        self
+    }
+
+    /// Record the path of the database the error occurred in
+    pub fn with_path(mut self, path: &Path) -> Error {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Record the encoded key the error occurred for
+    ///
+    /// Only the first 32 bytes are kept.
+    pub fn with_key(mut self, key: &[u8]) -> Error {
+        let mut hex: String = key.iter()
+            .take(MAX_KEY_BYTES)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        if key.len() > MAX_KEY_BYTES {
+            hex.push_str("...");
+        }
+        self.key = Some(hex);
+        self
+    }
+
+    /// The operation the error occurred in, if known
+    pub fn operation(&self) -> Option<Operation> {
+        self.operation
+    }
+
+    /// The path of the database the error occurred in, if known
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The key the error occurred for as hex, `...` marking a truncated
+    /// key, if known
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// The kind of this error
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The full error message
+    pub fn message(&self) -> &str {
+        &self.message
     }
 
     /// create an error from a c-string buffer.
     ///
-    /// This method is `unsafe` because the pointer must be valid and point to heap.
+    /// # Safety
+    ///
+    /// The pointer must be valid and point to heap.
     /// The pointer will be passed to `free`!
     pub unsafe fn new_from_i8(message: *const i8) -> Error {
         use std::str::from_utf8;
@@ -33,7 +204,27 @@ impl Error {
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "LevelDB error: {}", self.message)
+        write!(f, "LevelDB error: {}", self.message)?;
+        let mut context = String::new();
+        if let Some(operation) = self.operation {
+            context.push_str(operation.name());
+        }
+        if let Some(ref path) = self.path {
+            if !context.is_empty() {
+                context.push(' ');
+            }
+            context.push_str(&format!("on {}", path.display()));
+        }
+        if let Some(ref key) = self.key {
+            if !context.is_empty() {
+                context.push_str(", ");
+            }
+            context.push_str(&format!("key {}", key));
+        }
+        if !context.is_empty() {
+            write!(f, " ({})", context)?;
+        }
+        Ok(())
     }
 }
 
@@ -41,7 +232,24 @@ impl std::error::Error for Error {
     fn description(&self) -> &str {
         &self.message
     }
-    fn cause(&self) -> Option<&std::error::Error> {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
         None
     }
 }
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> std::io::Error {
+        use std::io::ErrorKind as IoKind;
+
+        let kind = match error.kind {
+            ErrorKind::NotFound => IoKind::NotFound,
+            ErrorKind::Corruption => IoKind::InvalidData,
+            ErrorKind::NotSupported => IoKind::Unsupported,
+            ErrorKind::InvalidArgument => IoKind::InvalidInput,
+            ErrorKind::Conflict => IoKind::Interrupted,
+            ErrorKind::ConditionFailed => IoKind::Other,
+            ErrorKind::IOError | ErrorKind::Other(_) => IoKind::Other,
+        };
+        std::io::Error::new(kind, error)
+    }
+}
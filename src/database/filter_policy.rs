@@ -0,0 +1,39 @@
+//! Structs and traits to work with leveldb filter policies.
+use leveldb_sys::{leveldb_filterpolicy_t, leveldb_filterpolicy_create_bloom,
+                  leveldb_filterpolicy_destroy};
+use libc::c_int;
+
+#[allow(missing_docs)]
+struct RawFilterPolicy {
+    ptr: *mut leveldb_filterpolicy_t,
+}
+
+impl Drop for RawFilterPolicy {
+    fn drop(&mut self) {
+        unsafe {
+            leveldb_filterpolicy_destroy(self.ptr);
+        }
+    }
+}
+
+/// Represents a leveldb filter policy
+///
+/// A filter policy reduces the number of disk reads for lookups of
+/// keys that are not present.
+pub struct FilterPolicy {
+    raw: RawFilterPolicy,
+}
+
+impl FilterPolicy {
+    /// Create a bloom filter policy using roughly `bits_per_key` bits
+    /// per key. 10 is a good value, yielding ~1% false positives.
+    pub fn bloom(bits_per_key: c_int) -> FilterPolicy {
+        let policy = unsafe { leveldb_filterpolicy_create_bloom(bits_per_key) };
+        FilterPolicy { raw: RawFilterPolicy { ptr: policy } }
+    }
+
+    #[allow(missing_docs)]
+    pub fn raw_ptr(&self) -> *mut leveldb_filterpolicy_t {
+        self.raw.ptr
+    }
+}
@@ -0,0 +1,156 @@
+//! Health checks
+//!
+//! `Database::health_check` is a cheap probe for readiness endpoints. It
+//! writes a canary entry under the reserved prefix, reads it back and
+//! deletes it again, and compares the file counts leveldb reports against
+//! `HealthThresholds`. The result says whether the database is healthy,
+//! degraded, falling behind on compactions for instance, or unhealthy,
+//! and lists what it found in human readable details.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::batch::{Batch, Writebatch};
+use super::kv::get_raw_bytes;
+use super::properties::{NUM_LEVELS, Properties};
+use super::rate_limit::SLOWDOWN_LEVEL0_FILES;
+
+// the entry written and read back by the probe
+const CANARY: &[u8] = b"\xff\xffleveldb.health/canary";
+// the level-0 file count at which leveldb stops writes until compactions
+// caught up
+const STOP_WRITES_LEVEL0_FILES: usize = 12;
+// leveldb's default for `max_open_files`
+const DEFAULT_MAX_OPEN_FILES: usize = 1000;
+
+/// How well a database is doing, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthStatus {
+    /// Everything checked is fine
+    Healthy,
+    /// The database works, but slower than it should
+    Degraded,
+    /// The database can't serve reads or writes
+    Unhealthy,
+}
+
+/// The result of `Database::health_check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    /// The worst status found by any check
+    pub status: HealthStatus,
+    /// A description of every problem found, empty if healthy
+    pub details: Vec<String>,
+}
+
+impl Health {
+    fn report(&mut self, status: HealthStatus, detail: String) {
+        self.status = self.status.max(status);
+        self.details.push(detail);
+    }
+}
+
+/// The limits `Database::health_check_with` compares properties against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthThresholds {
+    /// The level-0 file count from which the database is degraded
+    ///
+    /// default: `SLOWDOWN_LEVEL0_FILES`
+    pub max_level0_files: usize,
+    /// The table file count above which the database is degraded, as the
+    /// files no longer fit into the table cache
+    ///
+    /// default: None, for the `max_open_files` the database was opened
+    /// with
+    pub max_files: Option<usize>,
+}
+
+impl HealthThresholds {
+    /// Create thresholds with the default limits
+    pub fn new() -> HealthThresholds {
+        HealthThresholds {
+            max_level0_files: SLOWDOWN_LEVEL0_FILES,
+            max_files: None,
+        }
+    }
+}
+
+impl Default for HealthThresholds {
+    fn default() -> HealthThresholds {
+        HealthThresholds::new()
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Probe the database, with the default thresholds
+    pub fn health_check(&self) -> Health {
+        self.health_check_with(&HealthThresholds::new())
+    }
+
+    /// Probe the database, comparing its file counts against `thresholds`
+    pub fn health_check_with(&self, thresholds: &HealthThresholds) -> Health {
+        let mut health = Health {
+            status: HealthStatus::Healthy,
+            details: vec![],
+        };
+        if let Err(detail) = self.probe_canary() {
+            health.report(HealthStatus::Unhealthy, detail);
+        }
+
+        let mut files = 0;
+        for level in 0..NUM_LEVELS {
+            match self.num_files_at_level(level) {
+                Some(count) => files += count,
+                None => {
+                    health.report(HealthStatus::Degraded,
+                                  format!("the file count of level {} is unavailable", level));
+                }
+            }
+        }
+        let level0 = self.num_files_at_level(0).unwrap_or(0);
+        if level0 >= STOP_WRITES_LEVEL0_FILES {
+            health.report(HealthStatus::Unhealthy,
+                          format!("{} files at level 0, leveldb stopped writes", level0));
+        } else if level0 >= thresholds.max_level0_files {
+            health.report(HealthStatus::Degraded,
+                          format!("{} files at level 0, compactions are falling behind", level0));
+        }
+        let max_files = thresholds.max_files.unwrap_or_else(|| {
+            self.database.options.max_open_files.map_or(DEFAULT_MAX_OPEN_FILES, |n| n as usize)
+        });
+        if files > max_files {
+            health.report(HealthStatus::Degraded,
+                          format!("{} table files, more than the {} kept open", files, max_files));
+        }
+        health
+    }
+
+    // write the canary, read it back and delete it
+    fn probe_canary(&self) -> Result<(), String> {
+        // concurrent probes would overwrite each other's canary
+        let _guard = self.database.locks.lock(CANARY)
+            .map_err(|e| format!("locking the canary failed: {}", e.message()))?;
+        let value = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+            .to_be_bytes();
+        let mut batch: Writebatch<K> = Writebatch::new();
+        batch.put_raw(CANARY, &value);
+        self.write(WriteOptions::new(), &batch)
+            .map_err(|e| format!("writing the canary failed: {}", e.message()))?;
+
+        let c_readoptions = self.database.c_options.read(&ReadOptions::<K>::new());
+        let read = unsafe { get_raw_bytes(self.database.ptr, c_readoptions.ptr, CANARY) }
+            .map_err(|e| format!("reading the canary failed: {}", e.message()))?;
+        if read.as_deref() != Some(&value[..]) {
+            return Err("the canary read back differs from the one written".to_string());
+        }
+
+        batch.clear();
+        batch.delete_raw(CANARY);
+        self.write(WriteOptions::new(), &batch)
+            .map_err(|e| format!("deleting the canary failed: {}", e.message()))
+    }
+}
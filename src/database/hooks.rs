@@ -0,0 +1,93 @@
+//! Write hooks
+//!
+//! A `WriteHook` registered with `Database::add_write_hook` sees every
+//! put, delete and batch written through the database or any of its
+//! clones, as the list of operations on encoded keys. It runs before the
+//! write, where it can veto it by returning an error, and after it
+//! succeeded, to record it: for audit logs, invariant checks or capturing
+//! changes for replication.
+//!
+//! While hooks are registered, writes are serialised, so hooks see them in
+//! the order they were applied. Hooks must not write to the database
+//! themselves, that would wait for the write running them. Writes to the
+//! metadata of `Database::meta` are not passed to hooks.
+use std::sync::{Mutex, MutexGuard};
+
+use database::key::Key;
+use super::Database;
+use super::error::Error;
+use super::backend::Operation;
+use super::batch;
+use super::meta::is_reserved;
+
+/// Called around the writes to a database.
+pub trait WriteHook: Send + Sync {
+    /// Called before `operations` are written, returning an error vetoes
+    /// the write, which then fails with that error
+    fn before_write(&self, operations: &[Operation]) -> Result<(), Error> {
+        let _ = operations;
+        Ok(())
+    }
+
+    /// Called after `operations` were written
+    fn after_write(&self, operations: &[Operation]) {
+        let _ = operations;
+    }
+}
+
+pub(crate) struct Hooks {
+    hooks: Mutex<Vec<Box<dyn WriteHook>>>,
+}
+
+impl Hooks {
+    pub(crate) fn new() -> Hooks {
+        Hooks { hooks: Mutex::new(vec![]) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Box<dyn WriteHook>>> {
+        self.hooks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run `write`, passing the `operations` it applies to the hooks
+    /// before and after
+    pub(crate) fn run<W, O>(&self, write: W, operations: O) -> Result<(), Error>
+        where W: FnOnce() -> Result<(), Error>,
+              O: FnOnce() -> Vec<batch::Operation>
+    {
+        let hooks = self.lock();
+        if hooks.is_empty() {
+            drop(hooks);
+            return write();
+        }
+        let operations: Vec<Operation> = operations()
+            .into_iter()
+            .filter(|(key, _)| !is_reserved(key))
+            .map(|(key, value)| match value {
+                Some(value) => Operation::Put(key, value),
+                None => Operation::Delete(key),
+            })
+            .collect();
+        if operations.is_empty() {
+            drop(hooks);
+            return write();
+        }
+        for hook in hooks.iter() {
+            hook.before_write(&operations)?;
+        }
+        write()?;
+        for hook in hooks.iter() {
+            hook.after_write(&operations);
+        }
+        Ok(())
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Register `hook`, to run around all writes from now on
+    ///
+    /// Hooks run in the order they were added, a veto skips the hooks
+    /// after it.
+    pub fn add_write_hook(&self, hook: Box<dyn WriteHook>) {
+        self.database.hooks.lock().push(hook);
+    }
+}
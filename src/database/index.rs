@@ -0,0 +1,209 @@
+//! Secondary indexes
+//!
+//! `IndexedDatabase` keeps primary entries and the entries of any number of
+//! secondary indexes in the same database. Every write updates the primary
+//! entry and all affected index entries in a single batch, so the indexes
+//! can't get out of sync with the data, even if the process dies mid-write.
+//!
+//! Entries are namespaced using `CompositeKey`s, so the database should be
+//! used exclusively through `IndexedDatabase`.
+use std::sync::Mutex;
+
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::iterator::{self, Iterable, LevelDBIterator};
+use super::keys::CompositeKey;
+use super::snapshots::Snapshots;
+
+// namespaces of the two kinds of entries
+const PRIMARY: u8 = 0;
+const INDEX: u8 = 1;
+
+fn primary_key(key: &[u8]) -> CompositeKey {
+    CompositeKey::new().push_u8(PRIMARY).push_bytes(key)
+}
+
+fn index_prefix(name: &str) -> CompositeKey {
+    CompositeKey::new().push_u8(INDEX).push_str(name)
+}
+
+type Extractor = Box<dyn Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync>;
+
+/// A primary key along with its value.
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// A secondary index, deriving index keys from the stored values.
+pub struct SecondaryIndex {
+    name: String,
+    extract: Extractor,
+}
+
+impl SecondaryIndex {
+    /// Create an index called `name`
+    ///
+    /// `extract` returns the index keys of a value, there may be any number
+    /// of them. It must be deterministic, as it is used again to find the
+    /// entries to remove once the value is overwritten or deleted.
+    pub fn new<F>(name: &str, extract: F) -> SecondaryIndex
+        where F: Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static
+    {
+        SecondaryIndex {
+            name: name.to_string(),
+            extract: Box::new(extract),
+        }
+    }
+
+    /// The name of the index
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entries(&self, primary: &[u8], value: &[u8]) -> Vec<CompositeKey> {
+        (self.extract)(value)
+            .iter()
+            .map(|key| index_prefix(&self.name).push_bytes(key).push_bytes(primary))
+            .collect()
+    }
+}
+
+/// A database maintaining secondary indexes along with its entries.
+pub struct IndexedDatabase {
+    database: Database<CompositeKey>,
+    indexes: Vec<SecondaryIndex>,
+    // serialises the read-modify-write cycle of puts and deletes
+    write_lock: Mutex<()>,
+}
+
+impl IndexedDatabase {
+    /// Wrap a database, maintaining the given indexes
+    ///
+    /// Indexes are only maintained for writes done through this wrapper,
+    /// an index added later does not cover existing entries.
+    pub fn new(database: Database<CompositeKey>, indexes: Vec<SecondaryIndex>) -> IndexedDatabase {
+        IndexedDatabase {
+            database,
+            indexes,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<CompositeKey> {
+        &self.database
+    }
+
+    /// get a value by its primary key.
+    pub fn get(&self, options: ReadOptions<CompositeKey>, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.database.get(options, primary_key(key))
+    }
+
+    /// put a value into the database, updating all indexes.
+    pub fn put(&self, options: WriteOptions, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut batch = Writebatch::new();
+        self.remove_index_entries(&mut batch, key)?;
+        batch.put(primary_key(key), value);
+        for index in self.indexes.iter() {
+            for entry in index.entries(key, value) {
+                batch.put(entry, &[]);
+            }
+        }
+        self.database.write(options, &batch)
+    }
+
+    /// delete a value from the database, updating all indexes.
+    pub fn delete(&self, options: WriteOptions, key: &[u8]) -> Result<(), Error> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut batch = Writebatch::new();
+        self.remove_index_entries(&mut batch, key)?;
+        batch.delete(primary_key(key));
+        self.database.write(options, &batch)
+    }
+
+    fn remove_index_entries(&self, batch: &mut Writebatch<CompositeKey>, key: &[u8]) -> Result<(), Error> {
+        if let Some(old) = self.database.get_bytes(ReadOptions::new(), primary_key(key))? {
+            for index in self.indexes.iter() {
+                for entry in index.entries(key, &old) {
+                    batch.delete(entry);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn index(&self, name: &str) -> Result<&SecondaryIndex, Error> {
+        self.indexes
+            .iter()
+            .find(|index| index.name == name)
+            .ok_or_else(|| Error::with_kind(ErrorKind::InvalidArgument, format!("unknown index {}", name)))
+    }
+
+    /// Return the (primary key, value) pairs whose value has `index_key`
+    /// in the index `name`, ordered by primary key.
+    ///
+    /// The index and the values are read from the same snapshot.
+    pub fn lookup_by_index(&self, name: &str, index_key: &[u8]) -> Result<Vec<Entry>, Error> {
+        self.index(name)?;
+        let snapshot = self.database.snapshot();
+        let prefix = index_prefix(name).push_bytes(index_key);
+        let entries = IndexIterator::new(snapshot.iter(ReadOptions::new()), prefix);
+        let mut result = vec![];
+        for (_, primary) in entries {
+            if let Some(value) = snapshot.get(ReadOptions::new(), primary_key(&primary))? {
+                result.push((primary, value));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Return an iterator over the (index key, primary key) pairs of the
+    /// index `name`, ordered by index key.
+    pub fn iter_index<'a>(&'a self,
+                          options: ReadOptions<'a, CompositeKey>,
+                          name: &str)
+                          -> Result<IndexIterator<'a>, Error> {
+        self.index(name)?;
+        Ok(IndexIterator::new(self.database.iter(options), index_prefix(name)))
+    }
+}
+
+/// An iterator over the entries of a secondary index.
+///
+/// Returns index key and primary key as a tuple.
+pub struct IndexIterator<'a> {
+    inner: iterator::Iterator<'a, CompositeKey>,
+    prefix: Vec<u8>,
+}
+
+impl<'a> IndexIterator<'a> {
+    fn new(mut inner: iterator::Iterator<'a, CompositeKey>, prefix: CompositeKey) -> IndexIterator<'a> {
+        inner.seek(&prefix);
+        IndexIterator {
+            inner,
+            prefix: prefix.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl<'a> Iterator for IndexIterator<'a> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        if !self.inner.advance() {
+            return None;
+        }
+        let key = self.inner.key();
+        if !key.as_bytes().starts_with(&self.prefix) {
+            return None;
+        }
+        let mut reader = key.reader();
+        reader.read_u8()?;
+        reader.read_str()?;
+        let index_key = reader.read_bytes()?;
+        let primary = reader.read_bytes()?;
+        Some((index_key, primary))
+    }
+}
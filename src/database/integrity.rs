@@ -0,0 +1,108 @@
+//! Checking a live database for corruption
+//!
+//! `Database::verify_integrity` reads every entry with checksum
+//! verification turned on, like `Backup::verify` does for a closed
+//! database, and reports what it found instead of failing: how many
+//! entries are still readable and where the first damage lies, to decide
+//! between `management::repair` and restoring a backup.
+//!
+//! leveldb skips a block that fails its checksum and goes on with the next
+//! one, keeping the error. The scan notices the error as soon as it
+//! appears, so the damaged block lies between the last entry read before it
+//! and the next one read. Later damage isn't located, but the entries
+//! behind it are still counted.
+use leveldb_sys::{leveldb_create_iterator, leveldb_iter_next, leveldb_iter_seek_to_first};
+use database::key::Key;
+use options::ReadOptions;
+use super::Database;
+use super::iterator::{RawIterator, raw_key, raw_status, raw_valid, raw_value};
+
+/// A range of keys that couldn't be read.
+///
+/// Both bounds are exclusive, they are the readable keys around the
+/// damage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptRange {
+    /// The last key read before the damage, `None` if it lies at the start
+    pub after: Option<Vec<u8>>,
+    /// The first key read after the damage, `None` if it lies at the end
+    pub before: Option<Vec<u8>>,
+    /// The error reported by leveldb
+    pub message: String,
+}
+
+/// The result of `Database::verify_integrity`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Number of entries read
+    pub readable_keys: u64,
+    /// Total size of the keys and values read, in bytes
+    pub readable_bytes: u64,
+    /// The damage found first, if any
+    pub first_corruption: Option<CorruptRange>,
+}
+
+impl IntegrityReport {
+    /// Whether the scan found no damage
+    pub fn is_ok(&self) -> bool {
+        self.first_corruption.is_none()
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Read every entry, verifying the checksums, and report the damage
+    ///
+    /// The snapshot of `options` is used, if any. Checksums are verified
+    /// and the blocks read are kept out of the cache, whatever `options`
+    /// say. The scan reads the whole database, including the reserved
+    /// entries.
+    pub fn verify_integrity<'a>(&self, mut options: ReadOptions<'a, K>) -> IntegrityReport {
+        options.verify_checksums = true;
+        options.fill_cache = false;
+        let iter = unsafe {
+            let c_readoptions = self.database.c_options.read(&options);
+            let ptr = leveldb_create_iterator(self.database.ptr, c_readoptions.ptr);
+            RawIterator { ptr }
+        };
+        let mut report = IntegrityReport {
+            readable_keys: 0,
+            readable_bytes: 0,
+            first_corruption: None,
+        };
+        // the last key read, reusing the buffer
+        let mut last = vec![];
+        unsafe { leveldb_iter_seek_to_first(iter.ptr) };
+        loop {
+            let valid = raw_valid(iter.ptr);
+            if report.first_corruption.is_none() {
+                if let Err(error) = raw_status(iter.ptr) {
+                    report.first_corruption = Some(CorruptRange {
+                        after: if report.readable_keys > 0 {
+                            Some(last.clone())
+                        } else {
+                            None
+                        },
+                        before: if valid {
+                            Some(unsafe { raw_key(iter.ptr) }.to_vec())
+                        } else {
+                            None
+                        },
+                        message: error.message().to_string(),
+                    });
+                }
+            }
+            if !valid {
+                break;
+            }
+            let (key, value) = unsafe { (raw_key(iter.ptr), raw_value(iter.ptr)) };
+            report.readable_keys += 1;
+            report.readable_bytes += (key.len() + value.len()) as u64;
+            if report.first_corruption.is_none() {
+                last.clear();
+                last.extend_from_slice(key);
+            }
+            unsafe { leveldb_iter_next(iter.ptr) };
+        }
+        report
+    }
+}
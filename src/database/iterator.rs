@@ -2,21 +2,33 @@
 //!
 //! Iteration is one of the most important parts of leveldb. This module provides
 //! Iterators to iterate over key, values and pairs of both.
+//!
+//! The iterators skip the reserved namespace of `meta`, so they only return
+//! keys written by the application.
 use leveldb_sys::{leveldb_iterator_t, leveldb_iter_seek_to_first, leveldb_iter_destroy,
                   leveldb_iter_seek_to_last, leveldb_create_iterator, leveldb_iter_valid,
                   leveldb_iter_next, leveldb_iter_key, leveldb_iter_value,
-                  leveldb_readoptions_destroy, leveldb_iter_seek};
+                  leveldb_iter_seek, leveldb_iter_prev,
+                  leveldb_iter_get_error};
 use libc::{size_t, c_char};
 use std::iter;
 use super::Database;
-use super::options::{ReadOptions, c_readoptions};
+use super::options::ReadOptions;
+use super::error::Error;
+use super::trace::Timer;
 use super::key::{Key, from_u8};
+use super::meta::{RESERVED_END, RESERVED_PREFIX, is_reserved};
 use std::slice::from_raw_parts;
-use std::marker::PhantomData;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::ptr;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
 
 #[allow(missing_docs)]
-struct RawIterator {
-    ptr: *mut leveldb_iterator_t,
+pub(crate) struct RawIterator {
+    pub(crate) ptr: *mut leveldb_iterator_t,
 }
 
 #[allow(missing_docs)]
@@ -31,13 +43,22 @@ impl Drop for RawIterator {
 /// Returns key and value as a tuple.
 pub struct Iterator<'a, K: Key + 'a> {
     start: bool,
-    // Iterator accesses the Database through a leveldb_iter_t pointer
-    // but needs to hold the reference for lifetime tracking
-    #[allow(dead_code)]
-    database: PhantomData<&'a Database<K>>,
+    reverse: bool,
+    // Iterator accesses the Database through a leveldb_iter_t pointer,
+    // the reference is used for lifetime tracking and comparing bounds
+    database: &'a Database<K>,
     iter: RawIterator,
     from: Option<&'a K>,
     to: Option<&'a K>,
+    to_exclusive: bool,
+    prefix: Option<&'a [u8]>,
+    // the bounds of the read options, the upper one excluded
+    lower: Option<&'a K>,
+    upper: Option<&'a K>,
+    // reports the lifetime of the iterator
+    timer: Timer,
+    // the estimate of all entries, computed on first use
+    estimate: Cell<Option<u64>>,
 }
 
 /// An iterator over the leveldb keyspace.
@@ -55,129 +76,478 @@ pub struct ValueIterator<'a, K: Key + 'a> {
 }
 
 
+/// An iterator over batches of (Key,Value) pairs.
+///
+/// Every batch but the last holds the chunk size of entries.
+pub struct Chunks<'a, K: Key + 'a> {
+    inner: Iterator<'a, K>,
+    chunk_size: usize,
+    // set once the entries ran out, leveldb mustn't step past the end
+    done: bool,
+}
+
 /// A trait to allow access to the three main iteration styles of leveldb.
 pub trait Iterable<'a, K: Key + 'a> {
     /// Return an Iterator iterating over (Key,Value) pairs
-    fn iter(&'a self, options: ReadOptions<'a, K>) -> Iterator<K>;
+    fn iter(&'a self, options: ReadOptions<'a, K>) -> Iterator<'a, K>;
     /// Returns an Iterator iterating over Keys only.
-    fn keys_iter(&'a self, options: ReadOptions<'a, K>) -> KeyIterator<K>;
+    fn keys_iter(&'a self, options: ReadOptions<'a, K>) -> KeyIterator<'a, K>;
     /// Returns an Iterator iterating over Values only.
-    fn value_iter(&'a self, options: ReadOptions<'a, K>) -> ValueIterator<K>;
+    fn value_iter(&'a self, options: ReadOptions<'a, K>) -> ValueIterator<'a, K>;
+
+    /// Return an Iterator iterating over (Key,Value) pairs whose encoded
+    /// key starts with `prefix`.
+    ///
+    /// Keys sharing a prefix are only adjacent under bytewise ordering, so
+    /// this is meant for databases using the default comparator.
+    fn scan_prefix(&'a self, options: ReadOptions<'a, K>, prefix: &'a [u8]) -> Iterator<'a, K> {
+        self.iter(options).prefix(prefix)
+    }
+
+    /// Return an Iterator over batches of up to `chunk_size` (Key,Value)
+    /// pairs.
+    ///
+    /// Panics if `chunk_size` is 0.
+    fn iter_chunks(&'a self, options: ReadOptions<'a, K>, chunk_size: usize) -> Chunks<'a, K> {
+        self.iter(options).chunks(chunk_size)
+    }
+
+    /// Return the first key, `None` if there are no keys.
+    fn first_key(&'a self, options: ReadOptions<'a, K>) -> Result<Option<K>, Error> {
+        first(self.keys_iter(options))
+    }
+
+    /// Return the last key, `None` if there are no keys.
+    fn last_key(&'a self, options: ReadOptions<'a, K>) -> Result<Option<K>, Error> {
+        last(self.keys_iter(options))
+    }
+
+    /// Return the first key from `from` to `to`, both included, `None` if
+    /// there are no keys in that range.
+    fn first_key_in(&'a self, options: ReadOptions<'a, K>, from: &'a K, to: &'a K) -> Result<Option<K>, Error> {
+        first(self.keys_iter(options).from(from).to(to))
+    }
+
+    /// Return the last key from `from` to `to`, both included, `None` if
+    /// there are no keys in that range.
+    fn last_key_in(&'a self, options: ReadOptions<'a, K>, from: &'a K, to: &'a K) -> Result<Option<K>, Error> {
+        last(self.keys_iter(options).from(from).to(to))
+    }
 }
 
 impl<'a, K: Key + 'a> Iterable<'a, K> for Database<K> {
-    fn iter(&'a self, options: ReadOptions<'a, K>) -> Iterator<K> {
+    fn iter(&'a self, options: ReadOptions<'a, K>) -> Iterator<'a, K> {
         Iterator::new(self, options)
     }
 
-    fn keys_iter(&'a self, options: ReadOptions<'a, K>) -> KeyIterator<K> {
+    fn keys_iter(&'a self, options: ReadOptions<'a, K>) -> KeyIterator<'a, K> {
         KeyIterator::new(self, options)
     }
 
-    fn value_iter(&'a self, options: ReadOptions<'a, K>) -> ValueIterator<K> {
+    fn value_iter(&'a self, options: ReadOptions<'a, K>) -> ValueIterator<'a, K> {
         ValueIterator::new(self, options)
     }
 }
 
 #[allow(missing_docs)]
+#[allow(clippy::wrong_self_convention)]
 pub trait LevelDBIterator<'a, K: Key> {
-    #[inline]
     fn raw_iterator(&self) -> *mut leveldb_iterator_t;
 
-    #[inline]
     fn start(&self) -> bool;
 
-    #[inline]
     fn started(&mut self);
 
+    /// Marks the current position as not yet returned, e.g. after seeking.
+    fn reset_start(&mut self);
+
+    /// Only returns keys at or after `key`.
     fn from(self, key: &'a K) -> Self;
+    /// Only returns keys at or before `key`.
     fn to(self, key: &'a K) -> Self;
+    /// Only returns keys before `key`.
+    fn until(self, key: &'a K) -> Self;
+    /// Only returns keys whose encoding starts with `prefix`.
+    fn prefix(self, prefix: &'a [u8]) -> Self;
+
+    /// Iterate backwards, starting at the last key (or the `to` key if set).
+    fn reverse(self) -> Self;
+    fn reversed(&self) -> bool;
 
     fn from_key(&self) -> Option<&K>;
     fn to_key(&self) -> Option<&K>;
+    fn prefix_key(&self) -> Option<&[u8]>;
+
+    /// Whether the current entry lies within the `from`, `to` and `prefix` bounds,
+    /// compared by the ordering of the database.
+    ///
+    /// Must only be called on a valid position.
+    fn in_bounds(&self) -> bool;
 
     fn valid(&self) -> bool {
-        unsafe { leveldb_iter_valid(self.raw_iterator()) != 0 }
+        raw_valid(self.raw_iterator()) && self.in_bounds()
     }
 
-    fn advance(&mut self) -> bool {
-        unsafe {
-            if !self.start() {
+    /// The error that ended the iteration early, if any.
+    ///
+    /// leveldb stops iterating on corruption or I/O errors as if the end
+    /// was reached, check this once the iterator returned `None`.
+    fn status(&self) -> Result<(), Error> {
+        raw_status(self.raw_iterator())
+    }
 
-                leveldb_iter_next(self.raw_iterator());
+    fn advance(&mut self) -> bool {
+        if !self.start() {
+            if self.reversed() {
+                unsafe { leveldb_iter_prev(self.raw_iterator()) };
             } else {
-                if let Some(k) = self.from_key() {
-                    self.seek(k)
-                }
-                self.started();
+                unsafe { leveldb_iter_next(self.raw_iterator()) };
             }
+        } else {
+            self.started();
         }
+        skip_reserved(self.raw_iterator(), self.reversed());
         self.valid()
     }
 
     fn key(&self) -> K {
-        unsafe {
-            let length: size_t = 0;
-            let value = leveldb_iter_key(self.raw_iterator(), &length) as *const u8;
-            from_u8(from_raw_parts(value, length as usize))
-        }
+        unsafe { from_u8(raw_key(self.raw_iterator())) }
+    }
+
+    /// The encoded key at the current position, borrowed from leveldb.
+    ///
+    /// Must only be called on a valid position. Unlike `key`, nothing is
+    /// decoded or copied, and the borrow ends before the iterator moves.
+    fn key_bytes(&self) -> &[u8] {
+        unsafe { raw_key(self.raw_iterator()) }
+    }
+
+    /// The value at the current position, borrowed from leveldb.
+    ///
+    /// Must only be called on a valid position. Unlike `value`, nothing is
+    /// copied, and the borrow ends before the iterator moves.
+    fn value_bytes(&self) -> &[u8] {
+        unsafe { raw_value(self.raw_iterator()) }
     }
 
     fn value(&self) -> Vec<u8> {
-        unsafe {
-            let length: size_t = 0;
-            let value = leveldb_iter_value(self.raw_iterator(), &length) as *const u8;
-            from_raw_parts(value, length as usize).to_vec()
+        unsafe { raw_value(self.raw_iterator()).to_vec() }
+    }
+
+    /// Repositions the iterator at the first key within bounds.
+    ///
+    /// The next call to `next` returns the entry at that position.
+    fn seek_to_first(&mut self) {
+        let iter = self.raw_iterator();
+        match self.from_key() {
+            Some(k) => raw_seek(iter, k),
+            None => unsafe { leveldb_iter_seek_to_first(iter) },
         }
+        if let Some(prefix) = self.prefix_key() {
+            if !raw_valid(iter) || unsafe { raw_key(iter) } < prefix {
+                raw_seek_bytes(iter, prefix);
+            }
+        }
+        skip_reserved(iter, false);
+        self.reset_start();
     }
 
-    fn seek_to_first(&self) {
-        unsafe { leveldb_iter_seek_to_first(self.raw_iterator()) }
+    /// Repositions the iterator at the last key within bounds.
+    ///
+    /// The next call to `next` returns the entry at that position.
+    fn seek_to_last(&mut self) {
+        let iter = self.raw_iterator();
+        // the end of the bounds, the smaller of the `to` key and the first
+        // key past the prefix
+        let to = self.to_key().map(|to| to.as_slice(|t| t.to_vec()));
+        let end = match (to, self.prefix_key().and_then(prefix_successor)) {
+            (Some(to), Some(successor)) => Some(to.min(successor)),
+            (to, successor) => to.or(successor),
+        };
+        if let Some(ref end) = end {
+            raw_seek_bytes(iter, end);
+        }
+        if end.is_none() || !raw_valid(iter) {
+            unsafe { leveldb_iter_seek_to_last(iter) };
+        }
+        skip_reserved(iter, true);
+        // seek positions at or past the end, step back while out of bounds.
+        // Out of bounds keys before the end are either past the prefix, if
+        // the database isn't ordered bytewise, or before the start, where
+        // stepping back finds nothing.
+        let mut past_end = true;
+        while past_end && raw_valid(iter) && !self.in_bounds() {
+            unsafe { leveldb_iter_prev(iter) };
+            skip_reserved(iter, true);
+            past_end = match self.prefix_key() {
+                Some(prefix) => raw_valid(iter) && unsafe { raw_key(iter) } > prefix,
+                None => false,
+            };
+        }
+        self.reset_start();
     }
 
-    fn seek_to_last(&self) {
-        if let Some(k) = self.to_key() {
-            self.seek(k);
-        } else {
-            unsafe {
-                leveldb_iter_seek_to_last(self.raw_iterator());
-            }
+    /// Repositions the iterator at the first key at or past `key`.
+    ///
+    /// The next call to `next` returns the entry at that position.
+    fn seek(&mut self, key: &K) {
+        raw_seek(self.raw_iterator(), key);
+        skip_reserved(self.raw_iterator(), false);
+        self.reset_start();
+    }
+
+    /// Repositions the iterator at the last key at or before `key`.
+    ///
+    /// The next call to `next` returns the entry at that position, so a
+    /// reversed iterator goes on with the keys before it.
+    fn seek_for_prev(&mut self, key: &K) {
+        let iter = self.raw_iterator();
+        raw_seek(iter, key);
+        if !raw_valid(iter) {
+            unsafe { leveldb_iter_seek_to_last(iter) };
+        } else if key.as_slice(|k| unsafe { raw_key(iter) } != k) {
+            // seek overshot, step back to the key before
+            unsafe { leveldb_iter_prev(iter) };
         }
+        skip_reserved(iter, true);
+        self.reset_start();
+    }
+}
+
+fn first<'a, K: Key>(mut iter: KeyIterator<'a, K>) -> Result<Option<K>, Error> {
+    match iter.next() {
+        Some(key) => Ok(Some(key)),
+        None => iter.status().map(|_| None),
+    }
+}
+
+fn last<'a, K: Key>(mut iter: KeyIterator<'a, K>) -> Result<Option<K>, Error> {
+    iter.seek_to_last();
+    if iter.valid() {
+        Ok(Some(iter.key()))
+    } else {
+        iter.status().map(|_| None)
+    }
+}
+
+pub(crate) fn raw_status(iter: *mut leveldb_iterator_t) -> Result<(), Error> {
+    let mut error = ptr::null();
+    unsafe { leveldb_iter_get_error(iter, ptr::addr_of_mut!(error)) };
+    if error.is_null() {
+        Ok(())
+    } else {
+        Err(unsafe { Error::new_from_i8(error) })
     }
+}
+
+pub(crate) fn raw_valid(iter: *mut leveldb_iterator_t) -> bool {
+    unsafe { leveldb_iter_valid(iter) != 0 }
+}
+
+// leveldb-sys declares the length as `*const`, but leveldb writes it
+pub(crate) unsafe fn raw_key<'b>(iter: *mut leveldb_iterator_t) -> &'b [u8] {
+    let mut length: size_t = 0;
+    let value = leveldb_iter_key(iter, &mut length as *mut size_t as *const size_t) as *const u8;
+    from_raw_parts(value, length)
+}
+
+pub(crate) unsafe fn raw_value<'b>(iter: *mut leveldb_iterator_t) -> &'b [u8] {
+    let mut length: size_t = 0;
+    let value = leveldb_iter_value(iter, &mut length as *mut size_t as *const size_t) as *const u8;
+    from_raw_parts(value, length)
+}
+
+fn raw_seek<K: Key>(iter: *mut leveldb_iterator_t, key: &K) {
+    key.as_slice(|k| raw_seek_bytes(iter, k))
+}
 
-    fn seek(&self, key: &K) {
-        unsafe {
-            key.as_slice(|k| {
-                leveldb_iter_seek(self.raw_iterator(),
-                                  k.as_ptr() as *mut c_char,
-                                  k.len() as size_t);
-            })
+pub(crate) fn raw_seek_bytes(iter: *mut leveldb_iterator_t, key: &[u8]) {
+    unsafe {
+        leveldb_iter_seek(iter,
+                          key.as_ptr() as *mut c_char,
+                          key.len() as size_t);
+    }
+}
+
+// move off the reserved namespace, forward by seeking past its end, which
+// custom comparators sort after all other keys too, or backward by stepping
+// back from its start
+fn skip_reserved(iter: *mut leveldb_iterator_t, reverse: bool) {
+    if !raw_valid(iter) || !is_reserved(unsafe { raw_key(iter) }) {
+        return;
+    }
+    if reverse {
+        raw_seek_bytes(iter, RESERVED_PREFIX);
+        unsafe { leveldb_iter_prev(iter) };
+    } else {
+        raw_seek_bytes(iter, RESERVED_END);
+    }
+}
+
+/// The smallest byte string greater than all strings starting with `prefix`.
+pub(crate) fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_vec();
+    while let Some(last) = successor.pop() {
+        if last < 0xff {
+            successor.push(last + 1);
+            return Some(successor);
         }
     }
+    None
 }
 
 
 impl<'a, K: Key> Iterator<'a, K> {
     fn new(database: &'a Database<K>, options: ReadOptions<'a, K>) -> Iterator<'a, K> {
-        unsafe {
-            let c_readoptions = c_readoptions(&options);
-            let ptr = leveldb_create_iterator(database.database.ptr, c_readoptions);
-            leveldb_readoptions_destroy(c_readoptions);
+        let mut iter = unsafe {
+            let c_readoptions = database.database.c_options.read(&options);
+            let ptr = leveldb_create_iterator(database.database.ptr, c_readoptions.ptr);
             leveldb_iter_seek_to_first(ptr);
             Iterator {
                 start: true,
-                iter: RawIterator { ptr: ptr },
-                database: PhantomData,
+                reverse: false,
+                iter: RawIterator { ptr },
+                database,
                 from: None,
                 to: None,
+                to_exclusive: false,
+                prefix: None,
+                lower: options.iterate_lower_bound,
+                upper: options.iterate_upper_bound,
+                timer: Timer::start("iterator"),
+                estimate: Cell::new(None),
+            }
+        };
+        if iter.lower.is_some() {
+            iter.seek_to_first();
+        }
+        iter
+    }
+
+    // the later of the `from` bound and the lower bound of the options
+    fn lower_bound(&self) -> Option<&'a K> {
+        match (self.from, self.lower) {
+            (Some(from), Some(lower)) => {
+                if self.compare(from, lower) == Ordering::Less {
+                    Some(lower)
+                } else {
+                    Some(from)
+                }
+            }
+            (from, lower) => from.or(lower),
+        }
+    }
+
+    // the earlier of the `to` bound and the upper bound of the options,
+    // with whether it is included
+    fn upper_bound(&self) -> Option<(&'a K, bool)> {
+        let to = self.to.map(|to| (to, !self.to_exclusive));
+        match (to, self.upper) {
+            (Some((to, inclusive)), Some(upper)) => {
+                if inclusive && self.compare(to, upper) == Ordering::Less {
+                    Some((to, true))
+                } else if !inclusive && self.compare(to, upper) != Ordering::Greater {
+                    Some((to, false))
+                } else {
+                    Some((upper, false))
+                }
+            }
+            (to, upper) => to.or(upper.map(|upper| (upper, false))),
+        }
+    }
+
+    // move to the start of the bounds set so far, in the direction set so
+    // far, so the builders can be called in any order
+    fn reposition(&mut self) {
+        if self.reverse {
+            self.seek_to_last();
+        } else {
+            self.seek_to_first();
+        }
+    }
+
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        a.as_slice(|a| b.as_slice(|b| self.database.compare_keys(a, b)))
+    }
+
+    /// The database iterated over, for comparing keys
+    pub(crate) fn database(&self) -> &'a Database<K> {
+        self.database
+    }
+
+    /// Return the entries in batches of up to `chunk_size`, keeping the
+    /// bounds and direction set so far
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn chunks(self, chunk_size: usize) -> Chunks<'a, K> {
+        assert!(chunk_size > 0, "chunk size must not be 0");
+        Chunks {
+            inner: self,
+            chunk_size,
+            done: false,
+        }
+    }
+
+    /// Estimate the number of entries within the bounds
+    ///
+    /// The estimate is computed on first use, from the size of the range on
+    /// disk and the average size of the first entries in it, for
+    /// pre-allocating or reporting progress. `size_hint` doesn't use it, so
+    /// collecting doesn't pay for it. Small ranges are counted exactly, for
+    /// larger ones the estimate tends to be low, as the data on disk is
+    /// compressed and recent writes are not on disk yet. Entries are
+    /// counted from the database as it is now, not from the snapshot of the
+    /// iterator, nor do entries already returned count down.
+    pub fn estimated_len(&self) -> u64 {
+        if let Some(estimate) = self.estimate.get() {
+            return estimate;
+        }
+        let from = self.lower_bound().map(|from| from.as_slice(|f| f.to_vec()));
+        // the later of the `from` bound and the prefix
+        let start = match (from, self.prefix) {
+            (Some(from), Some(prefix)) => {
+                if self.database.compare_keys(&from, prefix) == Ordering::Less {
+                    Some(prefix.to_vec())
+                } else {
+                    Some(from)
+                }
             }
+            (from, prefix) => from.or_else(|| prefix.map(|prefix| prefix.to_vec())),
+        };
+        let limit = match self.upper_bound() {
+            Some((to, inclusive)) => Some((to.as_slice(|t| t.to_vec()), inclusive)),
+            None => self.prefix.and_then(prefix_successor).map(|successor| (successor, false)),
+        };
+        let estimate = self.database.estimate_entries(start.as_deref(),
+                                                      limit.as_ref().map(|&(ref l, i)| (&l[..], i)),
+                                                      self.prefix);
+        self.estimate.set(Some(estimate));
+        estimate
+    }
+
+    /// Advance and return the encoded key and the value, borrowed from
+    /// leveldb, `None` at the end
+    ///
+    /// Like `next` without allocating, for scans that don't keep the
+    /// entries: `while let Some((key, value)) = iter.next_entry() { .. }`.
+    pub fn next_entry(&mut self) -> Option<(&[u8], &[u8])> {
+        self.database.database.metrics.step();
+        if self.advance() {
+            self.timer.entry();
+            Some((self.key_bytes(), self.value_bytes()))
+        } else {
+            None
         }
     }
 
     /// return the last element of the iterator
-    pub fn last(self) -> Option<(K, Vec<u8>)> {
+    pub fn last(mut self) -> Option<(K, Vec<u8>)> {
         self.seek_to_last();
-        Some((self.key(), self.value()))
+        if self.valid() {
+            Some((self.key(), self.value()))
+        } else {
+            None
+        }
     }
 }
 
@@ -197,22 +567,83 @@ impl<'a, K: Key> LevelDBIterator<'a, K> for Iterator<'a,K> {
         self.start = false
     }
 
+    #[inline]
+    fn reset_start(&mut self) {
+        self.start = true
+    }
+
     fn from(mut self, key: &'a K) -> Self {
         self.from = Some(key);
+        self.reposition();
         self
     }
 
     fn to(mut self, key: &'a K) -> Self {
         self.to = Some(key);
+        self.to_exclusive = false;
+        self.reposition();
+        self
+    }
+
+    fn until(mut self, key: &'a K) -> Self {
+        self.to = Some(key);
+        self.to_exclusive = true;
+        self.reposition();
+        self
+    }
+
+    fn prefix(mut self, prefix: &'a [u8]) -> Self {
+        self.prefix = Some(prefix);
+        self.reposition();
+        self
+    }
+
+    fn prefix_key(&self) -> Option<&[u8]> {
+        self.prefix
+    }
+
+    fn in_bounds(&self) -> bool {
+        if self.from.is_none() && self.to.is_none() && self.prefix.is_none() &&
+           self.lower.is_none() && self.upper.is_none() {
+            return true;
+        }
+        let key = unsafe { raw_key(self.iter.ptr) };
+        if let Some(prefix) = self.prefix {
+            if !key.starts_with(prefix) {
+                return false;
+            }
+        }
+        if let Some(from) = self.lower_bound() {
+            if from.as_slice(|f| self.database.compare_keys(key, f)) == Ordering::Less {
+                return false;
+            }
+        }
+        if let Some((to, inclusive)) = self.upper_bound() {
+            match to.as_slice(|t| self.database.compare_keys(key, t)) {
+                Ordering::Greater => return false,
+                Ordering::Equal => return inclusive,
+                Ordering::Less => {}
+            }
+        }
+        true
+    }
+
+    fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self.reposition();
         self
     }
 
+    fn reversed(&self) -> bool {
+        self.reverse
+    }
+
     fn from_key(&self) -> Option<&K> {
-        self.from
+        self.lower_bound()
     }
 
     fn to_key(&self) -> Option<&K> {
-        self.to
+        self.upper_bound().map(|(to, _)| to)
     }
 }
 
@@ -222,9 +653,13 @@ impl<'a,K: Key> KeyIterator<'a,K> {
     }
 
     /// return the last element of the iterator
-    pub fn last(self) -> Option<K> {
+    pub fn last(mut self) -> Option<K> {
         self.seek_to_last();
-        Some(self.key())
+        if self.valid() {
+            Some(self.key())
+        } else {
+            None
+        }
     }
 }
 
@@ -244,22 +679,54 @@ impl<'a,K: Key> LevelDBIterator<'a, K> for KeyIterator<'a,K> {
         self.inner.start = false
     }
 
+    #[inline]
+    fn reset_start(&mut self) {
+        self.inner.start = true
+    }
+
     fn from(mut self, key: &'a K) -> Self {
-        self.inner.from = Some(key);
+        self.inner = self.inner.from(key);
         self
     }
 
     fn to(mut self, key: &'a K) -> Self {
-        self.inner.to = Some(key);
+        self.inner = self.inner.to(key);
+        self
+    }
+
+    fn until(mut self, key: &'a K) -> Self {
+        self.inner = self.inner.until(key);
+        self
+    }
+
+    fn prefix(mut self, prefix: &'a [u8]) -> Self {
+        self.inner = self.inner.prefix(prefix);
         self
     }
 
+    fn prefix_key(&self) -> Option<&[u8]> {
+        self.inner.prefix
+    }
+
+    fn in_bounds(&self) -> bool {
+        self.inner.in_bounds()
+    }
+
+    fn reverse(mut self) -> Self {
+        self.inner = self.inner.reverse();
+        self
+    }
+
+    fn reversed(&self) -> bool {
+        self.inner.reverse
+    }
+
     fn from_key(&self) -> Option<&K> {
-        self.inner.from
+        self.inner.from_key()
     }
 
     fn to_key(&self) -> Option<&K> {
-        self.inner.to
+        self.inner.to_key()
     }
 }
 
@@ -269,9 +736,13 @@ impl<'a,K: Key> ValueIterator<'a,K> {
     }
 
     /// return the last element of the iterator
-    pub fn last(self) -> Option<Vec<u8>> {
+    pub fn last(mut self) -> Option<Vec<u8>> {
         self.seek_to_last();
-        Some(self.value())
+        if self.valid() {
+            Some(self.value())
+        } else {
+            None
+        }
     }
 }
 
@@ -291,22 +762,54 @@ impl<'a,K: Key> LevelDBIterator<'a, K> for ValueIterator<'a,K> {
         self.inner.start = false
     }
 
+    #[inline]
+    fn reset_start(&mut self) {
+        self.inner.start = true
+    }
+
     fn from(mut self, key: &'a K) -> Self {
-        self.inner.from = Some(key);
+        self.inner = self.inner.from(key);
         self
     }
 
     fn to(mut self, key: &'a K) -> Self {
-        self.inner.to = Some(key);
+        self.inner = self.inner.to(key);
+        self
+    }
+
+    fn until(mut self, key: &'a K) -> Self {
+        self.inner = self.inner.until(key);
+        self
+    }
+
+    fn prefix(mut self, prefix: &'a [u8]) -> Self {
+        self.inner = self.inner.prefix(prefix);
+        self
+    }
+
+    fn prefix_key(&self) -> Option<&[u8]> {
+        self.inner.prefix
+    }
+
+    fn in_bounds(&self) -> bool {
+        self.inner.in_bounds()
+    }
+
+    fn reverse(mut self) -> Self {
+        self.inner = self.inner.reverse();
         self
     }
 
+    fn reversed(&self) -> bool {
+        self.inner.reverse
+    }
+
     fn from_key(&self) -> Option<&K> {
-        self.inner.from
+        self.inner.from_key()
     }
 
     fn to_key(&self) -> Option<&K> {
-        self.inner.to
+        self.inner.to_key()
     }
 }
 
@@ -314,7 +817,9 @@ impl<'a,K: Key> iter::Iterator for Iterator<'a,K> {
   type Item = (K,Vec<u8>);
 
     fn next(&mut self) -> Option<(K, Vec<u8>)> {
+        self.database.database.metrics.step();
         if self.advance() {
+            self.timer.entry();
             Some((self.key(), self.value()))
         } else {
             None
@@ -326,7 +831,9 @@ impl<'a, K: Key> iter::Iterator for KeyIterator<'a,K> {
   type Item = K;
 
     fn next(&mut self) -> Option<K> {
+        self.inner.database.database.metrics.step();
         if self.advance() {
+            self.inner.timer.entry();
             Some(self.key())
         } else {
             None
@@ -338,10 +845,269 @@ impl<'a, K: Key> iter::Iterator for ValueIterator<'a,K> {
   type Item = Vec<u8>;
 
     fn next(&mut self) -> Option<Vec<u8>> {
+        self.inner.database.database.metrics.step();
         if self.advance() {
+            self.inner.timer.entry();
             Some(self.value())
         } else {
             None
         }
     }
 }
+
+impl<'a, K: Key> Chunks<'a, K> {
+    /// The error that ended the iteration early, if any.
+    ///
+    /// Check this once the iterator returned `None`.
+    pub fn status(&self) -> Result<(), Error> {
+        self.inner.status()
+    }
+}
+
+impl<'a, K: Key> iter::Iterator for Chunks<'a, K> {
+    type Item = Vec<(K, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Vec<(K, Vec<u8>)>> {
+        if self.done {
+            return None;
+        }
+        let chunk: Vec<_> = self.inner.by_ref().take(self.chunk_size).collect();
+        self.done = chunk.len() < self.chunk_size;
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+/// An iterator over the leveldb keyspace, owning a handle to the database.
+///
+/// In contrast to `Iterator`, it does not borrow the database and can be
+/// returned from functions or moved into other threads.
+///
+/// Returns key and value as a tuple.
+pub struct OwnedIterator<K: Key + 'static> {
+    // declared first, the iterator must be destroyed before the database
+    inner: Iterator<'static, K>,
+    #[allow(dead_code)]
+    database: Arc<Database<K>>,
+}
+
+unsafe impl<K: Key + Send + Sync> Send for OwnedIterator<K> {}
+
+impl<K: Key + 'static> OwnedIterator<K> {
+    /// Advance and return the encoded key and the value, borrowed from
+    /// leveldb, see `Iterator::next_entry`
+    pub fn next_entry(&mut self) -> Option<(&[u8], &[u8])> {
+        self.inner.next_entry()
+    }
+}
+
+impl<K: Key + Send + Sync + 'static> OwnedIterator<K> {
+    /// Read the entries on a helper thread, up to `entries` ahead of the
+    /// consumer
+    ///
+    /// Stepping the leveldb iterator and copying out the entries overlaps
+    /// with processing them, which speeds up long sequential scans. The
+    /// bounds and direction set so far apply, seeking is no longer
+    /// possible.
+    pub fn prefetch(self, entries: usize) -> PrefetchIterator<K> {
+        let shared = Arc::new(PrefetchShared {
+            state: Mutex::new(PrefetchState {
+                buffer: VecDeque::with_capacity(entries.max(1)),
+                status: Ok(()),
+                done: false,
+                closed: false,
+            }),
+            space: Condvar::new(),
+            ready: Condvar::new(),
+        });
+        let producer = Prefetcher { shared: shared.clone() };
+        let capacity = entries.max(1);
+        thread::spawn(move || {
+            let shared = &producer.shared;
+            let mut iter = self;
+            for entry in iter.by_ref() {
+                let mut state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+                while state.buffer.len() >= capacity && !state.closed {
+                    state = shared.space.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                if state.closed {
+                    return;
+                }
+                state.buffer.push_back(entry);
+                shared.ready.notify_one();
+            }
+            let mut state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.status = iter.status();
+        });
+        PrefetchIterator { shared }
+    }
+}
+
+struct PrefetchState<K> {
+    buffer: VecDeque<(K, Vec<u8>)>,
+    status: Result<(), Error>,
+    done: bool,
+    closed: bool,
+}
+
+struct PrefetchShared<K> {
+    state: Mutex<PrefetchState<K>>,
+    // signalled when the consumer took an entry
+    space: Condvar,
+    // signalled when the helper thread added an entry or finished
+    ready: Condvar,
+}
+
+// ends the iteration even if reading from the iterator panics
+struct Prefetcher<K> {
+    shared: Arc<PrefetchShared<K>>,
+}
+
+impl<K> Drop for Prefetcher<K> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.done = true;
+        self.shared.ready.notify_one();
+    }
+}
+
+/// An iterator over (Key,Value) pairs read ahead on a helper thread.
+///
+/// Created by `OwnedIterator::prefetch`. Dropping it stops the helper
+/// thread.
+pub struct PrefetchIterator<K> {
+    shared: Arc<PrefetchShared<K>>,
+}
+
+impl<K> PrefetchIterator<K> {
+    /// The error that ended the iteration early, if any.
+    ///
+    /// Check this once the iterator returned `None`. The error is only
+    /// returned once.
+    pub fn status(&mut self) -> Result<(), Error> {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        ::std::mem::replace(&mut state.status, Ok(()))
+    }
+}
+
+impl<K> iter::Iterator for PrefetchIterator<K> {
+    type Item = (K, Vec<u8>);
+
+    fn next(&mut self) -> Option<(K, Vec<u8>)> {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(entry) = state.buffer.pop_front() {
+                self.shared.space.notify_one();
+                return Some(entry);
+            }
+            if state.done {
+                return None;
+            }
+            state = self.shared.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl<K> Drop for PrefetchIterator<K> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.closed = true;
+        self.shared.space.notify_one();
+    }
+}
+
+/// Structs implementing this trait can create iterators that own
+/// a handle to the database.
+pub trait OwnedIterable<K: Key + 'static> {
+    /// Return an OwnedIterator iterating over (Key,Value) pairs
+    fn owned_iter(&self, options: ReadOptions<'static, K>) -> OwnedIterator<K>;
+}
+
+impl<K: Key + 'static> OwnedIterable<K> for Arc<Database<K>> {
+    fn owned_iter(&self, options: ReadOptions<'static, K>) -> OwnedIterator<K> {
+        // the iterator keeps its own handle, so the database outlives the reference
+        let database: &'static Database<K> = unsafe { &*(&**self as *const Database<K>) };
+        OwnedIterator {
+            inner: Iterator::new(database, options),
+            database: self.clone(),
+        }
+    }
+}
+
+impl<K: Key + 'static> LevelDBIterator<'static, K> for OwnedIterator<K> {
+    #[inline]
+    fn raw_iterator(&self) -> *mut leveldb_iterator_t {
+        self.inner.iter.ptr
+    }
+
+    #[inline]
+    fn start(&self) -> bool {
+        self.inner.start
+    }
+
+    #[inline]
+    fn started(&mut self) {
+        self.inner.start = false
+    }
+
+    #[inline]
+    fn reset_start(&mut self) {
+        self.inner.start = true
+    }
+
+    fn from(mut self, key: &'static K) -> Self {
+        self.inner = self.inner.from(key);
+        self
+    }
+
+    fn to(mut self, key: &'static K) -> Self {
+        self.inner = self.inner.to(key);
+        self
+    }
+
+    fn until(mut self, key: &'static K) -> Self {
+        self.inner = self.inner.until(key);
+        self
+    }
+
+    fn prefix(mut self, prefix: &'static [u8]) -> Self {
+        self.inner = self.inner.prefix(prefix);
+        self
+    }
+
+    fn reverse(mut self) -> Self {
+        self.inner = self.inner.reverse();
+        self
+    }
+
+    fn reversed(&self) -> bool {
+        self.inner.reverse
+    }
+
+    fn from_key(&self) -> Option<&K> {
+        self.inner.from_key()
+    }
+
+    fn to_key(&self) -> Option<&K> {
+        self.inner.to_key()
+    }
+
+    fn prefix_key(&self) -> Option<&[u8]> {
+        self.inner.prefix
+    }
+
+    fn in_bounds(&self) -> bool {
+        self.inner.in_bounds()
+    }
+}
+
+impl<K: Key + 'static> iter::Iterator for OwnedIterator<K> {
+    type Item = (K, Vec<u8>);
+
+    fn next(&mut self) -> Option<(K, Vec<u8>)> {
+        self.inner.next()
+    }
+}
@@ -0,0 +1,365 @@
+//! Ready-made key types
+//!
+//! `Key` is defined in the `db-key` crate, so it can't be implemented for
+//! standard types from here. These thin wrappers cover the common cases
+//! instead. All of them encode so that the bytewise order of the encoding
+//! matches their `Ord` implementation, which makes them usable with both
+//! the default comparator and `OrdComparator`.
+//!
+//! For keys of several fields, `#[derive(DbKey)]` from the `derive`
+//! feature encodes structs like a `CompositeKey`, field by field through
+//! `KeyComponent`.
+pub use database::key::Key;
+
+/// A key made of arbitrary bytes.
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Default)]
+pub struct BinaryKey(pub Vec<u8>);
+
+impl Key for BinaryKey {
+    fn from_u8(key: &[u8]) -> BinaryKey {
+        BinaryKey(key.to_vec())
+    }
+
+    fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+        f(&self.0)
+    }
+}
+
+impl From<Vec<u8>> for BinaryKey {
+    fn from(key: Vec<u8>) -> BinaryKey {
+        BinaryKey(key)
+    }
+}
+
+impl<'a> From<&'a [u8]> for BinaryKey {
+    fn from(key: &'a [u8]) -> BinaryKey {
+        BinaryKey(key.to_vec())
+    }
+}
+
+/// A key made of UTF-8 text.
+///
+/// Keys read from the database that are not valid UTF-8 are decoded lossily.
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Default)]
+pub struct StringKey(pub String);
+
+impl Key for StringKey {
+    fn from_u8(key: &[u8]) -> StringKey {
+        StringKey(String::from_utf8_lossy(key).into_owned())
+    }
+
+    fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+        f(self.0.as_bytes())
+    }
+}
+
+impl From<String> for StringKey {
+    fn from(key: String) -> StringKey {
+        StringKey(key)
+    }
+}
+
+impl<'a> From<&'a str> for StringKey {
+    fn from(key: &'a str) -> StringKey {
+        StringKey(key.to_string())
+    }
+}
+
+/// A key of exactly `N` bytes, such as a hash or an id.
+///
+/// Panics when reading a key of a different length.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct FixedKey<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Key for FixedKey<N> {
+    fn from_u8(key: &[u8]) -> FixedKey<N> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(key);
+        FixedKey(bytes)
+    }
+
+    fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+        f(&self.0)
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for FixedKey<N> {
+    fn from(key: [u8; N]) -> FixedKey<N> {
+        FixedKey(key)
+    }
+}
+
+/// A fixed-width integer key, encoded big-endian.
+///
+/// Signed integers have their sign bit flipped, so negative numbers sort
+/// before positive ones.
+///
+/// Panics when reading a key of a different width.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Default)]
+pub struct IntKey<T>(pub T);
+
+macro_rules! int_key {
+    ($($t:ty => $flip:expr),*) => {
+        $(
+            impl Key for IntKey<$t> {
+                fn from_u8(key: &[u8]) -> IntKey<$t> {
+                    let mut bytes = [0; ::std::mem::size_of::<$t>()];
+                    bytes.copy_from_slice(key);
+                    IntKey(<$t>::from_be_bytes(bytes) ^ $flip)
+                }
+
+                fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+                    f(&(self.0 ^ $flip).to_be_bytes())
+                }
+            }
+
+            impl From<$t> for IntKey<$t> {
+                fn from(key: $t) -> IntKey<$t> {
+                    IntKey(key)
+                }
+            }
+        )*
+    }
+}
+
+int_key!(u8 => 0, u16 => 0, u32 => 0, u64 => 0, u128 => 0,
+         i8 => i8::MIN, i16 => i16::MIN, i32 => i32::MIN, i64 => i64::MIN, i128 => i128::MIN);
+
+/// A key made of several components, encoded so that keys sort by their
+/// first component, then their second, and so on.
+///
+/// ```
+/// use leveldb::keys::CompositeKey;
+///
+/// let key = CompositeKey::new().push_u64(1_500_000_000).push_bytes(b"id");
+/// let mut reader = key.reader();
+/// assert_eq!(Some(1_500_000_000), reader.read_u64());
+/// assert_eq!(Some(b"id".to_vec()), reader.read_bytes());
+/// ```
+///
+/// Every component is self-delimiting, so the encoding of the leading
+/// components of a key is a prefix of the full key, suitable for
+/// `Iterable::scan_prefix`.
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Default)]
+pub struct CompositeKey {
+    bytes: Vec<u8>,
+}
+
+// byte strings escape 0x00 as 0x00 0xff and end with 0x00 0x01
+const ESCAPE: u8 = 0x00;
+const ESCAPED_NUL: u8 = 0xff;
+const TERMINATOR: u8 = 0x01;
+
+impl CompositeKey {
+    /// Create a key without components
+    pub fn new() -> CompositeKey {
+        CompositeKey { bytes: vec![] }
+    }
+
+    /// Append an unsigned 8 bit integer
+    pub fn push_u8(mut self, value: u8) -> CompositeKey {
+        self.bytes.push(value);
+        self
+    }
+
+    /// Append an unsigned 32 bit integer
+    pub fn push_u32(mut self, value: u32) -> CompositeKey {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Append an unsigned 64 bit integer
+    pub fn push_u64(mut self, value: u64) -> CompositeKey {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Append a signed 64 bit integer, negative numbers sorting first
+    pub fn push_i64(self, value: i64) -> CompositeKey {
+        self.push_u64((value ^ i64::MIN) as u64)
+    }
+
+    /// Append a byte string of any length
+    pub fn push_bytes(mut self, value: &[u8]) -> CompositeKey {
+        for &byte in value {
+            self.bytes.push(byte);
+            if byte == ESCAPE {
+                self.bytes.push(ESCAPED_NUL);
+            }
+        }
+        self.bytes.push(ESCAPE);
+        self.bytes.push(TERMINATOR);
+        self
+    }
+
+    /// Append a string of any length
+    pub fn push_str(self, value: &str) -> CompositeKey {
+        self.push_bytes(value.as_bytes())
+    }
+
+    fn push_raw(mut self, value: &[u8]) -> CompositeKey {
+        self.bytes.extend_from_slice(value);
+        self
+    }
+
+    /// The encoded key
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Read the components back, in the order they were pushed
+    pub fn reader<'a>(&'a self) -> CompositeKeyReader<'a> {
+        CompositeKeyReader { rest: &self.bytes }
+    }
+}
+
+impl Key for CompositeKey {
+    fn from_u8(key: &[u8]) -> CompositeKey {
+        CompositeKey { bytes: key.to_vec() }
+    }
+
+    fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+        f(&self.bytes)
+    }
+}
+
+/// Decodes the components of a `CompositeKey`.
+///
+/// Each read returns `None` if the remaining bytes don't hold a component
+/// of the requested type, leaving the reader unchanged.
+pub struct CompositeKeyReader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> CompositeKeyReader<'a> {
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        if self.rest.len() < N {
+            return None;
+        }
+        let (head, tail) = self.rest.split_at(N);
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(head);
+        self.rest = tail;
+        Some(bytes)
+    }
+
+    /// Read an unsigned 8 bit integer
+    pub fn read_u8(&mut self) -> Option<u8> {
+        self.read_array::<1>().map(|b| b[0])
+    }
+
+    /// Read an unsigned 32 bit integer
+    pub fn read_u32(&mut self) -> Option<u32> {
+        self.read_array().map(u32::from_be_bytes)
+    }
+
+    /// Read an unsigned 64 bit integer
+    pub fn read_u64(&mut self) -> Option<u64> {
+        self.read_array().map(u64::from_be_bytes)
+    }
+
+    /// Read a signed 64 bit integer
+    pub fn read_i64(&mut self) -> Option<i64> {
+        self.read_u64().map(|v| (v as i64) ^ i64::MIN)
+    }
+
+    /// Read a byte string
+    pub fn read_bytes(&mut self) -> Option<Vec<u8>> {
+        let mut value = vec![];
+        let mut i = 0;
+        loop {
+            match (self.rest.get(i), self.rest.get(i + 1)) {
+                (Some(&ESCAPE), Some(&TERMINATOR)) => break,
+                (Some(&ESCAPE), Some(&ESCAPED_NUL)) => {
+                    value.push(ESCAPE);
+                    i += 2;
+                }
+                (Some(&ESCAPE), _) | (None, _) => return None,
+                (Some(&byte), _) => {
+                    value.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        self.rest = &self.rest[i + 2..];
+        Some(value)
+    }
+
+    /// Read a string, failing if it is not valid UTF-8
+    pub fn read_str(&mut self) -> Option<String> {
+        let rest = self.rest;
+        let value = self.read_bytes().and_then(|v| String::from_utf8(v).ok());
+        if value.is_none() {
+            self.rest = rest;
+        }
+        value
+    }
+
+    /// Whether all components have been read
+    pub fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+}
+
+/// A field of a composite key, encoded in the format of `CompositeKey`.
+///
+/// Integers are fixed-width, signed ones sorting negative numbers first,
+/// byte arrays are stored as they are, and byte strings and strings are
+/// escaped and terminated, so each field sorts on its own and the next
+/// one only breaks ties. Used by `#[derive(DbKey)]`.
+pub trait KeyComponent: Sized {
+    /// Append the field to `key`
+    fn append_to(&self, key: CompositeKey) -> CompositeKey;
+
+    /// Read the field, `None` if the next bytes don't hold one
+    fn read_from(reader: &mut CompositeKeyReader) -> Option<Self>;
+}
+
+macro_rules! int_component {
+    ($($t:ty => $flip:expr),*) => {
+        $(
+            impl KeyComponent for $t {
+                fn append_to(&self, key: CompositeKey) -> CompositeKey {
+                    key.push_raw(&(*self ^ $flip).to_be_bytes())
+                }
+
+                fn read_from(reader: &mut CompositeKeyReader) -> Option<$t> {
+                    reader.read_array().map(|b| <$t>::from_be_bytes(b) ^ $flip)
+                }
+            }
+        )*
+    }
+}
+
+int_component!(u8 => 0, u16 => 0, u32 => 0, u64 => 0, u128 => 0,
+               i8 => i8::MIN, i16 => i16::MIN, i32 => i32::MIN, i64 => i64::MIN, i128 => i128::MIN);
+
+impl<const N: usize> KeyComponent for [u8; N] {
+    fn append_to(&self, key: CompositeKey) -> CompositeKey {
+        key.push_raw(self)
+    }
+
+    fn read_from(reader: &mut CompositeKeyReader) -> Option<[u8; N]> {
+        reader.read_array()
+    }
+}
+
+impl KeyComponent for Vec<u8> {
+    fn append_to(&self, key: CompositeKey) -> CompositeKey {
+        key.push_bytes(self)
+    }
+
+    fn read_from(reader: &mut CompositeKeyReader) -> Option<Vec<u8>> {
+        reader.read_bytes()
+    }
+}
+
+impl KeyComponent for String {
+    fn append_to(&self, key: CompositeKey) -> CompositeKey {
+        key.push_str(self)
+    }
+
+    fn read_from(reader: &mut CompositeKeyReader) -> Option<String> {
+        reader.read_str()
+    }
+}
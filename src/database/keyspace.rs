@@ -0,0 +1,382 @@
+//! Keyspaces, similar to column families
+//!
+//! `Keyspaces` partitions one database into named keyspaces. Every keyspace
+//! gets a numeric id when it is first opened, which is stored in the
+//! database and prefixed to all of its keys, so the file format doesn't
+//! change. A `Keyspace` offers the `KV` and `Batch` traits scoped to it,
+//! and iterators over its entries, keys and values.
+//!
+//! Keys of different keyspaces are kept apart by their prefix, which only
+//! works with the default bytewise comparator.
+use std::borrow::Borrow;
+use std::iter;
+use std::marker::PhantomData;
+
+use leveldb_sys::*;
+use libc::{c_char, size_t};
+use database::key::{Key, from_u8};
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::atomic::KeyGuard;
+use super::error::{Error, ErrorKind};
+use super::kv::{KV, get_raw_bytes};
+use super::batch::{Batch, Writebatch};
+use super::bytes::Bytes;
+use super::keys::BinaryKey;
+use super::iterator::{Iterable, LevelDBIterator, RawIterator, prefix_successor, raw_key, raw_status, raw_value};
+
+// id 0 holds the registry, mapping keyspace names to their ids
+const REGISTRY: u32 = 0;
+// number of deletes written per batch when dropping a keyspace
+const DROP_BATCH: usize = 1000;
+
+fn registry_key(name: &str) -> BinaryKey {
+    let mut key = REGISTRY.to_be_bytes().to_vec();
+    key.extend_from_slice(name.as_bytes());
+    BinaryKey(key)
+}
+
+fn decode_id(bytes: &[u8]) -> Result<u32, Error> {
+    if bytes.len() != 4 {
+        return Err(Error::with_kind(ErrorKind::Corruption, "malformed keyspace id".to_string()));
+    }
+    let mut id = [0; 4];
+    id.copy_from_slice(bytes);
+    Ok(u32::from_be_bytes(id))
+}
+
+/// The keyspaces of a database.
+pub struct Keyspaces {
+    database: Database<BinaryKey>,
+}
+
+impl Keyspaces {
+    /// Manage the keyspaces of `database`
+    ///
+    /// The database should only be accessed through keyspaces from then on.
+    pub fn new(database: Database<BinaryKey>) -> Keyspaces {
+        Keyspaces { database }
+    }
+
+    /// Open the keyspace `name`, registering it if it doesn't exist yet
+    pub fn keyspace<K: Key>(&self, name: &str) -> Result<Keyspace<K>, Error> {
+        let _guard = self.lock_registry()?;
+        let id = match self.database.get(ReadOptions::new(), registry_key(name))? {
+            Some(id) => decode_id(&id)?,
+            None => {
+                let mut last = REGISTRY;
+                for (_, id) in self.registered()? {
+                    last = last.max(id);
+                }
+                let id = last.checked_add(1)
+                    .ok_or_else(|| Error::with_kind(ErrorKind::InvalidArgument, "too many keyspaces".to_string()))?;
+                self.database.put(WriteOptions::new(), registry_key(name), &id.to_be_bytes())?;
+                id
+            }
+        };
+        Ok(Keyspace {
+            database: self.database.clone(),
+            name: name.to_string(),
+            prefix: id.to_be_bytes().to_vec(),
+            marker: PhantomData,
+        })
+    }
+
+    // serialises registering and dropping keyspaces, also through other
+    // `Keyspaces` over clones of the database
+    fn lock_registry(&self) -> Result<KeyGuard<'_>, Error> {
+        self.database.database.locks.lock(&REGISTRY.to_be_bytes())
+    }
+
+    fn registered(&self) -> Result<Vec<(String, u32)>, Error> {
+        let prefix = REGISTRY.to_be_bytes();
+        self.database
+            .iter(ReadOptions::new())
+            .prefix(&prefix)
+            .map(|(key, id)| {
+                let name = String::from_utf8_lossy(&key.0[prefix.len()..]).into_owned();
+                decode_id(&id).map(|id| (name, id))
+            })
+            .collect()
+    }
+
+    /// Return the names of all registered keyspaces, in sorted order
+    pub fn names(&self) -> Result<Vec<String>, Error> {
+        Ok(self.registered()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// Delete the keyspace `name` with all of its entries
+    ///
+    /// The entries are deleted in several batches, the keyspace is only
+    /// unregistered after all of them are gone. Handles to the keyspace
+    /// must not be used afterwards.
+    pub fn drop_keyspace(&self, name: &str) -> Result<(), Error> {
+        let _guard = self.lock_registry()?;
+        let prefix = match self.database.get(ReadOptions::new(), registry_key(name))? {
+            Some(id) => id,
+            None => return Err(Error::with_kind(ErrorKind::NotFound, format!("keyspace {}", name))),
+        };
+        loop {
+            let mut batch = Writebatch::new();
+            for key in self.database.keys_iter(ReadOptions::new()).prefix(&prefix).take(DROP_BATCH) {
+                batch.delete(key);
+            }
+            if batch.is_empty() {
+                break;
+            }
+            self.database.write(WriteOptions::new(), &batch)?;
+        }
+        self.database.delete(WriteOptions::new(), registry_key(name))
+    }
+}
+
+/// A keyspace, storing its keys under its own prefix.
+pub struct Keyspace<K: Key> {
+    database: Database<BinaryKey>,
+    name: String,
+    prefix: Vec<u8>,
+    marker: PhantomData<K>,
+}
+
+impl<K: Key> Keyspace<K> {
+    /// The name of the keyspace
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn prefixed(&self, key: &[u8]) -> Vec<u8> {
+        let mut prefixed = Vec::with_capacity(self.prefix.len() + key.len());
+        prefixed.extend_from_slice(&self.prefix);
+        prefixed.extend_from_slice(key);
+        prefixed
+    }
+
+    fn prefixed_key(&self, key: &K) -> BinaryKey {
+        BinaryKey(key.as_slice(|k| self.prefixed(k)))
+    }
+
+    fn multi_get_bytes(&self, options: ReadOptions<K>, keys: &[&K]) -> Vec<Result<Option<Bytes>, Error>> {
+        let c_readoptions = self.database.database.c_options.read(&options);
+        keys.iter()
+            .map(|key| {
+                key.as_slice(|k| unsafe {
+                    get_raw_bytes(self.database.database.ptr, c_readoptions.ptr, &self.prefixed(k))
+                })
+            })
+            .collect()
+    }
+
+    /// Return an iterator over the (Key,Value) pairs of this keyspace
+    pub fn iter<'a>(&'a self, options: ReadOptions<'a, K>) -> KeyspaceIterator<'a, K> {
+        unsafe {
+            let c_readoptions = self.database.database.c_options.read(&options);
+            let ptr = leveldb_create_iterator(self.database.database.ptr, c_readoptions.ptr);
+            let mut iter = KeyspaceIterator {
+                iter: RawIterator { ptr },
+                prefix: &self.prefix,
+                start: true,
+                reverse: false,
+                marker: PhantomData,
+            };
+            iter.seek_bytes(&self.prefix);
+            iter
+        }
+    }
+
+    /// Return an iterator over the keys of this keyspace
+    pub fn keys_iter<'a>(&'a self, options: ReadOptions<'a, K>) -> KeyspaceKeyIterator<'a, K> {
+        KeyspaceKeyIterator { inner: self.iter(options) }
+    }
+
+    /// Return an iterator over the values of this keyspace
+    pub fn value_iter<'a>(&'a self, options: ReadOptions<'a, K>) -> KeyspaceValueIterator<'a, K> {
+        KeyspaceValueIterator { inner: self.iter(options) }
+    }
+}
+
+impl<K: Key> KV<K> for Keyspace<K> {
+    fn get<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        self.get_bytes(options, key).map(|val| val.map(Into::into))
+    }
+
+    fn get_bytes<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Bytes>, Error> {
+        self.multi_get_bytes(options, &[key.borrow()]).remove(0)
+    }
+
+    fn multi_get<'a>(&self, options: ReadOptions<'a, K>, keys: &[K]) -> Vec<Result<Option<Vec<u8>>, Error>> {
+        let keys: Vec<&K> = keys.iter().collect();
+        self.multi_get_bytes(options, &keys)
+            .into_iter()
+            .map(|result| result.map(|val| val.map(Into::into)))
+            .collect()
+    }
+
+    fn put<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<(), Error> {
+        self.database.put(options, self.prefixed_key(key.borrow()), value)
+    }
+
+    fn delete<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<(), Error> {
+        self.database.delete(options, self.prefixed_key(key.borrow()))
+    }
+
+    fn put_if_absent<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<bool, Error> {
+        self.database.put_if_absent(options, self.prefixed_key(key.borrow()), value)
+    }
+
+    fn take<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        self.database.take(options, self.prefixed_key(key.borrow()))
+    }
+}
+
+impl<K: Key> Batch<K> for Keyspace<K> {
+    /// Write a batch to the keyspace, prefixing all its keys
+    fn write(&self, options: WriteOptions, batch: &Writebatch<K>) -> Result<(), Error> {
+        let prefixed: Writebatch<BinaryKey> = batch.map_keys(|key| self.prefixed(key));
+        self.database.write(options, &prefixed)
+    }
+}
+
+/// An iterator over the entries of a keyspace.
+///
+/// Returns key and value as a tuple.
+pub struct KeyspaceIterator<'a, K: Key + 'a> {
+    iter: RawIterator,
+    prefix: &'a [u8],
+    start: bool,
+    reverse: bool,
+    marker: PhantomData<K>,
+}
+
+impl<'a, K: Key + 'a> KeyspaceIterator<'a, K> {
+    fn seek_bytes(&mut self, key: &[u8]) {
+        unsafe { leveldb_iter_seek(self.iter.ptr, key.as_ptr() as *mut c_char, key.len() as size_t) };
+        self.start = true;
+    }
+
+    fn raw_key(&self) -> &[u8] {
+        unsafe { raw_key(self.iter.ptr) }
+    }
+
+    fn valid(&self) -> bool {
+        unsafe { leveldb_iter_valid(self.iter.ptr) != 0 && self.raw_key().starts_with(self.prefix) }
+    }
+
+    /// Continue at the first key at or after `key`.
+    pub fn from(mut self, key: &K) -> Self {
+        let start = key.as_slice(|k| {
+            let mut start = self.prefix.to_vec();
+            start.extend_from_slice(k);
+            start
+        });
+        self.seek_bytes(&start);
+        self
+    }
+
+    /// The error that ended the iteration early, if any.
+    ///
+    /// leveldb stops iterating on corruption or I/O errors as if the end
+    /// was reached, check this once the iterator returned `None`.
+    pub fn status(&self) -> Result<(), Error> {
+        raw_status(self.iter.ptr)
+    }
+
+    /// Iterate backwards, starting at the last key of the keyspace.
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        let successor = prefix_successor(self.prefix);
+        if let Some(ref successor) = successor {
+            self.seek_bytes(successor);
+        }
+        unsafe {
+            // step back from the first key past the keyspace, if there is one
+            if successor.is_some() && leveldb_iter_valid(self.iter.ptr) != 0 {
+                leveldb_iter_prev(self.iter.ptr);
+            } else {
+                leveldb_iter_seek_to_last(self.iter.ptr);
+            }
+        }
+        self.start = true;
+        self
+    }
+}
+
+impl<'a, K: Key + 'a> iter::Iterator for KeyspaceIterator<'a, K> {
+    type Item = (K, Vec<u8>);
+
+    fn next(&mut self) -> Option<(K, Vec<u8>)> {
+        if self.start {
+            self.start = false;
+        } else if self.reverse {
+            unsafe { leveldb_iter_prev(self.iter.ptr) };
+        } else {
+            unsafe { leveldb_iter_next(self.iter.ptr) };
+        }
+        if !self.valid() {
+            return None;
+        }
+        let key = from_u8(&self.raw_key()[self.prefix.len()..]);
+        let value = unsafe { raw_value(self.iter.ptr).to_vec() };
+        Some((key, value))
+    }
+}
+
+/// An iterator over the keys of a keyspace.
+pub struct KeyspaceKeyIterator<'a, K: Key + 'a> {
+    inner: KeyspaceIterator<'a, K>,
+}
+
+impl<'a, K: Key + 'a> KeyspaceKeyIterator<'a, K> {
+    /// Continue at the first key at or after `key`.
+    pub fn from(self, key: &K) -> Self {
+        KeyspaceKeyIterator { inner: self.inner.from(key) }
+    }
+
+    /// Iterate backwards, starting at the last key of the keyspace.
+    pub fn reverse(self) -> Self {
+        KeyspaceKeyIterator { inner: self.inner.reverse() }
+    }
+
+    /// The error that ended the iteration early, if any.
+    pub fn status(&self) -> Result<(), Error> {
+        self.inner.status()
+    }
+}
+
+impl<'a, K: Key + 'a> iter::Iterator for KeyspaceKeyIterator<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<K> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+/// An iterator over the values of a keyspace.
+pub struct KeyspaceValueIterator<'a, K: Key + 'a> {
+    inner: KeyspaceIterator<'a, K>,
+}
+
+impl<'a, K: Key + 'a> KeyspaceValueIterator<'a, K> {
+    /// Continue at the value of the first key at or after `key`.
+    pub fn from(self, key: &K) -> Self {
+        KeyspaceValueIterator { inner: self.inner.from(key) }
+    }
+
+    /// Iterate backwards, starting at the value of the last key of the
+    /// keyspace.
+    pub fn reverse(self) -> Self {
+        KeyspaceValueIterator { inner: self.inner.reverse() }
+    }
+
+    /// The error that ended the iteration early, if any.
+    pub fn status(&self) -> Result<(), Error> {
+        self.inner.status()
+    }
+}
+
+impl<'a, K: Key + 'a> iter::Iterator for KeyspaceValueIterator<'a, K> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.inner.next().map(|(_, value)| value)
+    }
+}
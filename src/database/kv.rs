@@ -2,14 +2,17 @@
 
 use super::Database;
 
-use options::{WriteOptions, ReadOptions, c_writeoptions, c_readoptions};
-use super::error::Error;
+use options::{WriteOptions, ReadOptions};
+use super::error::{Error, Operation};
 use database::key::Key;
 use std::ptr;
 use std::borrow::Borrow;
 use libc::{c_char, size_t};
 use leveldb_sys::*;
 use super::bytes::Bytes;
+use super::metrics::OperationKind;
+use super::trace::Timer;
+use super::meta::{is_reserved_or_end, reserved_key_error};
 
 /// Key-Value-Access to the leveldb database, providing
 /// a basic interface.
@@ -24,8 +27,23 @@ pub trait KV<K: Key> {
     /// The passed key will be compared using the comparator.
     ///
     /// This version returns bytes allocated by leveldb without converting to `Vec<u8>`, which may
-    /// lead to better performance.
+    /// lead to better performance. The buffer is released when the returned
+    /// `Bytes` is dropped.
     fn get_bytes<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Bytes>, Error>;
+
+    /// get multiple values from the database.
+    ///
+    /// The results are returned in the order of `keys`. The read options are
+    /// shared across all lookups.
+    fn multi_get<'a>(&self, options: ReadOptions<'a, K>, keys: &[K]) -> Vec<Result<Option<Vec<u8>>, Error>>;
+
+    /// check whether the database holds a value for a key.
+    ///
+    /// The value is released right away instead of being copied.
+    fn contains_key<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<bool, Error> {
+        self.get_bytes(options, key).map(|value| value.is_some())
+    }
+
     /// put a binary value into the database.
     ///
     /// If the key is already present in the database, it will be overwritten.
@@ -42,6 +60,46 @@ pub trait KV<K: Key> {
     /// The database will be synced to disc if `options.sync == true`. This is
     /// NOT the default.
     fn delete<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<(), Error>;
+
+    /// put a binary value into the database, unless the key is present.
+    ///
+    /// Returns whether the value was written. This is atomic with respect
+    /// to the other atomic operations on the same key, e.g. `take`.
+    fn put_if_absent<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<bool, Error>;
+
+    /// delete a value from the database, returning it.
+    ///
+    /// This is atomic with respect to the other atomic operations on the
+    /// same key, e.g. `put_if_absent`.
+    fn take<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<Option<Vec<u8>>, Error>;
+}
+
+impl<K: Key> Database<K> {
+    /// The read options set with `Options::default_read_options`
+    pub fn default_read_options<'a>(&self) -> ReadOptions<'a, K> {
+        self.database.options.default_read_options.read_options()
+    }
+
+    /// The write options set with `Options::default_write_options`
+    pub fn default_write_options(&self) -> WriteOptions {
+        self.database.options.default_write_options
+    }
+
+    /// get a value from the database, with the default read options
+    pub fn get_default<BK: Borrow<K>>(&self, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        self.get(self.default_read_options(), key)
+    }
+
+    /// put a binary value into the database, with the default write
+    /// options
+    pub fn put_default<BK: Borrow<K>>(&self, key: BK, value: &[u8]) -> Result<(), Error> {
+        self.put(self.default_write_options(), key, value)
+    }
+
+    /// delete a value from the database, with the default write options
+    pub fn delete_default<BK: Borrow<K>>(&self, key: BK) -> Result<(), Error> {
+        self.delete(self.default_write_options(), key)
+    }
 }
 
 impl<K: Key> KV<K> for Database<K> {
@@ -53,11 +111,17 @@ impl<K: Key> KV<K> for Database<K> {
     ///
     /// The database will be synced to disc if `options.sync == true`. This is
     /// NOT the default.
+    ///
+    /// Fails with `InvalidArgument` for keys in the reserved namespace of
+    /// `meta`.
     fn put<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<(), Error> {
-        unsafe {
-            key.borrow().as_slice(|k| {
+        key.borrow().as_slice(|k| {
+            if is_reserved_or_end(k) {
+                return Err(self.with_context(reserved_key_error(), Operation::Put, Some(k)));
+            }
+            let write = || unsafe {
                 let mut error = ptr::null_mut();
-                let c_writeoptions = c_writeoptions(options);
+                let c_writeoptions = self.database.c_options.write(options);
                 leveldb_put(self.database.ptr,
                             c_writeoptions,
                             k.as_ptr() as *mut c_char,
@@ -65,15 +129,21 @@ impl<K: Key> KV<K> for Database<K> {
                             value.as_ptr() as *mut c_char,
                             value.len() as size_t,
                             &mut error);
-                leveldb_writeoptions_destroy(c_writeoptions);
 
-                if error == ptr::null_mut() {
+                if error.is_null() {
                     Ok(())
                 } else {
-                    Err(Error::new_from_i8(error))
+                    Err(self.with_context(Error::new_from_i8(error), Operation::Put, Some(k)))
                 }
-            })
-        }
+            };
+            let mut timer = Timer::start("put");
+            timer.key_length(k.len());
+            timer.value_length(value.len());
+            timer.finish(self.database.metrics.measure(OperationKind::Put, || {
+                let operations = || vec![(k.to_vec(), Some(value.to_vec()))];
+                self.database.hooks.run(|| self.database.watchers.notify(write, operations), operations)
+            }))
+        })
     }
 
     /// delete a value from the database.
@@ -82,50 +152,110 @@ impl<K: Key> KV<K> for Database<K> {
     ///
     /// The database will be synced to disc if `options.sync == true`. This is
     /// NOT the default.
+    ///
+    /// Fails with `InvalidArgument` for keys in the reserved namespace of
+    /// `meta`.
     fn delete<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<(), Error> {
-        unsafe {
-            key.borrow().as_slice(|k| {
+        key.borrow().as_slice(|k| {
+            if is_reserved_or_end(k) {
+                return Err(self.with_context(reserved_key_error(), Operation::Delete, Some(k)));
+            }
+            let write = || unsafe {
                 let mut error = ptr::null_mut();
-                let c_writeoptions = c_writeoptions(options);
+                let c_writeoptions = self.database.c_options.write(options);
                 leveldb_delete(self.database.ptr,
                                c_writeoptions,
                                k.as_ptr() as *mut c_char,
                                k.len() as size_t,
                                &mut error);
-                leveldb_writeoptions_destroy(c_writeoptions);
-                if error == ptr::null_mut() {
+                if error.is_null() {
                     Ok(())
                 } else {
-                    Err(Error::new_from_i8(error))
+                    Err(self.with_context(Error::new_from_i8(error), Operation::Delete, Some(k)))
                 }
-            })
+            };
+            let mut timer = Timer::start("delete");
+            timer.key_length(k.len());
+            timer.finish(self.database.metrics.measure(OperationKind::Delete, || {
+                let operations = || vec![(k.to_vec(), None)];
+                self.database.hooks.run(|| self.database.watchers.notify(write, operations), operations)
+            }))?;
+            self.record_delete(k);
+            Ok(())
+        })
+    }
+
+    fn put_if_absent<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<bool, Error> {
+        let key = key.borrow();
+        let _guard = key.as_slice(|k| self.database.locks.lock(k))?;
+        if self.get_bytes(ReadOptions::new(), key)?.is_some() {
+            return Ok(false);
         }
+        self.put(options, key, value).map(|_| true)
     }
 
-    fn get_bytes<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Bytes>, Error> {
-        unsafe {
-            key.borrow().as_slice(|k| {
-                let mut error = ptr::null_mut();
-                let mut length: size_t = 0;
-                let c_readoptions = c_readoptions(&options);
-                let result = leveldb_get(self.database.ptr,
-                                         c_readoptions,
-                                         k.as_ptr() as *mut c_char,
-                                         k.len() as size_t,
-                                         &mut length,
-                                         &mut error);
-                leveldb_readoptions_destroy(c_readoptions);
-
-                if error == ptr::null_mut() {
-                    Ok(Bytes::from_raw(result as *mut u8, length))
-                } else {
-                    Err(Error::new_from_i8(error))
-                }
-            })
+    fn take<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.borrow();
+        let _guard = key.as_slice(|k| self.database.locks.lock(k))?;
+        let value = self.get(ReadOptions::new(), key)?;
+        if value.is_some() {
+            self.delete(options, key)?;
         }
+        Ok(value)
+    }
+
+    fn get_bytes<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Bytes>, Error> {
+        let c_readoptions = self.database.c_options.read(&options);
+        unsafe { get_raw(self, c_readoptions.ptr, key.borrow()) }
     }
 
     fn get<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Vec<u8>>, Error> {
         self.get_bytes(options, key).map(|val| val.map(Into::into))
     }
+
+    fn multi_get<'a>(&self, options: ReadOptions<'a, K>, keys: &[K]) -> Vec<Result<Option<Vec<u8>>, Error>> {
+        let c_readoptions = self.database.c_options.read(&options);
+        keys.iter()
+            .map(|key| unsafe { get_raw(self, c_readoptions.ptr, key) }.map(|val| val.map(Into::into)))
+            .collect()
+    }
+}
+
+unsafe fn get_raw<K: Key>(database: &Database<K>,
+                          c_readoptions: *mut leveldb_readoptions_t,
+                          key: &K)
+                          -> Result<Option<Bytes>, Error> {
+    key.as_slice(|k| {
+        let mut timer = Timer::start("get");
+        timer.key_length(k.len());
+        let result = database.database.metrics.measure(OperationKind::Get, || {
+            get_raw_bytes(database.database.ptr, c_readoptions, k)
+                .map_err(|error| database.with_context(error, Operation::Get, Some(k)))
+        });
+        if let Ok(Some(ref value)) = result {
+            timer.value_length(value.len());
+        }
+        timer.finish(result)
+    })
+}
+
+/// Reads an encoded key from the database.
+pub(crate) unsafe fn get_raw_bytes(database: *mut leveldb_t,
+                                   c_readoptions: *mut leveldb_readoptions_t,
+                                   key: &[u8])
+                                   -> Result<Option<Bytes>, Error> {
+    let mut error = ptr::null_mut();
+    let mut length: size_t = 0;
+    let result = leveldb_get(database,
+                             c_readoptions,
+                             key.as_ptr() as *mut c_char,
+                             key.len() as size_t,
+                             &mut length,
+                             &mut error);
+
+    if error.is_null() {
+        Ok(Bytes::from_raw(result as *mut u8, length))
+    } else {
+        Err(Error::new_from_i8(error))
+    }
 }
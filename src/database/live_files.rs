@@ -0,0 +1,313 @@
+//! Listing the files of the current version
+//!
+//! A leveldb database consists of the `CURRENT` file naming the manifest,
+//! the manifest recording which tables make up each level, the tables
+//! themselves and the write-ahead logs not flushed into tables yet. Other
+//! files in the directory are obsolete or belong to the info log.
+//!
+//! `Database::live_files` parses the manifest, validates that the files it
+//! names exist, and keeps these tables from being deleted by compactions
+//! while the returned `LiveFiles` lives, so an external tool can copy them.
+//! Tables never change once written. The manifest and the logs grow while
+//! the database is written, so the manifest must only be copied up to
+//! `manifest_size`, as later records may name tables that aren't listed.
+//! The logs aren't kept, copy them right after listing the files. They can
+//! be copied as they are, leveldb ignores a record cut off at the end, so
+//! the copy holds the writes made until each log was copied. Copy `CURRENT`
+//! last.
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use leveldb_sys::leveldb_create_iterator;
+use database::key::Key;
+use options::ReadOptions;
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::checksum::masked_crc32c;
+use super::iterator::RawIterator;
+
+// the log format of the manifest
+const BLOCK_SIZE: usize = 32768;
+const HEADER_SIZE: usize = 7;
+const FULL: u8 = 1;
+const FIRST: u8 = 2;
+const MIDDLE: u8 = 3;
+const LAST: u8 = 4;
+
+// the tags of a version edit
+const COMPARATOR: u32 = 1;
+const LOG_NUMBER: u32 = 2;
+const NEXT_FILE_NUMBER: u32 = 3;
+const LAST_SEQUENCE: u32 = 4;
+const COMPACT_POINTER: u32 = 5;
+const DELETED_FILE: u32 = 6;
+const NEW_FILE: u32 = 7;
+const PREV_LOG_NUMBER: u32 = 9;
+
+// the number of times the manifest is read before giving up on finding it
+// unchanged around pinning its version
+const ATTEMPTS: usize = 10;
+
+fn io_error(error: ::std::io::Error) -> Error {
+    Error::with_kind(ErrorKind::IOError, format!("{}", error))
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::with_kind(ErrorKind::Corruption, what.to_string())
+}
+
+/// The files making up a database at one point in time.
+///
+/// The tables stay on disk while this lives.
+pub struct LiveFiles<K: Key> {
+    /// The `CURRENT` file, naming the manifest
+    pub current: PathBuf,
+    /// The manifest
+    pub manifest: PathBuf,
+    /// The size of the manifest the listing was read from, to copy no more
+    pub manifest_size: u64,
+    /// The tables, by file number
+    pub tables: Vec<PathBuf>,
+    /// The write-ahead logs, by file number
+    pub logs: Vec<PathBuf>,
+    // pins the version of the tables, dropped before the database
+    #[allow(dead_code)]
+    iter: RawIterator,
+    #[allow(dead_code)]
+    database: Database<K>,
+}
+
+impl<K: Key> LiveFiles<K> {
+    /// All files, in the order to copy them in, `CURRENT` last
+    pub fn paths(&self) -> Vec<&Path> {
+        let mut paths: Vec<&Path> = self.tables.iter().map(|path| path.as_path()).collect();
+        paths.push(&self.manifest);
+        paths.extend(self.logs.iter().map(|path| path.as_path()));
+        paths.push(&self.current);
+        paths
+    }
+}
+
+// the state recorded by a manifest
+#[derive(PartialEq, Eq)]
+pub(crate) struct Version {
+    pub(crate) manifest: String,
+    pub(crate) manifest_size: u64,
+    log_number: u64,
+    prev_log_number: u64,
+    // by level and file number
+    pub(crate) tables: BTreeSet<(u32, u64)>,
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0;
+        for (i, &byte) in self.data.iter().enumerate().take(10) {
+            value |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                self.data = &self.data[i + 1..];
+                return Ok(value);
+            }
+        }
+        Err(corrupt("malformed varint in manifest"))
+    }
+
+    fn varint32(&mut self) -> Result<u32, Error> {
+        let value = self.varint()?;
+        if value > u32::MAX as u64 {
+            return Err(corrupt("malformed varint in manifest"));
+        }
+        Ok(value as u32)
+    }
+
+    fn slice(&mut self) -> Result<&'a [u8], Error> {
+        let length = self.varint()? as usize;
+        if length > self.data.len() {
+            return Err(corrupt("truncated version edit"));
+        }
+        let (slice, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(slice)
+    }
+}
+
+// the records of a file in leveldb's log format, stopping at a record cut
+// off at the end, which is still being written
+fn records(data: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut records = vec![];
+    let mut record: Option<Vec<u8>> = None;
+    let mut offset = 0;
+    while offset + HEADER_SIZE <= data.len() {
+        let block_left = BLOCK_SIZE - offset % BLOCK_SIZE;
+        if block_left < HEADER_SIZE {
+            // the trailer of a block
+            offset += block_left;
+            continue;
+        }
+        let header = &data[offset..offset + HEADER_SIZE];
+        let length = u16::from_le_bytes([header[4], header[5]]) as usize;
+        let kind = header[6];
+        if kind == 0 && length == 0 {
+            // preallocated space
+            offset += block_left;
+            continue;
+        }
+        if HEADER_SIZE + length > block_left {
+            return Err(corrupt("bad record length in manifest"));
+        }
+        if offset + HEADER_SIZE + length > data.len() {
+            break;
+        }
+        let crc = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        if masked_crc32c(&data[offset + 6..offset + HEADER_SIZE + length]) != crc {
+            return Err(corrupt("manifest checksum mismatch"));
+        }
+        let payload = &data[offset + HEADER_SIZE..offset + HEADER_SIZE + length];
+        offset += HEADER_SIZE + length;
+        match (kind, record.as_mut()) {
+            (FULL, None) => records.push(payload.to_vec()),
+            (FIRST, None) => record = Some(payload.to_vec()),
+            (MIDDLE, Some(record)) => record.extend_from_slice(payload),
+            (LAST, Some(_)) => {
+                let mut last = record.take().unwrap_or_default();
+                last.extend_from_slice(payload);
+                records.push(last);
+            }
+            _ => return Err(corrupt("bad record type in manifest")),
+        }
+    }
+    Ok(records)
+}
+
+fn apply_edit(version: &mut Version, edit: &[u8]) -> Result<(), Error> {
+    let mut decoder = Decoder { data: edit };
+    while !decoder.data.is_empty() {
+        match decoder.varint32()? {
+            COMPARATOR => {
+                decoder.slice()?;
+            }
+            LOG_NUMBER => version.log_number = decoder.varint()?,
+            PREV_LOG_NUMBER => version.prev_log_number = decoder.varint()?,
+            NEXT_FILE_NUMBER | LAST_SEQUENCE => {
+                decoder.varint()?;
+            }
+            COMPACT_POINTER => {
+                decoder.varint32()?;
+                decoder.slice()?;
+            }
+            DELETED_FILE => {
+                let level = decoder.varint32()?;
+                let number = decoder.varint()?;
+                version.tables.remove(&(level, number));
+            }
+            NEW_FILE => {
+                let level = decoder.varint32()?;
+                let number = decoder.varint()?;
+                // the size and the smallest and largest key
+                decoder.varint()?;
+                decoder.slice()?;
+                decoder.slice()?;
+                version.tables.insert((level, number));
+            }
+            _ => return Err(corrupt("unknown tag in version edit")),
+        }
+    }
+    Ok(())
+}
+
+// read the version `CURRENT` points at
+pub(crate) fn read_version(directory: &Path) -> Result<Version, Error> {
+    let current = fs::read_to_string(directory.join("CURRENT")).map_err(io_error)?;
+    let manifest = match current.strip_suffix('\n') {
+        Some(name) if name.starts_with("MANIFEST-") && !name.contains('/') => name.to_string(),
+        _ => return Err(corrupt("CURRENT doesn't name a manifest")),
+    };
+    let data = fs::read(directory.join(&manifest)).map_err(io_error)?;
+    let mut version = Version {
+        manifest,
+        manifest_size: data.len() as u64,
+        log_number: 0,
+        prev_log_number: 0,
+        tables: BTreeSet::new(),
+    };
+    for edit in records(&data)? {
+        apply_edit(&mut version, &edit)?;
+    }
+    Ok(version)
+}
+
+// the path of table `number`, written as `.ldb`, or `.sst` by old versions
+pub(crate) fn table_path(directory: &Path, number: u64) -> Result<PathBuf, Error> {
+    for extension in &["ldb", "sst"] {
+        let path = directory.join(format!("{:06}.{}", number, extension));
+        if path.is_file() {
+            return Ok(path);
+        }
+    }
+    Err(corrupt(&format!("table {} listed in the manifest is missing", number)))
+}
+
+// the logs not flushed into tables yet
+pub(crate) fn log_paths(directory: &Path, version: &Version) -> Result<Vec<PathBuf>, Error> {
+    let mut logs = vec![];
+    for entry in fs::read_dir(directory).map_err(io_error)? {
+        let name = entry.map_err(io_error)?.file_name();
+        let number = name.to_str()
+            .and_then(|name| name.strip_suffix(".log"))
+            .and_then(|number| number.parse::<u64>().ok());
+        if let Some(number) = number {
+            if number >= version.log_number || (number == version.prev_log_number && number != 0) {
+                logs.push((number, directory.join(name)));
+            }
+        }
+    }
+    logs.sort();
+    Ok(logs.into_iter().map(|(_, path)| path).collect())
+}
+
+impl<K: Key> Database<K> {
+    /// List the files making up the database now
+    ///
+    /// The tables listed aren't deleted while the returned `LiveFiles`
+    /// lives. Fails with `Corruption` if the manifest is damaged or names
+    /// a table that doesn't exist.
+    pub fn live_files(&self) -> Result<LiveFiles<K>, Error> {
+        let directory = self.path();
+        for _ in 0..ATTEMPTS {
+            let before = read_version(directory)?;
+            // an iterator keeps the tables of the version it reads from
+            let iter = unsafe {
+                let c_readoptions = self.database.c_options.read(&ReadOptions::<K>::new());
+                RawIterator { ptr: leveldb_create_iterator(self.database.ptr, c_readoptions.ptr) }
+            };
+            let version = read_version(directory)?;
+            if version.tables != before.tables || version.manifest != before.manifest {
+                // a compaction finished meanwhile, the iterator may hold
+                // either version
+                continue;
+            }
+            let tables = version.tables
+                .iter()
+                .map(|&(_, number)| number)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .map(|number| table_path(directory, number))
+                .collect::<Result<Vec<_>, Error>>()?;
+            return Ok(LiveFiles {
+                current: directory.join("CURRENT"),
+                manifest: directory.join(&version.manifest),
+                manifest_size: version.manifest_size,
+                tables,
+                logs: log_paths(directory, &version)?,
+                iter,
+                database: self.clone(),
+            });
+        }
+        Err(Error::with_kind(ErrorKind::Conflict, "the manifest kept changing while listing the live files".to_string()))
+    }
+}
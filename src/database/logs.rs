@@ -0,0 +1,454 @@
+//! Reading leveldb's info log
+//!
+//! leveldb writes what it does in the background to the file `LOG` in the
+//! database directory, and moves the log of the previous session to
+//! `LOG.old` when opening. Flushes of the memtable, compactions and the
+//! recovery on open are only reported there, with the time of each step.
+//!
+//! `read_logs` parses both files into `LogEntry`s, `parse_line` parses a
+//! single line, and a `LogTail` returns the lines appended since it last
+//! looked, for following a live database. `compactions` pairs the start
+//! and end of each compaction to tell how long it took.
+//!
+//! Lines start with the local time and the id of the writing thread. The
+//! time is kept as written, durations are computed ignoring the time zone,
+//! so they are off when a compaction spans a change of daylight saving
+//! time.
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::error::{Error, ErrorKind};
+
+/// The time of a line in the log, in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LogTime {
+    /// The year
+    pub year: i32,
+    /// The month, from 1
+    pub month: u32,
+    /// The day of the month, from 1
+    pub day: u32,
+    /// The hour
+    pub hour: u32,
+    /// The minute
+    pub minute: u32,
+    /// The second
+    pub second: u32,
+    /// The microseconds within the second
+    pub micros: u32,
+}
+
+impl LogTime {
+    // parses "2026/10/14-12:20:36.948864"
+    fn parse(text: &str) -> Option<LogTime> {
+        let (date, time) = text.split_once('-')?;
+        let mut date = date.splitn(3, '/');
+        let year = date.next()?.parse().ok()?;
+        let month = date.next()?.parse().ok()?;
+        let day = date.next()?.parse().ok()?;
+        let (time, micros) = time.split_once('.')?;
+        let mut time = time.splitn(3, ':');
+        let hour = time.next()?.parse().ok()?;
+        let minute = time.next()?.parse().ok()?;
+        let second = time.next()?.parse().ok()?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) || micros.len() != 6 {
+            return None;
+        }
+        Some(LogTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            micros: micros.parse().ok()?,
+        })
+    }
+
+    // microseconds since 1970-01-01 in the same time zone
+    fn to_micros(self) -> i64 {
+        // days from civil, after Howard Hinnant
+        let year = if self.month <= 2 { self.year - 1 } else { self.year } as i64;
+        let era = if year >= 0 { year } else { year - 399 } / 400;
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+        let seconds = days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        seconds * 1_000_000 + self.micros as i64
+    }
+
+    /// The time passed since `earlier`, `None` if `earlier` is later
+    pub fn duration_since(&self, earlier: &LogTime) -> Option<Duration> {
+        let micros = self.to_micros() - earlier.to_micros();
+        if micros < 0 {
+            None
+        } else {
+            Some(Duration::from_micros(micros as u64))
+        }
+    }
+}
+
+/// Why leveldb made a write wait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallReason {
+    /// The memtable is full and the previous one is still being flushed
+    MemtableFull,
+    /// Level 0 holds too many files
+    Level0Files,
+}
+
+/// What a line of the log reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogEvent {
+    /// Replaying the write-ahead log `log` on open
+    RecoveringLog {
+        /// The number of the log file
+        log: u64,
+    },
+    /// Writing the memtable to the level-0 table `table` started
+    FlushStarted {
+        /// The number of the table file
+        table: u64,
+    },
+    /// Writing the memtable to the level-0 table `table` finished
+    FlushFinished {
+        /// The number of the table file
+        table: u64,
+        /// The size of the table
+        bytes: u64,
+        /// `"OK"`, or the error the flush failed with
+        status: String,
+    },
+    /// A compaction of `inputs` files at `level` with `next_inputs` files
+    /// at the level below started
+    CompactionStarted {
+        /// The level compacted
+        level: usize,
+        /// The number of files compacted at `level`
+        inputs: usize,
+        /// The number of files compacted at `level + 1`
+        next_inputs: usize,
+    },
+    /// A compaction wrote the table `table`
+    TableGenerated {
+        /// The number of the table file
+        table: u64,
+        /// The level compacted
+        level: usize,
+        /// The number of entries in the table
+        keys: u64,
+        /// The size of the table
+        bytes: u64,
+    },
+    /// A compaction finished, writing `bytes` to the level below `level`
+    CompactionFinished {
+        /// The level compacted
+        level: usize,
+        /// The number of files compacted at `level`
+        inputs: usize,
+        /// The number of files compacted at `level + 1`
+        next_inputs: usize,
+        /// The total size of the tables written
+        bytes: u64,
+    },
+    /// The number of files at each level after a compaction
+    LevelSummary {
+        /// The file counts, by level
+        files: Vec<usize>,
+    },
+    /// A table was moved to `level` without rewriting it
+    TableMoved {
+        /// The number of the table file
+        table: u64,
+        /// The level it was moved to
+        level: usize,
+        /// The size of the table
+        bytes: u64,
+    },
+    /// A manual compaction of `level` ran, e.g. for `compact_range`
+    ManualCompaction {
+        /// The level compacted
+        level: usize,
+    },
+    /// A background compaction failed
+    CompactionError {
+        /// The error it failed with
+        message: String,
+    },
+    /// A write waited for a background flush or compaction
+    WriteStall {
+        /// What it waited for
+        reason: StallReason,
+    },
+    /// An obsolete file was deleted
+    FileDeleted {
+        /// leveldb's type of the file, 0 for logs and 2 for tables
+        kind: u32,
+        /// The number of the file
+        number: u64,
+    },
+    /// Any other line, with the time and thread stripped
+    Other(String),
+}
+
+/// A line of the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// When the line was written
+    pub time: LogTime,
+    /// The id of the thread that wrote it
+    pub thread: u64,
+    /// What happened
+    pub event: LogEvent,
+}
+
+// parses "4@0", a number of files at a level
+fn files_at(text: &str) -> Option<(usize, usize)> {
+    let (count, level) = text.split_once('@')?;
+    Some((count.parse().ok()?, level.parse().ok()?))
+}
+
+// parses "4@0 + 1@1 files"
+fn compaction_inputs(text: &str) -> Option<(usize, usize, usize)> {
+    let inputs = text.strip_suffix(" files")?;
+    let (first, second) = inputs.split_once(" + ")?;
+    let (inputs, level) = files_at(first)?;
+    let (next_inputs, _) = files_at(second)?;
+    Some((level, inputs, next_inputs))
+}
+
+fn parse_event(message: &str) -> Option<LogEvent> {
+    if let Some(rest) = message.strip_prefix("Level-0 table #") {
+        let (table, rest) = rest.split_once(": ")?;
+        let table = table.parse().ok()?;
+        if rest == "started" {
+            return Some(LogEvent::FlushStarted { table });
+        }
+        let (bytes, status) = rest.split_once(" bytes ")?;
+        return Some(LogEvent::FlushFinished {
+            table,
+            bytes: bytes.parse().ok()?,
+            status: status.to_string(),
+        });
+    }
+    if let Some(rest) = message.strip_prefix("Compacting ") {
+        let (level, inputs, next_inputs) = compaction_inputs(rest)?;
+        return Some(LogEvent::CompactionStarted { level, inputs, next_inputs });
+    }
+    if let Some(rest) = message.strip_prefix("Compacted ") {
+        let (inputs, bytes) = rest.split_once(" => ")?;
+        let (level, inputs, next_inputs) = compaction_inputs(inputs)?;
+        return Some(LogEvent::CompactionFinished {
+            level,
+            inputs,
+            next_inputs,
+            bytes: bytes.strip_suffix(" bytes")?.parse().ok()?,
+        });
+    }
+    if let Some(rest) = message.strip_prefix("Generated table #") {
+        let (table, rest) = rest.split_once(": ")?;
+        let (table, level) = table.split_once('@')?;
+        let (keys, bytes) = rest.split_once(" keys, ")?;
+        return Some(LogEvent::TableGenerated {
+            table: table.parse().ok()?,
+            level: level.parse().ok()?,
+            keys: keys.parse().ok()?,
+            bytes: bytes.strip_suffix(" bytes")?.parse().ok()?,
+        });
+    }
+    if let Some(rest) = message.strip_prefix("compacted to: files[") {
+        let files = rest.strip_suffix(']')?
+            .split_whitespace()
+            .map(|count| count.parse().ok())
+            .collect::<Option<Vec<usize>>>()?;
+        return Some(LogEvent::LevelSummary { files });
+    }
+    if let Some(rest) = message.strip_prefix("Moved #") {
+        let (table, rest) = rest.split_once(" to level-")?;
+        let mut fields = rest.splitn(3, ' ');
+        let level = fields.next()?.parse().ok()?;
+        let bytes = fields.next()?.parse().ok()?;
+        return Some(LogEvent::TableMoved {
+            table: table.parse().ok()?,
+            level,
+            bytes,
+        });
+    }
+    if let Some(rest) = message.strip_prefix("Manual compaction at level-") {
+        let (level, _) = rest.split_once(' ')?;
+        return Some(LogEvent::ManualCompaction { level: level.parse().ok()? });
+    }
+    if let Some(rest) = message.strip_prefix("Recovering log #") {
+        return Some(LogEvent::RecoveringLog { log: rest.parse().ok()? });
+    }
+    if let Some(rest) = message.strip_prefix("Delete type=") {
+        let (kind, number) = rest.split_once(" #")?;
+        return Some(LogEvent::FileDeleted {
+            kind: kind.parse().ok()?,
+            number: number.parse().ok()?,
+        });
+    }
+    let error = message.strip_prefix("Compaction error: ")
+        .or_else(|| message.strip_prefix("Waiting after background compaction error: "));
+    if let Some(error) = error {
+        return Some(LogEvent::CompactionError { message: error.to_string() });
+    }
+    match message {
+        "Current memtable full; waiting..." => Some(LogEvent::WriteStall { reason: StallReason::MemtableFull }),
+        "Too many L0 files; waiting..." => Some(LogEvent::WriteStall { reason: StallReason::Level0Files }),
+        _ => None,
+    }
+}
+
+/// Parse a line of the log, `None` if it doesn't start with a time and a
+/// thread id
+///
+/// Messages that aren't understood are returned as `LogEvent::Other`.
+pub fn parse_line(line: &str) -> Option<LogEntry> {
+    let mut fields = line.trim_end().splitn(3, ' ');
+    let time = LogTime::parse(fields.next()?)?;
+    let thread = fields.next()?.parse().ok()?;
+    let message = fields.next().unwrap_or("");
+    let event = parse_event(message).unwrap_or_else(|| LogEvent::Other(message.to_string()));
+    Some(LogEntry { time, thread, event })
+}
+
+fn io_error(error: io::Error) -> Error {
+    Error::with_kind(ErrorKind::IOError, format!("{}", error))
+}
+
+fn parse_lines(text: &str) -> impl Iterator<Item = LogEntry> + '_ {
+    text.lines().filter_map(parse_line)
+}
+
+/// Read the log of the database in `directory`, `LOG.old` first
+///
+/// Lines that can't be parsed are skipped, missing files are treated as
+/// empty.
+pub fn read_logs(directory: &Path) -> Result<Vec<LogEntry>, Error> {
+    let mut entries = vec![];
+    for name in &["LOG.old", "LOG"] {
+        let mut text = String::new();
+        match File::open(directory.join(name)) {
+            Ok(mut file) => file.read_to_string(&mut text).map_err(io_error)?,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(io_error(error)),
+        };
+        entries.extend(parse_lines(&text));
+    }
+    Ok(entries)
+}
+
+// the number of bytes at the start of a log compared to detect new logs,
+// enough to cover the time of the first line
+const HEAD_BYTES: usize = 64;
+
+/// Follows the `LOG` of a database as leveldb writes it.
+pub struct LogTail {
+    path: PathBuf,
+    offset: u64,
+    // the start of the file read, telling a new log from the old one
+    head: Vec<u8>,
+    // the start of a line that isn't complete yet
+    partial: String,
+}
+
+impl LogTail {
+    /// Follow the log of the database in `directory`, from its start
+    pub fn new(directory: &Path) -> LogTail {
+        LogTail {
+            path: directory.join("LOG"),
+            offset: 0,
+            head: vec![],
+            partial: String::new(),
+        }
+    }
+
+    /// Return the entries of the lines completed since the last call
+    ///
+    /// When the database is reopened, leveldb starts a new `LOG`, which the
+    /// tail then reads from its start, telling it from the old one by the
+    /// first line. Lines written to the old one after the last call are
+    /// lost.
+    pub fn poll(&mut self) -> Result<Vec<LogEntry>, Error> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(io_error(error)),
+        };
+        let length = file.metadata().map_err(io_error)?.len();
+        let mut head = vec![];
+        (&mut file).take(self.head.len() as u64).read_to_end(&mut head).map_err(io_error)?;
+        if length < self.offset || head != self.head {
+            self.offset = 0;
+            self.head.clear();
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset)).map_err(io_error)?;
+        let mut bytes = vec![];
+        file.take(length - self.offset).read_to_end(&mut bytes).map_err(io_error)?;
+        self.offset += bytes.len() as u64;
+        if self.head.len() < HEAD_BYTES {
+            let missing = (HEAD_BYTES - self.head.len()).min(bytes.len());
+            self.head.extend_from_slice(&bytes[..missing]);
+        }
+        self.partial.push_str(&String::from_utf8_lossy(&bytes));
+
+        let complete = match self.partial.rfind('\n') {
+            Some(end) => end + 1,
+            None => return Ok(vec![]),
+        };
+        let entries = parse_lines(&self.partial[..complete]).collect();
+        self.partial.drain(..complete);
+        Ok(entries)
+    }
+}
+
+/// A compaction found in the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionTiming {
+    /// When it started
+    pub started: LogTime,
+    /// How long it took
+    pub duration: Duration,
+    /// The level compacted
+    pub level: usize,
+    /// The number of files compacted at `level` and the level below
+    pub inputs: usize,
+    /// The total size of the tables written
+    pub bytes: u64,
+}
+
+/// Pair the starts and ends of the compactions in `entries`
+///
+/// Compactions without both lines, e.g. one still running, are left out.
+pub fn compactions(entries: &[LogEntry]) -> Vec<CompactionTiming> {
+    let mut running: Vec<(u64, LogTime)> = vec![];
+    let mut timings = vec![];
+    for entry in entries {
+        match entry.event {
+            LogEvent::CompactionStarted { .. } => {
+                running.retain(|&(thread, _)| thread != entry.thread);
+                running.push((entry.thread, entry.time));
+            }
+            LogEvent::CompactionFinished { level, inputs, next_inputs, bytes } => {
+                if let Some(i) = running.iter().position(|&(thread, _)| thread == entry.thread) {
+                    let (_, started) = running.remove(i);
+                    timings.push(CompactionTiming {
+                        started,
+                        duration: entry.time.duration_since(&started).unwrap_or_default(),
+                        level,
+                        inputs: inputs + next_inputs,
+                        bytes,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    timings
+}
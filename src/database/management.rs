@@ -5,9 +5,12 @@ use std::ffi::CString;
 use std::ptr;
 use std::path::Path;
 
-use leveldb_sys::{leveldb_destroy_db, leveldb_repair_db};
+use leveldb_sys::{leveldb_destroy_db, leveldb_repair_db, leveldb_options_destroy};
 
 /// destroy a database. You shouldn't hold a handle on the database anywhere at that time.
+///
+/// Returns an error if the database is still opened by this process, as
+/// the lock file can not be acquired.
 pub fn destroy(name: &Path, options: Options) -> Result<(), Error> {
     let mut error = ptr::null_mut();
     unsafe {
@@ -16,8 +19,9 @@ pub fn destroy(name: &Path, options: Options) -> Result<(), Error> {
         leveldb_destroy_db(c_options,
                            c_string.as_bytes_with_nul().as_ptr() as *const i8,
                            &mut error);
+        leveldb_options_destroy(c_options);
 
-        if error == ptr::null_mut() {
+        if error.is_null() {
             Ok(())
         } else {
             Err(Error::new_from_i8(error))
@@ -26,6 +30,9 @@ pub fn destroy(name: &Path, options: Options) -> Result<(), Error> {
 }
 
 /// repair the database. The database should be closed at this moment.
+///
+/// Repair tries to recover as much data as possible, e.g. after the
+/// manifest got lost. Some data may be lost in the process.
 pub fn repair(name: &Path, options: Options) -> Result<(), Error> {
     let mut error = ptr::null_mut();
     unsafe {
@@ -34,8 +41,9 @@ pub fn repair(name: &Path, options: Options) -> Result<(), Error> {
         leveldb_repair_db(c_options,
                           c_string.as_bytes_with_nul().as_ptr() as *const i8,
                           &mut error);
+        leveldb_options_destroy(c_options);
 
-        if error == ptr::null_mut() {
+        if error.is_null() {
             Ok(())
         } else {
             Err(Error::new_from_i8(error))
@@ -0,0 +1,118 @@
+//! Opening many databases
+//!
+//! leveldb allows only one handle per database and process, opening it a
+//! second time fails on its lock. A `DatabaseManager` opens databases by
+//! name under a root directory and hands out clones of the one handle of
+//! each, so parts of an application can open stores without coordinating.
+//! All databases are opened with options from the same template, and by
+//! default share one block cache, so they don't each need a budget.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use database::key::Key;
+use options::Options;
+use super::Database;
+use super::cache::Cache;
+use super::error::{Error, ErrorKind};
+
+type Template = Box<dyn Fn(&str) -> Options + Send + Sync>;
+
+/// Opens databases by name, keeping one handle each.
+pub struct DatabaseManager<K: Key> {
+    root: PathBuf,
+    cache: Option<Cache>,
+    template: Template,
+    databases: Mutex<HashMap<String, Database<K>>>,
+}
+
+impl<K: Key> DatabaseManager<K> {
+    /// Manage the databases in `root`, creating them if missing
+    pub fn new<P: AsRef<Path>>(root: P) -> DatabaseManager<K> {
+        DatabaseManager {
+            root: root.as_ref().to_path_buf(),
+            cache: None,
+            template: Box::new(|_| {
+                let mut options = Options::new();
+                options.create_if_missing = true;
+                options
+            }),
+            databases: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Share `cache` among the databases whose options don't set one
+    pub fn cache(mut self, cache: Cache) -> DatabaseManager<K> {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Open databases with the options returned by `template` for their
+    /// name
+    pub fn options<F>(mut self, template: F) -> DatabaseManager<K>
+        where F: Fn(&str) -> Options + Send + Sync + 'static
+    {
+        self.template = Box::new(template);
+        self
+    }
+
+    /// The directory holding the databases
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Database<K>>> {
+        self.databases.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Return the handle of the database `name`, opening it if it isn't
+    /// open yet
+    ///
+    /// Names are directory names below the root, they can't contain path
+    /// separators.
+    pub fn open(&self, name: &str) -> Result<Database<K>, Error> {
+        let mut databases = self.lock();
+        if let Some(database) = databases.get(name) {
+            return Ok(database.clone());
+        }
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, format!("invalid database name {:?}", name)));
+        }
+        let mut options = (self.template)(name);
+        if options.cache.is_none() {
+            options.cache = self.cache.clone();
+        }
+        if options.create_if_missing {
+            fs::create_dir_all(&self.root).map_err(|e| Error::with_kind(ErrorKind::IOError, format!("{}", e)))?;
+        }
+        let database = Database::open(&self.root.join(name), options)?;
+        databases.insert(name.to_string(), database.clone());
+        Ok(database)
+    }
+
+    /// Return the handle of the database `name`, if it is open
+    pub fn get(&self, name: &str) -> Option<Database<K>> {
+        self.lock().get(name).cloned()
+    }
+
+    /// The names of the open databases, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.lock().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Release the handle of the database `name`, returning whether it was
+    /// open
+    ///
+    /// The database closes once the clones handed out are dropped as well.
+    pub fn close(&self, name: &str) -> bool {
+        self.lock().remove(name).is_some()
+    }
+
+    /// Release the handles of all databases
+    pub fn close_all(&self) {
+        self.lock().clear();
+    }
+}
@@ -0,0 +1,99 @@
+//! Merging iterators
+//!
+//! `merge` combines the iterators of several databases or snapshots into
+//! one iterator returning their entries in key order, for sharded or tiered
+//! setups. All sources must order keys the same way, they are compared by
+//! the comparator of the first one. A `TieBreak` decides what happens to a
+//! key present in several sources.
+use std::cmp::Ordering;
+
+use database::key::Key;
+use super::Database;
+use super::iterator::Iterator;
+
+/// What to return for a key present in several sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreak {
+    /// The entry of the first of those sources, e.g. the newest tier
+    First,
+    /// The entry of the last of those sources
+    Last,
+    /// The entries of all of them, in the order of the sources
+    All,
+}
+
+// the next entry of a source, along with its encoded key
+pub(crate) type Head<K> = Option<(Vec<u8>, K, Vec<u8>)>;
+
+pub(crate) fn next_head<K: Key>(source: &mut Iterator<K>) -> Head<K> {
+    source.next().map(|(key, value)| (key.as_slice(|k| k.to_vec()), key, value))
+}
+
+/// Merge `sources` in key order
+///
+/// The bounds and direction of each source are kept, they should all be
+/// forward iterators.
+pub fn merge<'a, K: Key + 'a>(mut sources: Vec<Iterator<'a, K>>, tie_break: TieBreak) -> MergedIterator<'a, K> {
+    let heads = sources.iter_mut().map(next_head).collect();
+    MergedIterator {
+        database: sources.first().map(|source| source.database()),
+        sources,
+        heads,
+        tie_break,
+    }
+}
+
+/// An iterator over the entries of several sources, merged in key order.
+///
+/// Returns key and value as a tuple.
+pub struct MergedIterator<'a, K: Key + 'a> {
+    // used to compare keys, `None` without sources
+    database: Option<&'a Database<K>>,
+    sources: Vec<Iterator<'a, K>>,
+    heads: Vec<Head<K>>,
+    tie_break: TieBreak,
+}
+
+impl<'a, K: Key + 'a> ::std::iter::Iterator for MergedIterator<'a, K> {
+    type Item = (K, Vec<u8>);
+
+    fn next(&mut self) -> Option<(K, Vec<u8>)> {
+        let database = self.database?;
+        // the source with the smallest key, the last one of equals for `Last`
+        let mut smallest: Option<usize> = None;
+        for (index, head) in self.heads.iter().enumerate() {
+            if let Some((ref key, _, _)) = *head {
+                let replace = match smallest {
+                    Some(current) => {
+                        let current = &self.heads[current].as_ref().unwrap().0;
+                        match database.compare_keys(key, current) {
+                            Ordering::Less => true,
+                            Ordering::Equal => self.tie_break == TieBreak::Last,
+                            Ordering::Greater => false,
+                        }
+                    }
+                    None => true,
+                };
+                if replace {
+                    smallest = Some(index);
+                }
+            }
+        }
+        let index = smallest?;
+        let head = next_head(&mut self.sources[index]);
+        let (encoded, key, value) = ::std::mem::replace(&mut self.heads[index], head).unwrap();
+        if self.tie_break != TieBreak::All {
+            // drop the entries the other sources have for the key
+            for (source, head) in self.sources.iter_mut().zip(self.heads.iter_mut()) {
+                let equal = match *head {
+                    Some((ref other, _, _)) => database.compare_keys(other, &encoded) == Ordering::Equal,
+                    None => false,
+                };
+                if equal {
+                    *head = next_head(source);
+                }
+            }
+        }
+        Some((key, value))
+    }
+}
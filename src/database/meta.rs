@@ -0,0 +1,166 @@
+//! Metadata stored next to the data
+//!
+//! `Database::meta` gives access to a small store of named values kept in
+//! the database itself, for metadata managed by this crate or by the
+//! application: schema versions, replication cursors, backup markers.
+//!
+//! Metadata lives under the reserved prefix `0xff 0xff leveldb.`. Writes
+//! of application keys starting with it fail with
+//! `ErrorKind::InvalidArgument`, and the iterators of the database skip
+//! it, as do watchers. Custom comparators never see reserved keys, they
+//! sort after all application keys. Names are escaped and terminated like
+//! the strings of a `CompositeKey`, so any name maps to a key of its own
+//! inside the namespace.
+use leveldb_sys::{leveldb_create_iterator, leveldb_iter_next};
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::get_raw_bytes;
+use super::batch::{Batch, Writebatch};
+use super::keys::CompositeKey;
+use super::iterator::{RawIterator, raw_key, raw_seek_bytes, raw_valid, raw_status};
+
+/// The prefix reserved for keys managed by this crate.
+pub const RESERVED_PREFIX: &[u8] = b"\xff\xffleveldb.";
+
+// the namespace of `Meta` within the reserved prefix
+const META_PREFIX: &[u8] = b"\xff\xffleveldb.meta/";
+
+// the first key past the reserved namespace, bytewise
+pub(crate) const RESERVED_END: &[u8] = b"\xff\xffleveldb/";
+
+/// Whether `key` lies in the reserved namespace
+pub(crate) fn is_reserved(key: &[u8]) -> bool {
+    key.starts_with(RESERVED_PREFIX)
+}
+
+/// Whether `key` lies in the reserved namespace or is the key right past
+/// it, which iterators seek to for skipping the namespace. Application
+/// keys must not be either.
+pub(crate) fn is_reserved_or_end(key: &[u8]) -> bool {
+    is_reserved(key) || key == RESERVED_END
+}
+
+/// The error for writing an application key in the reserved namespace
+pub(crate) fn reserved_key_error() -> Error {
+    Error::with_kind(ErrorKind::InvalidArgument, "key starts with the reserved prefix".to_string())
+}
+
+/// The key storing the metadata `name`
+pub(crate) fn meta_key(name: &str) -> Vec<u8> {
+    let mut key = META_PREFIX.to_vec();
+    key.extend_from_slice(CompositeKey::new().push_str(name).as_bytes());
+    key
+}
+
+fn malformed(name: &str, kind: &str) -> Error {
+    Error::with_kind(ErrorKind::Corruption, format!("metadata {} is not a valid {}", name, kind))
+}
+
+/// The metadata of a database.
+pub struct Meta<K: Key> {
+    database: Database<K>,
+}
+
+impl<K: Key> Clone for Meta<K> {
+    fn clone(&self) -> Meta<K> {
+        Meta { database: self.database.clone() }
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Access the metadata stored in the database
+    pub fn meta(&self) -> Meta<K> {
+        Meta { database: self.clone() }
+    }
+}
+
+impl<K: Key> Meta<K> {
+    /// get the metadata `name`
+    pub fn get(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let c_readoptions = self.database.database.c_options.read(&ReadOptions::<K>::new());
+        let value = unsafe { get_raw_bytes(self.database.database.ptr, c_readoptions.ptr, &meta_key(name)) };
+        value.map(|value| value.map(Into::into))
+    }
+
+    /// put the metadata `name`
+    pub fn put(&self, options: WriteOptions, name: &str, value: &[u8]) -> Result<(), Error> {
+        let mut batch = Writebatch::new();
+        self.put_in(&mut batch, name, value);
+        self.database.write(options, &batch)
+    }
+
+    /// delete the metadata `name`
+    pub fn delete(&self, options: WriteOptions, name: &str) -> Result<(), Error> {
+        let mut batch = Writebatch::new();
+        self.delete_in(&mut batch, name);
+        self.database.write(options, &batch)
+    }
+
+    /// Add a put of the metadata `name` to `batch`, to write it together
+    /// with the data it describes
+    pub fn put_in(&self, batch: &mut Writebatch<K>, name: &str, value: &[u8]) {
+        batch.put_raw(&meta_key(name), value)
+    }
+
+    /// Add a delete of the metadata `name` to `batch`
+    pub fn delete_in(&self, batch: &mut Writebatch<K>, name: &str) {
+        batch.delete_raw(&meta_key(name))
+    }
+
+    /// get the metadata `name`, stored as a big-endian `u64`
+    pub fn get_u64(&self, name: &str) -> Result<Option<u64>, Error> {
+        match self.get(name)? {
+            Some(value) => {
+                if value.len() != 8 {
+                    return Err(malformed(name, "u64"));
+                }
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&value);
+                Ok(Some(u64::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// put the metadata `name` as a big-endian `u64`
+    pub fn put_u64(&self, options: WriteOptions, name: &str, value: u64) -> Result<(), Error> {
+        self.put(options, name, &value.to_be_bytes())
+    }
+
+    /// get the metadata `name`, stored as UTF-8
+    pub fn get_string(&self, name: &str) -> Result<Option<String>, Error> {
+        match self.get(name)? {
+            Some(value) => String::from_utf8(value).map(Some).map_err(|_| malformed(name, "string")),
+            None => Ok(None),
+        }
+    }
+
+    /// put the metadata `name` as UTF-8
+    pub fn put_string(&self, options: WriteOptions, name: &str, value: &str) -> Result<(), Error> {
+        self.put(options, name, value.as_bytes())
+    }
+
+    /// The names of all metadata, sorted by their encoding
+    pub fn names(&self) -> Result<Vec<String>, Error> {
+        let iter = unsafe {
+            let c_readoptions = self.database.database.c_options.read(&ReadOptions::<K>::new());
+            let ptr = leveldb_create_iterator(self.database.database.ptr, c_readoptions.ptr);
+            RawIterator { ptr }
+        };
+        let mut names = vec![];
+        raw_seek_bytes(iter.ptr, META_PREFIX);
+        while raw_valid(iter.ptr) {
+            let key = unsafe { raw_key(iter.ptr) };
+            if !key.starts_with(META_PREFIX) {
+                break;
+            }
+            let name = CompositeKey::from_u8(&key[META_PREFIX.len()..]).reader().read_str();
+            names.push(name.ok_or_else(|| Error::with_kind(ErrorKind::Corruption, "malformed metadata name".to_string()))?);
+            unsafe { leveldb_iter_next(iter.ptr) };
+        }
+        raw_status(iter.ptr)?;
+        Ok(names)
+    }
+}
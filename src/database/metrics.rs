@@ -0,0 +1,331 @@
+//! Operation metrics
+//!
+//! With the `metrics` feature, every database keeps counters and latency
+//! histograms of its gets, puts, deletes and batch writes, shared by all
+//! clones. `Database::metrics` takes a snapshot of them, along with the
+//! per-level figures parsed from the `leveldb.stats` property. With the
+//! `prometheus` feature, `Metrics::encode_prometheus` renders a snapshot
+//! in the Prometheus text format.
+//!
+//! Without the feature, recording compiles down to nothing.
+use super::error::Error;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+#[cfg(feature = "metrics")]
+use database::key::Key;
+#[cfg(feature = "metrics")]
+use super::Database;
+#[cfg(feature = "metrics")]
+use super::properties::Properties;
+
+/// The operations metrics are kept for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OperationKind {
+    Get,
+    Put,
+    Delete,
+    Write,
+}
+
+#[cfg(feature = "metrics")]
+const OPERATIONS: usize = 4;
+
+/// Upper bounds of the latency histogram buckets, in microseconds.
+#[cfg(feature = "metrics")]
+pub const BUCKET_BOUNDS: [u64; 12] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 100000];
+
+#[cfg(feature = "metrics")]
+struct AtomicHistogram {
+    // one more bucket than there are bounds, for slower operations
+    buckets: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+}
+
+#[cfg(feature = "metrics")]
+impl AtomicHistogram {
+    fn new() -> AtomicHistogram {
+        AtomicHistogram {
+            buckets: (0..=BUCKET_BOUNDS.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, micros: u64) {
+        let bucket = BUCKET_BOUNDS.iter().position(|&bound| micros <= bound).unwrap_or(BUCKET_BOUNDS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Histogram {
+        let buckets: Vec<u64> = self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).collect();
+        Histogram {
+            count: buckets.iter().sum(),
+            buckets,
+            sum_micros: self.sum_micros.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+struct OperationRecorder {
+    errors: AtomicU64,
+    latency: AtomicHistogram,
+}
+
+/// Records the metrics of a database.
+#[cfg(feature = "metrics")]
+pub(crate) struct Recorder {
+    operations: Vec<OperationRecorder>,
+    iterator_steps: AtomicU64,
+}
+
+#[cfg(feature = "metrics")]
+impl Recorder {
+    pub(crate) fn new() -> Recorder {
+        Recorder {
+            operations: (0..OPERATIONS)
+                .map(|_| {
+                    OperationRecorder {
+                        errors: AtomicU64::new(0),
+                        latency: AtomicHistogram::new(),
+                    }
+                })
+                .collect(),
+            iterator_steps: AtomicU64::new(0),
+        }
+    }
+
+    /// Run `f`, recording its latency and failure as `operation`
+    pub(crate) fn measure<T, F>(&self, operation: OperationKind, f: F) -> Result<T, Error>
+        where F: FnOnce() -> Result<T, Error>
+    {
+        let start = Instant::now();
+        let result = f();
+        let recorder = &self.operations[operation as usize];
+        recorder.latency.record(start.elapsed().as_micros() as u64);
+        if result.is_err() {
+            recorder.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Count a step of an iterator
+    pub(crate) fn step(&self) {
+        self.iterator_steps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, operation: OperationKind) -> OperationMetrics {
+        let recorder = &self.operations[operation as usize];
+        let latency = recorder.latency.snapshot();
+        OperationMetrics {
+            count: latency.count,
+            errors: recorder.errors.load(Ordering::Relaxed),
+            latency,
+        }
+    }
+}
+
+/// Records nothing, without the `metrics` feature.
+#[cfg(not(feature = "metrics"))]
+pub(crate) struct Recorder;
+
+#[cfg(not(feature = "metrics"))]
+impl Recorder {
+    pub(crate) fn new() -> Recorder {
+        Recorder
+    }
+
+    #[inline]
+    pub(crate) fn measure<T, F>(&self, _operation: OperationKind, f: F) -> Result<T, Error>
+        where F: FnOnce() -> Result<T, Error>
+    {
+        f()
+    }
+
+    #[inline]
+    pub(crate) fn step(&self) {}
+}
+
+/// A latency histogram.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// Number of recorded operations per bucket, the bucket at index `i`
+    /// counting those taking up to `BUCKET_BOUNDS[i]` microseconds. The
+    /// last bucket counts those taking longer than all bounds.
+    pub buckets: Vec<u64>,
+    /// Number of recorded operations
+    pub count: u64,
+    /// Total latency of the recorded operations, in microseconds
+    pub sum_micros: u64,
+}
+
+/// The metrics of one kind of operation.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationMetrics {
+    /// Number of operations, failed ones included
+    pub count: u64,
+    /// Number of failed operations
+    pub errors: u64,
+    /// Latency of the operations
+    pub latency: Histogram,
+}
+
+/// The figures of one level, as reported by `leveldb.stats`.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelStats {
+    /// The level
+    pub level: usize,
+    /// Number of table files
+    pub files: u64,
+    /// Size of the table files, in megabytes
+    pub size_mb: f64,
+    /// Time spent compacting into the level, in seconds
+    pub compaction_secs: f64,
+    /// Data read by those compactions, in megabytes
+    pub read_mb: f64,
+    /// Data written by those compactions, in megabytes
+    pub write_mb: f64,
+}
+
+/// A snapshot of the metrics of a database.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// Single key reads, including every key of a `multi_get`
+    pub gets: OperationMetrics,
+    /// Puts
+    pub puts: OperationMetrics,
+    /// Deletes
+    pub deletes: OperationMetrics,
+    /// Batch writes
+    pub batch_writes: OperationMetrics,
+    /// Entries returned by iterators
+    pub iterator_steps: u64,
+    /// Failed operations of all kinds
+    pub errors: u64,
+    /// The levels holding table files, parsed from `leveldb.stats`
+    pub levels: Vec<LevelStats>,
+}
+
+/// Parse the compaction table of the `leveldb.stats` property.
+///
+/// Rows that don't parse are skipped.
+#[cfg(feature = "metrics")]
+pub fn parse_stats(stats: &str) -> Vec<LevelStats> {
+    stats.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 6 {
+                return None;
+            }
+            Some(LevelStats {
+                level: fields[0].parse().ok()?,
+                files: fields[1].parse().ok()?,
+                size_mb: fields[2].parse().ok()?,
+                compaction_secs: fields[3].parse().ok()?,
+                read_mb: fields[4].parse().ok()?,
+                write_mb: fields[5].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "metrics")]
+impl<K: Key> Database<K> {
+    /// Take a snapshot of the metrics of this database
+    pub fn metrics(&self) -> Metrics {
+        let recorder = &self.database.metrics;
+        let gets = recorder.snapshot(OperationKind::Get);
+        let puts = recorder.snapshot(OperationKind::Put);
+        let deletes = recorder.snapshot(OperationKind::Delete);
+        let batch_writes = recorder.snapshot(OperationKind::Write);
+        let errors = gets.errors + puts.errors + deletes.errors + batch_writes.errors;
+        Metrics {
+            gets,
+            puts,
+            deletes,
+            batch_writes,
+            iterator_steps: recorder.iterator_steps.load(Ordering::Relaxed),
+            errors,
+            levels: self.property_value("leveldb.stats").map(|stats| parse_stats(&stats)).unwrap_or_default(),
+        }
+    }
+}
+
+// name, help text and value of a per-level gauge
+#[cfg(feature = "prometheus")]
+type LevelGauge = (&'static str, &'static str, fn(&LevelStats) -> f64);
+
+#[cfg(feature = "prometheus")]
+impl Metrics {
+    /// Render the metrics in the Prometheus text exposition format
+    ///
+    /// All metric names are prefixed with `leveldb_`.
+    pub fn encode_prometheus(&self) -> String {
+        use std::fmt::Write;
+        const MB: f64 = 1048576.0;
+
+        let mut out = String::new();
+        let operations = [("get", &self.gets),
+                          ("put", &self.puts),
+                          ("delete", &self.deletes),
+                          ("write", &self.batch_writes)];
+
+        out.push_str("# HELP leveldb_operations_total Operations run, failed ones included.\n");
+        out.push_str("# TYPE leveldb_operations_total counter\n");
+        for &(name, metrics) in operations.iter() {
+            let _ = writeln!(out, "leveldb_operations_total{{op=\"{}\"}} {}", name, metrics.count);
+        }
+        out.push_str("# HELP leveldb_operation_errors_total Operations that failed.\n");
+        out.push_str("# TYPE leveldb_operation_errors_total counter\n");
+        for &(name, metrics) in operations.iter() {
+            let _ = writeln!(out, "leveldb_operation_errors_total{{op=\"{}\"}} {}", name, metrics.errors);
+        }
+        out.push_str("# HELP leveldb_operation_duration_seconds Latency of operations.\n");
+        out.push_str("# TYPE leveldb_operation_duration_seconds histogram\n");
+        for &(name, metrics) in operations.iter() {
+            let latency = &metrics.latency;
+            let mut cumulative = 0;
+            for (bound, count) in BUCKET_BOUNDS.iter().zip(latency.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(out,
+                                 "leveldb_operation_duration_seconds_bucket{{op=\"{}\",le=\"{}\"}} {}",
+                                 name,
+                                 *bound as f64 / 1e6,
+                                 cumulative);
+            }
+            let _ = writeln!(out,
+                             "leveldb_operation_duration_seconds_bucket{{op=\"{}\",le=\"+Inf\"}} {}",
+                             name,
+                             latency.count);
+            let _ = writeln!(out,
+                             "leveldb_operation_duration_seconds_sum{{op=\"{}\"}} {}",
+                             name,
+                             latency.sum_micros as f64 / 1e6);
+            let _ = writeln!(out, "leveldb_operation_duration_seconds_count{{op=\"{}\"}} {}", name, latency.count);
+        }
+        out.push_str("# HELP leveldb_iterator_steps_total Entries returned by iterators.\n");
+        out.push_str("# TYPE leveldb_iterator_steps_total counter\n");
+        let _ = writeln!(out, "leveldb_iterator_steps_total {}", self.iterator_steps);
+
+        let levels: [LevelGauge; 5] =
+            [("level_files", "Table files per level.", |level| level.files as f64),
+             ("level_size_bytes", "Size of the table files per level.", |level| level.size_mb * MB),
+             ("compaction_seconds", "Time spent compacting into each level.", |level| level.compaction_secs),
+             ("compaction_read_bytes", "Bytes read by compactions into each level.", |level| level.read_mb * MB),
+             ("compaction_write_bytes", "Bytes written by compactions into each level.", |level| level.write_mb * MB)];
+        for &(name, help, value) in levels.iter() {
+            let _ = writeln!(out, "# HELP leveldb_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE leveldb_{} gauge", name);
+            for level in self.levels.iter() {
+                let _ = writeln!(out, "leveldb_{}{{level=\"{}\"}} {}", name, level.level, value(level));
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,113 @@
+//! Schema migrations
+//!
+//! Applications evolving the format of their values register migrations
+//! with a `Migrations` runner, each moving the database to the next schema
+//! version. The version is stored in the database under a reserved key,
+//! and `Migrations::run`, or `Migrations::open` when opening, applies the
+//! steps the database hasn't seen yet, in order.
+//!
+//! A step reads from the database and collects its changes in a
+//! writebatch, which is written together with the new version, so a step
+//! is either applied completely or not at all. A failing step leaves the
+//! database at the version before it, and running the migrations again
+//! resumes from there.
+//!
+//! The version is the metadata `SCHEMA_VERSION`, see `Database::meta`.
+use std::path::Path;
+
+use database::key::Key;
+use options::{Options, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::batch::{Batch, Writebatch};
+use super::meta::meta_key;
+
+/// The name of the metadata holding the schema version
+pub const SCHEMA_VERSION: &str = "leveldb.schema-version";
+
+type Step<K> = Box<dyn Fn(&Database<K>, &mut Writebatch<K>) -> Result<(), Error>>;
+
+impl<K: Key> Database<K> {
+    /// The schema version of the database, 0 if no migration ran yet
+    pub fn schema_version(&self) -> Result<u64, Error> {
+        Ok(self.meta().get_u64(SCHEMA_VERSION)?.unwrap_or(0))
+    }
+}
+
+/// Migrations to apply to a database, by schema version.
+pub struct Migrations<K: Key> {
+    steps: Vec<(u64, Step<K>)>,
+}
+
+impl<K: Key> Migrations<K> {
+    /// No migrations
+    pub fn new() -> Migrations<K> {
+        Migrations { steps: vec![] }
+    }
+
+    /// Register `step`, migrating the database to `version`
+    ///
+    /// The step gets the database at the previous version and a batch to
+    /// collect its changes in. Changes written to the database directly
+    /// aren't rolled back if the step fails. Steps run under the lock
+    /// serialising migrations, a key lock as described in `atomic`, so
+    /// atomic operations in a step fail with `ErrorKind::Conflict`, and
+    /// **a step must not wait for other threads**. Panics unless versions
+    /// are registered in increasing order, starting from 1.
+    pub fn add<F>(mut self, version: u64, step: F) -> Migrations<K>
+        where F: Fn(&Database<K>, &mut Writebatch<K>) -> Result<(), Error> + 'static
+    {
+        let last = self.latest_version();
+        assert!(version > last, "migration {} registered after migration {}", version, last);
+        self.steps.push((version, Box::new(step)));
+        self
+    }
+
+    /// The version of the last registered migration, 0 without any
+    pub fn latest_version(&self) -> u64 {
+        self.steps.last().map_or(0, |&(version, _)| version)
+    }
+
+    /// Migrate `database` to the latest version, returning it
+    ///
+    /// Each step is written in its own synced batch. If a step fails, its
+    /// error is returned with the version appended, and the database stays
+    /// at the version before it. A database at a version newer than the
+    /// latest migration is rejected, as this code doesn't know its format.
+    pub fn run(&self, database: &Database<K>) -> Result<u64, Error> {
+        // serialises migrations running on clones of the database
+        let _guard = database.database.locks.lock(&meta_key(SCHEMA_VERSION))?;
+        let start = database.schema_version()?;
+        if start > self.latest_version() {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument,
+                                        format!("schema version {} is newer than the latest migration {}",
+                                                start,
+                                                self.latest_version())));
+        }
+        let mut current = start;
+        for &(version, ref step) in self.steps.iter().filter(|&&(version, _)| version > start) {
+            let mut batch = Writebatch::new();
+            step(database, &mut batch)
+                .map_err(|e| e.context(format!("migrating to schema version {}", version)))?;
+            database.meta().put_in(&mut batch, SCHEMA_VERSION, &version.to_be_bytes());
+            let mut options = WriteOptions::new();
+            options.sync = true;
+            database.write(options, &batch)?;
+            current = version;
+        }
+        Ok(current)
+    }
+
+    /// Open the database at `name` and migrate it to the latest version
+    pub fn open(&self, name: &Path, options: Options) -> Result<Database<K>, Error> {
+        let database = Database::open(name, options)?;
+        self.run(&database)?;
+        Ok(database)
+    }
+}
+
+impl<K: Key> Default for Migrations<K> {
+    fn default() -> Migrations<K> {
+        Migrations::new()
+    }
+}
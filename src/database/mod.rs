@@ -4,17 +4,26 @@ extern crate db_key as key;
 
 use leveldb_sys::*;
 
-use self::options::{Options, c_options};
-use self::error::Error;
+use self::options::{Options, OptionsCache, WriteOptions, c_options};
+use self::batch::{Batch, Writebatch};
+use self::error::{Error, ErrorKind, Operation};
 use std::ffi::CString;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use std::ptr;
-use comparator::{Comparator, create_comparator};
+use comparator::{Comparator, CompareFn, create_raw_comparator};
 use self::key::Key;
 
 use std::marker::PhantomData;
+use std::cmp::Ordering;
+use std::sync::Arc;
+use self::atomic::KeyLocks;
+use self::watch::Watchers;
+use self::hooks::Hooks;
+use self::metrics::Recorder;
+use self::auto_compaction::Scheduler;
+use libc::{c_void, size_t};
 
 pub mod options;
 pub mod error;
@@ -22,29 +31,110 @@ pub mod iterator;
 pub mod comparator;
 pub mod snapshots;
 pub mod cache;
+pub mod filter_policy;
+pub mod env;
 pub mod kv;
 pub mod batch;
 pub mod management;
 pub mod compaction;
 pub mod bytes;
+pub mod sizes;
+pub mod properties;
+pub mod asynchronous;
+pub mod keys;
+pub mod typed;
+pub mod ttl;
+pub mod index;
+pub mod transaction;
+pub mod atomic;
+pub mod keyspace;
+pub mod backup;
+pub mod watch;
+pub mod sharded;
+pub mod cursor;
+pub mod compression;
+pub mod queue;
+pub mod merge;
+pub mod auto_compaction;
+pub mod chunked;
+pub mod blob;
+pub mod backend;
+pub mod manager;
+pub mod retry;
+pub mod read_cache;
+pub mod coalescer;
+pub mod rate_limit;
+pub mod migrations;
+pub mod meta;
+pub mod hooks;
+pub mod replication;
+pub mod consensus;
+pub mod integrity;
+pub mod health;
+pub mod logs;
+pub mod live_files;
+pub mod secondary;
+mod checksum;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(not(feature = "metrics"))]
+mod metrics;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(not(feature = "tracing"))]
+mod trace;
 
 #[allow(missing_docs)]
-struct RawDB {
+pub(crate) struct RawDB {
     ptr: *mut leveldb_t,
+    // the path the database was opened at, for errors
+    path: PathBuf,
+    // this holds a reference passed into leveldb
+    // it must be kept around, Rust only uses it to compare keys
+    comparator: Option<RawComparator>,
+    // these hold multiple references that are used by the leveldb library
+    // and should survive as long as the database lives
+    #[allow(dead_code)]
+    options: Options,
+    // serialises atomic operations on the same key
+    locks: KeyLocks,
+    // watchers notified of writes
+    watchers: Watchers,
+    // hooks run around writes
+    hooks: Hooks,
+    // the C option structs used by reads and writes
+    c_options: OptionsCache,
+    // operation metrics, empty without the `metrics` feature
+    metrics: Recorder,
+    // the background compaction thread, if enabled
+    auto_compaction: Option<Scheduler>,
 }
 
+// the fields are dropped after `leveldb_close` ran, so the comparator
+// and options outlive the handle that references them
 #[allow(missing_docs)]
 impl Drop for RawDB {
     fn drop(&mut self) {
+        if let Some(ref scheduler) = self.auto_compaction {
+            scheduler.stop();
+        }
         unsafe {
             leveldb_close(self.ptr);
         }
     }
 }
 
+// leveldb synchronises access to the handle internally
+unsafe impl Send for RawDB {}
+unsafe impl Sync for RawDB {}
+
 #[allow(missing_docs)]
 struct RawComparator {
     ptr: *mut leveldb_comparator_t,
+    state: *mut c_void,
+    compare: CompareFn,
 }
 
 impl Drop for RawComparator {
@@ -67,35 +157,54 @@ impl Drop for RawComparator {
 ///
 /// Multiple Database objects can be kept around, as leveldb synchronises
 /// internally.
+///
+/// Cloning a `Database` is cheap: all clones share the same underlying
+/// handle, which is closed once the last clone is dropped.
 pub struct Database<K: Key> {
-    database: RawDB,
-    // this holds a reference passed into leveldb
-    // it is never read from Rust, but must be kept around
-    #[allow(dead_code)]
-    comparator: Option<RawComparator>,
-    // these hold multiple references that are used by the leveldb library
-    // and should survive as long as the database lives
-    #[allow(dead_code)]
-    options: Options,
+    database: Arc<RawDB>,
     marker: PhantomData<K>,
 }
 
-unsafe impl<K: Key> Sync for Database<K> {}
-unsafe impl<K: Key> Send for Database<K> {}
+impl<K: Key> Clone for Database<K> {
+    fn clone(&self) -> Database<K> {
+        Database {
+            database: self.database.clone(),
+            marker: PhantomData,
+        }
+    }
+}
 
 impl<K: Key> Database<K> {
     fn new(database: *mut leveldb_t,
+           path: &Path,
            options: Options,
-           comparator: Option<*mut leveldb_comparator_t>)
+           comparator: Option<(*mut leveldb_comparator_t, *mut c_void, CompareFn)>)
            -> Database<K> {
-        let raw_comp = match comparator {
-            Some(p) => Some(RawComparator { ptr: p }),
-            None => None,
-        };
-        Database {
-            database: RawDB { ptr: database },
+        let raw_comp = comparator.map(|(ptr, state, compare)| {
+            RawComparator {
+                ptr,
+                state,
+                compare,
+            }
+        });
+        let auto_compaction = options.auto_compaction;
+        let database = Arc::new(RawDB {
+            ptr: database,
+            path: path.to_path_buf(),
             comparator: raw_comp,
-            options: options,
+            options,
+            locks: KeyLocks::new(),
+            watchers: Watchers::new(),
+            hooks: Hooks::new(),
+            c_options: OptionsCache::new(),
+            metrics: Recorder::new(),
+            auto_compaction: auto_compaction.map(|_| Scheduler::new()),
+        });
+        if let (Some(scheduler), Some(config)) = (database.auto_compaction.as_ref(), auto_compaction) {
+            scheduler.start(Arc::downgrade(&database), config);
+        }
+        Database {
+            database,
             marker: PhantomData,
         }
     }
@@ -114,10 +223,10 @@ impl<K: Key> Database<K> {
                                   &mut error);
             leveldb_options_destroy(c_options);
 
-            if error == ptr::null_mut() {
-                Ok(Database::new(db, options, None))
+            if error.is_null() {
+                Ok(Database::new(db, name, options, None))
             } else {
-                Err(Error::new_from_i8(error))
+                Err(Error::new_from_i8(error).with_operation(Operation::Open).with_path(name))
             }
         }
     }
@@ -135,20 +244,76 @@ impl<K: Key> Database<K> {
                                                       comparator: C)
                                                       -> Result<Database<K>, Error> {
         let mut error = ptr::null_mut();
-        let comp_ptr = create_comparator(Box::new(comparator));
+        let raw_comp = create_raw_comparator(Box::new(comparator));
         unsafe {
             let c_string = CString::new(name.to_str().unwrap()).unwrap();
-            let c_options = c_options(&options, Some(comp_ptr));
+            let c_options = c_options(&options, Some(raw_comp.0));
             let db = leveldb_open(c_options as *const leveldb_options_t,
                                   c_string.as_bytes_with_nul().as_ptr() as *const i8,
                                   &mut error);
             leveldb_options_destroy(c_options);
 
-            if error == ptr::null_mut() {
-                Ok(Database::new(db, options, Some(comp_ptr)))
+            if error.is_null() {
+                Ok(Database::new(db, name, options, Some(raw_comp)))
             } else {
-                Err(Error::new_from_i8(error))
+                leveldb_comparator_destroy(raw_comp.0);
+                Err(Error::new_from_i8(error).with_operation(Operation::Open).with_path(name))
+            }
+        }
+    }
+
+    /// Close the database, reporting problems that dropping it ignores
+    ///
+    /// Syncs the log first, which fails if leveldb ran into an error in the
+    /// background, e.g. while compacting. Fails with `InvalidArgument` if
+    /// clones of this handle, such as those held by owned snapshots or
+    /// wrappers, are still alive; iterators and snapshots borrow the handle,
+    /// so none can be. The handle is released either way, the database
+    /// closes once the last one is gone.
+    pub fn close(self) -> Result<(), Error> {
+        let mut options = WriteOptions::new();
+        options.sync = true;
+        let synced = self.write(options, &Writebatch::new());
+        match Arc::try_unwrap(self.database) {
+            Ok(database) => {
+                drop(database);
+                synced
+            }
+            Err(database) => {
+                synced?;
+                Err(Error::with_kind(ErrorKind::InvalidArgument,
+                                     format!("{} other handles to the database are open",
+                                             Arc::strong_count(&database) - 1)))
+            }
+        }
+    }
+
+    /// The path the database was opened at
+    pub fn path(&self) -> &Path {
+        &self.database.path
+    }
+
+    // record `operation`, the path and the encoded `key` in an error
+    fn with_context(&self, error: Error, operation: Operation, key: Option<&[u8]>) -> Error {
+        let error = error.with_operation(operation).with_path(&self.database.path);
+        match key {
+            Some(key) => error.with_key(key),
+            None => error,
+        }
+    }
+
+    /// Compare two encoded keys using the ordering of this database.
+    fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        match self.database.comparator {
+            Some(ref c) => {
+                let result = (c.compare)(c.state,
+                                         a.as_ptr() as *const i8,
+                                         a.len() as size_t,
+                                         b.as_ptr() as *const i8,
+                                         b.len() as size_t);
+                result.cmp(&0)
             }
+            None => a.cmp(b),
         }
     }
 }
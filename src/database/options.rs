@@ -7,9 +7,22 @@
 use leveldb_sys::*;
 
 use libc::size_t;
+use std::ptr;
 use database::snapshots::Snapshot;
 use database::key::Key;
 use database::cache::Cache;
+use database::filter_policy::FilterPolicy;
+use database::env::Env;
+use database::error::{Error, ErrorKind};
+use database::auto_compaction::AutoCompaction;
+use database::comparator::Comparator;
+use database::Database;
+use std::path::Path;
+
+// leveldb's defaults for the sizes `OptionsBuilder` checks against each
+// other
+const DEFAULT_WRITE_BUFFER_SIZE: size_t = 4 << 20;
+const DEFAULT_BLOCK_SIZE: size_t = 4 << 10;
 
 /// Options to consider when opening a new or pre-existing database.
 ///
@@ -56,6 +69,27 @@ pub struct Options {
     ///
     /// default: None
     pub cache: Option<Cache>,
+    /// A filter policy to reduce disk reads for missing keys.
+    ///
+    /// default: None
+    pub filter_policy: Option<FilterPolicy>,
+    /// The environment used to access the file system.
+    ///
+    /// default: None
+    pub env: Option<Env>,
+    /// Compact in a background thread, see `auto_compaction`.
+    ///
+    /// default: None
+    pub auto_compaction: Option<AutoCompaction>,
+    /// The read options of `Database::get_default`.
+    ///
+    /// default: `DefaultReadOptions::new()`
+    pub default_read_options: DefaultReadOptions,
+    /// The write options of `Database::put_default` and
+    /// `Database::delete_default`.
+    ///
+    /// default: `WriteOptions::new()`
+    pub default_write_options: WriteOptions,
 }
 
 impl Options {
@@ -71,7 +105,186 @@ impl Options {
             block_restart_interval: None,
             compression: Compression::No,
             cache: None,
+            filter_policy: None,
+            env: None,
+            auto_compaction: None,
+            default_read_options: DefaultReadOptions::new(),
+            default_write_options: WriteOptions::new(),
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options::new()
+    }
+}
+
+/// A builder for `Options`.
+///
+/// The comparator is not part of the options. Once one is set with
+/// `comparator`, the builder opens the database itself with `open`,
+/// instead of building the options.
+pub struct OptionsBuilder<C = NoComparator> {
+    options: Options,
+    comparator: C,
+}
+
+/// Stands in for the comparator of an `OptionsBuilder` without one.
+pub struct NoComparator;
+
+impl OptionsBuilder {
+    /// Create a new builder, starting from the default settings.
+    pub fn new() -> OptionsBuilder {
+        OptionsBuilder {
+            options: Options::new(),
+            comparator: NoComparator,
+        }
+    }
+
+    /// Validate the settings and return the `Options`.
+    pub fn build(self) -> Result<Options, Error> {
+        self.validate()?;
+        Ok(self.options)
+    }
+}
+
+impl<C: Comparator> OptionsBuilder<C> {
+    /// Validate the settings and open the database at `name` with them and
+    /// the comparator, see `Database::open_with_comparator`.
+    pub fn open(self, name: &Path) -> Result<Database<C::K>, Error> {
+        self.validate()?;
+        Database::open_with_comparator(name, self.options, self.comparator)
+    }
+}
+
+impl<C> OptionsBuilder<C> {
+    /// create the database if missing
+    pub fn create_if_missing(mut self, value: bool) -> OptionsBuilder<C> {
+        self.options.create_if_missing = value;
+        self
+    }
+
+    /// report an error if the DB already exists instead of opening.
+    pub fn error_if_exists(mut self, value: bool) -> OptionsBuilder<C> {
+        self.options.error_if_exists = value;
+        self
+    }
+
+    /// report an error as soon as corruption is detected.
+    pub fn paranoid_checks(mut self, value: bool) -> OptionsBuilder<C> {
+        self.options.paranoid_checks = value;
+        self
+    }
+
+    /// the size of the write buffer to use.
+    pub fn write_buffer_size(mut self, size: size_t) -> OptionsBuilder<C> {
+        self.options.write_buffer_size = Some(size);
+        self
+    }
+
+    /// the max number of open files.
+    pub fn max_open_files(mut self, num: i32) -> OptionsBuilder<C> {
+        self.options.max_open_files = Some(num);
+        self
+    }
+
+    /// the size of the blocks leveldb uses for writing and caching.
+    pub fn block_size(mut self, size: size_t) -> OptionsBuilder<C> {
+        self.options.block_size = Some(size);
+        self
+    }
+
+    /// the interval between restart points.
+    pub fn block_restart_interval(mut self, interval: i32) -> OptionsBuilder<C> {
+        self.options.block_restart_interval = Some(interval);
+        self
+    }
+
+    /// whether leveldb should write compressed or not.
+    pub fn compression(mut self, compression: Compression) -> OptionsBuilder<C> {
+        self.options.compression = compression;
+        self
+    }
+
+    /// a cache to use during read operations.
+    pub fn cache(mut self, cache: Cache) -> OptionsBuilder<C> {
+        self.options.cache = Some(cache);
+        self
+    }
+
+    /// a filter policy to reduce disk reads for missing keys.
+    pub fn filter_policy(mut self, policy: FilterPolicy) -> OptionsBuilder<C> {
+        self.options.filter_policy = Some(policy);
+        self
+    }
+
+    /// the environment used to access the file system.
+    pub fn env(mut self, env: Env) -> OptionsBuilder<C> {
+        self.options.env = Some(env);
+        self
+    }
+
+    /// compact in a background thread.
+    pub fn auto_compaction(mut self, config: AutoCompaction) -> OptionsBuilder<C> {
+        self.options.auto_compaction = Some(config);
+        self
+    }
+
+    /// the read options of `Database::get_default`
+    pub fn default_read_options(mut self, options: DefaultReadOptions) -> OptionsBuilder<C> {
+        self.options.default_read_options = options;
+        self
+    }
+
+    /// the write options of `Database::put_default` and
+    /// `Database::delete_default`
+    pub fn default_write_options(mut self, options: WriteOptions) -> OptionsBuilder<C> {
+        self.options.default_write_options = options;
+        self
+    }
+
+    /// the comparator ordering the keys, which are then of its key type.
+    pub fn comparator<D: Comparator>(self, comparator: D) -> OptionsBuilder<D> {
+        OptionsBuilder {
+            options: self.options,
+            comparator,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let options = &self.options;
+        if options.write_buffer_size == Some(0) {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, "write_buffer_size must be positive".to_string()));
         }
+        if options.block_size == Some(0) {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, "block_size must be positive".to_string()));
+        }
+        if options.max_open_files.is_some_and(|n| n <= 0) {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, "max_open_files must be positive".to_string()));
+        }
+        if options.block_restart_interval.is_some_and(|n| n <= 0) {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, "block_restart_interval must be positive".to_string()));
+        }
+        // a block is cut from the write buffer when it's flushed, it can't
+        // be the larger of the two
+        let write_buffer_size = options.write_buffer_size.unwrap_or(DEFAULT_WRITE_BUFFER_SIZE);
+        let block_size = options.block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        if block_size > write_buffer_size {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument,
+                                        format!("block_size {} exceeds write_buffer_size {}", block_size, write_buffer_size)));
+        }
+        if options.error_if_exists && !options.create_if_missing {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument,
+                                        "error_if_exists without create_if_missing fails whether or not the database exists".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Default for OptionsBuilder {
+    fn default() -> OptionsBuilder {
+        OptionsBuilder::new()
     }
 }
 
@@ -91,6 +304,12 @@ impl WriteOptions {
     }
 }
 
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions::new()
+    }
+}
+
 /// The read options to use for any read operation.
 #[allow(missing_copy_implementations)]
 pub struct ReadOptions<'a, K: Key + 'a> {
@@ -110,6 +329,20 @@ pub struct ReadOptions<'a, K: Key + 'a> {
     ///
     /// default: None
     pub snapshot: Option<&'a Snapshot<'a, K>>,
+    /// The smallest key iterators return, included.
+    ///
+    /// Applies on top of the bounds set on the iterator itself, reads of
+    /// single keys ignore it.
+    ///
+    /// default: None
+    pub iterate_lower_bound: Option<&'a K>,
+    /// The key iterators stop before, excluded.
+    ///
+    /// Applies on top of the bounds set on the iterator itself, reads of
+    /// single keys ignore it.
+    ///
+    /// default: None
+    pub iterate_upper_bound: Option<&'a K>,
 }
 
 impl<'a, K: Key + 'a> ReadOptions<'a, K> {
@@ -119,11 +352,69 @@ impl<'a, K: Key + 'a> ReadOptions<'a, K> {
             verify_checksums: false,
             fill_cache: true,
             snapshot: None,
+            iterate_lower_bound: None,
+            iterate_upper_bound: None,
+        }
+    }
+}
+
+impl<'a, K: Key + 'a> Default for ReadOptions<'a, K> {
+    fn default() -> ReadOptions<'a, K> {
+        ReadOptions::new()
+    }
+}
+
+/// Read options to keep as the defaults of a database.
+///
+/// Like `ReadOptions`, without the snapshot, which doesn't outlive a
+/// single read.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct DefaultReadOptions {
+    /// Whether to verify the saved checksums on read.
+    ///
+    /// default: false
+    pub verify_checksums: bool,
+    /// Whether to fill the internal cache with the
+    /// results of the read.
+    ///
+    /// default: true
+    pub fill_cache: bool,
+}
+
+impl DefaultReadOptions {
+    /// Return a `DefaultReadOptions` struct with the default values.
+    pub fn new() -> DefaultReadOptions {
+        DefaultReadOptions {
+            verify_checksums: false,
+            fill_cache: true,
+        }
+    }
+
+    /// Return `ReadOptions` with these settings and no snapshot
+    pub fn read_options<'a, K: Key + 'a>(&self) -> ReadOptions<'a, K> {
+        ReadOptions {
+            verify_checksums: self.verify_checksums,
+            fill_cache: self.fill_cache,
+            snapshot: None,
+            iterate_lower_bound: None,
+            iterate_upper_bound: None,
         }
     }
 }
 
-#[allow(missing_docs)]
+impl Default for DefaultReadOptions {
+    fn default() -> DefaultReadOptions {
+        DefaultReadOptions::new()
+    }
+}
+
+/// Build a C options struct from `options`.
+///
+/// # Safety
+///
+/// The returned pointer must be released using `leveldb_options_destroy`.
+/// The cache, filter policy, environment and comparator referenced must outlive any database opened
+/// with it.
 pub unsafe fn c_options(options: &Options,
                         comparator: Option<*mut leveldb_comparator_t>)
                         -> *mut leveldb_options_t {
@@ -150,17 +441,32 @@ pub unsafe fn c_options(options: &Options,
     if let Some(ref cache) = options.cache {
         leveldb_options_set_cache(c_options, cache.raw_ptr());
     }
+    if let Some(ref policy) = options.filter_policy {
+        leveldb_options_set_filter_policy(c_options, policy.raw_ptr());
+    }
+    if let Some(ref env) = options.env {
+        leveldb_options_set_env(c_options, env.raw_ptr());
+    }
     c_options
 }
 
-#[allow(missing_docs)]
+/// Build a C write options struct from `options`.
+///
+/// # Safety
+///
+/// The returned pointer must be released using `leveldb_writeoptions_destroy`.
 pub unsafe fn c_writeoptions(options: WriteOptions) -> *mut leveldb_writeoptions_t {
     let c_writeoptions = leveldb_writeoptions_create();
     leveldb_writeoptions_set_sync(c_writeoptions, options.sync as u8);
     c_writeoptions
 }
 
-#[allow(missing_docs)]
+/// Build a C read options struct from `options`.
+///
+/// # Safety
+///
+/// The returned pointer must be released using `leveldb_readoptions_destroy`
+/// and must not outlive the snapshot referenced by `options`.
 pub unsafe fn c_readoptions<'a, K>(options: &ReadOptions<'a, K>) -> *mut leveldb_readoptions_t
     where K: Key
 {
@@ -168,8 +474,88 @@ pub unsafe fn c_readoptions<'a, K>(options: &ReadOptions<'a, K>) -> *mut leveldb
     leveldb_readoptions_set_verify_checksums(c_readoptions, options.verify_checksums as u8);
     leveldb_readoptions_set_fill_cache(c_readoptions, options.fill_cache as u8);
 
-    if let Some(ref snapshot) = options.snapshot {
+    if let Some(snapshot) = options.snapshot {
         leveldb_readoptions_set_snapshot(c_readoptions, snapshot.raw_ptr());
     }
     c_readoptions
 }
+
+/// C option structs for the settings without a snapshot, built once per
+/// database instead of on every read and write.
+///
+/// leveldb only reads the option structs, so they are shared between
+/// threads.
+pub(crate) struct OptionsCache {
+    // by `verify_checksums` and `fill_cache`
+    read: [*mut leveldb_readoptions_t; 4],
+    // by `sync`
+    write: [*mut leveldb_writeoptions_t; 2],
+}
+
+impl OptionsCache {
+    pub(crate) fn new() -> OptionsCache {
+        unsafe {
+            let mut read = [ptr::null_mut(); 4];
+            for (i, c_readoptions) in read.iter_mut().enumerate() {
+                *c_readoptions = leveldb_readoptions_create();
+                leveldb_readoptions_set_verify_checksums(*c_readoptions, (i & 1) as u8);
+                leveldb_readoptions_set_fill_cache(*c_readoptions, (i >> 1) as u8);
+            }
+            let mut write = [ptr::null_mut(); 2];
+            for (i, c_writeoptions) in write.iter_mut().enumerate() {
+                *c_writeoptions = leveldb_writeoptions_create();
+                leveldb_writeoptions_set_sync(*c_writeoptions, i as u8);
+            }
+            OptionsCache { read, write }
+        }
+    }
+
+    /// The C read options for `options`, built fresh if they reference a
+    /// snapshot
+    pub(crate) fn read<'a, K: Key>(&self, options: &ReadOptions<'a, K>) -> CReadOptions {
+        if options.snapshot.is_some() {
+            return CReadOptions {
+                ptr: unsafe { c_readoptions(options) },
+                owned: true,
+            };
+        }
+        let i = options.verify_checksums as usize | (options.fill_cache as usize) << 1;
+        CReadOptions {
+            ptr: self.read[i],
+            owned: false,
+        }
+    }
+
+    /// The C write options for `options`, valid while the cache lives
+    pub(crate) fn write(&self, options: WriteOptions) -> *mut leveldb_writeoptions_t {
+        self.write[options.sync as usize]
+    }
+}
+
+impl Drop for OptionsCache {
+    fn drop(&mut self) {
+        unsafe {
+            for &c_readoptions in self.read.iter() {
+                leveldb_readoptions_destroy(c_readoptions);
+            }
+            for &c_writeoptions in self.write.iter() {
+                leveldb_writeoptions_destroy(c_writeoptions);
+            }
+        }
+    }
+}
+
+/// C read options taken from an `OptionsCache`, released on drop unless
+/// they are cached.
+pub(crate) struct CReadOptions {
+    pub(crate) ptr: *mut leveldb_readoptions_t,
+    owned: bool,
+}
+
+impl Drop for CReadOptions {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe { leveldb_readoptions_destroy(self.ptr) };
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Database properties
+//!
+//! leveldb exposes internal state of a database through named
+//! properties, e.g. `leveldb.stats` or `leveldb.sstables`.
+use super::Database;
+use super::key::Key;
+use leveldb_sys::{leveldb_t, leveldb_property_value, leveldb_free};
+use libc::{c_char, c_void};
+use std::ffi::{CStr, CString};
+
+/// The number of levels leveldb organizes its tables in.
+pub const NUM_LEVELS: usize = 7;
+
+/// A summary of the database state, assembled from multiple properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseStats {
+    /// The number of table files at each level, starting with level 0.
+    pub files_at_level: Vec<usize>,
+    /// The approximate number of bytes used by memtables and caches.
+    pub approximate_memory_usage: Option<u64>,
+}
+
+/// The compaction statistics of one level, from `leveldb.stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LevelStats {
+    /// The number of table files
+    pub files: usize,
+    /// The size of the tables, in MB
+    pub size_mb: f64,
+    /// The time spent compacting into the level, in seconds
+    pub time_secs: f64,
+    /// The data read by those compactions, in MB
+    pub read_mb: f64,
+    /// The data written by those compactions, in MB
+    pub write_mb: f64,
+}
+
+/// The compaction statistics of all levels, parsed from `leveldb.stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionStats {
+    /// The statistics of each level, starting with level 0. leveldb
+    /// leaves out idle levels, they are all zeros here.
+    pub levels: Vec<LevelStats>,
+}
+
+impl CompactionStats {
+    /// Parse the value of the `leveldb.stats` property
+    ///
+    /// Returns `None` if the text doesn't hold leveldb's table of levels.
+    pub fn parse(text: &str) -> Option<CompactionStats> {
+        let mut lines = text.lines().map(|line| line.trim());
+        let header = lines.find(|line| line.starts_with("Level"))?;
+        let columns: Vec<&str> = header.split_whitespace().collect();
+        if columns != ["Level", "Files", "Size(MB)", "Time(sec)", "Read(MB)", "Write(MB)"] {
+            return None;
+        }
+        let mut levels = vec![LevelStats::default(); NUM_LEVELS];
+        for line in lines.filter(|line| !line.starts_with("---") && !line.is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != columns.len() {
+                return None;
+            }
+            let level: usize = fields[0].parse().ok()?;
+            if level >= NUM_LEVELS {
+                return None;
+            }
+            levels[level] = LevelStats {
+                files: fields[1].parse().ok()?,
+                size_mb: fields[2].parse().ok()?,
+                time_secs: fields[3].parse().ok()?,
+                read_mb: fields[4].parse().ok()?,
+                write_mb: fields[5].parse().ok()?,
+            };
+        }
+        Some(CompactionStats { levels })
+    }
+}
+
+/// Structs implementing this trait give access to leveldb properties.
+pub trait Properties {
+    /// Returns the value of the property `name`, or `None` if the
+    /// property is unknown.
+    fn property_value(&self, name: &str) -> Option<String>;
+
+    /// Returns the number of table files at `level`.
+    fn num_files_at_level(&self, level: usize) -> Option<usize> {
+        self.property_value(&format!("leveldb.num-files-at-level{}", level))
+            .and_then(|v| v.trim().parse().ok())
+    }
+
+    /// Returns the `leveldb.approximate-memory-usage` property.
+    fn approximate_memory_usage(&self) -> Option<u64> {
+        self.property_value("leveldb.approximate-memory-usage")
+            .and_then(|v| v.trim().parse().ok())
+    }
+
+    /// Returns a summary of the database state.
+    fn database_stats(&self) -> DatabaseStats {
+        DatabaseStats {
+            files_at_level: (0..NUM_LEVELS)
+                .map(|level| self.num_files_at_level(level).unwrap_or(0))
+                .collect(),
+            approximate_memory_usage: self.approximate_memory_usage(),
+        }
+    }
+
+    /// Returns the `leveldb.stats` property, parsed.
+    fn stats(&self) -> Option<CompactionStats> {
+        self.property_value("leveldb.stats")
+            .and_then(|v| CompactionStats::parse(&v))
+    }
+}
+
+impl<K: Key> Properties for Database<K> {
+    fn property_value(&self, name: &str) -> Option<String> {
+        property_value_raw(self.database.ptr, name)
+    }
+}
+
+pub(crate) fn property_value_raw(database: *mut leveldb_t, name: &str) -> Option<String> {
+    let c_name = match CString::new(name) {
+        Ok(c_name) => c_name,
+        Err(_) => return None,
+    };
+    unsafe {
+        let value = leveldb_property_value(database, c_name.as_bytes_with_nul().as_ptr() as *const c_char);
+        if value.is_null() {
+            None
+        } else {
+            let result = CStr::from_ptr(value).to_string_lossy().into_owned();
+            leveldb_free(value as *mut c_void);
+            Some(result)
+        }
+    }
+}
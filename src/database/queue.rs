@@ -0,0 +1,173 @@
+//! Durable FIFO queues
+//!
+//! A `Queue` stores its entries under increasing sequence numbers, so they
+//! are read back in the order they were pushed, also after a restart.
+//! Several named queues can share a database: the entries of a queue are
+//! keyed by its name followed by the big-endian sequence number, which
+//! keeps them apart and in order with the default bytewise comparator.
+//!
+//! Entries can be popped one by one, or peeked at and acknowledged once
+//! processed, so a crash before the acknowledgement leaves them queued.
+use std::sync::{Arc, Mutex};
+
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::keys::BinaryKey;
+use super::iterator::{Iterable, LevelDBIterator};
+
+const SEQUENCE_SIZE: usize = 8;
+
+// the sequence numbers of the first entry and the next one pushed
+struct Bounds {
+    head: u64,
+    tail: u64,
+}
+
+/// A durable first-in, first-out queue of byte values.
+pub struct Queue {
+    database: Database<BinaryKey>,
+    prefix: Vec<u8>,
+    // shared by clones, serialises the operations of the queue
+    bounds: Arc<Mutex<Bounds>>,
+}
+
+impl Clone for Queue {
+    fn clone(&self) -> Queue {
+        Queue {
+            database: self.database.clone(),
+            prefix: self.prefix.clone(),
+            bounds: self.bounds.clone(),
+        }
+    }
+}
+
+impl Queue {
+    /// Open the queue `name` in `database`, empty if it doesn't exist yet
+    ///
+    /// A queue should only be opened once per database, clones share it.
+    pub fn open(database: Database<BinaryKey>, name: &str) -> Result<Queue, Error> {
+        // the length keeps names that are prefixes of others apart
+        let mut prefix = (name.len() as u32).to_be_bytes().to_vec();
+        prefix.extend_from_slice(name.as_bytes());
+        let (head, tail) = {
+            let mut first = database.keys_iter(ReadOptions::new()).prefix(&prefix);
+            let head = match first.next() {
+                Some(key) => sequence(&prefix, &key)?,
+                None => 0,
+            };
+            let tail = match database.keys_iter(ReadOptions::new()).prefix(&prefix).last() {
+                Some(key) => sequence(&prefix, &key)? + 1,
+                None => 0,
+            };
+            (head, tail)
+        };
+        Ok(Queue {
+            database,
+            prefix,
+            bounds: Arc::new(Mutex::new(Bounds { head, tail })),
+        })
+    }
+
+    fn key(&self, sequence: u64) -> BinaryKey {
+        let mut key = self.prefix.clone();
+        key.extend_from_slice(&sequence.to_be_bytes());
+        BinaryKey(key)
+    }
+
+    fn entry(&self, sequence: u64) -> Result<(u64, Vec<u8>), Error> {
+        match self.database.get(ReadOptions::new(), self.key(sequence))? {
+            Some(value) => Ok((sequence, value)),
+            None => Err(Error::with_kind(ErrorKind::Corruption, format!("queue entry {} is missing", sequence))),
+        }
+    }
+
+    /// Append `value`, returning its sequence number
+    pub fn push(&self, options: WriteOptions, value: &[u8]) -> Result<u64, Error> {
+        self.push_all(options, &[value]).map(|first| first.unwrap())
+    }
+
+    /// Append all `values` atomically, returning the sequence number of the
+    /// first, `None` if there are none
+    pub fn push_all(&self, options: WriteOptions, values: &[&[u8]]) -> Result<Option<u64>, Error> {
+        if values.is_empty() {
+            return Ok(None);
+        }
+        let mut bounds = self.bounds.lock().unwrap_or_else(|e| e.into_inner());
+        let mut batch = Writebatch::new();
+        for (i, value) in values.iter().enumerate() {
+            batch.put(self.key(bounds.tail + i as u64), value);
+        }
+        self.database.write(options, &batch)?;
+        let first = bounds.tail;
+        bounds.tail += values.len() as u64;
+        Ok(Some(first))
+    }
+
+    /// Return the first entry without removing it
+    pub fn peek(&self) -> Result<Option<(u64, Vec<u8>)>, Error> {
+        Ok(self.peek_many(1)?.pop())
+    }
+
+    /// Return up to `count` entries from the front, without removing them
+    pub fn peek_many(&self, count: usize) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+        let bounds = self.bounds.lock().unwrap_or_else(|e| e.into_inner());
+        let end = bounds.tail.min(bounds.head.saturating_add(count as u64));
+        (bounds.head..end).map(|sequence| self.entry(sequence)).collect()
+    }
+
+    /// Remove and return the first entry
+    pub fn pop(&self, options: WriteOptions) -> Result<Option<(u64, Vec<u8>)>, Error> {
+        let mut bounds = self.bounds.lock().unwrap_or_else(|e| e.into_inner());
+        if bounds.head == bounds.tail {
+            return Ok(None);
+        }
+        let entry = self.entry(bounds.head)?;
+        self.database.delete(options, self.key(bounds.head))?;
+        bounds.head += 1;
+        Ok(Some(entry))
+    }
+
+    /// Remove the entries up to and including `sequence` atomically,
+    /// returning how many were removed
+    ///
+    /// Acknowledges entries returned by `peek_many` once processed.
+    pub fn ack(&self, options: WriteOptions, sequence: u64) -> Result<u64, Error> {
+        let mut bounds = self.bounds.lock().unwrap_or_else(|e| e.into_inner());
+        let end = bounds.tail.min(sequence.saturating_add(1));
+        if end <= bounds.head {
+            return Ok(0);
+        }
+        let mut batch = Writebatch::new();
+        for sequence in bounds.head..end {
+            batch.delete(self.key(sequence));
+        }
+        self.database.write(options, &batch)?;
+        let removed = end - bounds.head;
+        bounds.head = end;
+        Ok(removed)
+    }
+
+    /// Return the number of entries
+    pub fn len(&self) -> u64 {
+        let bounds = self.bounds.lock().unwrap_or_else(|e| e.into_inner());
+        bounds.tail - bounds.head
+    }
+
+    /// Return whether the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn sequence(prefix: &[u8], key: &BinaryKey) -> Result<u64, Error> {
+    let bytes = &key.0[prefix.len()..];
+    if bytes.len() != SEQUENCE_SIZE {
+        return Err(Error::with_kind(ErrorKind::Corruption, "malformed queue key".to_string()));
+    }
+    let mut sequence = [0; SEQUENCE_SIZE];
+    sequence.copy_from_slice(bytes);
+    Ok(u64::from_be_bytes(sequence))
+}
@@ -0,0 +1,139 @@
+//! Throttling writes
+//!
+//! Heavy writers, like bulk ingest jobs, fill level 0 faster than leveldb
+//! compacts it. Once it holds 8 files leveldb delays every write by a
+//! millisecond, at 12 it stops writing until compactions caught up, and
+//! meanwhile the compactions compete with foreground reads for the disk.
+//!
+//! A `RateLimiter` caps the bytes per second a writer hands to leveldb,
+//! with a token bucket: writers call `acquire` with the size of each write
+//! and are put to sleep once they exceed the rate. `write_stall_hint`
+//! tells writers to back off before leveldb stalls them itself.
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use database::key::Key;
+use super::Database;
+use super::properties::Properties;
+
+/// The level-0 file count from which `write_stall_hint` suggests slowing
+/// down, ahead of leveldb's own slowdown at 8.
+pub const SLOWDOWN_LEVEL0_FILES: usize = 6;
+/// The level-0 file count at which leveldb starts delaying writes.
+pub const STOP_LEVEL0_FILES: usize = 8;
+
+/// How hard the database is pressed by writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStall {
+    /// Compactions keep up with writes
+    Clear,
+    /// Level 0 is filling up, writers should slow down
+    SlowDown,
+    /// leveldb is delaying writes, and will stop them soon, writers should
+    /// pause until the hint clears
+    Stop,
+}
+
+impl<K: Key> Database<K> {
+    /// Tell writers whether to slow down, from the number of level-0 files
+    ///
+    /// `Clear` if the number can't be read.
+    pub fn write_stall_hint(&self) -> WriteStall {
+        match self.num_files_at_level(0) {
+            Some(files) if files >= STOP_LEVEL0_FILES => WriteStall::Stop,
+            Some(files) if files >= SLOWDOWN_LEVEL0_FILES => WriteStall::SlowDown,
+            _ => WriteStall::Clear,
+        }
+    }
+}
+
+struct Bucket {
+    // negative while writers are in debt
+    tokens: f64,
+    refilled: Instant,
+    rate: f64,
+    burst: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled = now;
+    }
+}
+
+/// A token bucket limiting the bytes written per second.
+///
+/// Clones share the bucket, so writers on several threads can share one
+/// budget.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    /// Allow `bytes_per_second`, with bursts of up to `burst` bytes
+    pub fn new(bytes_per_second: u64, burst: u64) -> RateLimiter {
+        assert!(bytes_per_second > 0, "rate must be positive");
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: burst as f64,
+                refilled: Instant::now(),
+                rate: bytes_per_second as f64,
+                burst: burst as f64,
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take `bytes` from the bucket, sleeping until the rate allows them
+    ///
+    /// Taking more than the bucket holds leaves it in debt, the caller
+    /// sleeps until the debt is paid off, and so do the writers after it.
+    pub fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.lock();
+            bucket.refill();
+            bucket.tokens -= bytes as f64;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+            } else {
+                Duration::from_secs(0)
+            }
+        };
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Take `bytes` from the bucket if it holds enough, without waiting
+    pub fn try_acquire(&self, bytes: u64) -> bool {
+        let mut bucket = self.lock();
+        bucket.refill();
+        if bucket.tokens >= bytes as f64 {
+            bucket.tokens -= bytes as f64;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Change the rate, e.g. following `write_stall_hint`
+    pub fn set_rate(&self, bytes_per_second: u64) {
+        assert!(bytes_per_second > 0, "rate must be positive");
+        let mut bucket = self.lock();
+        bucket.refill();
+        bucket.rate = bytes_per_second as f64;
+    }
+
+    /// The current rate in bytes per second
+    pub fn rate(&self) -> u64 {
+        self.lock().rate as u64
+    }
+}
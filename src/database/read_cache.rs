@@ -0,0 +1,187 @@
+//! Caching reads in memory
+//!
+//! `CachedDatabase` keeps the results of recent reads, including misses,
+//! in an LRU cache in front of the database, so hot keys skip the FFI call
+//! and block decoding. It watches the database, so writes done through
+//! any of its clones, single or batched, invalidate the cached keys before
+//! the next read sees them.
+//!
+//! Reads from a snapshot bypass the cache. As a watched database
+//! serialises its writes, the cache suits read-mostly workloads.
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::Error;
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::watch::Watcher;
+
+/// Counters of a `CachedDatabase`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads answered from the cache
+    pub hits: u64,
+    /// Reads that went to the database
+    pub misses: u64,
+    /// Entries dropped to make room
+    pub evictions: u64,
+    /// Entries dropped because their key was written
+    pub invalidations: u64,
+}
+
+struct State<K: Key> {
+    // the cached result and the tick of its last use, by encoded key
+    entries: HashMap<Vec<u8>, (Option<Vec<u8>>, u64)>,
+    // the entries by last use, oldest first
+    recency: BTreeMap<u64, Vec<u8>>,
+    tick: u64,
+    stats: CacheStats,
+    writes: Watcher<K>,
+}
+
+impl<K: Key> State<K> {
+    // drop the entries written since the last call
+    fn invalidate(&mut self) {
+        while let Some((key, _)) = self.writes.try_recv() {
+            let key = key.as_slice(|k| k.to_vec());
+            if let Some((_, tick)) = self.entries.remove(&key) {
+                self.recency.remove(&tick);
+                self.stats.invalidations += 1;
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &[u8]) -> Option<Option<Vec<u8>>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.1);
+        self.recency.insert(tick, key.to_vec());
+        entry.1 = tick;
+        Some(entry.0.clone())
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Option<Vec<u8>>, capacity: usize) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some((_, old)) = self.entries.insert(key.clone(), (value, tick)) {
+            self.recency.remove(&old);
+        }
+        self.recency.insert(tick, key);
+        while self.entries.len() > capacity {
+            let oldest = *self.recency.keys().next().unwrap();
+            let key = self.recency.remove(&oldest).unwrap();
+            self.entries.remove(&key);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+/// A database with an LRU cache of recent reads.
+pub struct CachedDatabase<K: Key> {
+    database: Database<K>,
+    capacity: usize,
+    // shared by clones
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K: Key> Clone for CachedDatabase<K> {
+    fn clone(&self) -> CachedDatabase<K> {
+        CachedDatabase {
+            database: self.database.clone(),
+            capacity: self.capacity,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K: Key> CachedDatabase<K> {
+    /// Cache the results of up to `capacity` keys read from `database`
+    pub fn new(database: Database<K>, capacity: usize) -> CachedDatabase<K> {
+        let writes = database.watch_prefix(&[]);
+        CachedDatabase {
+            database,
+            capacity,
+            state: Arc::new(Mutex::new(State {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+                stats: CacheStats::default(),
+                writes,
+            })),
+        }
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<K>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// get a value, from the cache if it was read recently
+    pub fn get<BK: Borrow<K>>(&self, options: ReadOptions<K>, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        if options.snapshot.is_some() {
+            return self.database.get(options, key);
+        }
+        let key = key.borrow();
+        let encoded = key.as_slice(|k| k.to_vec());
+        {
+            let mut state = self.lock();
+            state.invalidate();
+            if let Some(value) = state.touch(&encoded) {
+                state.stats.hits += 1;
+                return Ok(value);
+            }
+            state.stats.misses += 1;
+        }
+        // read without holding the lock, a write racing with it is
+        // invalidated by the next call
+        let value = self.database.get(options, key)?;
+        if self.capacity > 0 {
+            self.lock().insert(encoded, value.clone(), self.capacity);
+        }
+        Ok(value)
+    }
+
+    /// put a value, invalidating its cached entry
+    pub fn put<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<(), Error> {
+        self.database.put(options, key, value)
+    }
+
+    /// delete a value, invalidating its cached entry
+    pub fn delete<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<(), Error> {
+        self.database.delete(options, key)
+    }
+
+    /// write a batch, invalidating the cached entries of its keys
+    pub fn write(&self, options: WriteOptions, batch: &Writebatch<K>) -> Result<(), Error> {
+        self.database.write(options, batch)
+    }
+
+    /// The counters of the cache, shared by clones
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// The number of cached keys
+    pub fn cached(&self) -> usize {
+        let mut state = self.lock();
+        state.invalidate();
+        state.entries.len()
+    }
+
+    /// Drop all cached entries
+    pub fn clear_cache(&self) {
+        let mut state = self.lock();
+        state.invalidate();
+        state.entries.clear();
+        state.recency.clear();
+    }
+}
@@ -0,0 +1,299 @@
+//! Capturing writes for replication
+//!
+//! A `ReplicationLog` numbers every batch written through it with the
+//! next sequence, and stores the serialized batch under that sequence in
+//! the reserved namespace, in the same atomic write as the batch itself.
+//! Followers tail the log with `read_since`, apply the batches with
+//! `Writebatch::from_bytes`, and remember the last sequence they applied.
+//! The leader trims entries all followers have seen, by sequence or age.
+//!
+//! Only writes done through the log are captured. The last sequence is
+//! kept in the metadata of `Database::meta`, so sequences keep increasing
+//! after the log was trimmed and the database reopened.
+//!
+//! On the follower, a `Replica` applies the batches. It stores the
+//! sequence it applied last in the same write as each batch, and skips
+//! batches it already applied, so entries delivered more than once, e.g.
+//! when a follower retries after a crash, are applied exactly once.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use leveldb_sys::{leveldb_create_iterator, leveldb_iter_next};
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::batch::{Batch, Writebatch};
+use super::meta::meta_key;
+use super::iterator::{RawIterator, prefix_successor, raw_key, raw_seek_bytes, raw_status, raw_valid, raw_value};
+
+// the log entries, by big-endian sequence
+const LOG_PREFIX: &[u8] = b"\xff\xffleveldb.replication/";
+// the metadata holding the last sequence
+const SEQUENCE: &str = "leveldb.replication.sequence";
+// the metadata holding the last sequence applied by a replica
+const APPLIED: &str = "leveldb.replication.applied";
+// the size of the batches `trim` deletes in
+const TRIM_BATCH: usize = 1000;
+// every entry starts with its commit time, in milliseconds since the unix
+// epoch, big-endian
+const TIMESTAMP_SIZE: usize = 8;
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn entry_key(sequence: u64) -> Vec<u8> {
+    let mut key = LOG_PREFIX.to_vec();
+    key.extend_from_slice(&sequence.to_be_bytes());
+    key
+}
+
+fn malformed() -> Error {
+    Error::with_kind(ErrorKind::Corruption, "malformed replication log entry".to_string())
+}
+
+fn decode_entry(key: &[u8], value: &[u8]) -> Result<LogEntry, Error> {
+    let suffix = &key[LOG_PREFIX.len()..];
+    if suffix.len() != 8 || value.len() < TIMESTAMP_SIZE {
+        return Err(malformed());
+    }
+    let mut sequence = [0; 8];
+    sequence.copy_from_slice(suffix);
+    let mut timestamp = [0; TIMESTAMP_SIZE];
+    timestamp.copy_from_slice(&value[..TIMESTAMP_SIZE]);
+    Ok(LogEntry {
+        sequence: u64::from_be_bytes(sequence),
+        timestamp: UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(timestamp)),
+        batch: value[TIMESTAMP_SIZE..].to_vec(),
+    })
+}
+
+/// A batch committed through a `ReplicationLog`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The sequence of the batch, starting from 1
+    pub sequence: u64,
+    /// When the batch was committed, with millisecond precision
+    pub timestamp: SystemTime,
+    /// The batch, serialized by `Writebatch::to_bytes`
+    pub batch: Vec<u8>,
+}
+
+/// Writes to a database, recording each batch in a log.
+///
+/// Logs over clones of one database share its sequence.
+pub struct ReplicationLog<K: Key> {
+    database: Database<K>,
+}
+
+impl<K: Key> ReplicationLog<K> {
+    /// Record the writes to `database`, continuing after its last sequence
+    ///
+    /// Fails if the stored sequence is malformed.
+    pub fn new(database: Database<K>) -> Result<ReplicationLog<K>, Error> {
+        database.meta().get_u64(SEQUENCE)?;
+        Ok(ReplicationLog { database })
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// The sequence of the last committed batch, 0 if there is none
+    pub fn last_sequence(&self) -> Result<u64, Error> {
+        Ok(self.database.meta().get_u64(SEQUENCE)?.unwrap_or(0))
+    }
+
+    /// Write `batch` and record it under the next sequence, returning the
+    /// sequence
+    pub fn write(&self, options: WriteOptions, batch: &Writebatch<K>) -> Result<u64, Error> {
+        // serialises writes through logs over clones of the database, so
+        // sequences are assigned once and committed in order
+        let _guard = self.database.database.locks.lock(&meta_key(SEQUENCE))?;
+        let sequence = self.last_sequence()? + 1;
+        let mut entry = now_millis().to_be_bytes().to_vec();
+        entry.extend_from_slice(&batch.to_bytes());
+
+        let mut logged = Writebatch::new();
+        logged.append(batch);
+        logged.put_raw(&entry_key(sequence), &entry);
+        self.database.meta().put_in(&mut logged, SEQUENCE, &sequence.to_be_bytes());
+        self.database.write(options, &logged)?;
+        Ok(sequence)
+    }
+
+    /// Put a value, as a batch of its own
+    pub fn put(&self, options: WriteOptions, key: K, value: &[u8]) -> Result<u64, Error> {
+        let mut batch = Writebatch::new();
+        batch.put(key, value);
+        self.write(options, &batch)
+    }
+
+    /// Delete a value, as a batch of its own
+    pub fn delete(&self, options: WriteOptions, key: K) -> Result<u64, Error> {
+        let mut batch = Writebatch::new();
+        batch.delete(key);
+        self.write(options, &batch)
+    }
+
+    /// Iterate over the entries with a sequence after `sequence`, in order
+    ///
+    /// Entries committed while iterating may or may not be returned.
+    pub fn read_since(&self, sequence: u64) -> LogEntries<K> {
+        let iter = unsafe {
+            let c_readoptions = self.database.database.c_options.read(&ReadOptions::<K>::new());
+            let ptr = leveldb_create_iterator(self.database.database.ptr, c_readoptions.ptr);
+            RawIterator { ptr }
+        };
+        match sequence.checked_add(1) {
+            Some(next) => raw_seek_bytes(iter.ptr, &entry_key(next)),
+            // nothing comes after the largest sequence
+            None => raw_seek_bytes(iter.ptr, &prefix_successor(LOG_PREFIX).unwrap()),
+        }
+        LogEntries {
+            iter,
+            started: false,
+            failed: false,
+            database: self.database.clone(),
+        }
+    }
+
+    /// Delete the entries up to and including `sequence`, returning the
+    /// number of entries deleted
+    pub fn trim_to(&self, sequence: u64) -> Result<u64, Error> {
+        self.trim(|entry| entry.sequence <= sequence)
+    }
+
+    /// Delete the entries committed more than `age` ago, returning the
+    /// number of entries deleted
+    ///
+    /// Entries are trimmed in sequence order, up to the first one that is
+    /// young enough, even if the clock went back for a later one. An `age`
+    /// reaching back before the clock can tell trims nothing.
+    pub fn trim_older_than(&self, age: Duration) -> Result<u64, Error> {
+        match SystemTime::now().checked_sub(age) {
+            Some(cutoff) => self.trim(|entry| entry.timestamp < cutoff),
+            None => Ok(0),
+        }
+    }
+
+    fn trim<F: Fn(&LogEntry) -> bool>(&self, trim: F) -> Result<u64, Error> {
+        let mut count = 0;
+        let mut batch: Writebatch<K> = Writebatch::new();
+        for entry in self.read_since(0) {
+            let entry = entry?;
+            if !trim(&entry) {
+                break;
+            }
+            batch.delete_raw(&entry_key(entry.sequence));
+            count += 1;
+            if batch.len() >= TRIM_BATCH {
+                self.database.write(WriteOptions::new(), &batch)?;
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            self.database.write(WriteOptions::new(), &batch)?;
+        }
+        Ok(count)
+    }
+}
+
+/// An iterator over the entries of a `ReplicationLog`.
+///
+/// Yields `Err` as its last item if reading fails.
+pub struct LogEntries<K: Key> {
+    // dropped before the database it iterates over
+    iter: RawIterator,
+    started: bool,
+    failed: bool,
+    #[allow(dead_code)]
+    database: Database<K>,
+}
+
+impl<K: Key> Iterator for LogEntries<K> {
+    type Item = Result<LogEntry, Error>;
+
+    fn next(&mut self) -> Option<Result<LogEntry, Error>> {
+        if self.failed {
+            return None;
+        }
+        if self.started {
+            unsafe { leveldb_iter_next(self.iter.ptr) };
+        }
+        self.started = true;
+        if !raw_valid(self.iter.ptr) {
+            return match raw_status(self.iter.ptr) {
+                Ok(()) => None,
+                Err(error) => {
+                    self.failed = true;
+                    Some(Err(error))
+                }
+            };
+        }
+        let (key, value) = unsafe { (raw_key(self.iter.ptr), raw_value(self.iter.ptr)) };
+        if !key.starts_with(LOG_PREFIX) {
+            return None;
+        }
+        let entry = decode_entry(key, value);
+        self.failed = entry.is_err();
+        Some(entry)
+    }
+}
+
+/// Applies the batches of a `ReplicationLog` to a follower database.
+pub struct Replica<K: Key> {
+    database: Database<K>,
+}
+
+impl<K: Key> Replica<K> {
+    /// Apply batches to `database`
+    pub fn new(database: Database<K>) -> Replica<K> {
+        Replica { database }
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// The sequence of the last batch applied, 0 if there is none
+    pub fn applied(&self) -> Result<u64, Error> {
+        Ok(self.database.meta().get_u64(APPLIED)?.unwrap_or(0))
+    }
+
+    /// Apply the batch `batch_bytes`, serialized by `Writebatch::to_bytes`,
+    /// if it has the next sequence, returning whether it was applied
+    ///
+    /// Batches with a sequence that was already applied are skipped.
+    /// Fails with `InvalidArgument` if `sequence` skips ahead, which means
+    /// entries were lost on the way, and with `Corruption` if the batch is
+    /// malformed.
+    pub fn apply(&self, sequence: u64, batch_bytes: &[u8]) -> Result<bool, Error> {
+        // serialises replicas applying to clones of the database
+        let _guard = self.database.database.locks.lock(&meta_key(APPLIED))?;
+        let applied = self.applied()?;
+        if sequence <= applied {
+            return Ok(false);
+        }
+        if sequence != applied + 1 {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument,
+                                        format!("expected replication sequence {}, got {}",
+                                                applied + 1,
+                                                sequence)));
+        }
+        let mut batch = Writebatch::from_bytes(batch_bytes)?;
+        self.database.meta().put_in(&mut batch, APPLIED, &sequence.to_be_bytes());
+        self.database.write(WriteOptions::new(), &batch)?;
+        Ok(true)
+    }
+
+    /// Apply a log entry, like `apply`
+    pub fn apply_entry(&self, entry: &LogEntry) -> Result<bool, Error> {
+        self.apply(entry.sequence, &entry.batch)
+    }
+}
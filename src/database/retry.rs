@@ -0,0 +1,104 @@
+//! Opening databases that are still locked
+//!
+//! leveldb takes an exclusive lock on the `LOCK` file of a database while
+//! it is open, and opening it elsewhere fails until that lock is released,
+//! e.g. while a restarting service waits for its previous instance to shut
+//! down, or another thread to drop its handle. `Database::open_with_retry`
+//! retries such opens with exponential backoff.
+//!
+//! The lock is an `fcntl` lock, which the kernel releases when the process
+//! holding it dies, so a leftover `LOCK` file never blocks an open by
+//! itself and there is no stale lock to break: a lock that is still held
+//! belongs to a live process, or another handle of this one.
+use std::ffi::CString;
+use std::path::Path;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use leveldb_sys::*;
+use database::key::Key;
+use options::{Options, c_options};
+use super::Database;
+use super::error::{Error, ErrorKind, Operation};
+
+/// How often and how long to retry opening a locked database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of attempts, including the first one
+    pub attempts: u32,
+    /// The wait before the first retry, doubled after each one
+    pub initial_backoff: Duration,
+    /// The longest wait between attempts
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Try 10 times, waiting from 50ms up to 2s in between
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 10,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+// leveldb reports a held lock as `IO error: lock <path>/LOCK: <reason>`
+fn is_lock_error(error: &Error) -> bool {
+    *error.kind() == ErrorKind::IOError && error.message().contains("LOCK")
+}
+
+impl<K: Key> Database<K> {
+    /// Open a database like `open`, retrying while it is locked elsewhere
+    ///
+    /// Other errors are returned right away. Once all attempts failed, the
+    /// error names the path and the number of attempts.
+    pub fn open_with_retry(name: &Path, options: Options, policy: RetryPolicy) -> Result<Database<K>, Error> {
+        let c_name = name.to_str()
+            .and_then(|name| CString::new(name).ok())
+            .ok_or_else(|| Error::with_kind(ErrorKind::InvalidArgument, "invalid database path".to_string()))?;
+        let mut backoff = policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let mut error = ptr::null_mut();
+            let opened = unsafe {
+                let c_options = c_options(&options, None);
+                let db = leveldb_open(c_options as *const leveldb_options_t,
+                                      c_name.as_bytes_with_nul().as_ptr() as *const i8,
+                                      &mut error);
+                leveldb_options_destroy(c_options);
+                if error.is_null() {
+                    Ok(db)
+                } else {
+                    Err(Error::new_from_i8(error))
+                }
+            };
+            let error = match opened {
+                Ok(db) => return Ok(Database::new(db, name, options, None)),
+                Err(error) => error.with_operation(Operation::Open).with_path(name),
+            };
+            if !is_lock_error(&error) {
+                return Err(error);
+            }
+            if attempt >= policy.attempts {
+                return Err(Error::with_kind(ErrorKind::IOError, format!("{} is locked by another process or handle, \
+                                               gave up after {} attempts: {}",
+                                              name.display(),
+                                              attempt,
+                                              error.message()))
+                    .with_operation(Operation::Open)
+                    .with_path(name));
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(policy.max_backoff);
+            attempt += 1;
+        }
+    }
+}
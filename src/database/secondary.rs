@@ -0,0 +1,208 @@
+//! Catch-up readers of another process's database
+//!
+//! leveldb allows only one process to open a database, the others fail on
+//! its lock. A `SecondaryDatabase` reads the database of another process
+//! anyway, from a mirror: it links the tables the manifest of the primary
+//! lists into a directory of its own, since tables never change once
+//! written, copies the manifest and the logs, and opens the copy.
+//! `try_catch_up` mirrors the primary again once its manifest or logs
+//! changed and reopens the copy, so readers on the same machine follow the
+//! primary without talking to it.
+//!
+//! Every mirror lives in a numbered directory below the one passed to
+//! `SecondaryDatabase::open`. Mirrors replaced by a catch-up are removed
+//! as soon as no clone of their database is left, and opening removes the
+//! mirrors left behind by earlier runs. The tables are hard links where
+//! the file system allows it and copies otherwise, so the mirror takes
+//! little space on the file system of the primary.
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use database::key::Key;
+use options::Options;
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::live_files::{log_paths, read_version, table_path};
+
+type Template = Box<dyn Fn() -> Options + Send + Sync>;
+
+// the number of times the primary is mirrored before giving up on
+// finding its files unchanged while copying them
+const ATTEMPTS: usize = 10;
+
+fn io_error(error: io::Error) -> Error {
+    Error::with_kind(ErrorKind::IOError, format!("{}", error))
+}
+
+// what the primary looked like, to tell whether it changed since
+#[derive(PartialEq, Eq)]
+struct Observed {
+    current: String,
+    manifest_size: u64,
+    // by name, with their size
+    logs: Vec<(String, u64)>,
+}
+
+fn observe(primary: &Path) -> Result<Observed, Error> {
+    let current = fs::read_to_string(primary.join("CURRENT")).map_err(io_error)?;
+    let manifest_size = fs::metadata(primary.join(current.trim_end()))
+        .map_err(io_error)?
+        .len();
+    let mut logs = vec![];
+    for entry in fs::read_dir(primary).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if let Some(name) = entry.file_name().to_str().filter(|name| name.ends_with(".log")) {
+            logs.push((name.to_string(), entry.metadata().map_err(io_error)?.len()));
+        }
+    }
+    logs.sort();
+    Ok(Observed { current, manifest_size, logs })
+}
+
+fn link_or_copy(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Err(ref e) if e.kind() != io::ErrorKind::NotFound => fs::copy(from, to).map(|_| ()),
+        result => result,
+    }
+}
+
+// copy the files of the version the primary is at into `directory`,
+// returning false if they changed while copying
+fn mirror(primary: &Path, directory: &Path) -> Result<bool, Error> {
+    let version = read_version(primary)?;
+    let copied = || -> io::Result<()> {
+        for &(_, number) in &version.tables {
+            let table = match table_path(primary, number) {
+                Ok(table) => table,
+                Err(_) => return Err(io::ErrorKind::NotFound.into()),
+            };
+            link_or_copy(&table, &directory.join(table.file_name().unwrap()))?;
+        }
+        let mut manifest = vec![];
+        fs::File::open(primary.join(&version.manifest))?
+            .take(version.manifest_size)
+            .read_to_end(&mut manifest)?;
+        fs::File::create(directory.join(&version.manifest))?.write_all(&manifest)?;
+        let logs = log_paths(primary, &version).map_err(|e| {
+            io::Error::other(e.message().to_string())
+        })?;
+        for log in logs {
+            fs::copy(&log, directory.join(log.file_name().unwrap()))?;
+        }
+        fs::write(directory.join("CURRENT"), format!("{}\n", version.manifest))
+    };
+    match copied() {
+        Ok(()) => Ok(true),
+        // a compaction or a flush deleted a file meanwhile
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+/// A read replica of a database opened by another process.
+pub struct SecondaryDatabase<K: Key> {
+    primary: PathBuf,
+    root: PathBuf,
+    template: Template,
+    generation: u64,
+    observed: Observed,
+    database: Database<K>,
+    // replaced mirrors, with the database still open on them
+    retired: Vec<(PathBuf, Database<K>)>,
+}
+
+impl<K: Key> SecondaryDatabase<K> {
+    /// Mirror the database `primary` below `root` and open it
+    pub fn open(primary: &Path, root: &Path) -> Result<SecondaryDatabase<K>, Error> {
+        SecondaryDatabase::open_with(primary, root, Options::new)
+    }
+
+    /// Mirror the database `primary` below `root` and open it, with the
+    /// options returned by `template` each time
+    ///
+    /// The options can't create the mirror, `create_if_missing` is
+    /// ignored.
+    pub fn open_with<F>(primary: &Path, root: &Path, template: F) -> Result<SecondaryDatabase<K>, Error>
+        where F: Fn() -> Options + Send + Sync + 'static
+    {
+        fs::create_dir_all(root).map_err(io_error)?;
+        let mut generation = 0;
+        for entry in fs::read_dir(root).map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+            if let Some(number) = entry.file_name().to_str().and_then(|name| name.parse::<u64>().ok()) {
+                fs::remove_dir_all(entry.path()).map_err(io_error)?;
+                generation = generation.max(number);
+            }
+        }
+        let template: Template = Box::new(template);
+        let (observed, database) = SecondaryDatabase::open_mirror(primary,
+                                                                   &root.join(format!("{:06}", generation + 1)),
+                                                                   &template)?;
+        Ok(SecondaryDatabase {
+            primary: primary.to_path_buf(),
+            root: root.to_path_buf(),
+            template,
+            generation: generation + 1,
+            observed,
+            database,
+            retired: vec![],
+        })
+    }
+
+    fn open_mirror(primary: &Path, directory: &Path, template: &Template) -> Result<(Observed, Database<K>), Error> {
+        for _ in 0..ATTEMPTS {
+            let observed = observe(primary)?;
+            if directory.exists() {
+                fs::remove_dir_all(directory).map_err(io_error)?;
+            }
+            fs::create_dir_all(directory).map_err(io_error)?;
+            if mirror(primary, directory)? {
+                let mut options = template();
+                options.create_if_missing = false;
+                options.error_if_exists = false;
+                return Ok((observed, Database::open(directory, options)?));
+            }
+        }
+        Err(Error::with_kind(ErrorKind::Conflict, "the primary kept changing while mirroring it".to_string()))
+    }
+
+    /// The directory of the primary database
+    pub fn primary(&self) -> &Path {
+        &self.primary
+    }
+
+    /// Return the database, holding the state of the last catch-up
+    ///
+    /// Clones keep reading the state they were taken at. Writes go to the
+    /// mirror only, and are lost with the next catch-up.
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// Reopen the database on a new mirror if the primary changed since the
+    /// last catch-up, returning whether it did
+    pub fn try_catch_up(&mut self) -> Result<bool, Error> {
+        if observe(&self.primary)? == self.observed {
+            return Ok(false);
+        }
+        let directory = self.root.join(format!("{:06}", self.generation + 1));
+        let (observed, database) = SecondaryDatabase::open_mirror(&self.primary, &directory, &self.template)?;
+        let previous = self.root.join(format!("{:06}", self.generation));
+        self.generation += 1;
+        self.observed = observed;
+        let database = ::std::mem::replace(&mut self.database, database);
+        self.retired.push((previous, database));
+        let (retired, closed): (Vec<_>, Vec<_>) = self.retired
+            .drain(..)
+            .partition(|(_, database)| Arc::strong_count(&database.database) > 1);
+        self.retired = retired;
+        for (directory, database) in closed {
+            // close the database before removing its files
+            drop(database);
+            let _ = fs::remove_dir_all(directory);
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,473 @@
+//! HTTP access to a database
+//!
+//! `Server` exposes a database over plain HTTP/1.1, for internal tooling
+//! that wants a remote key-value store without linking leveldb. Keys are
+//! hex encoded in paths and query strings, values are sent as raw bodies:
+//!
+//! * `GET /keys/<key>` returns the value, or 404
+//! * `PUT /keys/<key>` stores the body
+//! * `DELETE /keys/<key>` deletes the key
+//! * `GET /scan?prefix=<prefix>&from=<key>&limit=<n>` returns a line per
+//!   entry, the hex encoded key and value separated by a space
+//! * `POST /batch` applies a body of `put <key> <value>` and
+//!   `delete <key>` lines, with hex encoded values, atomically
+//!
+//! A read-only server refuses writes, and with allowed prefixes set only
+//! keys under them can be read or written, both with 403. There is no
+//! authentication or encryption, bind to trusted networks only. Each
+//! connection carries a single request and is handled on its own thread,
+//! with a bounded number of connections at once. Reads and writes on a
+//! stalled connection time out, so slow clients can't hold a thread
+//! forever.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::keys::BinaryKey;
+use super::iterator::{Iterable, LevelDBIterator};
+
+// limits on requests, to keep clients from exhausting memory
+const MAX_HEAD_BYTES: u64 = 16 << 10;
+const MAX_BODY_BYTES: u64 = 64 << 20;
+const DEFAULT_SCAN_LIMIT: usize = 1000;
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+// the pause after failing to accept, e.g. when out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+fn io_error(error: io::Error) -> Error {
+    Error::with_kind(ErrorKind::IOError, format!("{}", error))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, body: Vec<u8>) -> Response {
+        Response { status, body }
+    }
+
+    fn empty(status: u16) -> Response {
+        Response::new(status, vec![])
+    }
+
+    fn text(status: u16, message: &str) -> Response {
+        Response::new(status, format!("{}\n", message).into_bytes())
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+impl From<Error> for Response {
+    fn from(error: Error) -> Response {
+        Response::text(500, &error.to_string())
+    }
+}
+
+// read a request, `Err` holding the response to refuse it with
+fn read_request<R: Read>(stream: R) -> Result<Request, Response> {
+    let bad_request = |message: &str| Response::text(400, message);
+    let mut reader = BufReader::new(stream);
+    let mut head = reader.by_ref().take(MAX_HEAD_BYTES);
+    let mut line = String::new();
+    head.read_line(&mut line).map_err(|_| bad_request("unreadable request"))?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(bad_request("malformed request line")),
+    };
+
+    let mut length: u64 = 0;
+    loop {
+        line.clear();
+        if head.read_line(&mut line).map_err(|_| bad_request("unreadable request"))? == 0 {
+            return Err(bad_request("truncated request head"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(colon) = header.find(':') {
+            if header[..colon].eq_ignore_ascii_case("content-length") {
+                length = header[colon + 1..].trim().parse().map_err(|_| bad_request("malformed content length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY_BYTES {
+        return Err(Response::text(413, "body too large"));
+    }
+    let mut body = Vec::new();
+    reader.take(length).read_to_end(&mut body).map_err(|_| bad_request("unreadable body"))?;
+    if body.len() as u64 != length {
+        return Err(bad_request("truncated body"));
+    }
+
+    let (path, query) = match target.find('?') {
+        Some(mark) => (target[..mark].to_string(), &target[mark + 1..]),
+        None => (target.clone(), ""),
+    };
+    let query = query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(equals) => (pair[..equals].to_string(), pair[equals + 1..].to_string()),
+            None => (pair.to_string(), String::new()),
+        })
+        .collect();
+    Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    })
+}
+
+fn write_response<W: Write>(mut stream: W, response: &Response) -> io::Result<()> {
+    write!(stream,
+           "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+           response.status,
+           response.reason(),
+           response.body.len())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// An HTTP server exposing a database.
+pub struct Server {
+    database: Database<BinaryKey>,
+    read_only: bool,
+    prefixes: Vec<Vec<u8>>,
+    max_connections: usize,
+    timeout: Duration,
+}
+
+impl Server {
+    /// Serve `database`, allowing reads and writes of all keys
+    pub fn new(database: Database<BinaryKey>) -> Server {
+        Server {
+            database,
+            read_only: false,
+            prefixes: vec![],
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Handle at most `max_connections` connections at once, 64 by default
+    ///
+    /// Further connections wait in the backlog of the listener until a
+    /// connection is done. Panics if `max_connections` is 0.
+    pub fn max_connections(mut self, max_connections: usize) -> Server {
+        assert!(max_connections > 0, "max connections must not be 0");
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Close connections where a read or a write waits longer than
+    /// `timeout`, 30 seconds by default
+    ///
+    /// Panics if `timeout` is zero.
+    pub fn timeout(mut self, timeout: Duration) -> Server {
+        assert!(timeout > Duration::from_secs(0), "timeout must not be zero");
+        self.timeout = timeout;
+        self
+    }
+
+    /// Refuse writes
+    pub fn read_only(mut self, read_only: bool) -> Server {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Allow access to the keys under `prefix`
+    ///
+    /// Once a prefix is allowed, keys outside all allowed prefixes can't be
+    /// read or written.
+    pub fn allow_prefix(mut self, prefix: &[u8]) -> Server {
+        self.prefixes.push(prefix.to_vec());
+        self
+    }
+
+    fn allowed(&self, key: &[u8]) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|prefix| key.starts_with(prefix))
+    }
+
+    /// Accept connections on `listener`, forever
+    ///
+    /// Failures to accept a connection are reported on stderr, and
+    /// accepting goes on after a pause.
+    pub fn serve(self, listener: TcpListener) -> Result<(), Error> {
+        serve(Arc::new(self), listener, None)
+    }
+
+    /// Bind to `address` and serve connections on a background thread,
+    /// until the returned handle is dropped
+    pub fn spawn<A: ToSocketAddrs>(self, address: A) -> Result<RunningServer, Error> {
+        let listener = TcpListener::bind(address).map_err(io_error)?;
+        let address = listener.local_addr().map_err(io_error)?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let server = Arc::new(self);
+        let flag = stopped.clone();
+        let thread = thread::spawn(move || {
+            let _ = serve(server, listener, Some(flag));
+        });
+        Ok(RunningServer {
+            address,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        if stream.set_read_timeout(Some(self.timeout)).is_err() ||
+           stream.set_write_timeout(Some(self.timeout)).is_err() {
+            return;
+        }
+        let response = match read_request(&stream) {
+            Ok(request) => self.respond(request),
+            Err(response) => response,
+        };
+        let _ = write_response(&stream, &response);
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        if self.read_only && ["PUT", "DELETE", "POST"].contains(&request.method.as_str()) {
+            return Response::text(403, "read-only server");
+        }
+        let result = if request.path.starts_with("/keys/") {
+            let key = match unhex(&request.path["/keys/".len()..]) {
+                Some(key) => key,
+                None => return Response::text(400, "malformed key"),
+            };
+            if !self.allowed(&key) {
+                return Response::text(403, "key not allowed");
+            }
+            self.key(&request.method, BinaryKey(key), &request.body)
+        } else if request.path == "/scan" {
+            if request.method != "GET" {
+                return Response::empty(405);
+            }
+            self.scan(&request.query)
+        } else if request.path == "/batch" {
+            if request.method != "POST" {
+                return Response::empty(405);
+            }
+            self.batch(&request.body)
+        } else {
+            return Response::empty(404);
+        };
+        result.unwrap_or_else(Response::from)
+    }
+
+    fn key(&self, method: &str, key: BinaryKey, body: &[u8]) -> Result<Response, Error> {
+        match method {
+            "GET" => Ok(match self.database.get(ReadOptions::new(), key)? {
+                Some(value) => Response::new(200, value),
+                None => Response::empty(404),
+            }),
+            "PUT" => {
+                self.database.put(WriteOptions::new(), key, body)?;
+                Ok(Response::empty(204))
+            }
+            "DELETE" => {
+                self.database.delete(WriteOptions::new(), key)?;
+                Ok(Response::empty(204))
+            }
+            _ => Ok(Response::empty(405)),
+        }
+    }
+
+    fn scan(&self, query: &[(String, String)]) -> Result<Response, Error> {
+        let mut prefix = vec![];
+        let mut from = None;
+        let mut limit = DEFAULT_SCAN_LIMIT;
+        for (name, value) in query {
+            match name.as_str() {
+                "prefix" | "from" => {
+                    let bytes = match unhex(value) {
+                        Some(bytes) => bytes,
+                        None => return Ok(Response::text(400, "malformed key")),
+                    };
+                    if name == "prefix" {
+                        prefix = bytes;
+                    } else {
+                        from = Some(bytes);
+                    }
+                }
+                "limit" => match value.parse() {
+                    Ok(value) => limit = value,
+                    Err(_) => return Ok(Response::text(400, "malformed limit")),
+                },
+                _ => return Ok(Response::text(400, "unknown parameter")),
+            }
+        }
+        if !self.allowed(&prefix) {
+            return Ok(Response::text(403, "prefix not allowed"));
+        }
+        let from = from.map(BinaryKey);
+        let mut iter = self.database.iter(ReadOptions::new()).prefix(&prefix);
+        if let Some(ref from) = from {
+            iter = iter.from(from);
+        }
+        let mut body = vec![];
+        for (key, value) in iter.by_ref().take(limit) {
+            body.extend_from_slice(format!("{} {}\n", hex(&key.0), hex(&value)).as_bytes());
+        }
+        iter.status()?;
+        Ok(Response::new(200, body))
+    }
+
+    fn batch(&self, body: &[u8]) -> Result<Response, Error> {
+        let body = match ::std::str::from_utf8(body) {
+            Ok(body) => body,
+            Err(_) => return Ok(Response::text(400, "malformed batch")),
+        };
+        let mut batch = Writebatch::new();
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (key, value) = match fields.as_slice() {
+                ["put", key, value] => (unhex(key), unhex(value).map(Some)),
+                ["delete", key] => (unhex(key), Some(None)),
+                _ => return Ok(Response::text(400, "malformed batch")),
+            };
+            let (key, value) = match (key, value) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return Ok(Response::text(400, "malformed batch")),
+            };
+            if !self.allowed(&key) {
+                return Ok(Response::text(403, "key not allowed"));
+            }
+            match value {
+                Some(value) => batch.put(BinaryKey(key), &value),
+                None => batch.delete(BinaryKey(key)),
+            }
+        }
+        self.database.write(WriteOptions::new(), &batch)?;
+        Ok(Response::empty(204))
+    }
+}
+
+// the number of connections being handled
+struct Connections {
+    active: Mutex<usize>,
+    done: Condvar,
+}
+
+// a connection being handled, counted until dropped
+struct Connection(Arc<Connections>);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        *self.0.active.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.done.notify_one();
+    }
+}
+
+impl Connections {
+    // wait until fewer than `max` connections are handled, and count one more
+    fn acquire(connections: &Arc<Connections>, max: usize) -> Connection {
+        let mut active = connections.active.lock().unwrap_or_else(|e| e.into_inner());
+        while *active >= max {
+            active = connections.done.wait(active).unwrap_or_else(|e| e.into_inner());
+        }
+        *active += 1;
+        Connection(connections.clone())
+    }
+}
+
+fn serve(server: Arc<Server>, listener: TcpListener, stopped: Option<Arc<AtomicBool>>) -> Result<(), Error> {
+    let connections = Arc::new(Connections {
+        active: Mutex::new(0),
+        done: Condvar::new(),
+    });
+    loop {
+        let connection = Connections::acquire(&connections, server.max_connections);
+        let accepted = listener.accept();
+        if stopped.as_ref().is_some_and(|stopped| stopped.load(Ordering::SeqCst)) {
+            return Ok(());
+        }
+        match accepted {
+            Ok((stream, _)) => {
+                let server = server.clone();
+                thread::spawn(move || {
+                    let _connection = connection;
+                    server.handle(stream)
+                });
+            }
+            Err(error) => {
+                eprintln!("leveldb server: failed to accept a connection: {}", error);
+                thread::sleep(ACCEPT_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Handle to a server running on a background thread, stopping it on drop.
+///
+/// Requests in flight are completed on their own threads. With all
+/// connections taken, dropping waits for one to be done.
+pub struct RunningServer {
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RunningServer {
+    /// The address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake the accepting thread up
+        let _ = TcpStream::connect(self.address);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Sharding over several databases
+//!
+//! `ShardedDatabase` spreads keys over several databases, so writes to
+//! different shards do not contend for the same write lock and log. Each
+//! key is placed by a `Partitioner`, either by hashing it or by ranges of
+//! keys. The placement is persistent: a sharded database must always be
+//! opened with the same shards, in the same order, and the same
+//! partitioner.
+//!
+//! Writes are only atomic per shard: a batch touching several shards is
+//! split into one batch per shard. Snapshots belong to a single database,
+//! so a snapshot passed in the read options is ignored.
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+
+use database::key::{Key, from_u8};
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::bytes::Bytes;
+use super::iterator::{self, Iterable};
+use super::merge::{self, MergedIterator, TieBreak};
+
+/// How keys are assigned to shards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Partitioner {
+    /// By a hash of the encoded key, spreading keys evenly
+    Hash,
+    /// By ranges of keys, given as the encoded keys the second and later
+    /// shards start at, in ascending order. Keeps ranges of keys on the
+    /// same shard.
+    Range(Vec<Vec<u8>>),
+}
+
+// FNV-1a, which unlike the std hashers is guaranteed to be stable, as the
+// placement of keys is persistent
+fn hash(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn unsnapshotted<'a, K: Key + 'a>(options: &ReadOptions<'a, K>) -> ReadOptions<'a, K> {
+    ReadOptions {
+        verify_checksums: options.verify_checksums,
+        fill_cache: options.fill_cache,
+        snapshot: None,
+        iterate_lower_bound: options.iterate_lower_bound,
+        iterate_upper_bound: options.iterate_upper_bound,
+    }
+}
+
+/// A database spread over several shards.
+pub struct ShardedDatabase<K: Key> {
+    shards: Vec<Database<K>>,
+    partitioner: Partitioner,
+}
+
+impl<K: Key> Clone for ShardedDatabase<K> {
+    fn clone(&self) -> ShardedDatabase<K> {
+        ShardedDatabase {
+            shards: self.shards.clone(),
+            partitioner: self.partitioner.clone(),
+        }
+    }
+}
+
+impl<K: Key> ShardedDatabase<K> {
+    /// Combine `shards` into one database
+    ///
+    /// Fails if there are no shards, or if a range partitioner doesn't
+    /// give ascending start keys for all shards but the first. All shards
+    /// must use the same comparator.
+    pub fn new(shards: Vec<Database<K>>, partitioner: Partitioner) -> Result<ShardedDatabase<K>, Error> {
+        if shards.is_empty() {
+            return Err(Error::with_kind(ErrorKind::InvalidArgument, "no shards".to_string()));
+        }
+        if let Partitioner::Range(ref starts) = partitioner {
+            if starts.len() != shards.len() - 1 {
+                return Err(Error::with_kind(ErrorKind::InvalidArgument,
+                                            format!("{} shards need {} range starts",
+                                                    shards.len(),
+                                                    shards.len() - 1)));
+            }
+            let ascending = starts.windows(2)
+                .all(|pair| shards[0].compare_keys(&pair[0], &pair[1]) == Ordering::Less);
+            if !ascending {
+                return Err(Error::with_kind(ErrorKind::InvalidArgument, "range starts must be ascending".to_string()));
+            }
+        }
+        Ok(ShardedDatabase {
+            shards,
+            partitioner,
+        })
+    }
+
+    /// Return the shards
+    pub fn shards(&self) -> &[Database<K>] {
+        &self.shards
+    }
+
+    /// Return the shard `key` is stored in
+    pub fn shard(&self, key: &K) -> &Database<K> {
+        &self.shards[key.as_slice(|k| self.shard_index(k))]
+    }
+
+    fn shard_index(&self, key: &[u8]) -> usize {
+        match self.partitioner {
+            Partitioner::Hash => (hash(key) % self.shards.len() as u64) as usize,
+            Partitioner::Range(ref starts) => {
+                starts.iter()
+                    .take_while(|start| self.shards[0].compare_keys(start, key) != Ordering::Greater)
+                    .count()
+            }
+        }
+    }
+
+    /// Return an iterator over the (Key,Value) pairs of all shards, in order
+    pub fn iter<'a>(&'a self, options: ReadOptions<'a, K>) -> ShardedIterator<'a, K> {
+        let shards: Vec<iterator::Iterator<'a, K>> =
+            self.shards.iter().map(|shard| shard.iter(unsnapshotted(&options))).collect();
+        // keys are on one shard only, so there are no ties
+        merge::merge(shards, TieBreak::All)
+    }
+}
+
+impl<K: Key> KV<K> for ShardedDatabase<K> {
+    fn get<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.borrow();
+        self.shard(key).get(unsnapshotted(&options), key)
+    }
+
+    fn get_bytes<'a, BK: Borrow<K>>(&self, options: ReadOptions<'a, K>, key: BK) -> Result<Option<Bytes>, Error> {
+        let key = key.borrow();
+        self.shard(key).get_bytes(unsnapshotted(&options), key)
+    }
+
+    fn multi_get<'a>(&self, options: ReadOptions<'a, K>, keys: &[K]) -> Vec<Result<Option<Vec<u8>>, Error>> {
+        keys.iter()
+            .map(|key| self.shard(key).get(unsnapshotted(&options), key))
+            .collect()
+    }
+
+    fn put<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<(), Error> {
+        let key = key.borrow();
+        self.shard(key).put(options, key, value)
+    }
+
+    fn delete<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<(), Error> {
+        let key = key.borrow();
+        self.shard(key).delete(options, key)
+    }
+
+    fn put_if_absent<BK: Borrow<K>>(&self, options: WriteOptions, key: BK, value: &[u8]) -> Result<bool, Error> {
+        let key = key.borrow();
+        self.shard(key).put_if_absent(options, key, value)
+    }
+
+    fn take<BK: Borrow<K>>(&self, options: WriteOptions, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.borrow();
+        self.shard(key).take(options, key)
+    }
+}
+
+impl<K: Key> Batch<K> for ShardedDatabase<K> {
+    /// Write a batch, split into one batch per shard it touches
+    ///
+    /// Each of those is atomic, but they are written one after another. If
+    /// one fails, the batches of the shards before it have been written.
+    fn write(&self, options: WriteOptions, batch: &Writebatch<K>) -> Result<(), Error> {
+        let mut batches: Vec<Writebatch<K>> = self.shards.iter().map(|_| Writebatch::new()).collect();
+        for (key, value) in batch.operations() {
+            let shard = &mut batches[self.shard_index(&key)];
+            match value {
+                Some(value) => shard.put(from_u8(&key), &value),
+                None => shard.delete(from_u8(&key)),
+            }
+        }
+        for (shard, batch) in self.shards.iter().zip(batches.iter()) {
+            if !batch.is_empty() {
+                shard.write(options, batch)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the entries of all shards, merged in key order.
+///
+/// Returns key and value as a tuple.
+pub type ShardedIterator<'a, K> = MergedIterator<'a, K>;
@@ -0,0 +1,458 @@
+//! Approximate on-disk sizes of key ranges
+use super::Database;
+use super::key::{Key, from_u8};
+use super::meta::RESERVED_PREFIX;
+use super::properties::Properties;
+use super::iterator::{RawIterator, raw_key, raw_seek_bytes, raw_valid, raw_value};
+use super::options::{ReadOptions, c_readoptions};
+use leveldb_sys::{leveldb_t, leveldb_approximate_sizes, leveldb_create_iterator,
+                  leveldb_readoptions_destroy, leveldb_iter_seek_to_first,
+                  leveldb_iter_seek_to_last, leveldb_iter_next};
+use libc::{c_char, c_int, size_t};
+use std::cmp::Ordering;
+
+// bisection steps per boundary, enough to split any range finer than the
+// block granularity of the estimates
+const MAX_BISECTIONS: usize = 128;
+
+/// Structs implementing this trait can estimate the disk space
+/// used by ranges of keys.
+pub trait ApproximateSizes<K: Key> {
+    /// Returns the approximate file system space used by each
+    /// `(start, limit)` range, in the order given.
+    ///
+    /// The sizes reflect compressed data on disk, recently written data
+    /// that is not yet compacted may not be accounted for. leveldb only
+    /// estimates the current files, snapshots return the sizes of their
+    /// database as it is now.
+    fn approximate_sizes(&self, ranges: &[(K, K)]) -> Vec<u64>;
+}
+
+impl<K: Key> ApproximateSizes<K> for Database<K> {
+    fn approximate_sizes(&self, ranges: &[(K, K)]) -> Vec<u64> {
+        let ranges: Vec<(Vec<u8>, Vec<u8>)> = ranges.iter()
+            .map(|r| (r.0.as_slice(|s| s.to_vec()), r.1.as_slice(|l| l.to_vec())))
+            .collect();
+        approximate_sizes_raw(self.database.ptr, &ranges)
+    }
+}
+
+fn approximate_sizes_raw(database: *mut leveldb_t, ranges: &[(Vec<u8>, Vec<u8>)]) -> Vec<u64> {
+    let start_ptrs: Vec<*const c_char> = ranges.iter().map(|r| r.0.as_ptr() as *const c_char).collect();
+    let start_lens: Vec<size_t> = ranges.iter().map(|r| r.0.len() as size_t).collect();
+    let limit_ptrs: Vec<*const c_char> = ranges.iter().map(|r| r.1.as_ptr() as *const c_char).collect();
+    let limit_lens: Vec<size_t> = ranges.iter().map(|r| r.1.len() as size_t).collect();
+    let mut sizes = vec![0u64; ranges.len()];
+    unsafe {
+        leveldb_approximate_sizes(database,
+                                  ranges.len() as c_int,
+                                  start_ptrs.as_ptr(),
+                                  start_lens.as_ptr(),
+                                  limit_ptrs.as_ptr(),
+                                  limit_lens.as_ptr(),
+                                  sizes.as_mut_ptr());
+    }
+    sizes
+}
+
+// entries read to estimate the average size of the entries in a range
+const SAMPLE_ENTRIES: u64 = 64;
+
+// entries read per sample of `estimate_num_keys`, also the size up to
+// which databases are counted
+const DENSITY_SAMPLE_ENTRIES: u64 = 1024;
+// bytes read per sample at most, well above the block size of the estimates
+const DENSITY_SAMPLE_BYTES: u64 = 64 << 10;
+const DENSITY_SAMPLES: u64 = 16;
+// the memory a memtable entry takes besides its key and value: the skiplist
+// node, the sequence number and the length prefixes
+const MEMTABLE_ENTRY_OVERHEAD: u64 = 40;
+
+/// An estimate of the number of keys, returned by
+/// `Database::estimate_num_keys`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCountEstimate {
+    /// The most likely number of keys
+    pub estimate: u64,
+    /// The fewest keys there likely are
+    pub low: u64,
+    /// The most keys there likely are
+    pub high: u64,
+}
+
+impl KeyCountEstimate {
+    fn exact(count: u64) -> KeyCountEstimate {
+        KeyCountEstimate {
+            estimate: count,
+            low: count,
+            high: count,
+        }
+    }
+
+    /// Whether the keys were counted
+    pub fn is_exact(&self) -> bool {
+        self.low == self.high
+    }
+}
+
+// the middle of `a` and `b`, read as big-endian numbers of equal length
+fn midpoint(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut sum = vec![0u16; a.len()];
+    let mut carry = 0;
+    for i in (0..a.len()).rev() {
+        let s = a[i] as u16 + b[i] as u16 + carry;
+        sum[i] = s & 0xff;
+        carry = s >> 8;
+    }
+    let mut mid = vec![0u8; a.len()];
+    for i in 0..a.len() {
+        mid[i] = ((carry << 7) | (sum[i] >> 1)) as u8;
+        carry = sum[i] & 1;
+    }
+    mid
+}
+
+fn padded(key: &[u8], len: usize) -> Vec<u8> {
+    let mut key = key.to_vec();
+    key.resize(len, 0);
+    key
+}
+
+impl<K: Key> Database<K> {
+    // bisect between `low` and `high` for the smallest byte string with
+    // `target` bytes between `first` and it, moving `low` up to the last
+    // string below, where the search for a larger target can start
+    fn bisect(&self, first: &[u8], low: &mut Vec<u8>, high: &[u8], target: u64) -> Vec<u8> {
+        let mut high = high.to_vec();
+        for _ in 0..MAX_BISECTIONS {
+            let mid = midpoint(low, &high);
+            if mid == *low {
+                break;
+            }
+            let size = approximate_sizes_raw(self.database.ptr, &[(first.to_vec(), mid.clone())])[0];
+            if size < target {
+                *low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        high
+    }
+
+    /// Split the keyspace into up to `n` ranges of about equal size on disk
+    ///
+    /// Each range is a `(start, end)` pair of encoded keys, `start`
+    /// included and `end` excluded, the end of the last range is empty and
+    /// stands for the end of the keyspace. The ranges are contiguous and
+    /// all bounds are keys of the database, so callers can scan them with
+    /// `from` and `until` on threads of their own, each from its own
+    /// snapshot.
+    ///
+    /// The split bisects byte strings with `approximate_sizes`, so data
+    /// still in the memtable isn't accounted for, and with a custom
+    /// comparator, whose order the bisection can't follow, the keyspace is
+    /// returned as a single range. Fewer than `n` ranges are returned if
+    /// the data is too small to split. An empty database has no ranges.
+    pub fn split_ranges(&self, n: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        assert!(n > 0, "can't split into 0 ranges");
+        let iter = unsafe {
+            let c_readoptions = c_readoptions(&ReadOptions::<K>::new());
+            let ptr = leveldb_create_iterator(self.database.ptr, c_readoptions);
+            leveldb_readoptions_destroy(c_readoptions);
+            RawIterator { ptr }
+        };
+        let first = unsafe {
+            leveldb_iter_seek_to_first(iter.ptr);
+            if !raw_valid(iter.ptr) {
+                return vec![];
+            }
+            raw_key(iter.ptr).to_vec()
+        };
+        let mut end = unsafe {
+            leveldb_iter_seek_to_last(iter.ptr);
+            raw_key(iter.ptr).to_vec()
+        };
+        // the smallest key after the last one
+        end.push(0);
+
+        let mut starts = vec![first.clone()];
+        if n > 1 && self.database.comparator.is_none() {
+            let total = approximate_sizes_raw(self.database.ptr, &[(first.clone(), end.clone())])[0];
+            let len = first.len().max(end.len());
+            let high = padded(&end, len);
+            let mut low = padded(&first, len);
+            for i in 1..n {
+                let target = total / n as u64 * i as u64;
+                let bound = self.bisect(&first, &mut low, &high, target);
+                // start the range at the first key of the database at the
+                // boundary
+                raw_seek_bytes(iter.ptr, &bound);
+                if !raw_valid(iter.ptr) {
+                    break;
+                }
+                let start = unsafe { raw_key(iter.ptr).to_vec() };
+                if start > *starts.last().unwrap() {
+                    starts.push(start);
+                }
+            }
+        }
+
+        let mut ranges = Vec::with_capacity(starts.len());
+        let mut starts = starts.into_iter().peekable();
+        while let Some(start) = starts.next() {
+            let end = starts.peek().cloned().unwrap_or_default();
+            ranges.push((start, end));
+        }
+        ranges
+    }
+    /// Return about `n` keys sampled evenly across the keyspace, in order
+    ///
+    /// The samples are the first keys at the points splitting the
+    /// keyspace into `n` parts of equal size on disk, found by bisecting
+    /// like `split_ranges`, so only a few blocks are read per key. With a
+    /// custom comparator, or while all data is still in the memtable, every
+    /// n-th key of a scan is taken instead. Keys are read from the
+    /// snapshot of `options`, if any. Fewer keys are returned if samples
+    /// fall on the same key, and reserved keys are never returned.
+    pub fn sample_keys<'a>(&self, options: ReadOptions<'a, K>, n: usize) -> Vec<K> {
+        if n == 0 {
+            return vec![];
+        }
+        let iter = unsafe {
+            let c_readoptions = self.database.c_options.read(&options);
+            RawIterator { ptr: leveldb_create_iterator(self.database.ptr, c_readoptions.ptr) }
+        };
+        let first = unsafe {
+            leveldb_iter_seek_to_first(iter.ptr);
+            if !raw_valid(iter.ptr) {
+                return vec![];
+            }
+            raw_key(iter.ptr).to_vec()
+        };
+        let mut end = unsafe {
+            leveldb_iter_seek_to_last(iter.ptr);
+            raw_key(iter.ptr).to_vec()
+        };
+        end.push(0);
+        let total = match self.database.comparator {
+            None => approximate_sizes_raw(self.database.ptr, &[(first.clone(), end.clone())])[0],
+            Some(_) => 0,
+        };
+
+        let mut samples: Vec<Vec<u8>> = Vec::with_capacity(n);
+        let mut sample = |key: &[u8]| {
+            if !key.starts_with(RESERVED_PREFIX) && samples.last().map_or(true, |last| &last[..] != key) {
+                samples.push(key.to_vec());
+            }
+        };
+        if total == 0 {
+            let mut count: u64 = 0;
+            unsafe { leveldb_iter_seek_to_first(iter.ptr) };
+            while raw_valid(iter.ptr) {
+                if !unsafe { raw_key(iter.ptr) }.starts_with(RESERVED_PREFIX) {
+                    count += 1;
+                }
+                unsafe { leveldb_iter_next(iter.ptr) };
+            }
+            // the middle entry of each of `n` parts
+            let mut index = 0;
+            let mut next = 0;
+            unsafe { leveldb_iter_seek_to_first(iter.ptr) };
+            while raw_valid(iter.ptr) && next < n as u64 {
+                let key = unsafe { raw_key(iter.ptr) };
+                if key.starts_with(RESERVED_PREFIX) {
+                    unsafe { leveldb_iter_next(iter.ptr) };
+                } else if index == (2 * next + 1) * count / (2 * n as u64) {
+                    sample(key);
+                    next += 1;
+                } else {
+                    index += 1;
+                    unsafe { leveldb_iter_next(iter.ptr) };
+                }
+            }
+        } else {
+            let len = first.len().max(end.len());
+            let high = padded(&end, len);
+            let mut low = padded(&first, len);
+            for i in 0..n as u64 {
+                // the middle of part `i`
+                let target = (total as u128 * (2 * i + 1) as u128 / (2 * n as u128)) as u64;
+                let bound = self.bisect(&first, &mut low, &high, target);
+                raw_seek_bytes(iter.ptr, &bound);
+                if !raw_valid(iter.ptr) {
+                    break;
+                }
+                sample(unsafe { raw_key(iter.ptr) });
+            }
+        }
+        samples.iter().map(|key| from_u8(key)).collect()
+    }
+
+    /// Estimate the number of keys, without reading them all
+    ///
+    /// Databases of up to a thousand keys are counted. For larger ones,
+    /// the number of keys per byte on disk is sampled at a few points
+    /// spread over the keyspace, and the tables hold the size on disk
+    /// times the average, within the lowest and highest sampled density.
+    /// The entries in the memtable are estimated from
+    /// `approximate_memory_usage` and the average size of the sampled
+    /// entries. They are left out of the lower bound, as the memtable may
+    /// as well hold updates of existing keys. The memory usage includes the block
+    /// cache, so the estimate tends to be high right after heavy reads.
+    ///
+    /// With a custom comparator, the density is only sampled at the start
+    /// of the keyspace. Reserved keys are counted as well.
+    pub fn estimate_num_keys(&self) -> KeyCountEstimate {
+        let mut options = ReadOptions::<K>::new();
+        options.fill_cache = false;
+        let iter = unsafe {
+            let c_readoptions = self.database.c_options.read(&options);
+            RawIterator { ptr: leveldb_create_iterator(self.database.ptr, c_readoptions.ptr) }
+        };
+        // read from `iter` on, returning the entries and bytes read
+        let read_sample = || {
+            let (mut count, mut bytes) = (0, 0);
+            while raw_valid(iter.ptr) && count < DENSITY_SAMPLE_ENTRIES && bytes < DENSITY_SAMPLE_BYTES {
+                let (key, value) = unsafe { (raw_key(iter.ptr), raw_value(iter.ptr)) };
+                count += 1;
+                bytes += (key.len() + value.len()) as u64;
+                unsafe { leveldb_iter_next(iter.ptr) };
+            }
+            (count, bytes)
+        };
+
+        unsafe { leveldb_iter_seek_to_first(iter.ptr) };
+        if !raw_valid(iter.ptr) {
+            return KeyCountEstimate::exact(0);
+        }
+        let first = unsafe { raw_key(iter.ptr) }.to_vec();
+        let (mut sampled_keys, mut sampled_bytes) = read_sample();
+        if !raw_valid(iter.ptr) {
+            return KeyCountEstimate::exact(sampled_keys);
+        }
+        // at least the keys read are there
+        let floor = sampled_keys;
+        let mut end = unsafe {
+            leveldb_iter_seek_to_last(iter.ptr);
+            raw_key(iter.ptr).to_vec()
+        };
+        end.push(0);
+        let total = approximate_sizes_raw(self.database.ptr, &[(first.clone(), end.clone())])[0];
+
+        let mut points = vec![first.clone()];
+        if self.database.comparator.is_none() && total > 0 {
+            let len = first.len().max(end.len());
+            let high = padded(&end, len);
+            let mut low = padded(&first, len);
+            for i in 0..DENSITY_SAMPLES {
+                let target = (total as u128 * (2 * i + 1) as u128 / (2 * DENSITY_SAMPLES) as u128) as u64;
+                points.push(self.bisect(&first, &mut low, &high, target));
+            }
+        }
+        let mut densities = vec![];
+        for point in points {
+            raw_seek_bytes(iter.ptr, &point);
+            if !raw_valid(iter.ptr) {
+                continue;
+            }
+            let start = unsafe { raw_key(iter.ptr) }.to_vec();
+            let (count, bytes) = read_sample();
+            let stop = if raw_valid(iter.ptr) {
+                unsafe { raw_key(iter.ptr) }.to_vec()
+            } else {
+                end.clone()
+            };
+            sampled_keys += count;
+            sampled_bytes += bytes;
+            let size = approximate_sizes_raw(self.database.ptr, &[(start, stop)])[0];
+            if size > 0 {
+                densities.push(count as f64 / size as f64);
+            }
+        }
+
+        let (mut estimate, mut low, mut high) = (0, floor, floor);
+        if !densities.is_empty() {
+            let mean = densities.iter().sum::<f64>() / densities.len() as f64;
+            let min = densities.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = densities.iter().cloned().fold(0.0, f64::max);
+            estimate = (total as f64 * mean) as u64;
+            low = low.max((total as f64 * min) as u64);
+            high = high.max((total as f64 * max) as u64);
+        }
+        let entry_size = sampled_bytes / sampled_keys.max(1) + MEMTABLE_ENTRY_OVERHEAD;
+        let in_memory = self.approximate_memory_usage().unwrap_or(0) / entry_size;
+        estimate += in_memory;
+        high += in_memory;
+        KeyCountEstimate {
+            estimate: estimate.clamp(low, high),
+            low,
+            high,
+        }
+    }
+
+    // estimate the number of entries from `start` on, up to `limit`,
+    // included if its flag is set, whose key starts with `prefix`
+    //
+    // Ranges of up to `SAMPLE_ENTRIES` are counted. Larger ones divide the
+    // size on disk by the average size of the first entries, which
+    // underestimates compressed data and misses data in the memtable.
+    pub(crate) fn estimate_entries(&self,
+                                   start: Option<&[u8]>,
+                                   limit: Option<(&[u8], bool)>,
+                                   prefix: Option<&[u8]>)
+                                   -> u64 {
+        let mut options = ReadOptions::<K>::new();
+        options.fill_cache = false;
+        let iter = unsafe {
+            let c_readoptions = self.database.c_options.read(&options);
+            RawIterator { ptr: leveldb_create_iterator(self.database.ptr, c_readoptions.ptr) }
+        };
+        let in_range = |key: &[u8]| {
+            prefix.map_or(true, |prefix| key.starts_with(prefix)) &&
+            limit.map_or(true, |(limit, inclusive)| match self.compare_keys(key, limit) {
+                Ordering::Less => true,
+                Ordering::Equal => inclusive,
+                Ordering::Greater => false,
+            })
+        };
+        match start {
+            Some(start) => raw_seek_bytes(iter.ptr, start),
+            None => unsafe { leveldb_iter_seek_to_first(iter.ptr) },
+        }
+        if !raw_valid(iter.ptr) {
+            return 0;
+        }
+        let first = unsafe { raw_key(iter.ptr) }.to_vec();
+        let mut count = 0;
+        let mut bytes = 0;
+        while count < SAMPLE_ENTRIES {
+            if !raw_valid(iter.ptr) {
+                return count;
+            }
+            let (key, value) = unsafe { (raw_key(iter.ptr), raw_value(iter.ptr)) };
+            if !in_range(key) {
+                return count;
+            }
+            count += 1;
+            bytes += (key.len() + value.len()) as u64;
+            unsafe { leveldb_iter_next(iter.ptr) };
+        }
+        let end = match limit {
+            Some((limit, inclusive)) => {
+                let mut end = limit.to_vec();
+                if inclusive {
+                    end.push(0);
+                }
+                end
+            }
+            None => unsafe {
+                leveldb_iter_seek_to_last(iter.ptr);
+                let mut end = raw_key(iter.ptr).to_vec();
+                end.push(0);
+                end
+            },
+        };
+        let size = approximate_sizes_raw(self.database.ptr, &[(first, end)])[0];
+        count.max(size / (bytes / count).max(1))
+    }
+}
@@ -8,12 +8,18 @@ use leveldb_sys::{leveldb_release_snapshot, leveldb_create_snapshot};
 use database::key::Key;
 use database::Database;
 use database::kv::KV;
+use database::bytes::Bytes;
 
 use database::error::Error;
 use database::options::ReadOptions;
 use database::iterator::{Iterable, Iterator, KeyIterator, ValueIterator};
+use database::sizes::ApproximateSizes;
+use database::trace::Timer;
+use database::merge::{Head, next_head};
 
 use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::sync::Arc;
 
 #[allow(missing_docs)]
 struct RawSnapshot {
@@ -47,14 +53,17 @@ pub trait Snapshots<K: Key> {
 impl<K: Key> Snapshots<K> for Database<K> {
     fn snapshot<'a>(&'a self) -> Snapshot<'a, K> {
         let db_ptr = self.database.ptr;
-        let snap = unsafe { leveldb_create_snapshot(db_ptr) };
+        let snap = {
+            let _timer = Timer::start("snapshot");
+            unsafe { leveldb_create_snapshot(db_ptr) }
+        };
 
         let raw = RawSnapshot {
-            db_ptr: db_ptr,
+            db_ptr,
             ptr: snap,
         };
         Snapshot {
-            raw: raw,
+            raw,
             database: self,
         }
     }
@@ -72,24 +81,244 @@ impl<'a, K: Key> Snapshot<'a, K> {
         self.database.get(options, key)
     }
 
+    /// fetches a key from the database without copying the value
+    ///
+    /// Inserts this snapshot into ReadOptions before reading
+    pub fn get_bytes<BK: Borrow<K>>(&'a self,
+                     mut options: ReadOptions<'a, K>,
+                     key: BK)
+                     -> Result<Option<Bytes>, Error> {
+        options.snapshot = Some(self);
+        self.database.get_bytes(options, key)
+    }
+
+    /// checks whether the snapshot holds a value for a key, without
+    /// copying the value
+    ///
+    /// Inserts this snapshot into ReadOptions before reading
+    pub fn contains_key<BK: Borrow<K>>(&'a self,
+                        mut options: ReadOptions<'a, K>,
+                        key: BK)
+                        -> Result<bool, Error> {
+        options.snapshot = Some(self);
+        self.database.contains_key(options, key)
+    }
+
     #[inline]
     #[allow(missing_docs)]
     pub fn raw_ptr(&self) -> *mut leveldb_snapshot_t {
         self.raw.ptr
     }
+
+    pub(crate) fn database(&self) -> &'a Database<K> {
+        self.database
+    }
 }
 
 impl<'a, K: Key + 'a> Iterable<'a, K> for Snapshot<'a, K> {
-    fn iter(&'a self, mut options: ReadOptions<'a, K>) -> Iterator<K> {
+    fn iter(&'a self, mut options: ReadOptions<'a, K>) -> Iterator<'a, K> {
         options.snapshot = Some(self);
         self.database.iter(options)
     }
-    fn keys_iter(&'a self, mut options: ReadOptions<'a, K>) -> KeyIterator<K> {
+    fn keys_iter(&'a self, mut options: ReadOptions<'a, K>) -> KeyIterator<'a, K> {
         options.snapshot = Some(self);
         self.database.keys_iter(options)
     }
-    fn value_iter(&'a self, mut options: ReadOptions<'a, K>) -> ValueIterator<K> {
+    fn value_iter(&'a self, mut options: ReadOptions<'a, K>) -> ValueIterator<'a, K> {
         options.snapshot = Some(self);
         self.database.value_iter(options)
     }
 }
+
+/// A difference between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change<K> {
+    /// The key was added, with its value
+    Added(K, Vec<u8>),
+    /// The key was removed
+    Removed(K),
+    /// The value of the key changed, from the first to the second value
+    Changed(K, Vec<u8>, Vec<u8>),
+}
+
+/// Compare the snapshots `older` and `newer` of the same database
+///
+/// Returns an iterator over the keys that differ, in key order. Both
+/// snapshots are scanned in one pass, with the flags of `options`.
+///
+/// # Panics
+///
+/// If the snapshots belong to different databases.
+pub fn diff<'a, K: Key + 'a>(older: &'a Snapshot<'a, K>,
+                             newer: &'a Snapshot<'a, K>,
+                             options: ReadOptions<'a, K>)
+                             -> Diff<'a, K> {
+    assert!(older.raw.db_ptr == newer.raw.db_ptr,
+            "snapshots of different databases can't be compared");
+    let scan = |snapshot: &'a Snapshot<'a, K>| {
+        snapshot.iter(ReadOptions {
+            verify_checksums: options.verify_checksums,
+            fill_cache: options.fill_cache,
+            snapshot: None,
+            iterate_lower_bound: options.iterate_lower_bound,
+            iterate_upper_bound: options.iterate_upper_bound,
+        })
+    };
+    let mut older_iter = scan(older);
+    let mut newer_iter = scan(newer);
+    Diff {
+        database: older.database,
+        older_head: next_head(&mut older_iter),
+        newer_head: next_head(&mut newer_iter),
+        older: older_iter,
+        newer: newer_iter,
+    }
+}
+
+/// An iterator over the differences between two snapshots.
+pub struct Diff<'a, K: Key + 'a> {
+    // used to compare keys
+    database: &'a Database<K>,
+    older: Iterator<'a, K>,
+    newer: Iterator<'a, K>,
+    older_head: Head<K>,
+    newer_head: Head<K>,
+}
+
+impl<'a, K: Key + 'a> ::std::iter::Iterator for Diff<'a, K> {
+    type Item = Change<K>;
+
+    fn next(&mut self) -> Option<Change<K>> {
+        loop {
+            let order = match (&self.older_head, &self.newer_head) {
+                (&None, &None) => return None,
+                (&Some(_), &None) => Ordering::Less,
+                (&None, &Some(_)) => Ordering::Greater,
+                (&Some((ref older, _, _)), &Some((ref newer, _, _))) => self.database.compare_keys(older, newer),
+            };
+            match order {
+                Ordering::Less => {
+                    let head = next_head(&mut self.older);
+                    let (_, key, _) = ::std::mem::replace(&mut self.older_head, head).unwrap();
+                    return Some(Change::Removed(key));
+                }
+                Ordering::Greater => {
+                    let head = next_head(&mut self.newer);
+                    let (_, key, value) = ::std::mem::replace(&mut self.newer_head, head).unwrap();
+                    return Some(Change::Added(key, value));
+                }
+                Ordering::Equal => {
+                    let older_head = next_head(&mut self.older);
+                    let newer_head = next_head(&mut self.newer);
+                    let (_, _, old) = ::std::mem::replace(&mut self.older_head, older_head).unwrap();
+                    let (_, key, new) = ::std::mem::replace(&mut self.newer_head, newer_head).unwrap();
+                    if old != new {
+                        return Some(Change::Changed(key, old, new));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: Key + 'a> ApproximateSizes<K> for Snapshot<'a, K> {
+    fn approximate_sizes(&self, ranges: &[(K, K)]) -> Vec<u64> {
+        self.database.approximate_sizes(ranges)
+    }
+}
+
+/// A database snapshot owning a handle to the database
+///
+/// In contrast to `Snapshot`, it does not borrow the database and can be
+/// stored alongside it or moved into other threads. The handle shares the
+/// internals of the database, which stay open until the snapshot is
+/// released on drop.
+pub struct OwnedSnapshot<K: Key + 'static> {
+    // declared first, the snapshot must be released before the database
+    snapshot: Snapshot<'static, K>,
+    // boxed, so the snapshot's reference stays valid when this is moved
+    #[allow(dead_code)]
+    database: Box<Database<K>>,
+}
+
+unsafe impl<K: Key + Send + Sync> Send for OwnedSnapshot<K> {}
+unsafe impl<K: Key + Send + Sync> Sync for OwnedSnapshot<K> {}
+
+impl<K: Key + 'static> OwnedSnapshot<K> {
+    fn new(database: Database<K>) -> OwnedSnapshot<K> {
+        let database = Box::new(database);
+        // the snapshot is dropped before the box, so the reference outlives it
+        let reference: &'static Database<K> = unsafe { &*(&*database as *const Database<K>) };
+        OwnedSnapshot {
+            snapshot: reference.snapshot(),
+            database,
+        }
+    }
+}
+
+/// Structs implementing this trait can create snapshots that own
+/// a handle to the database.
+pub trait OwnedSnapshots<K: Key + 'static> {
+    /// Creates a snapshot and returns a struct
+    /// representing it.
+    fn owned_snapshot(&self) -> OwnedSnapshot<K>;
+}
+
+impl<K: Key + 'static> OwnedSnapshots<K> for Database<K> {
+    fn owned_snapshot(&self) -> OwnedSnapshot<K> {
+        OwnedSnapshot::new(self.clone())
+    }
+}
+
+impl<K: Key + 'static> OwnedSnapshots<K> for Arc<Database<K>> {
+    fn owned_snapshot(&self) -> OwnedSnapshot<K> {
+        OwnedSnapshot::new((**self).clone())
+    }
+}
+
+impl<K: Key + 'static> OwnedSnapshot<K> {
+    /// Borrow the snapshot for use with APIs taking a `Snapshot`.
+    pub fn as_snapshot<'a>(&'a self) -> &'a Snapshot<'a, K> {
+        &self.snapshot
+    }
+
+    /// fetches a key from the database
+    pub fn get<'a, BK: Borrow<K>>(&'a self,
+                                  options: ReadOptions<'a, K>,
+                                  key: BK)
+                                  -> Result<Option<Vec<u8>>, Error> {
+        self.as_snapshot().get(options, key)
+    }
+
+    /// fetches a key from the database without copying the value
+    pub fn get_bytes<'a, BK: Borrow<K>>(&'a self,
+                                        options: ReadOptions<'a, K>,
+                                        key: BK)
+                                        -> Result<Option<Bytes>, Error> {
+        self.as_snapshot().get_bytes(options, key)
+    }
+
+    /// checks whether the snapshot holds a value for a key, without
+    /// copying the value
+    pub fn contains_key<'a, BK: Borrow<K>>(&'a self, options: ReadOptions<'a, K>, key: BK) -> Result<bool, Error> {
+        self.as_snapshot().contains_key(options, key)
+    }
+}
+
+impl<K: Key + 'static> ApproximateSizes<K> for OwnedSnapshot<K> {
+    fn approximate_sizes(&self, ranges: &[(K, K)]) -> Vec<u64> {
+        self.as_snapshot().approximate_sizes(ranges)
+    }
+}
+
+impl<'a, K: Key + 'static> Iterable<'a, K> for OwnedSnapshot<K> {
+    fn iter(&'a self, options: ReadOptions<'a, K>) -> Iterator<'a, K> {
+        self.as_snapshot().iter(options)
+    }
+    fn keys_iter(&'a self, options: ReadOptions<'a, K>) -> KeyIterator<'a, K> {
+        self.as_snapshot().keys_iter(options)
+    }
+    fn value_iter(&'a self, options: ReadOptions<'a, K>) -> ValueIterator<'a, K> {
+        self.as_snapshot().value_iter(options)
+    }
+}
@@ -0,0 +1,166 @@
+//! Tracing of database operations
+//!
+//! With the `tracing` feature, gets, puts, deletes, batch writes,
+//! compactions, snapshot creation and iterators report a `Span` to the
+//! `Tracer` installed with `set_tracer`, once they finish. Spans carry the
+//! key and value lengths where they apply and the time taken, for
+//! iterators the time from creation to drop. A tracer can forward them
+//! to a tracing or logging framework, to find slow calls.
+//!
+//! Without the feature, or while no tracer is installed, nothing is
+//! recorded.
+#[cfg(feature = "tracing")]
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "tracing")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "tracing")]
+use std::time::{Duration, Instant};
+
+/// A finished operation.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// The operation: `get`, `put`, `delete`, `write`, `compact`,
+    /// `snapshot` or `iterator`
+    pub name: &'static str,
+    /// Length of the key, for single key operations
+    pub key_length: Option<usize>,
+    /// Length of the value written or read, if there was one
+    pub value_length: Option<usize>,
+    /// Number of operations of a batch write, or entries returned by an
+    /// iterator
+    pub entries: Option<u64>,
+    /// Whether the operation failed
+    pub failed: bool,
+    /// Time taken by the operation
+    pub duration: Duration,
+}
+
+/// Receives the spans of all databases.
+#[cfg(feature = "tracing")]
+pub trait Tracer: Send + Sync {
+    /// Called once an operation finished
+    fn span(&self, span: &Span);
+}
+
+#[cfg(feature = "tracing")]
+static ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "tracing")]
+static TRACER: RwLock<Option<Arc<dyn Tracer>>> = RwLock::new(None);
+
+/// Install `tracer`, replacing the current one
+#[cfg(feature = "tracing")]
+pub fn set_tracer<T: Tracer + 'static>(tracer: T) {
+    *TRACER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(tracer));
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Remove the current tracer
+#[cfg(feature = "tracing")]
+pub fn clear_tracer() {
+    ENABLED.store(false, Ordering::Release);
+    *TRACER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// A running operation, reported to the tracer when dropped.
+#[cfg(feature = "tracing")]
+pub(crate) struct Timer {
+    // `None` if there was no tracer when the operation started
+    running: Option<(Instant, Span)>,
+}
+
+#[cfg(feature = "tracing")]
+impl Timer {
+    pub(crate) fn start(name: &'static str) -> Timer {
+        if !ENABLED.load(Ordering::Acquire) {
+            return Timer { running: None };
+        }
+        let span = Span {
+            name,
+            key_length: None,
+            value_length: None,
+            entries: None,
+            failed: false,
+            duration: Duration::from_secs(0),
+        };
+        Timer { running: Some((Instant::now(), span)) }
+    }
+
+    fn span(&mut self) -> Option<&mut Span> {
+        self.running.as_mut().map(|&mut (_, ref mut span)| span)
+    }
+
+    pub(crate) fn key_length(&mut self, length: usize) {
+        if let Some(span) = self.span() {
+            span.key_length = Some(length);
+        }
+    }
+
+    pub(crate) fn value_length(&mut self, length: usize) {
+        if let Some(span) = self.span() {
+            span.value_length = Some(length);
+        }
+    }
+
+    pub(crate) fn entries(&mut self, entries: u64) {
+        if let Some(span) = self.span() {
+            span.entries = Some(entries);
+        }
+    }
+
+    pub(crate) fn entry(&mut self) {
+        if let Some(span) = self.span() {
+            span.entries = Some(span.entries.unwrap_or(0) + 1);
+        }
+    }
+
+    /// Mark the operation failed if `result` is an error, passing it on
+    pub(crate) fn finish<T, E>(mut self, result: Result<T, E>) -> Result<T, E> {
+        if let Some(span) = self.span() {
+            span.failed = result.is_err();
+        }
+        result
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some((start, mut span)) = self.running.take() {
+            span.duration = start.elapsed();
+            let tracer = TRACER.read().unwrap_or_else(|e| e.into_inner()).clone();
+            if let Some(tracer) = tracer {
+                tracer.span(&span);
+            }
+        }
+    }
+}
+
+/// Records nothing, without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Timer;
+
+#[cfg(not(feature = "tracing"))]
+impl Timer {
+    #[inline]
+    pub(crate) fn start(_name: &'static str) -> Timer {
+        Timer
+    }
+
+    #[inline]
+    pub(crate) fn key_length(&mut self, _length: usize) {}
+
+    #[inline]
+    pub(crate) fn value_length(&mut self, _length: usize) {}
+
+    #[inline]
+    pub(crate) fn entries(&mut self, _entries: u64) {}
+
+    #[inline]
+    pub(crate) fn entry(&mut self) {}
+
+    #[inline]
+    pub(crate) fn finish<T, E>(self, result: Result<T, E>) -> Result<T, E> {
+        result
+    }
+}
@@ -0,0 +1,163 @@
+//! Optimistic transactions
+//!
+//! A `Transaction` reads from a snapshot taken when it began, overlaid
+//! with its own buffered writes. On commit, it checks whether any key it
+//! read was committed by another transaction in the meantime. If so, the
+//! commit fails with `ErrorKind::Conflict` and can be retried, otherwise
+//! all writes are applied atomically in one batch.
+//!
+//! Conflicts are only detected between transactions of the same
+//! `TransactionDatabase`, writes done directly on the database bypass them.
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::snapshots::{Snapshot, Snapshots};
+
+fn key_bytes<K: Key>(key: &K) -> Vec<u8> {
+    key.as_slice(|k| k.to_vec())
+}
+
+struct CommitState {
+    // number of the last commit
+    sequence: u64,
+    // the commit that last wrote each key, only kept while a running
+    // transaction might have read an older version
+    versions: HashMap<Vec<u8>, u64>,
+    // start sequences of the running transactions
+    active: BTreeMap<u64, usize>,
+}
+
+/// A database handing out optimistic transactions.
+pub struct TransactionDatabase<K: Key> {
+    database: Database<K>,
+    state: Mutex<CommitState>,
+}
+
+impl<K: Key> TransactionDatabase<K> {
+    /// Wrap a database
+    pub fn new(database: Database<K>) -> TransactionDatabase<K> {
+        TransactionDatabase {
+            database,
+            state: Mutex::new(CommitState {
+                sequence: 0,
+                versions: HashMap::new(),
+                active: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// Begin a transaction, reading from the current state of the database
+    pub fn begin<'a>(&'a self) -> Transaction<'a, K> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let start = state.sequence;
+        *state.active.entry(start).or_insert(0) += 1;
+        Transaction {
+            database: self,
+            snapshot: self.database.snapshot(),
+            start,
+            reads: HashSet::new(),
+            writes: HashMap::new(),
+            batch: Writebatch::new(),
+        }
+    }
+}
+
+/// A transaction, buffering writes until `commit`.
+///
+/// Dropping a transaction without committing discards its writes.
+pub struct Transaction<'a, K: Key + 'a> {
+    database: &'a TransactionDatabase<K>,
+    snapshot: Snapshot<'a, K>,
+    start: u64,
+    reads: HashSet<Vec<u8>>,
+    // the overlay of buffered writes, `None` marking deletes
+    writes: HashMap<Vec<u8>, Option<Vec<u8>>>,
+    batch: Writebatch<K>,
+}
+
+impl<'a, K: Key + 'a> Transaction<'a, K> {
+    /// get a value, seeing the writes of this transaction
+    ///
+    /// Keys read from the database are validated on commit.
+    pub fn get<BK: Borrow<K>>(&mut self, key: BK) -> Result<Option<Vec<u8>>, Error> {
+        let bytes = key_bytes(key.borrow());
+        if let Some(value) = self.writes.get(&bytes) {
+            return Ok(value.clone());
+        }
+        let mut options = ReadOptions::new();
+        options.snapshot = Some(&self.snapshot);
+        let value = self.database.database.get(options, key)?;
+        self.reads.insert(bytes);
+        Ok(value)
+    }
+
+    /// Buffer a put operation
+    pub fn put(&mut self, key: K, value: &[u8]) {
+        self.writes.insert(key_bytes(&key), Some(value.to_vec()));
+        self.batch.put(key, value);
+    }
+
+    /// Buffer a delete operation
+    pub fn delete(&mut self, key: K) {
+        self.writes.insert(key_bytes(&key), None);
+        self.batch.delete(key);
+    }
+
+    /// Apply all buffered writes atomically
+    ///
+    /// Fails with `ErrorKind::Conflict` if a key read by this transaction
+    /// was committed by another transaction after this one began.
+    pub fn commit(self, options: WriteOptions) -> Result<(), Error> {
+        let mut state = self.database.state.lock().unwrap_or_else(|e| e.into_inner());
+        let conflict = self.reads
+            .iter()
+            .any(|key| state.versions.get(key).is_some_and(|&version| version > self.start));
+        if conflict {
+            return Err(Error::with_kind(ErrorKind::Conflict, "a key read by the transaction was modified".to_string()));
+        }
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        self.database.database.write(options, &self.batch)?;
+        state.sequence += 1;
+        let sequence = state.sequence;
+        for key in self.writes.keys() {
+            state.versions.insert(key.clone(), sequence);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, K: Key + 'a> Drop for Transaction<'a, K> {
+    fn drop(&mut self) {
+        let mut state = self.database.state.lock().unwrap_or_else(|e| e.into_inner());
+        let remove = match state.active.get_mut(&self.start) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if remove {
+            state.active.remove(&self.start);
+        }
+        // versions no running transaction can have read past are not needed
+        let oldest = state.active.keys().next().cloned();
+        match oldest {
+            Some(oldest) => state.versions.retain(|_, version| *version > oldest),
+            None => state.versions.clear(),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Expiring entries
+//!
+//! `TtlDatabase` stores an expiry timestamp in front of every value.
+//! Expired entries are hidden from reads and iteration right away, and
+//! removed from disk by `purge_expired`, either called by hand or from a
+//! background `Sweeper`.
+//!
+//! The timestamp is part of the stored value, so a database should only be
+//! written through `TtlDatabase` once it is used with one.
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::iterator::{self, Iterable, LevelDBIterator};
+
+// every value is prefixed with its expiry time, in milliseconds since the
+// unix epoch, big-endian
+const EXPIRY_SIZE: usize = 8;
+
+// size of the batches expired entries are deleted in
+const PURGE_BATCH_BYTES: usize = 1 << 20;
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn split_expiry(stored: &[u8]) -> Option<(u64, &[u8])> {
+    if stored.len() < EXPIRY_SIZE {
+        return None;
+    }
+    let (expiry, value) = stored.split_at(EXPIRY_SIZE);
+    let mut bytes = [0; EXPIRY_SIZE];
+    bytes.copy_from_slice(expiry);
+    Some((u64::from_be_bytes(bytes), value))
+}
+
+/// A database whose entries expire after a time to live.
+pub struct TtlDatabase<K: Key> {
+    database: Database<K>,
+    ttl: Duration,
+}
+
+impl<K: Key> Clone for TtlDatabase<K> {
+    fn clone(&self) -> TtlDatabase<K> {
+        TtlDatabase {
+            database: self.database.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<K: Key> TtlDatabase<K> {
+    /// Wrap a database, letting entries written by `put` live for `ttl`
+    pub fn new(database: Database<K>, ttl: Duration) -> TtlDatabase<K> {
+        TtlDatabase { database, ttl }
+    }
+
+    /// Return the underlying database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// The time to live used by `put`
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// get a value from the database, `None` if it is missing or expired.
+    pub fn get(&self, options: ReadOptions<K>, key: K) -> Result<Option<Vec<u8>>, Error> {
+        let stored = match self.database.get_bytes(options, key)? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        match split_expiry(&stored) {
+            Some((expiry, value)) if expiry > now_millis() => Ok(Some(value.to_vec())),
+            Some(_) => Ok(None),
+            None => Err(Error::with_kind(ErrorKind::Corruption, "value without expiry".to_string())),
+        }
+    }
+
+    /// put a value into the database, expiring after the default time to live.
+    pub fn put(&self, options: WriteOptions, key: K, value: &[u8]) -> Result<(), Error> {
+        self.put_with_ttl(options, key, value, self.ttl)
+    }
+
+    /// put a value into the database, expiring after `ttl`.
+    pub fn put_with_ttl(&self,
+                        options: WriteOptions,
+                        key: K,
+                        value: &[u8],
+                        ttl: Duration)
+                        -> Result<(), Error> {
+        let expiry = now_millis().saturating_add(ttl.as_millis() as u64);
+        let mut stored = Vec::with_capacity(EXPIRY_SIZE + value.len());
+        stored.extend_from_slice(&expiry.to_be_bytes());
+        stored.extend_from_slice(value);
+        self.database.put(options, key, &stored)
+    }
+
+    /// delete a value from the database.
+    pub fn delete(&self, options: WriteOptions, key: K) -> Result<(), Error> {
+        self.database.delete(options, key)
+    }
+
+    /// Return an iterator over the (Key,Value) pairs that have not expired yet
+    pub fn iter<'a>(&'a self, options: ReadOptions<'a, K>) -> TtlIterator<'a, K> {
+        TtlIterator {
+            inner: self.database.iter(options),
+            now: now_millis(),
+        }
+    }
+
+    /// Delete all expired entries, returning how many were removed
+    ///
+    /// The space is reclaimed once leveldb compacts the affected range,
+    /// follow up with `Compaction::compact_all` to force that. The entries
+    /// are deleted in batches of about 1MiB, so a failed purge may have
+    /// removed some of them.
+    pub fn purge_expired(&self) -> Result<usize, Error> {
+        let now = now_millis();
+        let mut batch = Writebatch::new();
+        let mut purged = 0;
+        let mut iter = self.database.iter(ReadOptions::new());
+        while iter.advance() {
+            match split_expiry(&iter.value()) {
+                Some((expiry, _)) if expiry <= now => batch.delete(iter.key()),
+                _ => continue,
+            }
+            if batch.approximate_size_bytes() >= PURGE_BATCH_BYTES {
+                self.database.write(WriteOptions::new(), &batch)?;
+                purged += batch.len();
+                batch.clear();
+            }
+        }
+        iter.status()?;
+        if !batch.is_empty() {
+            self.database.write(WriteOptions::new(), &batch)?;
+            purged += batch.len();
+        }
+        Ok(purged)
+    }
+}
+
+impl<K: Key + Send + Sync + 'static> TtlDatabase<K> {
+    /// Start a background thread calling `purge_expired` every `interval`
+    ///
+    /// The thread stops when the returned `Sweeper` is dropped.
+    pub fn spawn_sweeper(&self, interval: Duration) -> Sweeper {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let database = self.clone();
+        let signal = stop.clone();
+        let thread = thread::spawn(move || {
+            let (ref stopped, ref condvar) = *signal;
+            let mut stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+            while !*stopped {
+                let (guard, timeout) = condvar.wait_timeout(stopped, interval)
+                    .unwrap_or_else(|e| e.into_inner());
+                stopped = guard;
+                if timeout.timed_out() && !*stopped {
+                    let _ = database.purge_expired();
+                }
+            }
+        });
+        Sweeper {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Handle to a background purge thread, stopping it on drop.
+pub struct Sweeper {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        let (ref stopped, ref condvar) = *self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// An iterator over the entries of a `TtlDatabase` that have not expired.
+///
+/// Entries without an expiry are skipped.
+pub struct TtlIterator<'a, K: Key + 'a> {
+    inner: iterator::Iterator<'a, K>,
+    now: u64,
+}
+
+impl<'a, K: Key + 'a> Iterator for TtlIterator<'a, K> {
+    type Item = (K, Vec<u8>);
+
+    fn next(&mut self) -> Option<(K, Vec<u8>)> {
+        while self.inner.advance() {
+            let stored = self.inner.value();
+            if let Some((expiry, value)) = split_expiry(&stored) {
+                if expiry > self.now {
+                    return Some((self.inner.key(), value.to_vec()));
+                }
+            }
+        }
+        None
+    }
+}
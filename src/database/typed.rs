@@ -0,0 +1,215 @@
+//! Typed values on top of the byte-oriented database
+//!
+//! `TypedDatabase` encodes values with a `Codec` on write and decodes them
+//! on read, so callers work with their own value types instead of bytes.
+//! Any serialization format can be plugged in by implementing `Codec`.
+use std::marker::PhantomData;
+
+use database::key::Key;
+use options::{ReadOptions, WriteOptions};
+use super::Database;
+use super::error::{Error, ErrorKind};
+use super::kv::KV;
+use super::batch::{Batch, Writebatch};
+use super::iterator::{self, Iterable, LevelDBIterator};
+
+/// Converts values of type `V` to and from their stored bytes.
+pub trait Codec<V> {
+    /// Encode a value for storage
+    fn encode(&self, value: &V) -> Vec<u8>;
+    /// Decode a stored value, failing on malformed bytes
+    fn decode(&self, bytes: &[u8]) -> Result<V, Error>;
+}
+
+/// Stores byte vectors unchanged.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct RawCodec;
+
+impl Codec<Vec<u8>> for RawCodec {
+    fn encode(&self, value: &Vec<u8>) -> Vec<u8> {
+        value.clone()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Stores strings as UTF-8.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct Utf8Codec;
+
+impl Codec<String> for Utf8Codec {
+    fn encode(&self, value: &String) -> Vec<u8> {
+        value.as_bytes().to_vec()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<String, Error> {
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| Error::with_kind(ErrorKind::Corruption, "value is not valid UTF-8".to_string()))
+    }
+}
+
+/// A database storing values of type `V`, encoded using the codec `C`.
+pub struct TypedDatabase<K: Key, V, C: Codec<V>> {
+    database: Database<K>,
+    codec: C,
+    marker: PhantomData<fn() -> V>,
+}
+
+impl<K: Key, V, C: Codec<V>> TypedDatabase<K, V, C> {
+    /// Wrap a database, encoding values using `codec`
+    pub fn new(database: Database<K>, codec: C) -> TypedDatabase<K, V, C> {
+        TypedDatabase {
+            database,
+            codec,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return the underlying byte-oriented database
+    pub fn database(&self) -> &Database<K> {
+        &self.database
+    }
+
+    /// Return the codec used for values
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// get a value from the database.
+    ///
+    /// Fails if the stored bytes can't be decoded.
+    pub fn get(&self, options: ReadOptions<K>, key: K) -> Result<Option<V>, Error> {
+        match self.database.get_bytes(options, key)? {
+            Some(bytes) => self.codec.decode(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// put a value into the database.
+    pub fn put(&self, options: WriteOptions, key: K, value: &V) -> Result<(), Error> {
+        self.database.put(options, key, &self.codec.encode(value))
+    }
+
+    /// delete a value from the database.
+    pub fn delete(&self, options: WriteOptions, key: K) -> Result<(), Error> {
+        self.database.delete(options, key)
+    }
+
+    /// Batch a put operation, encoding the value
+    pub fn batch_put(&self, batch: &mut Writebatch<K>, key: K, value: &V) {
+        batch.put(key, &self.codec.encode(value));
+    }
+
+    /// Write a batch to the database, ensuring success for all items or an error
+    pub fn write(&self, options: WriteOptions, batch: &Writebatch<K>) -> Result<(), Error> {
+        self.database.write(options, batch)
+    }
+
+    /// Start a batch of typed values, encoded with the codec of this
+    /// database
+    pub fn batch(&self) -> TypedWritebatch<'_, K, V, C> {
+        TypedWritebatch {
+            database: self,
+            batch: Writebatch::new(),
+        }
+    }
+
+    /// Return an iterator over (Key,Value) pairs, decoding the values
+    pub fn iter<'a>(&'a self, options: ReadOptions<'a, K>) -> TypedIterator<'a, K, V, C> {
+        TypedIterator {
+            inner: self.database.iter(options),
+            codec: &self.codec,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// A writebatch of typed values.
+///
+/// Values are encoded by `TypedDatabase::batch_put`, like the values of
+/// single puts, and the batch is written to the database it was started
+/// from.
+pub struct TypedWritebatch<'a, K: Key + 'a, V: 'a, C: Codec<V> + 'a> {
+    database: &'a TypedDatabase<K, V, C>,
+    batch: Writebatch<K>,
+}
+
+impl<'a, K: Key + 'a, V: 'a, C: Codec<V> + 'a> TypedWritebatch<'a, K, V, C> {
+    /// Batch a put operation, encoding the value
+    pub fn put(&mut self, key: K, value: &V) {
+        self.database.batch_put(&mut self.batch, key, value)
+    }
+
+    /// Batch a delete operation
+    pub fn delete(&mut self, key: K) {
+        self.batch.delete(key)
+    }
+
+    /// Number of put and delete operations in the batch
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Whether the batch contains no operations
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    /// Clear the batch
+    pub fn clear(&mut self) {
+        self.batch.clear()
+    }
+
+    /// The batch of encoded values
+    pub fn as_batch(&self) -> &Writebatch<K> {
+        &self.batch
+    }
+
+    /// The batch of encoded values, for operations of `Writebatch` like
+    /// savepoints
+    pub fn as_batch_mut(&mut self) -> &mut Writebatch<K> {
+        &mut self.batch
+    }
+
+    /// Write the batch to the database, ensuring success for all items or
+    /// an error
+    pub fn write(&self, options: WriteOptions) -> Result<(), Error> {
+        self.database.write(options, &self.batch)
+    }
+}
+
+/// An iterator over typed (Key,Value) pairs.
+///
+/// Each value is decoded on its own, so a malformed value is reported
+/// without ending the iteration.
+pub struct TypedIterator<'a, K: Key + 'a, V, C: Codec<V> + 'a> {
+    inner: iterator::Iterator<'a, K>,
+    codec: &'a C,
+    marker: PhantomData<fn() -> V>,
+}
+
+impl<'a, K: Key + 'a, V, C: Codec<V> + 'a> TypedIterator<'a, K, V, C> {
+    /// Wrap an iterator that already has its range configured
+    pub fn with_iterator(inner: iterator::Iterator<'a, K>, codec: &'a C) -> TypedIterator<'a, K, V, C> {
+        TypedIterator {
+            inner,
+            codec,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: Key + 'a, V, C: Codec<V> + 'a> Iterator for TypedIterator<'a, K, V, C> {
+    type Item = (K, Result<V, Error>);
+
+    fn next(&mut self) -> Option<(K, Result<V, Error>)> {
+        if self.inner.advance() {
+            let value = self.codec.decode(&self.inner.value());
+            Some((self.inner.key(), value))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Watching writes
+//!
+//! A `Watcher` receives an event for every put and delete done through
+//! the database, or any of its clones, on a watched key or prefix. Batch
+//! writes produce one event per operation. Events are delivered after the
+//! write succeeded, in the order the writes were applied. Writes to the
+//! metadata of `Database::meta` produce no events.
+//!
+//! Events are buffered without limit until received, a watcher that is
+//! not read from should be dropped.
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use database::key::{Key, from_u8};
+use super::Database;
+use super::error::Error;
+use super::batch::Operation;
+use super::meta::is_reserved;
+
+/// A write to a watched key: its new value, `None` if it was deleted.
+pub type Event<K> = (K, Option<Vec<u8>>);
+
+struct Subscription {
+    pattern: Vec<u8>,
+    prefix: bool,
+    sender: Sender<Operation>,
+}
+
+impl Subscription {
+    fn matches(&self, key: &[u8]) -> bool {
+        if self.prefix {
+            key.starts_with(&self.pattern)
+        } else {
+            key == &self.pattern[..]
+        }
+    }
+}
+
+pub(crate) struct Watchers {
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+impl Watchers {
+    pub(crate) fn new() -> Watchers {
+        Watchers { subscriptions: Mutex::new(vec![]) }
+    }
+
+    fn subscribe(&self, pattern: Vec<u8>, prefix: bool) -> Receiver<Operation> {
+        let (sender, receiver) = mpsc::channel();
+        let mut subscriptions = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        subscriptions.push(Subscription {
+            pattern,
+            prefix,
+            sender,
+        });
+        receiver
+    }
+
+    /// Run `write`, then send the `operations` it applied to the watchers
+    ///
+    /// While there are watchers, writes are serialised, so events arrive
+    /// in the order the writes were applied.
+    pub(crate) fn notify<W, O>(&self, write: W, operations: O) -> Result<(), Error>
+        where W: FnOnce() -> Result<(), Error>,
+              O: FnOnce() -> Vec<Operation>
+    {
+        let mut subscriptions = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        if subscriptions.is_empty() {
+            drop(subscriptions);
+            return write();
+        }
+        write()?;
+        // writes to the reserved namespace are not data, and the keys of
+        // watchers may not be able to decode them
+        for (key, value) in operations().into_iter().filter(|(key, _)| !is_reserved(key)) {
+            // subscriptions whose watcher was dropped are removed on the way
+            subscriptions.retain(|subscription| {
+                !subscription.matches(&key) || subscription.sender.send((key.clone(), value.clone())).is_ok()
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<K: Key> Database<K> {
+    /// Watch writes to `key`
+    pub fn watch(&self, key: &K) -> Watcher<K> {
+        let pattern = key.as_slice(|k| k.to_vec());
+        Watcher::new(self.database.watchers.subscribe(pattern, false))
+    }
+
+    /// Watch writes to all keys starting with the encoded bytes `prefix`
+    pub fn watch_prefix(&self, prefix: &[u8]) -> Watcher<K> {
+        Watcher::new(self.database.watchers.subscribe(prefix.to_vec(), true))
+    }
+}
+
+/// Receives the events of a watch.
+///
+/// Iterating blocks until the next event, ending once the database is closed.
+pub struct Watcher<K: Key> {
+    receiver: Receiver<Operation>,
+    marker: PhantomData<K>,
+}
+
+fn event<K: Key>((key, value): Operation) -> Event<K> {
+    (from_u8(&key), value)
+}
+
+impl<K: Key> Watcher<K> {
+    fn new(receiver: Receiver<Operation>) -> Watcher<K> {
+        Watcher {
+            receiver,
+            marker: PhantomData,
+        }
+    }
+
+    /// Wait for the next event, `None` once the database is closed
+    pub fn recv(&self) -> Option<Event<K>> {
+        self.receiver.recv().ok().map(event)
+    }
+
+    /// Return the next event if there is one, without waiting
+    pub fn try_recv(&self) -> Option<Event<K>> {
+        self.receiver.try_recv().ok().map(event)
+    }
+
+    /// Wait for the next event for at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Event<K>> {
+        self.receiver.recv_timeout(timeout).ok().map(event)
+    }
+}
+
+impl<K: Key> Iterator for Watcher<K> {
+    type Item = Event<K>;
+
+    fn next(&mut self) -> Option<Event<K>> {
+        self.recv()
+    }
+}
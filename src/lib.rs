@@ -45,6 +45,8 @@
 
 extern crate libc;
 extern crate leveldb_sys;
+#[cfg(feature = "derive")]
+extern crate leveldb_derive;
 
 use leveldb_sys::{leveldb_major_version, leveldb_minor_version};
 pub use database::options;
@@ -56,6 +58,50 @@ pub use database::kv;
 pub use database::batch;
 pub use database::management;
 pub use database::compaction;
+pub use database::sizes;
+pub use database::properties;
+pub use database::asynchronous;
+pub use database::keys;
+pub use database::typed;
+pub use database::ttl;
+pub use database::index;
+pub use database::transaction;
+pub use database::atomic;
+pub use database::keyspace;
+pub use database::backup;
+pub use database::watch;
+pub use database::sharded;
+pub use database::cursor;
+pub use database::compression;
+pub use database::queue;
+pub use database::merge;
+pub use database::auto_compaction;
+pub use database::chunked;
+pub use database::blob;
+pub use database::backend;
+pub use database::manager;
+pub use database::retry;
+pub use database::read_cache;
+pub use database::coalescer;
+pub use database::rate_limit;
+pub use database::migrations;
+pub use database::meta;
+pub use database::hooks;
+pub use database::replication;
+pub use database::consensus;
+pub use database::integrity;
+pub use database::health;
+pub use database::logs;
+pub use database::live_files;
+pub use database::secondary;
+#[cfg(feature = "server")]
+pub use database::server;
+#[cfg(feature = "metrics")]
+pub use database::metrics;
+#[cfg(feature = "tracing")]
+pub use database::trace;
+#[cfg(feature = "derive")]
+pub use leveldb_derive::DbKey;
 
 #[allow(missing_docs)]
 pub mod database;
@@ -63,7 +109,6 @@ pub mod database;
 /// Library version information
 ///
 /// Need a recent version of leveldb to be used.
-
 pub trait Version {
     /// The major version.
     fn major() -> isize {
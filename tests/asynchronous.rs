@@ -0,0 +1,102 @@
+use utils::{tmpdir,open_database,db_put_simple};
+use leveldb::asynchronous::AsyncDatabase;
+use leveldb::database::batch::Writebatch;
+use leveldb::iterator::{LevelDBIterator,OwnedIterable};
+use leveldb::asynchronous::EntryStream;
+use leveldb::options::{ReadOptions,WriteOptions};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context,Poll,Wake};
+use std::thread::{self,Thread};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+  fn wake(self: Arc<Self>) {
+    self.0.unpark();
+  }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+  let mut future = Box::pin(future);
+  let waker = Arc::new(ThreadWaker(thread::current())).into();
+  let mut cx = Context::from_waker(&waker);
+  loop {
+    match Pin::as_mut(&mut future).poll(&mut cx) {
+      Poll::Ready(output) => return output,
+      Poll::Pending => thread::park(),
+    }
+  }
+}
+
+#[test]
+fn test_async_put_get_delete() {
+  let tmp = tmpdir("async_put_get");
+  let database = AsyncDatabase::new(open_database(tmp.path(), true));
+  block_on(database.put(WriteOptions::new(), 1, vec![1])).unwrap();
+  assert_eq!(Some(vec![1]), block_on(database.get(1)).unwrap());
+  block_on(database.delete(WriteOptions::new(), 1)).unwrap();
+  assert_eq!(None, block_on(database.get(1)).unwrap());
+}
+
+#[test]
+fn test_async_write_and_compact() {
+  let tmp = tmpdir("async_write");
+  let database = AsyncDatabase::new(open_database(tmp.path(), true));
+  let mut batch = Writebatch::new();
+  batch.put(1, &[1]);
+  batch.put(2, &[2]);
+  block_on(database.write(WriteOptions::new(), batch)).unwrap();
+  block_on(database.compact_all());
+  assert_eq!(Some(vec![2]), block_on(database.get(2)).unwrap());
+}
+
+#[test]
+fn test_async_stream() {
+  let tmp = tmpdir("async_stream");
+  let database = open_database(tmp.path(), true);
+  // more entries than the stream buffers ahead
+  for i in 0..200 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let database = AsyncDatabase::new(database);
+  let mut stream = database.iter();
+  let mut keys = vec![];
+  while let Some((key, value)) = block_on(stream.next_entry()) {
+    assert_eq!(vec![key as u8], value);
+    keys.push(key);
+  }
+  assert_eq!((0..200).collect::<Vec<i32>>(), keys);
+}
+
+#[test]
+fn test_async_stream_from_owned_iterator() {
+  let tmp = tmpdir("async_stream_owned");
+  let database = open_database(tmp.path(), true);
+  for i in 0..10 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let iter = Arc::new(database).owned_iter(ReadOptions::new()).from(&3).to(&5).reverse();
+  let mut stream = EntryStream::from(iter);
+  let mut keys = vec![];
+  while let Some((key, _)) = block_on(stream.next_entry()) {
+    keys.push(key);
+  }
+  assert_eq!(vec![5, 4, 3], keys);
+}
+
+#[test]
+fn test_async_stream_dropped_early() {
+  let tmp = tmpdir("async_stream_dropped");
+  let database = open_database(tmp.path(), true);
+  for i in 0..200 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let database = AsyncDatabase::new(database);
+  let mut stream = database.iter();
+  assert_eq!(Some((0, vec![0])), block_on(stream.next_entry()));
+  drop(stream);
+  // the database stays usable after the producer stopped
+  assert_eq!(Some(vec![1]), block_on(database.get(1)).unwrap());
+}
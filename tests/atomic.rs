@@ -0,0 +1,235 @@
+use utils::{tmpdir,open_database,db_put_simple};
+use leveldb::atomic::Entry;
+use leveldb::batch::Writebatch;
+use leveldb::error::ErrorKind;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use std::thread;
+
+#[test]
+fn test_compare_and_swap() {
+  let tmp = tmpdir("cas");
+  let database = open_database(tmp.path(), true);
+  // insert only if missing
+  assert_eq!(Ok(()), database.compare_and_swap(WriteOptions::new(), 1, None, Some(&[1])).unwrap());
+  assert_eq!(Err(Some(vec![1])),
+             database.compare_and_swap(WriteOptions::new(), 1, None, Some(&[2])).unwrap());
+  assert_eq!(Err(Some(vec![1])),
+             database.compare_and_swap(WriteOptions::new(), 1, Some(&[3]), Some(&[2])).unwrap());
+  assert_eq!(Ok(()),
+             database.compare_and_swap(WriteOptions::new(), 1, Some(&[1]), Some(&[2])).unwrap());
+  assert_eq!(Some(vec![2]), database.get(ReadOptions::new(), 1).unwrap());
+  // delete if unchanged
+  assert_eq!(Ok(()), database.compare_and_swap(WriteOptions::new(), 1, Some(&[2]), None).unwrap());
+  assert_eq!(None, database.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(Err(None),
+             database.compare_and_swap(WriteOptions::new(), 1, Some(&[2]), None).unwrap());
+}
+
+#[test]
+fn test_compare_and_swap_counter_from_threads() {
+  let tmp = tmpdir("cas_threads");
+  let database = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &0u32.to_be_bytes());
+
+  let handles: Vec<_> = (0..4).map(|_| {
+    let database = database.clone();
+    thread::spawn(move || {
+      for _ in 0..50 {
+        let mut current = database.get(ReadOptions::new(), 1).unwrap().unwrap();
+        loop {
+          let mut bytes = [0; 4];
+          bytes.copy_from_slice(&current);
+          let next = (u32::from_be_bytes(bytes) + 1).to_be_bytes();
+          match database.compare_and_swap(WriteOptions::new(), 1, Some(&current), Some(&next)).unwrap() {
+            Ok(()) => break,
+            Err(value) => current = value.unwrap(),
+          }
+        }
+      }
+    })
+  }).collect();
+  for handle in handles {
+    handle.join().unwrap();
+  }
+  assert_eq!(Some(200u32.to_be_bytes().to_vec()), database.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_update() {
+  let tmp = tmpdir("update");
+  let database = open_database(tmp.path(), true);
+  let append = |old: Option<&[u8]>| {
+    let mut value = old.map(|v| v.to_vec()).unwrap_or_default();
+    value.push(value.len() as u8);
+    Some(value)
+  };
+  assert_eq!(Some(vec![0]), database.update(WriteOptions::new(), 1, append).unwrap());
+  assert_eq!(Some(vec![0, 1]), database.update(WriteOptions::new(), 1, append).unwrap());
+  assert_eq!(Some(vec![0, 1]), database.get(ReadOptions::new(), 1).unwrap());
+
+  assert_eq!(None, database.update(WriteOptions::new(), 1, |_| None).unwrap());
+  assert_eq!(None, database.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(None, database.update(WriteOptions::new(), 2, |old| {
+    assert!(old.is_none());
+    None
+  }).unwrap());
+}
+
+#[test]
+fn test_update_counter_from_threads() {
+  let tmp = tmpdir("update_threads");
+  let database = open_database(tmp.path(), true);
+  let handles: Vec<_> = (0..4).map(|_| {
+    let database = database.clone();
+    thread::spawn(move || {
+      for _ in 0..50 {
+        database.update(WriteOptions::new(), 1, |old| {
+          let count = old.map(|v| v[0]).unwrap_or(0);
+          Some(vec![count + 1])
+        }).unwrap();
+      }
+    })
+  }).collect();
+  for handle in handles {
+    handle.join().unwrap();
+  }
+  assert_eq!(Some(vec![200]), database.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_put_if_absent_and_take() {
+  let tmp = tmpdir("put_if_absent");
+  let database = open_database(tmp.path(), true);
+  assert!(database.put_if_absent(WriteOptions::new(), 1, &[1]).unwrap());
+  assert!(!database.put_if_absent(WriteOptions::new(), 1, &[2]).unwrap());
+  assert_eq!(Some(vec![1]), database.get(ReadOptions::new(), 1).unwrap());
+
+  assert_eq!(Some(vec![1]), database.take(WriteOptions::new(), 1).unwrap());
+  assert_eq!(None, database.take(WriteOptions::new(), 1).unwrap());
+  assert_eq!(None, database.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_put_if_absent_from_threads() {
+  let tmp = tmpdir("put_if_absent_threads");
+  let database = open_database(tmp.path(), true);
+  let threads: Vec<_> = (0..8u8).map(|i| {
+    let database = database.clone();
+    thread::spawn(move || {
+      let mut won = 0;
+      for key in 0..50 {
+        if database.put_if_absent(WriteOptions::new(), key, &[i]).unwrap() {
+          won += 1;
+        }
+      }
+      won
+    })
+  }).collect();
+  let won: i32 = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+  assert_eq!(50, won);
+
+  let takers: Vec<_> = (0..8).map(|_| {
+    let database = database.clone();
+    thread::spawn(move || (0..50).filter(|&key| database.take(WriteOptions::new(), key).unwrap().is_some()).count())
+  }).collect();
+  let taken: usize = takers.into_iter().map(|thread| thread.join().unwrap()).sum();
+  assert_eq!(50, taken);
+}
+
+#[test]
+fn test_entry() {
+  let tmp = tmpdir("entry");
+  let database = open_database(tmp.path(), true);
+  let value = database.entry(1).unwrap().or_insert_with(WriteOptions::new(), || vec![1]).unwrap();
+  assert_eq!(value, vec![1]);
+  let value = database.entry(1).unwrap().or_insert_with(WriteOptions::new(), || vec![2]).unwrap();
+  assert_eq!(value, vec![1]);
+
+  database.entry(1).unwrap().and_modify(WriteOptions::new(), |value| value.push(9)).unwrap();
+  assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![1, 9]));
+  match database.entry(2).unwrap().and_modify(WriteOptions::new(), |value| value.push(9)).unwrap() {
+    Entry::Vacant(entry) => assert_eq!(entry.key(), &2),
+    Entry::Occupied(_) => panic!("entry 2 should be vacant"),
+  }
+  assert_eq!(database.get(ReadOptions::new(), 2).unwrap(), None);
+
+  if let Entry::Occupied(mut entry) = database.entry(1).unwrap() {
+    assert_eq!(entry.insert(WriteOptions::new(), &[3]).unwrap(), vec![1, 9]);
+    assert_eq!(entry.get(), &[3]);
+  }
+  assert_eq!(database.entry(1).unwrap().remove(WriteOptions::new()).unwrap(), Some(vec![3]));
+  assert_eq!(database.entry(1).unwrap().remove(WriteOptions::new()).unwrap(), None);
+}
+
+#[test]
+fn test_entry_concurrent_increments() {
+  let tmp = tmpdir("entry_concurrent");
+  let database = open_database::<i32>(tmp.path(), true);
+  let threads: Vec<_> = (0..4).map(|_| {
+    let database = database.clone();
+    thread::spawn(move || {
+      for _ in 0..50 {
+        database.entry(1).unwrap()
+          .and_modify(WriteOptions::new(), |value| value[0] += 1).unwrap()
+          .or_insert_with(WriteOptions::new(), || vec![1]).unwrap();
+      }
+    })
+  }).collect();
+  for thread in threads {
+    thread.join().unwrap();
+  }
+  assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![200]));
+}
+
+#[test]
+fn test_atomic_operations_while_holding_entry() {
+  let tmp = tmpdir("entry_nested");
+  let database = open_database::<i32>(tmp.path(), true);
+  let entry = database.entry(0).unwrap();
+  // on the entry's own key as on keys of other stripes, which another
+  // thread could hold while waiting for the entry's
+  for key in 0..100 {
+    let error = database.update(WriteOptions::new(), key, |_| Some(vec![1])).unwrap_err();
+    assert_eq!(&ErrorKind::Conflict, error.kind());
+    let error = database.put_if_absent(WriteOptions::new(), key, &[1]).unwrap_err();
+    assert_eq!(&ErrorKind::Conflict, error.kind());
+    assert!(database.entry(key).is_err());
+  }
+  entry.or_insert_with(WriteOptions::new(), || vec![0]).unwrap();
+  assert_eq!(database.get(ReadOptions::new(), 0).unwrap(), Some(vec![0]));
+  assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), None);
+
+  // the lock is released along with the entry
+  let result = database.update(WriteOptions::new(), 1, |_| {
+    let error = database.take(WriteOptions::new(), 0).unwrap_err();
+    assert_eq!(&ErrorKind::Conflict, error.kind());
+    Some(vec![1])
+  });
+  assert_eq!(result.unwrap(), Some(vec![1]));
+  assert_eq!(database.take(WriteOptions::new(), 0).unwrap(), Some(vec![0]));
+}
+
+#[test]
+fn test_write_if() {
+  let tmp = tmpdir("write_if");
+  let database = open_database(tmp.path(), true);
+  let mut batch = Writebatch::new();
+  batch.put(1, &[1]);
+  batch.put(0, &[1]);
+  // the guard key is missing
+  database.write_if(WriteOptions::new(), &batch, 0, None).unwrap();
+  assert_eq!(Some(vec![1]), database.get(ReadOptions::new(), 1).unwrap());
+
+  let mut batch = Writebatch::new();
+  batch.put(1, &[2]);
+  batch.put(0, &[2]);
+  let error = database.write_if(WriteOptions::new(), &batch, 0, None).err().unwrap();
+  assert_eq!(ErrorKind::ConditionFailed, *error.kind());
+  let error = database.write_if(WriteOptions::new(), &batch, 0, Some(&[3])).err().unwrap();
+  assert_eq!(ErrorKind::ConditionFailed, *error.kind());
+  assert_eq!(Some(vec![1]), database.get(ReadOptions::new(), 1).unwrap());
+
+  database.write_if(WriteOptions::new(), &batch, 0, Some(&[1])).unwrap();
+  assert_eq!(Some(vec![2]), database.get(ReadOptions::new(), 1).unwrap());
+}
@@ -0,0 +1,46 @@
+use utils::{open_database,tmpdir};
+use leveldb::backend::{Backend,MemoryBackend,Operation};
+use leveldb::database::Database;
+use leveldb::keys::BinaryKey;
+use leveldb::options::WriteOptions;
+
+fn entries(backend: &dyn Backend, from: Option<&[u8]>, to: Option<&[u8]>) -> Vec<Vec<u8>> {
+  backend.range(from, to).map(|entry| entry.unwrap().0).collect()
+}
+
+// the same behaviour is expected of every backend
+fn exercise(backend: &dyn Backend) {
+  backend.put(WriteOptions::new(), b"b", b"2").unwrap();
+  backend.write(WriteOptions::new(), &[
+    Operation::Put(b"a".to_vec(), b"1".to_vec()),
+    Operation::Put(b"c".to_vec(), b"3".to_vec()),
+    Operation::Delete(b"b".to_vec()),
+  ]).unwrap();
+  assert_eq!(backend.get(b"a").unwrap(), Some(b"1".to_vec()));
+  assert_eq!(backend.get(b"b").unwrap(), None);
+
+  let snapshot = backend.snapshot();
+  backend.put(WriteOptions::new(), b"d", b"4").unwrap();
+  backend.delete(WriteOptions::new(), b"a").unwrap();
+  assert_eq!(entries(backend, None, None), vec![b"c".to_vec(), b"d".to_vec()]);
+  assert_eq!(entries(backend, Some(b"c"), Some(b"d")), vec![b"c".to_vec()]);
+  assert_eq!(entries(backend, Some(b"d"), Some(b"c")), Vec::<Vec<u8>>::new());
+  let old: Vec<_> = snapshot.entries().map(|entry| entry.unwrap()).collect();
+  assert_eq!(old, vec![(b"a".to_vec(), b"1".to_vec()), (b"c".to_vec(), b"3".to_vec())]);
+  assert_eq!(snapshot.get(b"d").unwrap(), None);
+  backend.compact(None, None);
+}
+
+#[test]
+fn test_leveldb_backend() {
+  let tmp = tmpdir("leveldb_backend");
+  let database: Database<BinaryKey> = open_database(tmp.path(), true);
+  exercise(&database);
+}
+
+#[test]
+fn test_memory_backend() {
+  let backend = MemoryBackend::new();
+  exercise(&backend);
+  assert_eq!(backend.len(), 2);
+}
@@ -0,0 +1,247 @@
+use utils::{open_database,open_ordered_database,tmpdir,db_put_simple};
+use std::fs;
+use leveldb::backup::Backup;
+use leveldb::compaction::Compaction;
+use leveldb::database::Database;
+use leveldb::error::ErrorKind;
+use leveldb::comparator::OrdComparator;
+use leveldb::iterator::Iterable;
+use leveldb::keys::BinaryKey;
+use leveldb::kv::KV;
+use leveldb::options::{Options,ReadOptions,WriteOptions};
+use leveldb::snapshots::Snapshots;
+
+#[test]
+fn test_checkpoint() {
+  let tmp = tmpdir("checkpoint");
+  let database: Database<i32> = open_database(&tmp.path().join("source"), true);
+  for i in 0..1000 {
+    db_put_simple(&database, i, &[(i % 256) as u8; 2000]);
+  }
+  database.delete(WriteOptions::new(), 3).unwrap();
+
+  let target = tmp.path().join("target");
+  database.checkpoint(&target).unwrap();
+  database.put(WriteOptions::new(), 1000, &[1]).unwrap();
+  drop(database);
+
+  let copy: Database<i32> = open_database(&target, false);
+  let keys: Vec<i32> = copy.keys_iter(ReadOptions::new()).collect();
+  let expected: Vec<i32> = (0..1000).filter(|&i| i != 3).collect();
+  assert_eq!(expected, keys);
+  assert_eq!(Some(vec![7; 2000]), copy.get(ReadOptions::new(), 7).unwrap());
+}
+
+#[test]
+fn test_checkpoint_does_not_overwrite() {
+  let tmp = tmpdir("checkpoint_exists");
+  let database: Database<i32> = open_database(&tmp.path().join("source"), true);
+  db_put_simple(&database, 1, &[1]);
+  let target = tmp.path().join("target");
+  database.checkpoint(&target).unwrap();
+  db_put_simple(&database, 2, &[2]);
+  assert!(database.checkpoint(&target).is_err());
+
+  let copy: Database<i32> = open_database(&target, false);
+  assert_eq!(None, copy.get(ReadOptions::new(), 2).unwrap());
+}
+
+#[test]
+fn test_checkpoint_with_comparator() {
+  let tmp = tmpdir("checkpoint_comparator");
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let database: Database<i32> = Database::open_with_comparator(&tmp.path().join("source"), opts, OrdComparator::<i32>::new("ord")).unwrap();
+  for i in [3, -1, 2] {
+    db_put_simple(&database, i, &[1]);
+  }
+  database.meta().put(WriteOptions::new(), "marker", &[1]).unwrap();
+  let target = tmp.path().join("target");
+  database.checkpoint(&target).unwrap();
+
+  let copy: Database<i32> = Database::open_with_comparator(&target, Options::new(), OrdComparator::<i32>::new("ord")).unwrap();
+  let keys: Vec<i32> = copy.keys_iter(ReadOptions::new()).collect();
+  assert_eq!(vec![-1, 2, 3], keys);
+  // the metadata is copied along
+  assert_eq!(Some(vec![1]), copy.meta().get("marker").unwrap());
+}
+
+#[test]
+fn test_restore_and_verify() {
+  let tmp = tmpdir("restore");
+  let database: Database<i32> = open_database(&tmp.path().join("source"), true);
+  for i in 0..100 {
+    db_put_simple(&database, i, &[1, 2, 3]);
+  }
+  let backup = tmp.path().join("backup");
+  database.checkpoint(&backup).unwrap();
+
+  let report = Backup::verify(&backup).unwrap();
+  assert_eq!(100, report.entries);
+  assert_eq!(100 * (4 + 3), report.data_bytes);
+  assert!(report.files > 0);
+
+  let restored = tmp.path().join("restored");
+  let report = Backup::restore(&backup, &restored).unwrap();
+  assert_eq!(100, report.entries);
+  let copy: Database<i32> = open_database(&restored, false);
+  assert_eq!(Some(vec![1, 2, 3]), copy.get(ReadOptions::new(), 99).unwrap());
+  drop(copy);
+
+  let error = Backup::restore(&backup, &restored).unwrap_err();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
+}
+
+#[test]
+fn test_verify_with_comparator() {
+  let tmp = tmpdir("verify_comparator");
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let database: Database<i32> = Database::open_with_comparator(tmp.path(), opts, OrdComparator::<i32>::new("ord")).unwrap();
+  db_put_simple(&database, 1, &[1]);
+  drop(database);
+
+  assert!(Backup::verify(tmp.path()).is_err());
+  let report = Backup::verify_with_comparator(tmp.path(), OrdComparator::<i32>::new("ord")).unwrap();
+  assert_eq!(1, report.entries);
+}
+
+#[test]
+fn test_verify_detects_corruption() {
+  let tmp = tmpdir("verify_corruption");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..1000 {
+    db_put_simple(&database, i, &[(i % 256) as u8; 100]);
+  }
+  database.compact_all();
+  drop(database);
+
+  let table = fs::read_dir(tmp.path()).unwrap()
+    .map(|entry| entry.unwrap().path())
+    .find(|path| path.extension().is_some_and(|ext| ext == "ldb"))
+    .unwrap();
+  let mut contents = fs::read(&table).unwrap();
+  for byte in contents[100..200].iter_mut() {
+    *byte ^= 0xff;
+  }
+  fs::write(&table, contents).unwrap();
+
+  let error = Backup::verify(tmp.path()).unwrap_err();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+}
+
+#[test]
+fn test_export_import() {
+  let tmp = tmpdir("export");
+  let source: Database<i32> = open_database(&tmp.path().join("source"), true);
+  for i in 0..500 {
+    db_put_simple(&source, i, &[(i % 256) as u8; 3000]);
+  }
+  db_put_simple(&source, 500, &[]);
+  let mut dump = vec![];
+  assert_eq!(501, source.export_to(&mut dump).unwrap());
+
+  let target: Database<i32> = open_database(&tmp.path().join("target"), true);
+  db_put_simple(&target, 7, &[1]);
+  db_put_simple(&target, 1000, &[1]);
+  assert_eq!(501, target.import_from(&dump[..]).unwrap());
+
+  let keys: Vec<i32> = target.keys_iter(ReadOptions::new()).collect();
+  let expected: Vec<i32> = (0..501).chain(1000..1001).collect();
+  assert_eq!(expected, keys);
+  assert_eq!(Some(vec![7; 3000]), target.get(ReadOptions::new(), 7).unwrap());
+  assert_eq!(Some(vec![]), target.get(ReadOptions::new(), 500).unwrap());
+}
+
+#[test]
+fn test_import_damaged_dump() {
+  let tmp = tmpdir("import_damaged");
+  let source: Database<i32> = open_database(&tmp.path().join("source"), true);
+  db_put_simple(&source, 1, &[1, 2, 3]);
+  let mut dump = vec![];
+  source.export_to(&mut dump).unwrap();
+
+  let target: Database<i32> = open_database(&tmp.path().join("target"), true);
+  let error = target.import_from(&dump[..dump.len() - 1]).unwrap_err();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  let error = target.import_from(&dump[..10]).unwrap_err();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  let error = target.import_from(&b"not a dump"[..]).unwrap_err();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  assert_eq!(None, target.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_export_import_ordered() {
+  let tmp = tmpdir("export_ordered");
+  let source = open_ordered_database(&tmp.path().join("source"));
+  db_put_simple(&source, 2, &[2]);
+  db_put_simple(&source, 1, &[1]);
+  source.meta().put(WriteOptions::new(), "marker", &[1]).unwrap();
+  let mut dump = vec![];
+  // the metadata is left out of dumps
+  assert_eq!(2, source.export_to(&mut dump).unwrap());
+
+  let target = open_ordered_database(&tmp.path().join("target"));
+  assert_eq!(2, target.import_from(&dump[..]).unwrap());
+  assert_eq!(vec![1, 2], target.keys_iter(ReadOptions::new()).collect::<Vec<i32>>());
+  assert_eq!(None, target.meta().get("marker").unwrap());
+}
+
+#[test]
+fn test_import_undecodable_dump() {
+  let tmp = tmpdir("import_undecodable");
+  let source: Database<BinaryKey> = open_database(&tmp.path().join("source"), true);
+  source.put(WriteOptions::new(), BinaryKey(vec![1, 2, 3]), &[1]).unwrap();
+  let mut dump = vec![];
+  source.export_to(&mut dump).unwrap();
+
+  let target = open_ordered_database(&tmp.path().join("target"));
+  let error = target.import_from(&dump[..]).unwrap_err();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  assert_eq!(0, target.keys_iter(ReadOptions::new()).count());
+}
+
+#[test]
+fn test_snapshot_export_import() {
+  let tmp = tmpdir("snapshot_export");
+  let source: Database<i32> = open_database(&tmp.path().join("source"), true);
+  for i in 0..300 {
+    db_put_simple(&source, i, &[(i % 256) as u8; 1000]);
+  }
+  let snapshot = source.snapshot();
+  db_put_simple(&source, 1000, &[1]);
+  let mut stream = vec![];
+  assert_eq!(300, snapshot.export(&mut stream).unwrap());
+  let mut again = vec![];
+  snapshot.export(&mut again).unwrap();
+  assert_eq!(stream, again);
+
+  let target: Database<i32> = open_database(&tmp.path().join("target"), true);
+  assert_eq!(300, target.import_snapshot(&stream[..]).unwrap());
+  let keys: Vec<i32> = target.keys_iter(ReadOptions::new()).collect();
+  assert_eq!((0..300).collect::<Vec<i32>>(), keys);
+  assert_eq!(Some(vec![7; 1000]), target.get(ReadOptions::new(), 7).unwrap());
+
+  let error = target.import_snapshot(&stream[..]).unwrap_err();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
+}
+
+#[test]
+fn test_import_damaged_snapshot() {
+  let tmp = tmpdir("import_damaged_snapshot");
+  let source: Database<i32> = open_database(&tmp.path().join("source"), true);
+  db_put_simple(&source, 1, &[1, 2, 3]);
+  let mut stream = vec![];
+  source.snapshot().export(&mut stream).unwrap();
+
+  let target: Database<i32> = open_database(&tmp.path().join("target"), true);
+  let mut flipped = stream.clone();
+  flipped[20] ^= 1;
+  let error = target.import_snapshot(&flipped[..]).unwrap_err();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  assert_eq!(None, target.get(ReadOptions::new(), 1).unwrap());
+  let error = target.import_snapshot(&stream[..stream.len() - 1]).unwrap_err();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  assert_eq!(0, target.keys_iter(ReadOptions::new()).count());
+}
@@ -55,3 +55,39 @@ fn test_get_from_filled_database() {
     Err(_) => { panic!("failed reading data") }
   }
 }
+
+#[test]
+fn test_get_bytes_from_filled_database() {
+  let tmp = tmpdir("get_bytes_filled");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1, 2, 3]);
+
+  let read_opts = ReadOptions::new();
+  let res = database.get_bytes(read_opts, 1);
+  match res {
+    Ok(data) => {
+      assert!(data.is_some());
+      assert_eq!(&*data.unwrap(), &[1, 2, 3]);
+    },
+    Err(_) => { panic!("failed reading data") }
+  }
+
+  let read_opts = ReadOptions::new();
+  let res = database.get_bytes(read_opts, 2);
+  assert!(res.unwrap().is_none());
+}
+
+#[test]
+fn test_multi_get_from_database() {
+  let tmp = tmpdir("multi_get");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1]);
+  db_put_simple(database, 3, &[3]);
+
+  let read_opts = ReadOptions::new();
+  let res = database.multi_get(read_opts, &[3, 2, 1]);
+  assert_eq!(res.len(), 3);
+  assert_eq!(res[0].as_ref().unwrap(), &Some(vec![3]));
+  assert_eq!(res[1].as_ref().unwrap(), &None);
+  assert_eq!(res[2].as_ref().unwrap(), &Some(vec![1]));
+}
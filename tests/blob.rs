@@ -0,0 +1,98 @@
+use utils::{open_database,tmpdir};
+use leveldb::blob::{BlobOptions,BlobStore};
+use leveldb::error::ErrorKind;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::snapshots::Snapshots;
+use std::fs;
+use std::path::Path;
+
+fn options() -> BlobOptions {
+  let mut options = BlobOptions::new();
+  options.min_blob_size = 16;
+  options.max_file_size = 1024;
+  options
+}
+
+fn blob_files(dir: &Path) -> usize {
+  fs::read_dir(dir).unwrap().count()
+}
+
+#[test]
+fn test_blob_store() {
+  let tmp = tmpdir("blob_store");
+  let dir = tmp.path().join("blobs");
+  let store = BlobStore::open(open_database(&tmp.path().join("db"), true), &dir, options()).unwrap();
+  let large = vec![7u8; 100];
+  store.put(WriteOptions::new(), 1, b"small").unwrap();
+  store.put(WriteOptions::new(), 2, &large).unwrap();
+  assert_eq!(store.get(ReadOptions::new(), 1).unwrap(), Some(b"small".to_vec()));
+  assert_eq!(store.get(ReadOptions::new(), 2).unwrap(), Some(large.clone()));
+  assert_eq!(store.get(ReadOptions::new(), 3).unwrap(), None);
+  // leveldb only holds a pointer
+  assert!(store.database().get(ReadOptions::new(), 2).unwrap().unwrap().len() < 20);
+  store.delete(WriteOptions::new(), 2).unwrap();
+  assert_eq!(store.get(ReadOptions::new(), 2).unwrap(), None);
+}
+
+#[test]
+fn test_blob_garbage_collection() {
+  let tmp = tmpdir("blob_gc");
+  let dir = tmp.path().join("blobs");
+  let store = BlobStore::open(open_database(&tmp.path().join("db"), true), &dir, options()).unwrap();
+  for i in 0..40 {
+    store.put(WriteOptions::new(), i, &[i as u8; 100]).unwrap();
+  }
+  for i in 0..40 {
+    if i % 4 != 0 {
+      store.delete(WriteOptions::new(), i).unwrap();
+    }
+  }
+  let before = blob_files(&dir);
+  assert!(before > 3);
+
+  let stats = store.collect_garbage(0.5).unwrap();
+  assert!(stats.files_removed > 0);
+  // 9 records fit a file, the last live one is in the current file
+  assert_eq!(stats.blobs_moved, 9);
+  assert!(blob_files(&dir) < before);
+  for i in 0..40 {
+    let expected = if i % 4 == 0 { Some(vec![i as u8; 100]) } else { None };
+    assert_eq!(store.get(ReadOptions::new(), i).unwrap(), expected);
+  }
+}
+
+#[test]
+fn test_blob_snapshot_after_garbage_collection() {
+  let tmp = tmpdir("blob_gc_snapshot");
+  let dir = tmp.path().join("blobs");
+  let store = BlobStore::open(open_database(&tmp.path().join("db"), true), &dir, options()).unwrap();
+  for i in 0..40 {
+    store.put(WriteOptions::new(), i, &[i as u8; 100]).unwrap();
+  }
+  let snapshot = store.database().snapshot();
+  let mut read_opts = ReadOptions::new();
+  read_opts.snapshot = Some(&snapshot);
+  assert_eq!(store.get(read_opts, 0).unwrap(), Some(vec![0; 100]));
+
+  store.collect_garbage(0.0).unwrap();
+  // the latest value was moved, the snapshot still points to the old file
+  assert_eq!(store.get(ReadOptions::new(), 0).unwrap(), Some(vec![0; 100]));
+  let mut read_opts = ReadOptions::new();
+  read_opts.snapshot = Some(&snapshot);
+  assert_eq!(store.get(read_opts, 0).unwrap_err().kind(), &ErrorKind::IOError);
+}
+
+#[test]
+fn test_blob_reopen() {
+  let tmp = tmpdir("blob_reopen");
+  let dir = tmp.path().join("blobs");
+  {
+    let store = BlobStore::open(open_database(&tmp.path().join("db"), true), &dir, options()).unwrap();
+    store.put(WriteOptions::new(), 1, &[1; 64]).unwrap();
+  }
+  let store = BlobStore::open(open_database(&tmp.path().join("db"), false), &dir, options()).unwrap();
+  assert_eq!(store.get(ReadOptions::new(), 1).unwrap(), Some(vec![1; 64]));
+  store.put(WriteOptions::new(), 2, &[2; 64]).unwrap();
+  assert_eq!(blob_files(&dir), 2);
+}
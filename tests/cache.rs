@@ -1,6 +1,7 @@
 use utils::{tmpdir};
 use leveldb::database::{Database};
-use leveldb::options::{Options};
+use leveldb::options::{Options,ReadOptions,WriteOptions};
+use leveldb::kv::KV;
 use leveldb::database::cache::{Cache};
 
 #[test]
@@ -12,3 +13,26 @@ fn test_open_database_with_cache() {
   let res: Result<Database<i32>,_> = Database::open(tmp.path(), opts);
   assert!(res.is_ok());
 }
+
+#[test]
+fn test_share_cache_between_databases() {
+  let cache = Cache::new(1024 * 1024);
+  let tmp1 = tmpdir("shared_cache_1");
+  let tmp2 = tmpdir("shared_cache_2");
+
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  opts.cache = Some(cache.clone());
+  let db1: Database<i32> = Database::open(tmp1.path(), opts).unwrap();
+
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  opts.cache = Some(cache.clone());
+  let db2: Database<i32> = Database::open(tmp2.path(), opts).unwrap();
+
+  drop(cache);
+  db1.put(WriteOptions::new(), 1, &[1]).unwrap();
+  db2.put(WriteOptions::new(), 1, &[2]).unwrap();
+  assert_eq!(db1.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  assert_eq!(db2.get(ReadOptions::new(), 1).unwrap(), Some(vec![2]));
+}
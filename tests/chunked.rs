@@ -0,0 +1,47 @@
+use utils::{open_database,tmpdir};
+use leveldb::chunked::ChunkedStore;
+use leveldb::iterator::Iterable;
+use leveldb::options::{ReadOptions,WriteOptions};
+use std::io::Read;
+
+#[test]
+fn test_chunked_roundtrip() {
+  let tmp = tmpdir("chunked_roundtrip");
+  let store = ChunkedStore::new(open_database(tmp.path(), true), 16);
+  let large: Vec<u8> = (0..100u8).collect();
+  store.put(WriteOptions::new(), b"small", b"tiny").unwrap();
+  store.put(WriteOptions::new(), b"large", &large).unwrap();
+  assert_eq!(store.get(b"small").unwrap(), Some(b"tiny".to_vec()));
+  assert_eq!(store.get(b"large").unwrap(), Some(large.clone()));
+  assert_eq!(store.get(b"missing").unwrap(), None);
+  // one header each, plus 7 chunks
+  assert_eq!(store.database().keys_iter(ReadOptions::new()).count(), 9);
+
+  let mut value = store.open(b"large").unwrap().unwrap();
+  assert_eq!(value.len(), 100);
+  let mut start = [0; 5];
+  value.read_exact(&mut start).unwrap();
+  assert_eq!(start, [0, 1, 2, 3, 4]);
+  store.put(WriteOptions::new(), b"large", b"replaced").unwrap();
+  let mut rest = vec![];
+  value.read_to_end(&mut rest).unwrap();
+  assert_eq!(rest, large[5..].to_vec());
+  drop(value);
+
+  assert_eq!(store.get(b"large").unwrap(), Some(b"replaced".to_vec()));
+  assert_eq!(store.database().keys_iter(ReadOptions::new()).count(), 2);
+  store.delete(WriteOptions::new(), b"large").unwrap();
+  assert_eq!(store.get(b"large").unwrap(), None);
+}
+
+#[test]
+fn test_chunked_put_reader() {
+  let tmp = tmpdir("chunked_put_reader");
+  let store = ChunkedStore::new(open_database(tmp.path(), true), 1000);
+  let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+  assert_eq!(store.put_reader(WriteOptions::new(), b"data", &data[..]).unwrap(), 10_000);
+  assert_eq!(store.get(b"data").unwrap(), Some(data));
+  assert_eq!(store.put_reader(WriteOptions::new(), b"data", &b""[..]).unwrap(), 0);
+  assert_eq!(store.get(b"data").unwrap(), Some(vec![]));
+  assert_eq!(store.database().keys_iter(ReadOptions::new()).count(), 1);
+}
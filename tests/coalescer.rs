@@ -0,0 +1,48 @@
+use utils::{open_database,tmpdir};
+use leveldb::coalescer::{CoalescerConfig,WriteCoalescer};
+use leveldb::database::Database;
+use leveldb::iterator::Iterable;
+use leveldb::kv::KV;
+use leveldb::options::ReadOptions;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_coalescer_groups_writes() {
+  let tmp = tmpdir("coalescer");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let mut config = CoalescerConfig::new();
+  config.max_delay = Duration::from_millis(20);
+  let coalescer = Arc::new(WriteCoalescer::new(database.clone(), config));
+  let threads: Vec<_> = (0..8).map(|t| {
+    let coalescer = coalescer.clone();
+    thread::spawn(move || {
+      for i in 0..20 {
+        coalescer.put(&(t * 100 + i), &[t as u8]).unwrap();
+      }
+      coalescer.delete(&(t * 100)).unwrap();
+    })
+  }).collect();
+  for thread in threads {
+    thread.join().unwrap();
+  }
+  assert_eq!(database.keys_iter(ReadOptions::new()).count(), 8 * 19);
+  assert_eq!(database.get(ReadOptions::new(), 701).unwrap(), Some(vec![7]));
+  // each thread waits for its write, so batches hold one write per thread
+  assert!(coalescer.batches() < 8 * 21);
+}
+
+#[test]
+fn test_coalescer_batch_bytes() {
+  let tmp = tmpdir("coalescer_bytes");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let mut config = CoalescerConfig::new();
+  config.max_delay = Duration::from_secs(60);
+  config.max_bytes = 10;
+  let coalescer = WriteCoalescer::new(database.clone(), config);
+  coalescer.write(&[(1, &[0; 8][..]), (2, &[0; 8][..])], &[3]).unwrap();
+  assert_eq!(coalescer.batches(), 1);
+  assert!(database.get(ReadOptions::new(), 2).unwrap().is_some());
+
+}
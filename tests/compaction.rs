@@ -2,6 +2,14 @@
 mod compaction {
      use utils::{open_database,tmpdir,db_put_simple};
      use leveldb::compaction::Compaction;
+     use leveldb::properties::Properties;
+     use leveldb::auto_compaction::AutoCompaction;
+     use leveldb::batch::{Batch, Writebatch};
+     use leveldb::database::Database;
+     use leveldb::kv::KV;
+     use leveldb::options::{OptionsBuilder, WriteOptions};
+     use std::thread;
+     use std::time::Duration;
 
     #[test]
     fn test_iterator_from_to() {
@@ -17,4 +25,88 @@ mod compaction {
         let to = 4;
         database.compact(&from, &to);
     }
+
+    #[test]
+    fn test_compact_range_open_ended() {
+        let tmp = tmpdir("compact_range");
+        let database = &mut open_database(tmp.path(), true);
+        db_put_simple(database, 1, &[1]);
+        db_put_simple(database, 2, &[2]);
+        db_put_simple(database, 3, &[3]);
+
+        let from = 2;
+        database.compact_range(Some(&from), None);
+        database.compact_range(None, Some(&from));
+    }
+
+    #[test]
+    fn test_compact_all() {
+        let tmp = tmpdir("compact_all");
+        let database = &mut open_database(tmp.path(), true);
+        db_put_simple(database, 1, &[1]);
+        db_put_simple(database, 2, &[2]);
+
+        assert_eq!(database.num_files_at_level(0), Some(0));
+        database.compact_all();
+        let files = database.database_stats().files_at_level;
+        assert_eq!(files.iter().sum::<usize>(), 1);
+    }
+
+    fn open_auto_compacted(path: &::std::path::Path) -> Database<i32> {
+        let options = OptionsBuilder::new()
+            .create_if_missing(true)
+            .auto_compaction(AutoCompaction {
+                interval: Duration::from_millis(10),
+                level0_files: 100,
+                deletes: 10,
+            })
+            .build()
+            .unwrap();
+        Database::open(path, options).unwrap()
+    }
+
+    fn wait_for_compactions(database: &Database<i32>, count: u64) -> bool {
+        for _ in 0..200 {
+            if database.auto_compactions() >= count {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn test_auto_compaction_after_deletes() {
+        let tmp = tmpdir("auto_compact");
+        let database = open_auto_compacted(tmp.path());
+        for i in 0..5 {
+            db_put_simple(&database, i, &[1]);
+            database.delete(WriteOptions::new(), i).unwrap();
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(database.auto_compactions(), 0);
+
+        let mut batch = Writebatch::new();
+        for i in 5..10 {
+            batch.delete(i);
+        }
+        database.write(WriteOptions::new(), &batch).unwrap();
+        assert!(wait_for_compactions(&database, 1));
+    }
+
+    #[test]
+    fn test_auto_compaction_pause() {
+        let tmp = tmpdir("auto_compact_pause");
+        let database = open_auto_compacted(tmp.path());
+        database.pause_auto_compaction();
+        for i in 0..20 {
+            database.delete(WriteOptions::new(), i).unwrap();
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(database.auto_compactions(), 0);
+        database.resume_auto_compaction();
+        assert!(wait_for_compactions(&database, 1));
+        // closing stops the background thread
+        drop(database);
+    }
 }
@@ -1,10 +1,9 @@
 #[cfg(test)]
 mod comparator {
-  use libc::c_char;
   use key::Key;
   use utils::{tmpdir, db_put_simple};
   use leveldb::database::{Database};
-  use leveldb::iterator::Iterable;
+  use leveldb::iterator::{Iterable,LevelDBIterator};
   use leveldb::options::{Options,ReadOptions};
   use leveldb::comparator::{Comparator,OrdComparator};
   use std::cmp::Ordering;
@@ -17,8 +16,8 @@ mod comparator {
   impl<K: Key + Ord> Comparator for ReverseComparator<K> {
     type K = K;
 
-    fn name(&self) -> *const c_char {
-      "reverse".as_ptr() as *const c_char
+    fn name(&self) -> &str {
+      "reverse"
     }
   
     fn compare(&self, a: &K, b: &K) -> Ordering {
@@ -59,4 +58,38 @@ mod comparator {
     assert_eq!((1, vec![1]), iter.next().unwrap());
     assert_eq!((2, vec![2]), iter.next().unwrap());
   }
+
+  #[test]
+  fn test_comparator_name_mismatch() {
+    let mut opts = Options::new();
+    opts.create_if_missing = true;
+    let tmp = tmpdir("comparator_name");
+    let database: Database<i32> =
+      Database::open_with_comparator(tmp.path(), opts, OrdComparator::new("foo")).unwrap();
+    drop(database);
+
+    let res: Result<Database<i32>, _> =
+      Database::open_with_comparator(tmp.path(), Options::new(), OrdComparator::new("bar"));
+    assert!(res.is_err());
+    let res: Result<Database<i32>, _> =
+      Database::open_with_comparator(tmp.path(), Options::new(), OrdComparator::new("foo"));
+    assert!(res.is_ok());
+  }
+
+  #[test]
+  fn test_comparator_bounds() {
+    let comparator: ReverseComparator<i32> = ReverseComparator { marker: PhantomData };
+    let mut opts = Options::new();
+    opts.create_if_missing = true;
+    let tmp = tmpdir("reverse_comparator_bounds");
+    let database = &mut Database::open_with_comparator(tmp.path(), opts, comparator).unwrap();
+    for i in 1..6 {
+      db_put_simple(database, i, &[i as u8]);
+    }
+
+    let from = 4;
+    let to = 2;
+    let iter = database.keys_iter(ReadOptions::new()).from(&from).until(&to);
+    assert_eq!(iter.collect::<Vec<_>>(), vec![4, 3]);
+  }
 }
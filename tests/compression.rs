@@ -0,0 +1,89 @@
+use utils::{open_database,tmpdir};
+use leveldb::compression::{Compressed,Compressor,UNCOMPRESSED};
+use leveldb::error::{Error,ErrorKind};
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::typed::{Codec,RawCodec,TypedDatabase};
+
+// run-length encodes pairs of count and byte
+struct RunLength;
+
+impl Compressor for RunLength {
+  fn tag(&self) -> u8 { 7 }
+
+  fn compress(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+    // refuses large inputs, like Lz4 does from 4 GiB on
+    if bytes.len() > 1000 {
+      return None;
+    }
+    let mut out = vec![];
+    for &byte in bytes {
+      let len = out.len();
+      if len > 0 && out[len - 1] == byte && out[len - 2] < 255 {
+        out[len - 2] += 1;
+      } else {
+        out.push(1);
+        out.push(byte);
+      }
+    }
+    Some(out)
+  }
+
+  fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    if bytes.len() % 2 != 0 {
+      return Err(Error::new("Corruption: odd run length data".to_string()));
+    }
+    Ok(bytes.chunks(2).flat_map(|pair| vec![pair[1]; pair[0] as usize]).collect())
+  }
+}
+
+#[test]
+fn test_compressed_codec() {
+  let codec = Compressed::new(RawCodec, RunLength, 8);
+  let small = vec![0; 4];
+  assert_eq!(codec.encode(&small), vec![UNCOMPRESSED, 0, 0, 0, 0]);
+  let large = vec![1; 100];
+  assert_eq!(codec.encode(&large), vec![7, 100, 1]);
+  // incompressible values are stored uncompressed
+  let noise: Vec<u8> = (0..100).collect();
+  assert_eq!(codec.encode(&noise)[0], UNCOMPRESSED);
+  // as are values the compressor refuses
+  let refused = vec![1; 2000];
+  assert_eq!(codec.encode(&refused)[0], UNCOMPRESSED);
+  for value in [small, large, noise, refused].iter() {
+    assert_eq!(&codec.decode(&codec.encode(value)).unwrap(), value);
+  }
+  assert_eq!(codec.decode(&[9, 1]).unwrap_err().kind(), &ErrorKind::Corruption);
+  assert_eq!(codec.decode(&[]).unwrap_err().kind(), &ErrorKind::Corruption);
+}
+
+#[test]
+fn test_compressed_database() {
+  let tmp = tmpdir("compressed");
+  let database = TypedDatabase::new(open_database(tmp.path(), true), Compressed::new(RawCodec, RunLength, 8));
+  database.put(WriteOptions::new(), 1, &vec![5; 1000]).unwrap();
+  assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![5; 1000]));
+  let stored = database.database().get(ReadOptions::new(), 1).unwrap().unwrap();
+  assert!(stored.len() < 20);
+}
+
+#[cfg(feature = "compression-lz4")]
+#[test]
+fn test_lz4() {
+  use leveldb::compression::Lz4;
+
+  let text = b"a brown fox jumps over the lazy dog, a brown fox jumps over the lazy dog again".repeat(20);
+  let inputs: Vec<Vec<u8>> = vec![vec![], vec![1], vec![0; 13], vec![3; 100000], text, (0..=255).collect()];
+  for input in inputs.iter() {
+    let compressed = Lz4.compress(input).unwrap();
+    assert_eq!(&Lz4.decompress(&compressed).unwrap(), input);
+  }
+  assert!(Lz4.compress(&inputs[3]).unwrap().len() < 1000);
+  let mut truncated = Lz4.compress(&inputs[4]).unwrap();
+  truncated.truncate(truncated.len() / 2);
+  assert_eq!(Lz4.decompress(&truncated).unwrap_err().kind(), &ErrorKind::Corruption);
+  // a length far beyond what the block expands to
+  let mut oversized = vec![0xff; 4];
+  oversized.extend_from_slice(&Lz4.compress(&inputs[1]).unwrap()[4..]);
+  assert_eq!(Lz4.decompress(&oversized).unwrap_err().kind(), &ErrorKind::Corruption);
+}
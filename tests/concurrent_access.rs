@@ -14,7 +14,7 @@ fn access_from_threads() {
     let database = open_database(tmp.path(), true);
     let shared = Arc::new(database);
 
-    (0..10).map(|i| {
+    let _ = (0..10).map(|i| {
          let local_db = shared.clone();
 
          thread::spawn(move || {
@@ -27,4 +27,31 @@ fn access_from_threads() {
     })
     .map(JoinHandle::join)
     .collect::<Vec<_>>();
-}
\ No newline at end of file
+}
+#[test]
+fn access_from_cloned_handles() {
+    use std::thread;
+    use leveldb::options::ReadOptions;
+
+    let tmp = tmpdir("cloned_handles");
+    let database = open_database(tmp.path(), true);
+
+    let handles = (0..10).map(|i| {
+         let local_db = database.clone();
+
+         thread::spawn(move || {
+             let write_opts = WriteOptions::new();
+             local_db.put(write_opts, i, &[i as u8]).unwrap();
+         })
+    }).collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // the original handle sees the writes and stays usable
+    // after all clones are gone
+    for i in 0..10 {
+        let read_opts = ReadOptions::new();
+        assert_eq!(Some(vec![i as u8]), database.get(read_opts, i).unwrap());
+    }
+}
@@ -0,0 +1,87 @@
+use utils::{open_database,open_ordered_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::batch::Writebatch;
+use leveldb::iterator::Iterable;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::error::ErrorKind;
+use leveldb::consensus::StateMachine;
+
+fn entry(key: i32, value: u8) -> Writebatch<i32> {
+  let mut batch = Writebatch::new();
+  batch.put(key, &[value]);
+  batch
+}
+
+#[test]
+fn test_apply_committed() {
+  let tmp = tmpdir("consensus_apply");
+  let machine = StateMachine::new(open_database::<i32>(tmp.path(), true));
+  assert_eq!(machine.last_applied_index().unwrap(), 0);
+  assert!(machine.apply_committed(1, &entry(1, 1)).unwrap());
+  assert!(machine.apply_committed(2, &entry(1, 2)).unwrap());
+  // retried after a crash
+  assert!(!machine.apply_committed(1, &entry(1, 1)).unwrap());
+  let error = machine.apply_committed(4, &entry(2, 4)).err().unwrap();
+  assert_eq!(*error.kind(), ErrorKind::InvalidArgument);
+  assert_eq!(machine.last_applied_index().unwrap(), 2);
+  assert_eq!(machine.database().get(ReadOptions::new(), 1).unwrap(), Some(vec![2]));
+  assert_eq!(machine.database().get(ReadOptions::new(), 2).unwrap(), None);
+}
+
+#[test]
+fn test_snapshot_transfer() {
+  let leader_dir = tmpdir("consensus_leader");
+  let peer_dir = tmpdir("consensus_peer");
+  let leader = StateMachine::new(open_database::<i32>(leader_dir.path(), true));
+  let peer = StateMachine::new(open_database::<i32>(peer_dir.path(), true));
+  for index in 1..4 {
+    let batch = entry(index as i32, index as u8);
+    leader.apply_committed(index, &batch).unwrap();
+    peer.apply_committed(index, &batch).unwrap();
+  }
+  leader.apply_committed(4, &entry(4, 4)).unwrap();
+
+  let mut stream = vec![];
+  assert_eq!(leader.export_snapshot(&mut stream).unwrap(), 4);
+  // the same log gives the same state
+  let mut peer_stream = vec![];
+  assert_eq!(peer.export_snapshot(&mut peer_stream).unwrap(), 3);
+  let fresh_dir = tmpdir("consensus_fresh");
+  let fresh: Database<i32> = open_database(fresh_dir.path(), true);
+  let fresh = StateMachine::new(fresh);
+  assert_eq!(fresh.install_snapshot(&peer_stream[..]).unwrap(), 3);
+  let mut fresh_stream = vec![];
+  fresh.export_snapshot(&mut fresh_stream).unwrap();
+  assert_eq!(fresh_stream, peer_stream);
+
+  // the lagging peer catches up from the snapshot
+  peer.database().put(WriteOptions::new(), 9, &[9]).unwrap();
+  assert_eq!(peer.install_snapshot(&stream[..]).unwrap(), 4);
+  assert_eq!(peer.database().get(ReadOptions::new(), 4).unwrap(), Some(vec![4]));
+  assert_eq!(peer.database().get(ReadOptions::new(), 9).unwrap(), None);
+  assert!(peer.apply_committed(5, &entry(5, 5)).unwrap());
+
+  let error = peer.install_snapshot(&stream[..stream.len() - 1]).err().unwrap();
+  assert_eq!(*error.kind(), ErrorKind::Corruption);
+  assert_eq!(peer.last_applied_index().unwrap(), 0);
+  assert_eq!(peer.database().get(ReadOptions::new(), 1).unwrap(), None);
+}
+
+#[test]
+fn test_state_machine_on_ordered_database() {
+  let leader_dir = tmpdir("consensus_ordered_leader");
+  let leader = StateMachine::new(open_ordered_database(leader_dir.path()));
+  assert!(leader.apply_committed(1, &entry(2, 2)).unwrap());
+  assert!(leader.apply_committed(2, &entry(1, 1)).unwrap());
+  assert_eq!(leader.last_applied_index().unwrap(), 2);
+  let keys: Vec<i32> = leader.database().keys_iter(ReadOptions::new()).collect();
+  assert_eq!(keys, vec![1, 2]);
+
+  let mut stream = vec![];
+  assert_eq!(leader.export_snapshot(&mut stream).unwrap(), 2);
+  let peer_dir = tmpdir("consensus_ordered_peer");
+  let peer = StateMachine::new(open_ordered_database(peer_dir.path()));
+  assert_eq!(peer.install_snapshot(&stream[..]).unwrap(), 2);
+  assert_eq!(peer.database().last_key(ReadOptions::new()).unwrap(), Some(2));
+}
@@ -0,0 +1,74 @@
+use utils::{open_database,tmpdir,db_put_simple};
+use leveldb::cursor::Cursor;
+use leveldb::error::ErrorKind;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::snapshots::Snapshots;
+
+fn keys(page: Vec<(i32, Vec<u8>)>) -> Vec<i32> {
+  page.into_iter().map(|(key, _)| key).collect()
+}
+
+#[test]
+fn test_cursor_pages() {
+  let tmp = tmpdir("cursor_pages");
+  let database = open_database(tmp.path(), true);
+  for i in 0..10 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let mut cursor = Cursor::new().from(&2).until(&9);
+  assert_eq!(keys(cursor.next_page(&database, ReadOptions::new(), 3)), vec![2, 3, 4]);
+  assert_eq!(cursor.last_key(), Some(4));
+  assert_eq!(keys(cursor.next_page(&database, ReadOptions::new(), 3)), vec![5, 6, 7]);
+  assert!(!cursor.is_done());
+  assert_eq!(keys(cursor.next_page(&database, ReadOptions::new(), 3)), vec![8]);
+  assert!(cursor.is_done());
+  assert!(cursor.next_page(&database, ReadOptions::new(), 3).is_empty());
+}
+
+#[test]
+fn test_cursor_reverse() {
+  let tmp = tmpdir("cursor_reverse");
+  let database = open_database(tmp.path(), true);
+  for i in 0..6 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let mut cursor = Cursor::new().from(&1).reverse();
+  assert_eq!(keys(cursor.next_page(&database, ReadOptions::new(), 2)), vec![5, 4]);
+  assert_eq!(keys(cursor.next_page(&database, ReadOptions::new(), 2)), vec![3, 2]);
+  assert_eq!(keys(cursor.next_page(&database, ReadOptions::new(), 2)), vec![1]);
+  assert!(cursor.is_done());
+}
+
+#[test]
+fn test_cursor_token_resumes_on_fresh_snapshot() {
+  let tmp = tmpdir("cursor_token");
+  let database = open_database(tmp.path(), true);
+  for i in 0..6 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let mut cursor = Cursor::new().to(&4);
+  assert_eq!(keys(cursor.next_page(&database, ReadOptions::new(), 2)), vec![0, 1]);
+  let token = cursor.token();
+
+  // writes behind the cursor are skipped, those ahead of it are seen
+  database.delete(WriteOptions::new(), 2).unwrap();
+  db_put_simple(&database, 0, &[10]);
+  let snapshot = database.snapshot();
+  let mut options = ReadOptions::new();
+  options.snapshot = Some(&snapshot);
+
+  let mut resumed: Cursor<i32> = Cursor::from_token(&token).unwrap();
+  assert_eq!(resumed.last_key(), Some(1));
+  assert_eq!(keys(resumed.next_page(&database, options, 10)), vec![3, 4]);
+  assert!(resumed.is_done());
+  assert!(Cursor::<i32>::from_token(&resumed.token()).unwrap().is_done());
+}
+
+#[test]
+fn test_cursor_malformed_token() {
+  for token in ["", "zz", "02", "0100", "01000000"].iter() {
+    let error = Cursor::<i32>::from_token(token).err().unwrap();
+    assert_eq!(error.kind(), &ErrorKind::InvalidArgument);
+  }
+}
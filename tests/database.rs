@@ -1,6 +1,14 @@
 use utils::{tmpdir};
 use leveldb::database::{Database};
-use leveldb::options::{Options};
+use leveldb::options::{DefaultReadOptions,Options,OptionsBuilder};
+use leveldb::database::cache::Cache;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::comparator::OrdComparator;
+use leveldb::iterator::Iterable;
+use leveldb::kv::KV;
+use leveldb::properties::Properties;
+use leveldb::error::ErrorKind;
+use std::fs;
 
 #[test]
 fn test_create_options() {
@@ -22,5 +30,171 @@ fn test_open_non_existant_database_without_create() {
   opts.create_if_missing = false;
   let tmp = tmpdir("missing");
   let res: Result<Database<i32>,_> = Database::open(tmp.path(), opts);
-  assert!(res.is_err());
+  assert_eq!(&ErrorKind::InvalidArgument, res.err().unwrap().kind());
+}
+
+#[test]
+fn test_open_database_with_builder() {
+  let opts = OptionsBuilder::new()
+    .create_if_missing(true)
+    .write_buffer_size(1024 * 1024)
+    .block_size(4096)
+    .max_open_files(100)
+    .cache(Cache::new(1024))
+    .build()
+    .unwrap();
+  let tmp = tmpdir("options_builder");
+  let res: Result<Database<i32>,_> = Database::open(tmp.path(), opts);
+  assert!(res.is_ok());
+}
+
+#[test]
+fn test_default_options() {
+  let mut write_options = WriteOptions::new();
+  write_options.sync = true;
+  let mut read_options = DefaultReadOptions::new();
+  read_options.verify_checksums = true;
+  let opts = OptionsBuilder::new()
+    .create_if_missing(true)
+    .default_read_options(read_options)
+    .default_write_options(write_options)
+    .build()
+    .unwrap();
+  let tmp = tmpdir("default_options");
+  let database: Database<i32> = Database::open(tmp.path(), opts).unwrap();
+  assert!(database.default_write_options().sync);
+  assert!(database.default_read_options().verify_checksums);
+  database.put_default(1, &[1]).unwrap();
+  assert_eq!(database.get_default(1).unwrap(), Some(vec![1]));
+  assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  database.delete_default(1).unwrap();
+  assert_eq!(database.get_default(1).unwrap(), None);
+}
+
+#[test]
+fn test_builder_validation() {
+  assert!(OptionsBuilder::new().block_size(0).build().is_err());
+  assert!(OptionsBuilder::new().max_open_files(-1).build().is_err());
+  assert!(OptionsBuilder::new().block_restart_interval(0).build().is_err());
+  // settings that don't go together
+  assert!(OptionsBuilder::new().write_buffer_size(1 << 20).block_size(2 << 20).build().is_err());
+  assert!(OptionsBuilder::new().block_size(8 << 20).build().is_err());
+  assert!(OptionsBuilder::new().error_if_exists(true).build().is_err());
+  assert!(OptionsBuilder::new().error_if_exists(true).create_if_missing(true).build().is_ok());
+}
+
+#[test]
+fn test_open_database_with_builder_comparator() {
+  let tmp = tmpdir("options_builder_comparator");
+  let database = OptionsBuilder::new()
+    .create_if_missing(true)
+    .comparator(OrdComparator::<i32>::new("ord"))
+    .block_size(4096)
+    .open(tmp.path())
+    .unwrap();
+  for key in [1, -1, 0] {
+    database.put(WriteOptions::new(), key, &[1]).unwrap();
+  }
+  assert_eq!(database.keys_iter(ReadOptions::new()).collect::<Vec<i32>>(), vec![-1, 0, 1]);
+
+  let error = OptionsBuilder::new()
+    .comparator(OrdComparator::<i32>::new("ord"))
+    .block_size(0)
+    .open(tmp.path())
+    .err()
+    .unwrap();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
+}
+
+#[test]
+fn test_open_database_with_tuning_options() {
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  opts.write_buffer_size = Some(64 * 1024);
+  opts.block_size = Some(1024);
+  opts.block_restart_interval = Some(8);
+  opts.max_open_files = Some(100);
+  let tmp = tmpdir("tuning_options");
+  let database: Database<i32> = Database::open(tmp.path(), opts).unwrap();
+  for i in 0..256 {
+    database.put(WriteOptions::new(), i, &[0; 1024]).unwrap();
+  }
+  // the small write buffer forces memtables to be flushed to tables
+  let files: usize = database.database_stats().files_at_level.iter().sum();
+  assert!(files > 0);
+}
+
+#[test]
+fn test_open_corrupted_database_with_paranoid_checks() {
+  let tmp = tmpdir("paranoid_checks");
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let database: Database<i32> = Database::open(tmp.path(), opts).unwrap();
+  database.put(WriteOptions::new(), 1, &[1; 100]).unwrap();
+  drop(database);
+
+  let log = fs::read_dir(tmp.path()).unwrap()
+    .map(|entry| entry.unwrap().path())
+    .find(|path| path.extension().is_some_and(|ext| ext == "log"))
+    .unwrap();
+  let mut contents = fs::read(&log).unwrap();
+  for byte in contents.iter_mut().skip(10).take(20) {
+    *byte = !*byte;
+  }
+  fs::write(&log, contents).unwrap();
+
+  let mut opts = Options::new();
+  opts.paranoid_checks = true;
+  let res: Result<Database<i32>,_> = Database::open(tmp.path(), opts);
+  assert_eq!(&ErrorKind::Corruption, res.err().unwrap().kind());
+
+  let res: Result<Database<i32>,_> = Database::open(tmp.path(), Options::new());
+  assert!(res.is_ok());
+}
+
+#[test]
+fn test_close() {
+  let tmp = tmpdir("close");
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let database: Database<i32> = Database::open(tmp.path(), opts).unwrap();
+  database.put(WriteOptions::new(), 1, &[1]).unwrap();
+  let clone = database.clone();
+  assert_eq!(&ErrorKind::InvalidArgument, database.close().err().unwrap().kind());
+  assert!(clone.close().is_ok());
+
+  let database: Database<i32> = Database::open(tmp.path(), Options::new()).unwrap();
+  assert_eq!(Some(vec![1]), database.get(ReadOptions::new(), 1).unwrap());
+  assert!(database.close().is_ok());
+}
+
+#[test]
+fn test_open_with_retry() {
+  use leveldb::retry::RetryPolicy;
+  use std::thread;
+  use std::time::Duration;
+
+  let tmp = tmpdir("open_with_retry");
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let held: Database<i32> = Database::open(tmp.path(), opts).unwrap();
+
+  let mut policy = RetryPolicy::new();
+  policy.attempts = 2;
+  policy.initial_backoff = Duration::from_millis(10);
+  let error = Database::<i32>::open_with_retry(tmp.path(), Options::new(), policy).err().unwrap();
+  assert_eq!(&ErrorKind::IOError, error.kind());
+  assert!(error.message().contains("after 2 attempts"));
+
+  let releaser = thread::spawn(move || {
+    thread::sleep(Duration::from_millis(100));
+    drop(held);
+  });
+  let database = Database::<i32>::open_with_retry(tmp.path(), Options::new(), RetryPolicy::new());
+  releaser.join().unwrap();
+  assert!(database.is_ok());
+
+  let missing = tmp.path().join("missing");
+  let error = Database::<i32>::open_with_retry(&missing, Options::new(), RetryPolicy::new()).err().unwrap();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
 }
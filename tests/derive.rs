@@ -0,0 +1,51 @@
+use utils::{open_database,tmpdir};
+use leveldb::DbKey;
+use leveldb::keys::Key;
+use leveldb::iterator::Iterable;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+
+#[derive(DbKey, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Event {
+  stream: String,
+  sequence: i64,
+  id: [u8; 2],
+}
+
+#[derive(DbKey, Debug, PartialEq)]
+struct Pair(u16, Vec<u8>);
+
+#[test]
+fn test_derive_roundtrip() {
+  let event = Event { stream: "a\u{0}b".to_string(), sequence: -5, id: [1, 2] };
+  let decoded: Event = event.as_slice(Event::from_u8);
+  assert_eq!(event, decoded);
+
+  let pair = Pair(7, vec![0, 0xff, 0]);
+  let decoded: Pair = pair.as_slice(Pair::from_u8);
+  assert_eq!(pair, decoded);
+}
+
+#[test]
+fn test_derive_order() {
+  let tmp = tmpdir("derive_order");
+  let database = open_database::<Event>(tmp.path(), true);
+  let mut events = vec![];
+  for stream in ["", "a", "a\u{0}", "ab", "b"].iter() {
+    for sequence in [i64::MIN, -1, 0, 1, i64::MAX].iter() {
+      events.push(Event { stream: stream.to_string(), sequence: *sequence, id: [0, 9] });
+    }
+  }
+  for event in events.iter().rev() {
+    database.put(WriteOptions::new(), event, &[]).unwrap();
+  }
+  let keys: Vec<Event> = database.keys_iter(ReadOptions::new()).collect();
+  events.sort();
+  assert_eq!(keys, events);
+}
+
+#[test]
+#[should_panic(expected = "key too long")]
+fn test_derive_trailing_bytes() {
+  let _: Pair = Key::from_u8(&[0, 1, 0, 1, 2]);
+}
@@ -0,0 +1,57 @@
+use leveldb::database::{Database};
+use leveldb::options::{Options};
+use leveldb::database::env::{Env};
+
+#[cfg(feature = "memory-env")]
+mod memory {
+  use leveldb::database::{Database};
+  use leveldb::options::{Options,ReadOptions,WriteOptions};
+  use leveldb::database::kv::{KV};
+  use leveldb::database::env::{Env};
+  use std::path::Path;
+
+  #[test]
+  fn test_open_database_in_memory() {
+    let path = Path::new("/leveldb-memory-test");
+    let env = Env::memory();
+    let mut opts = Options::new();
+    opts.create_if_missing = true;
+    opts.env = Some(env.clone());
+    let database: Database<i32> = Database::open(path, opts).unwrap();
+    database.put(WriteOptions::new(), 1, &[1]).unwrap();
+    assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+    assert!(!path.exists());
+    drop(database);
+
+    let mut opts = Options::new();
+    opts.env = Some(env);
+    let database: Database<i32> = Database::open(path, opts).unwrap();
+    assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  }
+
+  #[test]
+  fn test_memory_envs_are_separate() {
+    let path = Path::new("/leveldb-memory-separate");
+    let mut opts = Options::new();
+    opts.create_if_missing = true;
+    opts.env = Some(Env::memory());
+    let database: Database<i32> = Database::open(path, opts).unwrap();
+    database.put(WriteOptions::new(), 1, &[1]).unwrap();
+
+    let mut opts = Options::new();
+    opts.create_if_missing = true;
+    opts.env = Some(Env::memory());
+    let other: Database<i32> = Database::open(path, opts).unwrap();
+    assert_eq!(other.get(ReadOptions::new(), 1).unwrap(), None);
+  }
+}
+
+#[test]
+fn test_open_database_with_default_env() {
+  let tmp = ::utils::tmpdir("default_env");
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  opts.env = Some(Env::default_env());
+  let res: Result<Database<i32>,_> = Database::open(tmp.path(), opts);
+  assert!(res.is_ok());
+}
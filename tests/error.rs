@@ -0,0 +1,72 @@
+use utils::tmpdir;
+use leveldb::database::Database;
+use leveldb::error::{Error,ErrorKind,Operation};
+use leveldb::options::Options;
+use std::io;
+
+#[test]
+fn test_error_kind_from_status() {
+  assert_eq!(&ErrorKind::NotFound, Error::new("NotFound: missing".to_string()).kind());
+  assert_eq!(&ErrorKind::Corruption, Error::new("Corruption: bad block".to_string()).kind());
+  assert_eq!(&ErrorKind::NotSupported, Error::new("Not implemented: nope".to_string()).kind());
+  assert_eq!(&ErrorKind::InvalidArgument, Error::new("Invalid argument: foo".to_string()).kind());
+  assert_eq!(&ErrorKind::IOError, Error::new("IO error: disk full".to_string()).kind());
+
+  let error = Error::new("something else".to_string());
+  assert_eq!(&ErrorKind::Other("something else".to_string()), error.kind());
+  assert_eq!("something else", error.message());
+}
+
+#[test]
+fn test_error_into_io_error() {
+  let error: io::Error = Error::new("Corruption: bad block".to_string()).into();
+  assert_eq!(io::ErrorKind::InvalidData, error.kind());
+  let error: io::Error = Error::new("IO error: disk full".to_string()).into();
+  assert_eq!(io::ErrorKind::Other, error.kind());
+}
+
+#[test]
+fn test_error_context() {
+  let error = Error::new("Corruption: bad block".to_string());
+  assert_eq!(error.to_string(), "LevelDB error: Corruption: bad block");
+  assert_eq!(error.operation(), None);
+
+  let error = error.with_operation(Operation::Get).with_key(&[0, 1, 0xff]);
+  assert_eq!(error.operation(), Some(Operation::Get));
+  assert_eq!(error.key(), Some("0001ff"));
+  assert_eq!(error.message(), "Corruption: bad block");
+  assert_eq!(error.to_string(), "LevelDB error: Corruption: bad block (get, key 0001ff)");
+
+  let error = Error::new("IO error: disk full".to_string()).with_key(&[0xab; 40]);
+  assert_eq!(error.key(), Some(&format!("{}...", "ab".repeat(32))[..]));
+}
+
+#[test]
+fn test_open_error_context() {
+  let tmp = tmpdir("open_error_context");
+  let path = tmp.path().join("missing");
+  let error = Database::<i32>::open(&path, Options::new()).err().unwrap();
+  assert_eq!(error.operation(), Some(Operation::Open));
+  assert_eq!(error.path(), Some(path.as_path()));
+  assert!(error.to_string().contains(&format!("(open on {})", path.display())));
+}
+
+#[test]
+fn test_error_with_kind() {
+  let error = Error::with_kind(ErrorKind::Conflict, "key 1 changed".to_string());
+  assert_eq!(&ErrorKind::Conflict, error.kind());
+  assert_eq!("Conflict: key 1 changed", error.message());
+
+  let error = Error::with_kind(ErrorKind::Other("ignored".to_string()), "plain".to_string());
+  assert_eq!(&ErrorKind::Other("plain".to_string()), error.kind());
+  assert_eq!("plain", error.message());
+
+  let error = Error::with_kind(ErrorKind::Corruption, "bad block".to_string())
+    .with_operation(Operation::Get)
+    .with_key(&[1])
+    .context("migrating to schema version 2");
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  assert_eq!(error.operation(), Some(Operation::Get));
+  assert_eq!(error.key(), Some("01"));
+  assert_eq!("Corruption: bad block (migrating to schema version 2)", error.message());
+}
@@ -0,0 +1,17 @@
+use utils::{tmpdir};
+use leveldb::database::{Database};
+use leveldb::options::{Options,ReadOptions,WriteOptions};
+use leveldb::database::kv::{KV};
+use leveldb::database::filter_policy::{FilterPolicy};
+
+#[test]
+fn test_open_database_with_bloom_filter() {
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  opts.filter_policy = Some(FilterPolicy::bloom(10));
+  let tmp = tmpdir("bloom_filter");
+  let database: Database<i32> = Database::open(tmp.path(), opts).unwrap();
+  database.put(WriteOptions::new(), 1, &[1]).unwrap();
+  assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  assert_eq!(database.get(ReadOptions::new(), 2).unwrap(), None);
+}
@@ -0,0 +1,48 @@
+use utils::{db_put_simple,open_database,open_ordered_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::compaction::Compaction;
+use leveldb::iterator::Iterable;
+use leveldb::options::ReadOptions;
+use leveldb::health::{HealthStatus,HealthThresholds};
+
+#[test]
+fn test_health_check() {
+  let tmp = tmpdir("health");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &[1]);
+  let health = database.health_check();
+  assert_eq!(health.status, HealthStatus::Healthy);
+  assert!(health.details.is_empty());
+  // the canary is gone again
+  assert_eq!(database.keys_iter(ReadOptions::new()).collect::<Vec<_>>(), vec![1]);
+}
+
+#[test]
+fn test_health_check_on_ordered_database() {
+  let tmp = tmpdir("health_ordered");
+  let database = open_ordered_database(tmp.path());
+  db_put_simple(&database, 2, &[2]);
+  db_put_simple(&database, 1, &[1]);
+  // the comparator never sees the canary, which doesn't decode as an i32
+  assert_eq!(database.health_check().status, HealthStatus::Healthy);
+  assert_eq!(database.keys_iter(ReadOptions::new()).collect::<Vec<_>>(), vec![1, 2]);
+}
+
+#[test]
+fn test_health_check_thresholds() {
+  let tmp = tmpdir("health_thresholds");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &[1]);
+  database.compact_all();
+  let mut thresholds = HealthThresholds::new();
+  thresholds.max_files = Some(0);
+  let health = database.health_check_with(&thresholds);
+  assert_eq!(health.status, HealthStatus::Degraded);
+  assert_eq!(health.details.len(), 1);
+  assert!(health.details[0].contains("table files"));
+
+  let mut thresholds = HealthThresholds::new();
+  thresholds.max_level0_files = 0;
+  assert_eq!(database.health_check_with(&thresholds).status, HealthStatus::Degraded);
+  assert!(HealthStatus::Unhealthy > HealthStatus::Degraded);
+}
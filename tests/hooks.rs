@@ -0,0 +1,71 @@
+use utils::{open_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::backend::Operation;
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::error::Error;
+use leveldb::hooks::WriteHook;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use std::sync::{Arc,Mutex};
+
+struct Recorder {
+  before: Arc<Mutex<usize>>,
+  written: Arc<Mutex<Vec<Operation>>>,
+}
+
+impl WriteHook for Recorder {
+  fn before_write(&self, _: &[Operation]) -> Result<(), Error> {
+    *self.before.lock().unwrap() += 1;
+    Ok(())
+  }
+
+  fn after_write(&self, operations: &[Operation]) {
+    self.written.lock().unwrap().extend_from_slice(operations);
+  }
+}
+
+// rejects values longer than 2 bytes
+struct MaxLength;
+
+impl WriteHook for MaxLength {
+  fn before_write(&self, operations: &[Operation]) -> Result<(), Error> {
+    for operation in operations {
+      if let Operation::Put(_, ref value) = *operation {
+        if value.len() > 2 {
+          return Err(Error::new("Invalid argument: value too long".to_string()));
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn test_write_hooks() {
+  let tmp = tmpdir("write_hooks");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let before = Arc::new(Mutex::new(0));
+  let written = Arc::new(Mutex::new(vec![]));
+  database.add_write_hook(Box::new(MaxLength));
+  database.add_write_hook(Box::new(Recorder { before: before.clone(), written: written.clone() }));
+
+  database.put(WriteOptions::new(), 1, &[1]).unwrap();
+  database.delete(WriteOptions::new(), 1).unwrap();
+  let mut batch = Writebatch::new();
+  batch.put(2, &[2]);
+  batch.put(3, &[3, 3]);
+  database.write(WriteOptions::new(), &batch).unwrap();
+  // metadata isn't passed to hooks
+  database.meta().put(WriteOptions::new(), "marker", &[0; 10]).unwrap();
+
+  // vetoed writes fail before the later hooks and the write
+  assert!(database.put(WriteOptions::new(), 4, &[4, 4, 4]).is_err());
+  assert_eq!(database.get(ReadOptions::new(), 4).unwrap(), None);
+
+  assert_eq!(*before.lock().unwrap(), 3);
+  assert_eq!(*written.lock().unwrap(),
+             vec![Operation::Put(vec![0, 0, 0, 1], vec![1]),
+                  Operation::Delete(vec![0, 0, 0, 1]),
+                  Operation::Put(vec![0, 0, 0, 2], vec![2]),
+                  Operation::Put(vec![0, 0, 0, 3], vec![3, 3])]);
+}
@@ -0,0 +1,70 @@
+use utils::{tmpdir,open_database};
+use leveldb::error::ErrorKind;
+use leveldb::index::{IndexedDatabase,SecondaryIndex};
+use leveldb::iterator::Iterable;
+use leveldb::options::{ReadOptions,WriteOptions};
+
+// values are "<city>:<tag>,<tag>"
+fn open_indexed(name: &str) -> (::tempdir::TempDir, IndexedDatabase) {
+  let tmp = tmpdir(name);
+  let by_city = SecondaryIndex::new("city", |value: &[u8]| {
+    value.split(|&b| b == b':').take(1).map(|city| city.to_vec()).collect()
+  });
+  let by_tag = SecondaryIndex::new("tag", |value: &[u8]| {
+    match value.splitn(2, |&b| b == b':').nth(1) {
+      Some(tags) => tags.split(|&b| b == b',').map(|tag| tag.to_vec()).collect(),
+      None => vec![],
+    }
+  });
+  let database = IndexedDatabase::new(open_database(tmp.path(), true), vec![by_city, by_tag]);
+  (tmp, database)
+}
+
+#[test]
+fn test_lookup_by_index() {
+  let (_tmp, database) = open_indexed("index_lookup");
+  database.put(WriteOptions::new(), b"alice", b"berlin:a,b").unwrap();
+  database.put(WriteOptions::new(), b"bob", b"paris:b").unwrap();
+  database.put(WriteOptions::new(), b"carol", b"berlin:c").unwrap();
+
+  assert_eq!(Some(b"paris:b".to_vec()), database.get(ReadOptions::new(), b"bob").unwrap());
+  assert_eq!(vec![(b"alice".to_vec(), b"berlin:a,b".to_vec()),
+                  (b"carol".to_vec(), b"berlin:c".to_vec())],
+             database.lookup_by_index("city", b"berlin").unwrap());
+  let tagged: Vec<Vec<u8>> = database.lookup_by_index("tag", b"b").unwrap()
+    .into_iter().map(|(k, _)| k).collect();
+  assert_eq!(vec![b"alice".to_vec(), b"bob".to_vec()], tagged);
+  // "berlin" must not match a longer index key sharing its prefix
+  database.put(WriteOptions::new(), b"dave", b"berlinx:").unwrap();
+  assert_eq!(2, database.lookup_by_index("city", b"berlin").unwrap().len());
+
+  let error = database.lookup_by_index("country", b"de").err().unwrap();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
+}
+
+#[test]
+fn test_index_follows_updates_and_deletes() {
+  let (_tmp, database) = open_indexed("index_updates");
+  database.put(WriteOptions::new(), b"alice", b"berlin:a").unwrap();
+  database.put(WriteOptions::new(), b"alice", b"paris:a").unwrap();
+  assert!(database.lookup_by_index("city", b"berlin").unwrap().is_empty());
+  assert_eq!(1, database.lookup_by_index("city", b"paris").unwrap().len());
+
+  database.delete(WriteOptions::new(), b"alice").unwrap();
+  assert!(database.lookup_by_index("city", b"paris").unwrap().is_empty());
+  assert!(database.lookup_by_index("tag", b"a").unwrap().is_empty());
+  // only the primary entries and index entries written are in the database
+  assert_eq!(0, database.database().keys_iter(ReadOptions::new()).count());
+}
+
+#[test]
+fn test_iter_index() {
+  let (_tmp, database) = open_indexed("index_iter");
+  database.put(WriteOptions::new(), b"alice", b"paris:").unwrap();
+  database.put(WriteOptions::new(), b"bob", b"berlin:").unwrap();
+  let entries: Vec<(Vec<u8>, Vec<u8>)> = database.iter_index(ReadOptions::new(), "city")
+    .unwrap()
+    .collect();
+  assert_eq!(vec![(b"berlin".to_vec(), b"bob".to_vec()), (b"paris".to_vec(), b"alice".to_vec())],
+             entries);
+}
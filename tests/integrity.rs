@@ -0,0 +1,52 @@
+use utils::{db_put_simple,open_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::compaction::Compaction;
+use leveldb::options::ReadOptions;
+use key::Key;
+use std::fs;
+
+#[test]
+fn test_verify_integrity() {
+  let tmp = tmpdir("integrity");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..1000 {
+    db_put_simple(&database, i, &[(i % 256) as u8; 100]);
+  }
+  let report = database.verify_integrity(ReadOptions::new());
+  assert!(report.is_ok());
+  assert_eq!(report.readable_keys, 1000);
+  assert_eq!(report.readable_bytes, 1000 * 104);
+}
+
+#[test]
+fn test_verify_integrity_finds_corruption() {
+  let tmp = tmpdir("integrity_corruption");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..1000 {
+    db_put_simple(&database, i, &[(i % 256) as u8; 100]);
+  }
+  database.compact_all();
+  drop(database);
+
+  let table = fs::read_dir(tmp.path()).unwrap()
+    .map(|entry| entry.unwrap().path())
+    .find(|path| path.extension().is_some_and(|ext| ext == "ldb"))
+    .unwrap();
+  let mut contents = fs::read(&table).unwrap();
+  let middle = contents.len() / 2;
+  for byte in contents[middle..middle + 100].iter_mut() {
+    *byte ^= 0xff;
+  }
+  fs::write(&table, contents).unwrap();
+
+  let database: Database<i32> = open_database(tmp.path(), false);
+  let report = database.verify_integrity(ReadOptions::new());
+  assert!(!report.is_ok());
+  let range = report.first_corruption.unwrap();
+  assert!(range.message.starts_with("Corruption: "));
+  // the entries of the damaged block are skipped, the rest is read
+  let after = i32::from_u8(&range.after.unwrap());
+  let before = i32::from_u8(&range.before.unwrap());
+  assert!(after < before);
+  assert_eq!(report.readable_keys, 1000 - (before - after - 1) as u64);
+}
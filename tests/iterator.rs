@@ -1,7 +1,13 @@
 use utils::{open_database,tmpdir,db_put_simple};
-use leveldb::iterator::Iterable;
+use leveldb::iterator::{Iterable,OwnedIterable,OwnedIterator};
+use leveldb::database::Database;
+use std::sync::Arc;
+use std::thread;
 use leveldb::iterator::LevelDBIterator;
 use leveldb::options::{ReadOptions};
+use leveldb::compaction::Compaction;
+use leveldb::error::ErrorKind;
+use std::fs;
 
 #[test]
 fn test_iterator() {
@@ -62,7 +68,7 @@ fn test_key_iterator() {
   db_put_simple(database, 1, &[1]);
   db_put_simple(database, 2, &[2]);
 
-  let iterable: &mut Iterable<i32> = database;
+  let iterable: &mut dyn Iterable<i32> = database;
 
   let read_opts = ReadOptions::new();
   let mut iter = iterable.keys_iter(read_opts);
@@ -77,10 +83,412 @@ fn test_value_iterator() {
   db_put_simple(database, 1, &[1]);
   db_put_simple(database, 2, &[2]);
 
-  let iterable: &mut Iterable<i32> = database;
+  let iterable: &mut dyn Iterable<i32> = database;
 
   let read_opts = ReadOptions::new();
   let mut iter = iterable.value_iter(read_opts);
   let value = iter.next().unwrap();
   assert_eq!(value, vec![1]);
 }
+
+#[test]
+fn test_iterator_seek() {
+  let tmp = tmpdir("iter_seek");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1]);
+  db_put_simple(database, 2, &[2]);
+  db_put_simple(database, 4, &[4]);
+
+  let read_opts = ReadOptions::new();
+  let mut iter = database.iter(read_opts);
+  iter.seek(&2);
+  assert_eq!(iter.next().unwrap(), (2, vec![2]));
+  iter.seek(&3);
+  assert_eq!(iter.next().unwrap(), (4, vec![4]));
+  assert!(iter.next().is_none());
+
+  iter.seek_to_first();
+  assert_eq!(iter.next().unwrap(), (1, vec![1]));
+  iter.seek_to_last();
+  assert_eq!(iter.next().unwrap(), (4, vec![4]));
+  assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_iterator_seek_for_prev() {
+  let tmp = tmpdir("iter_seek_for_prev");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1]);
+  db_put_simple(database, 2, &[2]);
+  db_put_simple(database, 4, &[4]);
+
+  let mut iter = database.iter(ReadOptions::new());
+  iter.seek_for_prev(&2);
+  assert_eq!(iter.next().unwrap(), (2, vec![2]));
+  iter.seek_for_prev(&3);
+  assert_eq!(iter.next().unwrap(), (2, vec![2]));
+  assert_eq!(iter.next().unwrap(), (4, vec![4]));
+  iter.seek_for_prev(&9);
+  assert_eq!(iter.next().unwrap(), (4, vec![4]));
+
+  let mut keys = database.keys_iter(ReadOptions::new()).reverse();
+  keys.seek_for_prev(&3);
+  assert_eq!(keys.by_ref().collect::<Vec<_>>(), vec![2, 1]);
+  let mut keys = database.keys_iter(ReadOptions::new());
+  keys.seek_for_prev(&0);
+  assert!(keys.next().is_none());
+}
+
+#[test]
+fn test_key_and_value_iterator_seek() {
+  let tmp = tmpdir("key_value_iter_seek");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1]);
+  db_put_simple(database, 2, &[2]);
+
+  let mut keys = database.keys_iter(ReadOptions::new());
+  keys.seek(&2);
+  assert_eq!(keys.next(), Some(2));
+  keys.seek_to_first();
+  assert_eq!(keys.next(), Some(1));
+
+  let mut values = database.value_iter(ReadOptions::new());
+  values.seek_to_last();
+  assert_eq!(values.next(), Some(vec![2]));
+  assert_eq!(values.next(), None);
+}
+
+#[test]
+fn test_iterator_last_of_empty_database() {
+  let tmp = tmpdir("iter_last_empty");
+  let database = &mut open_database::<i32>(tmp.path(), true);
+  assert!(database.iter(ReadOptions::new()).last().is_none());
+}
+
+#[test]
+fn test_reverse_iterator() {
+  let tmp = tmpdir("reverse_iter");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1]);
+  db_put_simple(database, 2, &[2]);
+  db_put_simple(database, 3, &[3]);
+
+  let iter = database.iter(ReadOptions::new()).reverse();
+  assert_eq!(iter.collect::<Vec<_>>(), vec![(3, vec![3]), (2, vec![2]), (1, vec![1])]);
+
+  let keys = database.keys_iter(ReadOptions::new()).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![3, 2, 1]);
+
+  let mut values = database.value_iter(ReadOptions::new()).reverse();
+  values.seek(&2);
+  assert_eq!(values.collect::<Vec<_>>(), vec![vec![2], vec![1]]);
+}
+
+#[test]
+fn test_iterator_bounds() {
+  let tmp = tmpdir("iter_bounds");
+  let database = &mut open_database(tmp.path(), true);
+  for i in 1..6 {
+    db_put_simple(database, i, &[i as u8]);
+  }
+
+  let from = 2;
+  let to = 4;
+  let keys = database.keys_iter(ReadOptions::new()).from(&from).to(&to);
+  assert_eq!(keys.collect::<Vec<_>>(), vec![2, 3, 4]);
+
+  let keys = database.keys_iter(ReadOptions::new()).from(&from).until(&to);
+  assert_eq!(keys.collect::<Vec<_>>(), vec![2, 3]);
+
+  let values = database.value_iter(ReadOptions::new()).until(&to);
+  assert_eq!(values.collect::<Vec<_>>(), vec![vec![1], vec![2], vec![3]]);
+
+  let keys = database.keys_iter(ReadOptions::new()).from(&from).until(&to).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![3, 2]);
+
+  let iter = database.iter(ReadOptions::new()).until(&to);
+  assert_eq!(iter.last(), Some((3, vec![3])));
+
+  let missing = 10;
+  let keys = database.keys_iter(ReadOptions::new()).to(&missing).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![5, 4, 3, 2, 1]);
+}
+
+#[test]
+fn test_iterator_builder_order() {
+  let tmp = tmpdir("iter_builder_order");
+  let database = &mut open_database(tmp.path(), true);
+  for i in 1..8 {
+    db_put_simple(database, i, &[i as u8]);
+  }
+
+  let from = 2;
+  let to = 5;
+  let keys = database.keys_iter(ReadOptions::new()).reverse().to(&to);
+  assert_eq!(keys.collect::<Vec<_>>(), vec![5, 4, 3, 2, 1]);
+  let keys = database.keys_iter(ReadOptions::new()).to(&to).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![5, 4, 3, 2, 1]);
+
+  let keys = database.keys_iter(ReadOptions::new()).reverse().from(&to);
+  assert_eq!(keys.collect::<Vec<_>>(), vec![7, 6, 5]);
+  let keys = database.keys_iter(ReadOptions::new()).from(&to).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![7, 6, 5]);
+
+  let keys = database.keys_iter(ReadOptions::new()).reverse().until(&to).from(&from);
+  assert_eq!(keys.collect::<Vec<_>>(), vec![4, 3, 2]);
+  let keys = database.keys_iter(ReadOptions::new()).from(&from).until(&to).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![4, 3, 2]);
+
+  let values = database.value_iter(ReadOptions::new()).reverse().to(&from);
+  assert_eq!(values.collect::<Vec<_>>(), vec![vec![2], vec![1]]);
+  let iter = database.iter(ReadOptions::new()).until(&from).from(&from);
+  assert_eq!(iter.collect::<Vec<_>>(), vec![]);
+}
+
+#[test]
+fn test_scan_prefix() {
+  let tmp = tmpdir("scan_prefix");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 0x0001_0000, &[0]);
+  db_put_simple(database, 0x0100_0001, &[1]);
+  db_put_simple(database, 0x0100_0002, &[2]);
+  db_put_simple(database, 0x01ff_ffff, &[3]);
+  db_put_simple(database, 0x0200_0001, &[4]);
+
+  let prefix = [1u8];
+  let iter = database.scan_prefix(ReadOptions::new(), &prefix);
+  let keys: Vec<i32> = iter.map(|(k, _)| k).collect();
+  assert_eq!(keys, vec![0x0100_0001, 0x0100_0002, 0x01ff_ffff]);
+
+  let keys = database.keys_iter(ReadOptions::new()).prefix(&prefix).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![0x01ff_ffff, 0x0100_0002, 0x0100_0001]);
+
+  let from = 0x0100_0002;
+  let keys = database.keys_iter(ReadOptions::new()).from(&from).prefix(&prefix);
+  assert_eq!(keys.collect::<Vec<_>>(), vec![0x0100_0002, 0x01ff_ffff]);
+
+  let prefix = [3u8];
+  assert!(database.scan_prefix(ReadOptions::new(), &prefix).next().is_none());
+  let prefix = [0xffu8];
+  assert!(database.keys_iter(ReadOptions::new()).prefix(&prefix).reverse().next().is_none());
+}
+
+#[test]
+fn test_reverse_prefix_with_to() {
+  let tmp = tmpdir("reverse_prefix_to");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 0x0100_0001, &[1]);
+  db_put_simple(database, 0x0100_0002, &[2]);
+  db_put_simple(database, 0x0200_0001, &[3]);
+  db_put_simple(database, 0x0300_0001, &[4]);
+
+  let prefix = [1u8];
+  let to = 0x7f00_0000;
+  let keys = database.keys_iter(ReadOptions::new()).prefix(&prefix).to(&to).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![0x0100_0002, 0x0100_0001]);
+  let keys = database.keys_iter(ReadOptions::new()).prefix(&prefix).to(&to);
+  assert_eq!(keys.last(), Some(0x0100_0002));
+
+  let to = 0x0100_0001;
+  let keys = database.keys_iter(ReadOptions::new()).prefix(&prefix).until(&to).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![]);
+
+  let prefix = [5u8];
+  let keys = database.keys_iter(ReadOptions::new()).prefix(&prefix).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![]);
+  let prefix = [2u8];
+  let to = 0x7f00_0000;
+  let keys = database.keys_iter(ReadOptions::new()).prefix(&prefix).to(&to).reverse();
+  assert_eq!(keys.collect::<Vec<_>>(), vec![0x0200_0001]);
+}
+
+fn owned_keys(database: &Arc<Database<i32>>) -> OwnedIterator<i32> {
+  database.owned_iter(ReadOptions::new())
+}
+
+#[test]
+fn test_owned_iterator() {
+  let tmp = tmpdir("owned_iter");
+  let database = Arc::new(open_database(tmp.path(), true));
+  db_put_simple(&database, 1, &[1]);
+  db_put_simple(&database, 2, &[2]);
+
+  let iter = owned_keys(&database);
+  drop(database);
+  let entries = thread::spawn(move || iter.collect::<Vec<_>>()).join().unwrap();
+  assert_eq!(entries, vec![(1, vec![1]), (2, vec![2])]);
+}
+
+#[test]
+fn test_iterator_status() {
+  let tmp = tmpdir("iter_status");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..1000 {
+    db_put_simple(&database, i, &[(i % 256) as u8; 100]);
+  }
+  let mut iter = database.iter(ReadOptions::new());
+  assert_eq!(iter.by_ref().count(), 1000);
+  assert!(iter.status().is_ok());
+  drop(iter);
+  database.compact_all();
+  drop(database);
+
+  let table = fs::read_dir(tmp.path()).unwrap()
+    .map(|entry| entry.unwrap().path())
+    .find(|path| path.extension().is_some_and(|ext| ext == "ldb"))
+    .unwrap();
+  let mut contents = fs::read(&table).unwrap();
+  for byte in contents[100..200].iter_mut() {
+    *byte ^= 0xff;
+  }
+  fs::write(&table, contents).unwrap();
+
+  let database: Database<i32> = open_database(tmp.path(), false);
+  let mut options = ReadOptions::new();
+  options.verify_checksums = true;
+  let mut iter = database.iter(options);
+  assert!(iter.by_ref().count() < 1000);
+  assert_eq!(iter.status().unwrap_err().kind(), &ErrorKind::Corruption);
+}
+
+#[test]
+fn test_prefetch_iterator() {
+  let tmp = tmpdir("prefetch_iter");
+  let database = Arc::new(open_database(tmp.path(), true));
+  for i in 0..1000 {
+    db_put_simple(&database, i, &[(i % 256) as u8]);
+  }
+  let mut iter = database.owned_iter(ReadOptions::new()).prefetch(16);
+  let entries = iter.by_ref().collect::<Vec<_>>();
+  assert_eq!(entries.len(), 1000);
+  assert!(entries.iter().enumerate().all(|(i, &(key, ref value))| key == i as i32 && value == &vec![(i % 256) as u8]));
+  assert!(iter.status().is_ok());
+
+  let iter = database.owned_iter(ReadOptions::new()).from(&990).reverse().prefetch(1);
+  assert_eq!(iter.map(|(key, _)| key).take(3).collect::<Vec<_>>(), vec![999, 998, 997]);
+  // dropping the iterator early stops the helper thread
+  let mut iter = database.owned_iter(ReadOptions::new()).prefetch(4);
+  assert_eq!(iter.next(), Some((0, vec![0])));
+  drop(iter);
+}
+
+#[test]
+fn test_iter_chunks() {
+  let tmp = tmpdir("iter_chunks");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..10 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let mut chunks = database.iter_chunks(ReadOptions::new(), 4);
+  let sizes = chunks.by_ref().map(|chunk| chunk.len()).collect::<Vec<_>>();
+  assert_eq!(sizes, vec![4, 4, 2]);
+  assert!(chunks.status().is_ok());
+
+  let chunks = database.iter(ReadOptions::new()).from(&3).to(&8).reverse().chunks(3).collect::<Vec<_>>();
+  assert_eq!(chunks[0], vec![(8, vec![8]), (7, vec![7]), (6, vec![6])]);
+  assert_eq!(chunks.concat().into_iter().map(|(key, _)| key).collect::<Vec<_>>(), vec![8, 7, 6, 5, 4, 3]);
+}
+
+#[test]
+fn test_iterator_estimated_len() {
+  let tmp = tmpdir("iter_estimated_len");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..10 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  // small ranges are counted
+  let (from, to) = (2, 5);
+  let mut iter = database.iter(ReadOptions::new()).from(&from).to(&to);
+  assert_eq!(iter.size_hint(), (0, None));
+  assert_eq!(iter.estimated_len(), 4);
+  iter.next();
+  assert_eq!(iter.estimated_len(), 4);
+  assert_eq!(database.keys_iter(ReadOptions::new()).until(&to).size_hint(), (0, None));
+
+  for i in 0..10000 {
+    db_put_simple(&database, i, &[(i % 256) as u8; 100]);
+  }
+  database.compact_all();
+  let estimate = database.iter(ReadOptions::new()).estimated_len();
+  assert!(estimate > 1000 && estimate < 20000, "estimated {} entries", estimate);
+  let (from, to) = (0, 4999);
+  let half = database.iter(ReadOptions::new()).from(&from).to(&to).estimated_len();
+  assert!(half < estimate);
+}
+
+#[test]
+fn test_first_and_last_key() {
+  let tmp = tmpdir("first_last_key");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  assert_eq!(database.first_key(ReadOptions::new()).unwrap(), None);
+  assert_eq!(database.last_key(ReadOptions::new()).unwrap(), None);
+  for i in &[2, 4, 6, 8] {
+    db_put_simple(&database, *i, &[*i as u8]);
+  }
+  assert_eq!(database.first_key(ReadOptions::new()).unwrap(), Some(2));
+  assert_eq!(database.last_key(ReadOptions::new()).unwrap(), Some(8));
+
+  let (from, to) = (3, 6);
+  assert_eq!(database.first_key_in(ReadOptions::new(), &from, &to).unwrap(), Some(4));
+  assert_eq!(database.last_key_in(ReadOptions::new(), &from, &to).unwrap(), Some(6));
+  let (from, to) = (9, 10);
+  assert_eq!(database.first_key_in(ReadOptions::new(), &from, &to).unwrap(), None);
+  assert_eq!(database.last_key_in(ReadOptions::new(), &from, &to).unwrap(), None);
+  let (from, to) = (0, 1);
+  assert_eq!(database.last_key_in(ReadOptions::new(), &from, &to).unwrap(), None);
+}
+
+#[test]
+fn test_read_options_bounds() {
+  let tmp = tmpdir("read_options_bounds");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..10 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  let (lower, upper) = (3, 7);
+  let bounded = || {
+    let mut options = ReadOptions::new();
+    options.iterate_lower_bound = Some(&lower);
+    options.iterate_upper_bound = Some(&upper);
+    options
+  };
+  assert_eq!(database.keys_iter(bounded()).collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+  assert_eq!(database.iter(bounded()).reverse().map(|(key, _)| key).collect::<Vec<_>>(), vec![6, 5, 4, 3]);
+  assert_eq!(database.value_iter(bounded()).count(), 4);
+
+  // the bounds of the iterator narrow them further, but don't widen them
+  let (from, to) = (1, 5);
+  assert_eq!(database.keys_iter(bounded()).from(&from).to(&to).collect::<Vec<_>>(), vec![3, 4, 5]);
+  let to = 9;
+  assert_eq!(database.keys_iter(bounded()).to(&to).reverse().collect::<Vec<_>>(), vec![6, 5, 4, 3]);
+  let mut iter = database.keys_iter(bounded());
+  iter.seek(&8);
+  assert!(iter.next().is_none());
+  assert_eq!(database.first_key(bounded()).unwrap(), Some(3));
+  assert_eq!(database.last_key(bounded()).unwrap(), Some(6));
+}
+
+#[test]
+fn test_borrowed_entries() {
+  let tmp = tmpdir("borrowed_entries");
+  let database = Arc::new(open_database::<i32>(tmp.path(), true));
+  for i in 0..100 {
+    db_put_simple(&database, i, &[i as u8; 10]);
+  }
+  let mut iter = database.iter(ReadOptions::new());
+  let mut count: i32 = 0;
+  while let Some((key, value)) = iter.next_entry() {
+    assert_eq!(key, &count.to_be_bytes()[..]);
+    assert_eq!(value, &[count as u8; 10][..]);
+    count += 1;
+  }
+  assert_eq!(count, 100);
+
+  let mut keys = database.keys_iter(ReadOptions::new()).reverse();
+  assert!(keys.advance());
+  assert_eq!(keys.key_bytes(), &99i32.to_be_bytes()[..]);
+  assert_eq!(keys.value_bytes(), &[99; 10][..]);
+
+  let mut owned = database.owned_iter(ReadOptions::new());
+  assert_eq!(owned.next_entry().map(|(_, value)| value.len()), Some(10));
+}
@@ -0,0 +1,100 @@
+use utils::{tmpdir};
+use leveldb::database::Database;
+use leveldb::iterator::Iterable;
+use leveldb::keys::{BinaryKey,StringKey,FixedKey,IntKey,CompositeKey};
+use leveldb::kv::KV;
+use leveldb::options::{Options,ReadOptions,WriteOptions};
+use key::Key;
+
+fn open<K: Key>(name: &str) -> (::tempdir::TempDir, Database<K>) {
+  let tmp = tmpdir(name);
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let database = Database::open(tmp.path(), opts).unwrap();
+  (tmp, database)
+}
+
+#[test]
+fn test_binary_and_string_keys() {
+  let (_tmp, database) = open::<BinaryKey>("binary_key");
+  database.put(WriteOptions::new(), BinaryKey::from(&b"\x00\xff"[..]), &[1]).unwrap();
+  assert_eq!(Some(vec![1]), database.get(ReadOptions::new(), BinaryKey(vec![0, 255])).unwrap());
+
+  let (_tmp, database) = open::<StringKey>("string_key");
+  database.put(WriteOptions::new(), StringKey::from("b"), &[2]).unwrap();
+  database.put(WriteOptions::new(), StringKey::from("a"), &[1]).unwrap();
+  let keys: Vec<StringKey> = database.keys_iter(ReadOptions::new()).collect();
+  assert_eq!(vec![StringKey::from("a"), StringKey::from("b")], keys);
+}
+
+#[test]
+fn test_fixed_key() {
+  let (_tmp, database) = open::<FixedKey<3>>("fixed_key");
+  database.put(WriteOptions::new(), FixedKey([1, 2, 3]), &[1]).unwrap();
+  let keys: Vec<FixedKey<3>> = database.keys_iter(ReadOptions::new()).collect();
+  assert_eq!(vec![FixedKey([1, 2, 3])], keys);
+}
+
+#[test]
+fn test_int_keys_keep_their_order() {
+  let (_tmp, database) = open::<IntKey<i64>>("signed_int_key");
+  let values = [i64::MAX, 1, 0, -1, i64::MIN];
+  for value in values.iter() {
+    database.put(WriteOptions::new(), IntKey(*value), &[1]).unwrap();
+  }
+  let keys: Vec<i64> = database.keys_iter(ReadOptions::new()).map(|k| k.0).collect();
+  assert_eq!(vec![i64::MIN, -1, 0, 1, i64::MAX], keys);
+
+  let (_tmp, database) = open::<IntKey<u16>>("unsigned_int_key");
+  database.put(WriteOptions::new(), IntKey(256u16), &[1]).unwrap();
+  database.put(WriteOptions::new(), IntKey(1u16), &[1]).unwrap();
+  let keys: Vec<u16> = database.keys_iter(ReadOptions::new()).map(|k| k.0).collect();
+  assert_eq!(vec![1, 256], keys);
+}
+
+#[test]
+fn test_composite_key_roundtrip() {
+  let key = CompositeKey::new()
+    .push_u8(7)
+    .push_u32(42)
+    .push_i64(-5)
+    .push_bytes(b"a\x00b")
+    .push_str("name");
+  let decoded = CompositeKey::from_u8(key.as_bytes());
+  let mut reader = decoded.reader();
+  assert_eq!(Some(7), reader.read_u8());
+  assert_eq!(Some(42), reader.read_u32());
+  assert_eq!(Some(-5), reader.read_i64());
+  assert_eq!(Some(b"a\x00b".to_vec()), reader.read_bytes());
+  assert_eq!(None, reader.read_u64());
+  assert_eq!(Some("name".to_string()), reader.read_str());
+  assert!(reader.is_empty());
+  assert_eq!(None, reader.read_bytes());
+}
+
+#[test]
+fn test_composite_keys_sort_by_components() {
+  let (_tmp, database) = open::<CompositeKey>("composite_key");
+  let keys = [
+    CompositeKey::new().push_bytes(b"b").push_u64(1),
+    CompositeKey::new().push_bytes(b"a").push_u64(2),
+    CompositeKey::new().push_bytes(b"a\x00").push_u64(0),
+    CompositeKey::new().push_bytes(b"a").push_u64(1),
+    CompositeKey::new().push_bytes(b"ab").push_u64(0),
+  ];
+  for key in keys.iter() {
+    database.put(WriteOptions::new(), key, &[1]).unwrap();
+  }
+  let read: Vec<(Vec<u8>, u64)> = database.keys_iter(ReadOptions::new())
+    .map(|key| {
+      let mut reader = key.reader();
+      (reader.read_bytes().unwrap(), reader.read_u64().unwrap())
+    })
+    .collect();
+  assert_eq!(vec![(b"a".to_vec(), 1), (b"a".to_vec(), 2), (b"a\x00".to_vec(), 0),
+                  (b"ab".to_vec(), 0), (b"b".to_vec(), 1)], read);
+
+  let prefix = CompositeKey::new().push_bytes(b"a");
+  let count = database.scan_prefix(ReadOptions::new(), prefix.as_bytes()).count();
+  assert_eq!(2, count);
+}
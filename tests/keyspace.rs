@@ -0,0 +1,150 @@
+use utils::{tmpdir};
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::database::Database;
+use leveldb::error::ErrorKind;
+use leveldb::keys::{BinaryKey,StringKey};
+use leveldb::keyspace::Keyspaces;
+use leveldb::kv::KV;
+use leveldb::options::{Options,ReadOptions,WriteOptions};
+use std::thread;
+
+fn open(path: &::std::path::Path) -> Keyspaces {
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let database: Database<BinaryKey> = Database::open(path, opts).unwrap();
+  Keyspaces::new(database)
+}
+
+#[test]
+fn test_keyspaces_are_separate() {
+  let tmp = tmpdir("keyspaces");
+  let keyspaces = open(tmp.path());
+  let users = keyspaces.keyspace::<i32>("users").unwrap();
+  let orders = keyspaces.keyspace::<i32>("orders").unwrap();
+  users.put(WriteOptions::new(), 1, &[1]).unwrap();
+  orders.put(WriteOptions::new(), 1, &[2]).unwrap();
+  orders.put(WriteOptions::new(), 2, &[3]).unwrap();
+
+  assert_eq!(Some(vec![1]), users.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(Some(vec![2]), orders.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(None, users.get(ReadOptions::new(), 2).unwrap());
+  let results = orders.multi_get(ReadOptions::new(), &[2, 3]);
+  assert_eq!(Some(vec![3]), *results[0].as_ref().unwrap());
+  assert_eq!(None, *results[1].as_ref().unwrap());
+
+  let entries: Vec<(i32, Vec<u8>)> = orders.iter(ReadOptions::new()).collect();
+  assert_eq!(vec![(1, vec![2]), (2, vec![3])], entries);
+  let entries: Vec<(i32, Vec<u8>)> = orders.iter(ReadOptions::new()).reverse().collect();
+  assert_eq!(vec![(2, vec![3]), (1, vec![2])], entries);
+  let entries: Vec<(i32, Vec<u8>)> = orders.iter(ReadOptions::new()).from(&2).collect();
+  assert_eq!(vec![(2, vec![3])], entries);
+  let entries: Vec<(i32, Vec<u8>)> = users.iter(ReadOptions::new()).reverse().collect();
+  assert_eq!(vec![(1, vec![1])], entries);
+
+  users.delete(WriteOptions::new(), 1).unwrap();
+  assert_eq!(None, users.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(Some(vec![2]), orders.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_keyspace_batch() {
+  let tmp = tmpdir("keyspace_batch");
+  let keyspaces = open(tmp.path());
+  let names = keyspaces.keyspace::<StringKey>("names").unwrap();
+  let mut batch = Writebatch::new();
+  batch.put(StringKey::from("a"), &[1]);
+  batch.put(StringKey::from("b"), &[2]);
+  batch.delete(StringKey::from("a"));
+  names.write(WriteOptions::new(), &batch).unwrap();
+  let keys: Vec<StringKey> = names.iter(ReadOptions::new()).map(|(k, _)| k).collect();
+  assert_eq!(vec![StringKey::from("b")], keys);
+}
+
+#[test]
+fn test_keyspaces_persist_and_drop() {
+  let tmp = tmpdir("keyspace_drop");
+  {
+    let keyspaces = open(tmp.path());
+    let first = keyspaces.keyspace::<i32>("first").unwrap();
+    let second = keyspaces.keyspace::<i32>("second").unwrap();
+    for i in 0..2500 {
+      first.put(WriteOptions::new(), i, &[1]).unwrap();
+    }
+    second.put(WriteOptions::new(), 1, &[2]).unwrap();
+  }
+
+  let keyspaces = open(tmp.path());
+  assert_eq!(vec!["first".to_string(), "second".to_string()], keyspaces.names().unwrap());
+  let second = keyspaces.keyspace::<i32>("second").unwrap();
+  assert_eq!(Some(vec![2]), second.get(ReadOptions::new(), 1).unwrap());
+
+  keyspaces.drop_keyspace("first").unwrap();
+  assert_eq!(vec!["second".to_string()], keyspaces.names().unwrap());
+  let error = keyspaces.drop_keyspace("first").err().unwrap();
+  assert_eq!(&ErrorKind::NotFound, error.kind());
+  let first = keyspaces.keyspace::<i32>("first").unwrap();
+  assert_eq!(0, first.iter(ReadOptions::new()).count());
+  assert_eq!(Some(vec![2]), second.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_keyspace_put_if_absent_and_take() {
+  let tmp = tmpdir("keyspace_atomic");
+  let keyspaces = open(tmp.path());
+  let users = keyspaces.keyspace::<i32>("users").unwrap();
+  let orders = keyspaces.keyspace::<i32>("orders").unwrap();
+  assert!(users.put_if_absent(WriteOptions::new(), 1, &[1]).unwrap());
+  assert!(orders.put_if_absent(WriteOptions::new(), 1, &[2]).unwrap());
+  assert!(!users.put_if_absent(WriteOptions::new(), 1, &[3]).unwrap());
+  assert_eq!(Some(vec![1]), users.take(WriteOptions::new(), 1).unwrap());
+  assert_eq!(None, users.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(Some(vec![2]), orders.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_keyspace_keys_and_values() {
+  let tmp = tmpdir("keyspace_keys");
+  let keyspaces = open(tmp.path());
+  let users = keyspaces.keyspace::<i32>("users").unwrap();
+  let orders = keyspaces.keyspace::<i32>("orders").unwrap();
+  users.put(WriteOptions::new(), 2, &[2]).unwrap();
+  users.put(WriteOptions::new(), 1, &[1]).unwrap();
+  orders.put(WriteOptions::new(), 3, &[3]).unwrap();
+
+  assert_eq!(vec![1, 2], users.keys_iter(ReadOptions::new()).collect::<Vec<i32>>());
+  assert_eq!(vec![2, 1], users.keys_iter(ReadOptions::new()).reverse().collect::<Vec<i32>>());
+  assert_eq!(vec![vec![2]], users.value_iter(ReadOptions::new()).from(&2).collect::<Vec<_>>());
+  let mut iter = orders.value_iter(ReadOptions::new());
+  assert_eq!(Some(vec![3]), iter.next());
+  assert_eq!(None, iter.next());
+  assert!(iter.status().is_ok());
+}
+
+#[test]
+fn test_keyspaces_registered_from_clones() {
+  let tmp = tmpdir("keyspace_clones");
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  let database: Database<BinaryKey> = Database::open(tmp.path(), opts).unwrap();
+  let threads: Vec<_> = (0..4).map(|i| {
+    let keyspaces = Keyspaces::new(database.clone());
+    thread::spawn(move || {
+      for j in 0..10 {
+        let keyspace = keyspaces.keyspace::<i32>(&format!("{}-{}", i, j)).unwrap();
+        keyspace.put(WriteOptions::new(), 1, &[i, j]).unwrap();
+      }
+    })
+  }).collect();
+  for thread in threads {
+    thread.join().unwrap();
+  }
+  // every keyspace got an id of its own
+  let keyspaces = Keyspaces::new(database);
+  assert_eq!(40, keyspaces.names().unwrap().len());
+  for i in 0..4 {
+    for j in 0..10 {
+      let keyspace = keyspaces.keyspace::<i32>(&format!("{}-{}", i, j)).unwrap();
+      assert_eq!(Some(vec![i, j]), keyspace.get(ReadOptions::new(), 1).unwrap());
+    }
+  }
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::io::{Read,Write};
+use utils::{db_put_simple,open_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::compaction::Compaction;
+use leveldb::kv::KV;
+use leveldb::options::ReadOptions;
+
+#[test]
+fn test_live_files() {
+  let tmp = tmpdir("live_files");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..100 {
+    db_put_simple(&database, i, &[i as u8]);
+  }
+  database.compact_all();
+  db_put_simple(&database, 100, &[100]);
+
+  let live = database.live_files().unwrap();
+  assert_eq!(live.current, tmp.path().join("CURRENT"));
+  assert!(live.manifest.file_name().unwrap().to_str().unwrap().starts_with("MANIFEST-"));
+  assert!(!live.tables.is_empty());
+  assert!(live.tables.iter().all(|path| path.extension().unwrap() == "ldb"));
+  assert!(!live.logs.is_empty());
+  assert_eq!(*live.paths().last().unwrap(), live.current.as_path());
+
+  let copy = tmpdir("live_files_copy");
+  for path in live.paths() {
+    let target = copy.path().join(path.file_name().unwrap());
+    if path == live.manifest.as_path() {
+      let mut manifest = vec![];
+      fs::File::open(path).unwrap()
+        .take(live.manifest_size)
+        .read_to_end(&mut manifest)
+        .unwrap();
+      fs::File::create(target).unwrap().write_all(&manifest).unwrap();
+    } else {
+      fs::copy(path, target).unwrap();
+    }
+  }
+
+  // the tables listed outlive a compaction replacing them
+  for i in 0..100 {
+    db_put_simple(&database, i, &[i as u8 + 1]);
+  }
+  database.compact_all();
+  assert!(live.tables.iter().all(|path| path.is_file()));
+  drop(live);
+
+  let restored: Database<i32> = open_database(copy.path(), false);
+  for i in 0..101 {
+    let value = restored.get(ReadOptions::new(), i).unwrap();
+    assert_eq!(value, Some(vec![i as u8]));
+  }
+}
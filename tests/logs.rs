@@ -0,0 +1,79 @@
+use utils::tmpdir;
+use leveldb::database::Database;
+use leveldb::kv::KV;
+use leveldb::options::{Options,WriteOptions};
+use leveldb::logs::{LogEvent,LogTail,StallReason,compactions,parse_line,read_logs};
+use std::fs;
+use std::time::Duration;
+
+const LOG: &str = "\
+2026/10/14-12:20:48.610710 140463977912000 Compacting 4@0 + 1@1 files
+2026/10/14-12:20:48.612291 140463977912000 Generated table #16@0: 2180 keys, 251629 bytes
+2026/10/14-12:20:48.612296 140463977912000 Compacted 4@0 + 1@1 files => 251629 bytes
+2026/10/14-12:20:48.612379 140463977912000 compacted to: files[ 0 1 1 0 0 0 0 ]
+2026/10/14-12:20:36.950557 139941923378880 Level-0 table #5: started
+2026/10/14-12:20:36.951707 139941927692224 Current memtable full; waiting...
+2026/10/14-12:20:36.951898 139941923378880 Level-0 table #5: 49536 bytes OK
+2026/10/14-12:20:50.304500 140463982438336 Recovering log #615
+2026/10/14-12:20:50.305031 140463982438336 Delete type=3 #2
+2026/10/14-12:20:50.305031 140463982438336 Moved #12 to level-2 2110791 bytes OK: files[ 0 0 1 0 0 0 0 ]
+2026/10/14-12:20:50.252858 140463977912000 Manual compaction at level-1 from (begin) .. (end); will stop at (end)
+2026/10/14-12:20:50.252858 140463977912000 Something new
+not a log line
+";
+
+#[test]
+fn test_parse_log() {
+  let entries: Vec<_> = LOG.lines().filter_map(parse_line).collect();
+  assert_eq!(entries.len(), 12);
+  assert_eq!(entries[0].thread, 140463977912000);
+  assert_eq!(entries[0].time.second, 48);
+  assert_eq!(entries[0].time.micros, 610710);
+  assert_eq!(entries[0].event, LogEvent::CompactionStarted { level: 0, inputs: 4, next_inputs: 1 });
+  assert_eq!(entries[1].event, LogEvent::TableGenerated { table: 16, level: 0, keys: 2180, bytes: 251629 });
+  assert_eq!(entries[2].event, LogEvent::CompactionFinished { level: 0, inputs: 4, next_inputs: 1, bytes: 251629 });
+  assert_eq!(entries[3].event, LogEvent::LevelSummary { files: vec![0, 1, 1, 0, 0, 0, 0] });
+  assert_eq!(entries[4].event, LogEvent::FlushStarted { table: 5 });
+  assert_eq!(entries[5].event, LogEvent::WriteStall { reason: StallReason::MemtableFull });
+  assert_eq!(entries[6].event, LogEvent::FlushFinished { table: 5, bytes: 49536, status: "OK".to_string() });
+  assert_eq!(entries[7].event, LogEvent::RecoveringLog { log: 615 });
+  assert_eq!(entries[8].event, LogEvent::FileDeleted { kind: 3, number: 2 });
+  assert_eq!(entries[9].event, LogEvent::TableMoved { table: 12, level: 2, bytes: 2110791 });
+  assert_eq!(entries[10].event, LogEvent::ManualCompaction { level: 1 });
+  assert_eq!(entries[11].event, LogEvent::Other("Something new".to_string()));
+
+  let timings = compactions(&entries);
+  assert_eq!(timings.len(), 1);
+  assert_eq!(timings[0].duration, Duration::from_micros(1586));
+  assert_eq!(timings[0].inputs, 5);
+}
+
+#[test]
+fn test_read_logs() {
+  let tmp = tmpdir("logs");
+  let mut options = Options::new();
+  options.create_if_missing = true;
+  options.write_buffer_size = Some(64 * 1024);
+  let mut tail = LogTail::new(tmp.path());
+  assert!(tail.poll().unwrap().is_empty());
+  {
+    let database: Database<i32> = Database::open(tmp.path(), options).unwrap();
+    for i in 0..2000 {
+      database.put(WriteOptions::new(), i, &[0; 100]).unwrap();
+    }
+  }
+  let polled = tail.poll().unwrap();
+  assert!(polled.iter().any(|e| matches!(e.event, LogEvent::FlushFinished { .. })));
+  assert!(tail.poll().unwrap().is_empty());
+
+  // reopening moves the log to LOG.old
+  let mut options = Options::new();
+  options.create_if_missing = true;
+  let database: Database<i32> = Database::open(tmp.path(), options).unwrap();
+  let entries = read_logs(tmp.path()).unwrap();
+  assert!(entries.len() > polled.len());
+  assert!(entries.iter().any(|e| matches!(e.event, LogEvent::RecoveringLog { .. })));
+  assert!(tail.poll().unwrap().iter().any(|e| matches!(e.event, LogEvent::RecoveringLog { .. })));
+  drop(database);
+  assert!(fs::metadata(tmp.path().join("LOG.old")).is_ok());
+}
@@ -1,6 +1,8 @@
 use leveldb::management::*;
 use leveldb::options::*;
-use utils::{open_database,tmpdir};
+use leveldb::kv::KV;
+use utils::{open_database,tmpdir,db_put_simple};
+use std::fs;
 
 #[test]
 fn test_destroy_database() {
@@ -10,6 +12,7 @@ fn test_destroy_database() {
     let options = Options::new();
     let res = destroy(tmp.path(), options);
     assert!(res.is_ok());
+    assert!(!tmp.path().join("CURRENT").exists());
 }
 
 #[test]
@@ -22,13 +25,28 @@ fn test_repair_database() {
     assert!(res.is_ok());
 }
 
-// Deactivated due do library version dependence
-//#[test]
-//fn test_destroy_open_database() {
-//    let tmp = tmpdir("destroy_open");
-//    let database = open_database::<i32>(tmp.path(), true);
-//    let options = Options::new();
-//    let res = destroy(tmp.path(), options);
-//    assert!(res.is_err());
-//    drop(database);
-//}
+#[test]
+fn test_repair_lost_manifest() {
+    let tmp = tmpdir("repair_manifest");
+    let database = open_database::<i32>(tmp.path(), true);
+    db_put_simple(&database, 1, &[1]);
+    drop(database);
+    fs::remove_file(tmp.path().join("CURRENT")).unwrap();
+
+    let res = repair(tmp.path(), Options::new());
+    assert!(res.is_ok());
+
+    let database = open_database::<i32>(tmp.path(), false);
+    let res = database.get(ReadOptions::new(), 1);
+    assert_eq!(res.unwrap(), Some(vec![1]));
+}
+
+#[test]
+fn test_destroy_open_database() {
+    let tmp = tmpdir("destroy_open");
+    let database = open_database::<i32>(tmp.path(), true);
+    let options = Options::new();
+    let res = destroy(tmp.path(), options);
+    assert!(res.is_err());
+    drop(database);
+}
@@ -0,0 +1,46 @@
+use utils::{tmpdir,db_put_simple};
+use leveldb::database::cache::Cache;
+use leveldb::error::ErrorKind;
+use leveldb::kv::KV;
+use leveldb::manager::DatabaseManager;
+use leveldb::options::{Options,ReadOptions};
+
+#[test]
+fn test_manager_shares_handles() {
+  let tmp = tmpdir("manager");
+  let manager: DatabaseManager<i32> = DatabaseManager::new(tmp.path().join("stores")).cache(Cache::new(1 << 20));
+  let users = manager.open("users").unwrap();
+  let again = manager.open("users").unwrap();
+  let orders = manager.open("orders").unwrap();
+  db_put_simple(&users, 1, &[1]);
+  assert_eq!(again.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  assert_eq!(orders.get(ReadOptions::new(), 1).unwrap(), None);
+  assert_eq!(manager.names(), vec!["orders".to_string(), "users".to_string()]);
+  assert!(manager.get("users").is_some());
+  assert!(manager.get("missing").is_none());
+  assert!(tmp.path().join("stores").join("users").is_dir());
+
+  assert!(manager.close("users"));
+  assert!(!manager.close("users"));
+  drop(users);
+  drop(again);
+  let reopened = manager.open("users").unwrap();
+  assert_eq!(reopened.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  manager.close_all();
+  assert!(manager.names().is_empty());
+}
+
+#[test]
+fn test_manager_options_and_names() {
+  let tmp = tmpdir("manager_options");
+  let manager: DatabaseManager<i32> = DatabaseManager::new(tmp.path()).options(|name| {
+    let mut options = Options::new();
+    options.create_if_missing = name != "existing";
+    options
+  });
+  assert_eq!(&ErrorKind::InvalidArgument, manager.open("existing").err().unwrap().kind());
+  assert!(manager.open("new").is_ok());
+  for name in &["", "..", "a/b"] {
+    assert_eq!(&ErrorKind::InvalidArgument, manager.open(name).err().unwrap().kind());
+  }
+}
@@ -0,0 +1,29 @@
+use utils::{open_database,tmpdir,db_put_simple};
+use leveldb::iterator::Iterable;
+use leveldb::merge::{merge,TieBreak};
+use leveldb::options::ReadOptions;
+use leveldb::snapshots::Snapshots;
+
+#[test]
+fn test_merge_tie_breaks() {
+  let tmp = tmpdir("merge");
+  let newer = open_database(&tmp.path().join("newer"), true);
+  let older = open_database(&tmp.path().join("older"), true);
+  db_put_simple(&newer, 2, &[20]);
+  db_put_simple(&newer, 3, &[30]);
+  db_put_simple(&older, 1, &[1]);
+  db_put_simple(&older, 2, &[2]);
+  db_put_simple(&older, 4, &[4]);
+  let snapshot = older.snapshot();
+  db_put_simple(&older, 5, &[5]);
+
+  let sources = || vec![newer.iter(ReadOptions::new()), snapshot.iter(ReadOptions::new())];
+  let first: Vec<(i32, Vec<u8>)> = merge(sources(), TieBreak::First).collect();
+  assert_eq!(first, vec![(1, vec![1]), (2, vec![20]), (3, vec![30]), (4, vec![4])]);
+  let last: Vec<(i32, Vec<u8>)> = merge(sources(), TieBreak::Last).collect();
+  assert_eq!(last[1], (2, vec![2]));
+  assert_eq!(last.len(), 4);
+  let all: Vec<(i32, Vec<u8>)> = merge(sources(), TieBreak::All).collect();
+  assert_eq!(all, vec![(1, vec![1]), (2, vec![20]), (2, vec![2]), (3, vec![30]), (4, vec![4])]);
+  assert_eq!(merge::<i32>(vec![], TieBreak::First).count(), 0);
+}
@@ -0,0 +1,120 @@
+use utils::{open_database,open_ordered_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::error::ErrorKind;
+use leveldb::iterator::{Iterable,LevelDBIterator};
+use leveldb::keys::BinaryKey;
+use leveldb::kv::KV;
+use leveldb::meta::RESERVED_PREFIX;
+use leveldb::options::{ReadOptions,WriteOptions};
+use std::time::Duration;
+
+#[test]
+fn test_meta_put_get_delete() {
+  let tmp = tmpdir("meta");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let meta = database.meta();
+  assert_eq!(meta.get("cursor").unwrap(), None);
+  meta.put(WriteOptions::new(), "cursor", b"abc").unwrap();
+  meta.put_u64(WriteOptions::new(), "replication/offset", 42).unwrap();
+  meta.put_string(WriteOptions::new(), "backup", "2026-10-14").unwrap();
+  assert_eq!(meta.get("cursor").unwrap(), Some(b"abc".to_vec()));
+  assert_eq!(meta.get_u64("replication/offset").unwrap(), Some(42));
+  assert_eq!(meta.get_string("backup").unwrap(), Some("2026-10-14".to_string()));
+  assert_eq!(*meta.get_u64("cursor").err().unwrap().kind(), ErrorKind::Corruption);
+  assert_eq!(meta.names().unwrap(), vec!["backup", "cursor", "replication/offset"]);
+
+  meta.delete(WriteOptions::new(), "cursor").unwrap();
+  assert_eq!(meta.get("cursor").unwrap(), None);
+  assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), None);
+}
+
+#[test]
+fn test_meta_names_are_escaped() {
+  let tmp = tmpdir("meta_escaped");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let meta = database.meta();
+  meta.put(WriteOptions::new(), "a", b"1").unwrap();
+  meta.put(WriteOptions::new(), "a\u{0}", b"2").unwrap();
+  meta.put(WriteOptions::new(), "", b"3").unwrap();
+  assert_eq!(meta.get("a").unwrap(), Some(b"1".to_vec()));
+  assert_eq!(meta.get("a\u{0}").unwrap(), Some(b"2".to_vec()));
+  assert_eq!(meta.names().unwrap(), vec!["", "a", "a\u{0}"]);
+}
+
+#[test]
+fn test_meta_in_batch() {
+  let tmp = tmpdir("meta_batch");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let watcher = database.watch_prefix(&[]);
+  let mut batch = Writebatch::new();
+  batch.put(1, &[1]);
+  database.meta().put_in(&mut batch, "last", &[1]);
+  database.write(WriteOptions::new(), &batch).unwrap();
+  assert_eq!(database.meta().get("last").unwrap(), Some(vec![1]));
+  // only the data is watched
+  assert_eq!(watcher.recv_timeout(Duration::from_millis(100)), Some((1, Some(vec![1]))));
+  assert_eq!(watcher.recv_timeout(Duration::from_millis(10)), None);
+}
+
+#[test]
+fn test_meta_hidden_from_iterators() {
+  for &ordered in &[false, true] {
+    let tmp = tmpdir("meta_hidden");
+    let database: Database<i32> = if ordered {
+      open_ordered_database(tmp.path())
+    } else {
+      open_database(tmp.path(), true)
+    };
+    database.meta().put(WriteOptions::new(), "cursor", b"abc").unwrap();
+    assert_eq!(database.first_key(ReadOptions::new()).unwrap(), None);
+    assert_eq!(database.last_key(ReadOptions::new()).unwrap(), None);
+    for i in 1..4 {
+      database.put(WriteOptions::new(), i, &[i as u8]).unwrap();
+    }
+    database.meta().put(WriteOptions::new(), "other", b"def").unwrap();
+
+    let keys: Vec<i32> = database.keys_iter(ReadOptions::new()).collect();
+    assert_eq!(keys, vec![1, 2, 3]);
+    let keys: Vec<i32> = database.keys_iter(ReadOptions::new()).reverse().collect();
+    assert_eq!(keys, vec![3, 2, 1]);
+    assert_eq!(database.value_iter(ReadOptions::new()).count(), 3);
+    assert_eq!(database.first_key(ReadOptions::new()).unwrap(), Some(1));
+    assert_eq!(database.last_key(ReadOptions::new()).unwrap(), Some(3));
+    let mut iter = database.iter(ReadOptions::new());
+    iter.seek(&4);
+    assert!(iter.next().is_none());
+    assert_eq!(database.meta().get("cursor").unwrap(), Some(b"abc".to_vec()));
+  }
+}
+
+#[test]
+fn test_reserved_keys_rejected() {
+  let tmp = tmpdir("meta_reserved");
+  let database: Database<BinaryKey> = open_database(tmp.path(), true);
+  database.meta().put(WriteOptions::new(), "cursor", b"abc").unwrap();
+  let mut reserved = RESERVED_PREFIX.to_vec();
+  reserved.extend_from_slice(b"meta/");
+  let key = BinaryKey(reserved);
+  let error = database.put(WriteOptions::new(), &key, b"x").unwrap_err();
+  assert_eq!(error.kind(), &ErrorKind::InvalidArgument);
+  let error = database.delete(WriteOptions::new(), &key).unwrap_err();
+  assert_eq!(error.kind(), &ErrorKind::InvalidArgument);
+
+  let mut batch = Writebatch::new();
+  batch.put(BinaryKey(b"a".to_vec()), b"1");
+  batch.set_savepoint();
+  batch.delete(BinaryKey(key.0.clone()));
+  let error = database.write(WriteOptions::new(), &batch).unwrap_err();
+  assert_eq!(error.kind(), &ErrorKind::InvalidArgument);
+  batch.rollback_to_savepoint().unwrap();
+  database.write(WriteOptions::new(), &batch).unwrap();
+
+  // keys after the namespace are still iterated
+  database.put(WriteOptions::new(), BinaryKey(b"\xff\xffz".to_vec()), b"2").unwrap();
+  let keys: Vec<Vec<u8>> = database.keys_iter(ReadOptions::new()).map(|key| key.0).collect();
+  assert_eq!(keys, vec![b"a".to_vec(), b"\xff\xffz".to_vec()]);
+  let keys: Vec<Vec<u8>> = database.keys_iter(ReadOptions::new()).reverse().map(|key| key.0).collect();
+  assert_eq!(keys, vec![b"\xff\xffz".to_vec(), b"a".to_vec()]);
+  assert_eq!(database.meta().get("cursor").unwrap(), Some(b"abc".to_vec()));
+}
@@ -0,0 +1,75 @@
+use utils::{open_database,tmpdir,db_put_simple};
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::compaction::Compaction;
+use leveldb::database::Database;
+use leveldb::iterator::Iterable;
+use leveldb::kv::KV;
+use leveldb::metrics::parse_stats;
+use leveldb::options::{ReadOptions,WriteOptions};
+
+#[test]
+fn test_operation_metrics() {
+  let tmp = tmpdir("metrics");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &[1]);
+  db_put_simple(&database, 2, &[2]);
+  database.delete(WriteOptions::new(), 2).unwrap();
+  let mut batch = Writebatch::new();
+  batch.put(3, &[3]);
+  batch.put(4, &[4]);
+  database.write(WriteOptions::new(), &batch).unwrap();
+  database.get(ReadOptions::new(), 1).unwrap();
+  database.multi_get(ReadOptions::new(), &[2, 3]);
+  let entries = database.iter(ReadOptions::new()).count();
+
+  let metrics = database.clone().metrics();
+  assert_eq!(2, metrics.puts.count);
+  assert_eq!(1, metrics.deletes.count);
+  assert_eq!(1, metrics.batch_writes.count);
+  assert_eq!(3, metrics.gets.count);
+  assert_eq!(3, metrics.gets.latency.buckets.iter().sum::<u64>());
+  assert_eq!(0, metrics.errors);
+  // the final call returning `None` counts as a step as well
+  assert_eq!(entries as u64 + 1, metrics.iterator_steps);
+}
+
+#[test]
+fn test_level_stats() {
+  let tmp = tmpdir("metrics_levels");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  for i in 0..100 {
+    db_put_simple(&database, i, &[0; 100]);
+  }
+  database.compact_all();
+  let levels = database.metrics().levels;
+  assert!(!levels.is_empty());
+  assert!(levels.iter().map(|level| level.files).sum::<u64>() > 0);
+}
+
+#[test]
+fn test_parse_stats() {
+  let stats = "                               Compactions\n\
+Level  Files Size(MB) Time(sec) Read(MB) Write(MB)\n\
+--------------------------------------------------\n  \
+0        1        0         0        0         0\n  \
+2        3        5.5       1        2         3\n";
+  let levels = parse_stats(stats);
+  assert_eq!(2, levels.len());
+  assert_eq!(2, levels[1].level);
+  assert_eq!(3, levels[1].files);
+  assert_eq!(5.5, levels[1].size_mb);
+  assert_eq!(3.0, levels[1].write_mb);
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn test_encode_prometheus() {
+  let tmp = tmpdir("metrics_prometheus");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &[1]);
+  let encoded = database.metrics().encode_prometheus();
+  assert!(encoded.contains("# TYPE leveldb_operations_total counter\n"));
+  assert!(encoded.contains("leveldb_operations_total{op=\"put\"} 1\n"));
+  assert!(encoded.contains("leveldb_operation_duration_seconds_bucket{op=\"put\",le=\"+Inf\"} 1\n"));
+  assert!(encoded.contains("leveldb_operation_duration_seconds_count{op=\"get\"} 0\n"));
+}
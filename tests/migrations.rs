@@ -0,0 +1,114 @@
+use utils::{open_database,open_ordered_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::error::{Error,ErrorKind};
+use leveldb::iterator::{Iterable,LevelDBIterator};
+use leveldb::kv::KV;
+use leveldb::keys::BinaryKey;
+use leveldb::migrations::Migrations;
+use leveldb::options::{Options,ReadOptions,WriteOptions};
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn key(k: &str) -> BinaryKey {
+  BinaryKey(k.as_bytes().to_vec())
+}
+
+fn migrations() -> Migrations<BinaryKey> {
+  Migrations::new()
+    .add(1, |_, batch| {
+      batch.put(key("user/1"), b"alice");
+      Ok(())
+    })
+    .add(2, |db, batch| {
+      // upper-case all user names
+      for (k, v) in db.iter(ReadOptions::new()).prefix(b"user/") {
+        batch.put(k, &v.to_ascii_uppercase());
+      }
+      Ok(())
+    })
+}
+
+#[test]
+fn test_migrations_run_in_order() {
+  let tmp = tmpdir("migrations");
+  let database: Database<BinaryKey> = open_database(tmp.path(), true);
+  assert_eq!(database.schema_version().unwrap(), 0);
+  assert_eq!(migrations().run(&database).unwrap(), 2);
+  assert_eq!(database.schema_version().unwrap(), 2);
+  assert_eq!(database.get(ReadOptions::new(), key("user/1")).unwrap(), Some(b"ALICE".to_vec()));
+
+  // already at the latest version, nothing runs again
+  let runs = Rc::new(Cell::new(0));
+  let counter = runs.clone();
+  let again = migrations().add(3, move |_, _| {
+    counter.set(counter.get() + 1);
+    Ok(())
+  });
+  assert_eq!(again.run(&database).unwrap(), 3);
+  assert_eq!(again.run(&database).unwrap(), 3);
+  assert_eq!(runs.get(), 1);
+}
+
+#[test]
+fn test_migrations_failure_rolls_back_step() {
+  let tmp = tmpdir("migrations_failure");
+  let database: Database<BinaryKey> = open_database(tmp.path(), true);
+  let failing = migrations().add(3, |_, batch| {
+    batch.put(key("user/2"), b"bob");
+    Err(Error::new("Corruption: bad record".to_string()))
+  });
+  let error = failing.run(&database).err().unwrap();
+  assert_eq!(*error.kind(), ErrorKind::Corruption);
+  assert!(error.message().contains("schema version 3"));
+  assert_eq!(database.schema_version().unwrap(), 2);
+  assert_eq!(database.get(ReadOptions::new(), key("user/2")).unwrap(), None);
+}
+
+#[test]
+fn test_migrations_open() {
+  let tmp = tmpdir("migrations_open");
+  let mut options = Options::new();
+  options.create_if_missing = true;
+  let database = migrations().open(tmp.path(), options).unwrap();
+  assert_eq!(database.schema_version().unwrap(), 2);
+  database.put(WriteOptions::new(), key("x"), b"1").unwrap();
+}
+
+#[test]
+fn test_migrations_newer_database() {
+  let tmp = tmpdir("migrations_newer");
+  let database: Database<BinaryKey> = open_database(tmp.path(), true);
+  migrations().run(&database).unwrap();
+  let error = Migrations::new().add(1, |_, _| Ok(())).run(&database).err().unwrap();
+  assert_eq!(*error.kind(), ErrorKind::InvalidArgument);
+}
+
+#[test]
+#[should_panic(expected = "registered after")]
+fn test_migrations_out_of_order() {
+  let _ = Migrations::<BinaryKey>::new().add(2, |_, _| Ok(())).add(1, |_, _| Ok(()));
+}
+
+#[test]
+fn test_migrations_on_ordered_database() {
+  let tmp = tmpdir("migrations_ordered");
+  let database = open_ordered_database(tmp.path());
+  let migrations = Migrations::new()
+    .add(1, |_, batch| {
+      batch.put(2, b"two");
+      batch.put(1, b"one");
+      Ok(())
+    })
+    .add(2, |db: &Database<i32>, batch| {
+      let keys: Vec<i32> = db.keys_iter(ReadOptions::new()).collect();
+      assert_eq!(keys, vec![1, 2]);
+      batch.put(3, b"three");
+      Ok(())
+    });
+  assert_eq!(migrations.run(&database).unwrap(), 2);
+  assert_eq!(database.schema_version().unwrap(), 2);
+  let keys: Vec<i32> = database.keys_iter(ReadOptions::new()).collect();
+  assert_eq!(keys, vec![1, 2, 3]);
+  assert_eq!(database.last_key(ReadOptions::new()).unwrap(), Some(3));
+  assert_eq!(migrations.run(&database).unwrap(), 2);
+}
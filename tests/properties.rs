@@ -0,0 +1,60 @@
+use utils::{open_database,tmpdir,db_put_simple};
+use leveldb::properties::{Properties,NUM_LEVELS,CompactionStats,LevelStats};
+use leveldb::compaction::Compaction;
+
+#[test]
+fn test_property_value() {
+  let tmp = tmpdir("property_value");
+  let database = &mut open_database::<i32>(tmp.path(), true);
+  assert!(database.property_value("leveldb.stats").is_some());
+  assert!(database.property_value("leveldb.sstables").is_some());
+  assert!(database.property_value("leveldb.unknown").is_none());
+}
+
+#[test]
+fn test_num_files_at_level() {
+  let tmp = tmpdir("num_files_at_level");
+  let database = &mut open_database(tmp.path(), true);
+  assert_eq!(database.num_files_at_level(0), Some(0));
+  assert_eq!(database.num_files_at_level(NUM_LEVELS), None);
+
+  db_put_simple(database, 1, &[1]);
+  db_put_simple(database, 2, &[2]);
+  database.compact(&1, &2);
+
+  let stats = database.database_stats();
+  assert_eq!(stats.files_at_level.len(), NUM_LEVELS);
+  assert_eq!(stats.files_at_level.iter().sum::<usize>(), 1);
+  assert!(stats.approximate_memory_usage.is_some());
+}
+
+#[test]
+fn test_stats() {
+  let tmp = tmpdir("stats");
+  let database = &mut open_database(tmp.path(), true);
+  let stats = database.stats().unwrap();
+  assert_eq!(stats.levels.len(), NUM_LEVELS);
+  assert_eq!(stats.levels.iter().map(|l| l.files).sum::<usize>(), 0);
+
+  for i in 0..100 {
+    db_put_simple(database, i, &[0; 1024]);
+  }
+  database.compact(&0, &100);
+  let stats = database.stats().unwrap();
+  assert_eq!(stats.levels.iter().map(|l| l.files).sum::<usize>(), 1);
+}
+
+#[test]
+fn test_parse_compaction_stats() {
+  let text = "                               Compactions\n\
+              Level  Files Size(MB) Time(sec) Read(MB) Write(MB)\n\
+              --------------------------------------------------\n  \
+                0        2        1         0        0         1\n  \
+                2        5       12         3       20        12\n";
+  let stats = CompactionStats::parse(text).unwrap();
+  assert_eq!(stats.levels[0], LevelStats { files: 2, size_mb: 1.0, time_secs: 0.0, read_mb: 0.0, write_mb: 1.0 });
+  assert_eq!(stats.levels[1], LevelStats::default());
+  assert_eq!(stats.levels[2].files, 5);
+  assert_eq!(stats.levels[2].read_mb, 20.0);
+  assert!(CompactionStats::parse("no table here").is_none());
+}
@@ -0,0 +1,85 @@
+use utils::{open_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::keys::BinaryKey;
+use leveldb::options::WriteOptions;
+use leveldb::queue::Queue;
+use std::path::Path;
+use std::thread;
+
+fn open(path: &Path) -> Database<BinaryKey> {
+  open_database(path, true)
+}
+
+#[test]
+fn test_queue_fifo() {
+  let tmp = tmpdir("queue_fifo");
+  let queue = Queue::open(open(tmp.path()), "jobs").unwrap();
+  assert!(queue.is_empty());
+  assert_eq!(queue.pop(WriteOptions::new()).unwrap(), None);
+  assert_eq!(queue.push(WriteOptions::new(), b"a").unwrap(), 0);
+  assert_eq!(queue.push_all(WriteOptions::new(), &[b"b", b"c"]).unwrap(), Some(1));
+  assert_eq!(queue.len(), 3);
+  assert_eq!(queue.peek().unwrap(), Some((0, b"a".to_vec())));
+  assert_eq!(queue.pop(WriteOptions::new()).unwrap(), Some((0, b"a".to_vec())));
+  assert_eq!(queue.pop(WriteOptions::new()).unwrap(), Some((1, b"b".to_vec())));
+  assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn test_queue_ack() {
+  let tmp = tmpdir("queue_ack");
+  let queue = Queue::open(open(tmp.path()), "jobs").unwrap();
+  for i in 0..5u8 {
+    queue.push(WriteOptions::new(), &[i]).unwrap();
+  }
+  let batch = queue.peek_many(3).unwrap();
+  assert_eq!(batch.iter().map(|&(seq, _)| seq).collect::<Vec<_>>(), vec![0, 1, 2]);
+  assert_eq!(queue.ack(WriteOptions::new(), batch[2].0).unwrap(), 3);
+  assert_eq!(queue.ack(WriteOptions::new(), 1).unwrap(), 0);
+  assert_eq!(queue.peek().unwrap(), Some((3, vec![3])));
+  assert_eq!(queue.ack(WriteOptions::new(), u64::MAX).unwrap(), 2);
+  assert!(queue.is_empty());
+}
+
+#[test]
+fn test_queue_persists() {
+  let tmp = tmpdir("queue_persists");
+  {
+    let database = open(tmp.path());
+    let jobs = Queue::open(database.clone(), "jobs").unwrap();
+    let other = Queue::open(database, "jobs2").unwrap();
+    for i in 0..4u8 {
+      jobs.push(WriteOptions::new(), &[i]).unwrap();
+    }
+    other.push(WriteOptions::new(), b"other").unwrap();
+    jobs.pop(WriteOptions::new()).unwrap();
+  }
+  let database = open(tmp.path());
+  let jobs = Queue::open(database.clone(), "jobs").unwrap();
+  assert_eq!(jobs.len(), 3);
+  assert_eq!(jobs.push(WriteOptions::new(), &[4]).unwrap(), 4);
+  assert_eq!(jobs.pop(WriteOptions::new()).unwrap(), Some((1, vec![1])));
+  assert_eq!(Queue::open(database, "jobs2").unwrap().len(), 1);
+}
+
+#[test]
+fn test_queue_concurrent_pop() {
+  let tmp = tmpdir("queue_concurrent");
+  let queue = Queue::open(open(tmp.path()), "jobs").unwrap();
+  for i in 0..100u8 {
+    queue.push(WriteOptions::new(), &[i]).unwrap();
+  }
+  let workers: Vec<_> = (0..4).map(|_| {
+    let queue = queue.clone();
+    thread::spawn(move || {
+      let mut popped = vec![];
+      while let Some((seq, _)) = queue.pop(WriteOptions::new()).unwrap() {
+        popped.push(seq);
+      }
+      popped
+    })
+  }).collect();
+  let mut all: Vec<u64> = workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect();
+  all.sort();
+  assert_eq!(all, (0..100).collect::<Vec<_>>());
+}
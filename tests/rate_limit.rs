@@ -0,0 +1,28 @@
+use utils::{open_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::rate_limit::{RateLimiter,WriteStall};
+use std::time::{Duration,Instant};
+
+#[test]
+fn test_rate_limiter() {
+  let limiter = RateLimiter::new(1000, 100);
+  let start = Instant::now();
+  limiter.acquire(100);
+  assert!(start.elapsed() < Duration::from_millis(50));
+  assert!(!limiter.try_acquire(50));
+  limiter.acquire(200);
+  assert!(start.elapsed() >= Duration::from_millis(190));
+
+  limiter.set_rate(1_000_000);
+  assert_eq!(limiter.rate(), 1_000_000);
+  let start = Instant::now();
+  limiter.clone().acquire(50);
+  assert!(start.elapsed() < Duration::from_millis(50));
+}
+
+#[test]
+fn test_write_stall_hint() {
+  let tmp = tmpdir("write_stall_hint");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  assert_eq!(database.write_stall_hint(), WriteStall::Clear);
+}
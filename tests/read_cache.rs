@@ -0,0 +1,60 @@
+use utils::{open_database,tmpdir,db_put_simple};
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::database::Database;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::read_cache::CachedDatabase;
+use leveldb::snapshots::Snapshots;
+
+#[test]
+fn test_read_cache_hits_and_invalidation() {
+  let tmp = tmpdir("read_cache");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &[1]);
+  let cached = CachedDatabase::new(database.clone(), 10);
+  assert_eq!(cached.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  assert_eq!(cached.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  assert_eq!(cached.get(ReadOptions::new(), 2).unwrap(), None);
+  assert_eq!(cached.get(ReadOptions::new(), 2).unwrap(), None);
+  let stats = cached.stats();
+  assert_eq!((stats.hits, stats.misses), (2, 2));
+
+  // writes through the cache, the database and batches all invalidate
+  cached.put(WriteOptions::new(), 1, &[2]).unwrap();
+  assert_eq!(cached.get(ReadOptions::new(), 1).unwrap(), Some(vec![2]));
+  database.put(WriteOptions::new(), 2, &[3]).unwrap();
+  assert_eq!(cached.get(ReadOptions::new(), 2).unwrap(), Some(vec![3]));
+  let mut batch = Writebatch::new();
+  batch.delete(1);
+  batch.put(2, &[4]);
+  database.write(WriteOptions::new(), &batch).unwrap();
+  assert_eq!(cached.get(ReadOptions::new(), 1).unwrap(), None);
+  assert_eq!(cached.get(ReadOptions::new(), 2).unwrap(), Some(vec![4]));
+  assert_eq!(cached.stats().invalidations, 4);
+}
+
+#[test]
+fn test_read_cache_eviction() {
+  let tmp = tmpdir("read_cache_eviction");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let cached = CachedDatabase::new(database, 2);
+  for key in &[1, 2, 1, 3] {
+    cached.get(ReadOptions::new(), key).unwrap();
+  }
+  // 2 was the least recently used
+  assert_eq!(cached.cached(), 2);
+  assert_eq!(cached.stats().evictions, 1);
+  cached.get(ReadOptions::new(), 1).unwrap();
+  assert_eq!(cached.stats().hits, 2);
+  cached.get(ReadOptions::new(), 2).unwrap();
+  assert_eq!(cached.stats().misses, 4);
+
+  db_put_simple(cached.database(), 5, &[5]);
+  let snapshot = cached.database().snapshot();
+  cached.put(WriteOptions::new(), 5, &[6]).unwrap();
+  let mut options = ReadOptions::new();
+  options.snapshot = Some(&snapshot);
+  assert_eq!(cached.get(options, 5).unwrap(), Some(vec![5]));
+  cached.clear_cache();
+  assert_eq!(cached.cached(), 0);
+}
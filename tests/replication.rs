@@ -0,0 +1,147 @@
+use utils::{open_database,open_ordered_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::iterator::Iterable;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::error::ErrorKind;
+use leveldb::replication::{Replica,ReplicationLog};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_replication_log() {
+  let tmp = tmpdir("replication_log");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let log = ReplicationLog::new(database.clone()).unwrap();
+  assert_eq!(log.last_sequence().unwrap(), 0);
+  assert_eq!(log.put(WriteOptions::new(), 1, &[1]).unwrap(), 1);
+  let mut batch = Writebatch::new();
+  batch.put(2, &[2]);
+  batch.delete(1);
+  assert_eq!(log.write(WriteOptions::new(), &batch).unwrap(), 2);
+  assert_eq!(log.delete(WriteOptions::new(), 2).unwrap(), 3);
+
+  let entries: Vec<_> = log.read_since(0).map(|e| e.unwrap()).collect();
+  assert_eq!(entries.iter().map(|e| e.sequence).collect::<Vec<_>>(), vec![1, 2, 3]);
+  assert_eq!(entries[1].batch, batch.to_bytes());
+  assert_eq!(log.read_since(2).count(), 1);
+  assert_eq!(log.read_since(3).count(), 0);
+  assert_eq!(log.read_since(u64::MAX).count(), 0);
+
+  // a follower replays the log
+  let follower_dir = tmpdir("replication_follower");
+  let follower: Database<i32> = open_database(follower_dir.path(), true);
+  for entry in log.read_since(0) {
+    follower.write(WriteOptions::new(), &Writebatch::from_bytes(&entry.unwrap().batch).unwrap()).unwrap();
+  }
+  assert_eq!(follower.get(ReadOptions::new(), 1).unwrap(), None);
+  assert_eq!(follower.get(ReadOptions::new(), 2).unwrap(), None);
+}
+
+#[test]
+fn test_replication_log_trim() {
+  let tmp = tmpdir("replication_trim");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let log = ReplicationLog::new(database.clone()).unwrap();
+  for i in 0..5 {
+    log.put(WriteOptions::new(), i, &[0]).unwrap();
+  }
+  assert_eq!(log.trim_to(2).unwrap(), 2);
+  assert_eq!(log.read_since(0).next().unwrap().unwrap().sequence, 3);
+  assert_eq!(log.trim_older_than(Duration::from_secs(3600)).unwrap(), 0);
+  assert_eq!(log.trim_older_than(Duration::from_secs(u64::MAX)).unwrap(), 0);
+  // timestamps have millisecond precision
+  thread::sleep(Duration::from_millis(5));
+  assert_eq!(log.trim_older_than(Duration::from_secs(0)).unwrap(), 3);
+  assert_eq!(log.read_since(0).count(), 0);
+
+  // sequences continue after trimming and reopening the log
+  drop(log);
+  let log = ReplicationLog::new(database).unwrap();
+  assert_eq!(log.last_sequence().unwrap(), 5);
+  assert_eq!(log.put(WriteOptions::new(), 9, &[9]).unwrap(), 6);
+}
+
+#[test]
+fn test_replica_apply() {
+  let tmp = tmpdir("replica_leader");
+  let log = ReplicationLog::new(open_database::<i32>(tmp.path(), true)).unwrap();
+  log.put(WriteOptions::new(), 1, &[1]).unwrap();
+  log.put(WriteOptions::new(), 1, &[2]).unwrap();
+  log.put(WriteOptions::new(), 2, &[3]).unwrap();
+  let entries: Vec<_> = log.read_since(0).map(|e| e.unwrap()).collect();
+
+  let follower_dir = tmpdir("replica_follower");
+  let replica = Replica::new(open_database::<i32>(follower_dir.path(), true));
+  assert_eq!(replica.applied().unwrap(), 0);
+  // out of order
+  let error = replica.apply_entry(&entries[1]).err().unwrap();
+  assert_eq!(*error.kind(), ErrorKind::InvalidArgument);
+  assert!(replica.apply_entry(&entries[0]).unwrap());
+  assert!(replica.apply_entry(&entries[1]).unwrap());
+  // redelivered
+  assert!(!replica.apply_entry(&entries[0]).unwrap());
+  assert!(!replica.apply_entry(&entries[1]).unwrap());
+  assert_eq!(replica.database().get(ReadOptions::new(), 1).unwrap(), Some(vec![2]));
+  assert_eq!(replica.applied().unwrap(), 2);
+
+  let error = replica.apply(3, b"garbage").err().unwrap();
+  assert_eq!(*error.kind(), ErrorKind::Corruption);
+  assert_eq!(replica.applied().unwrap(), 2);
+  assert!(replica.apply_entry(&entries[2]).unwrap());
+  assert_eq!(replica.database().get(ReadOptions::new(), 2).unwrap(), Some(vec![3]));
+}
+
+#[test]
+fn test_replication_logs_share_sequence() {
+  let tmp = tmpdir("replication_shared");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let threads: Vec<_> = (0..4).map(|t| {
+    let log = ReplicationLog::new(database.clone()).unwrap();
+    thread::spawn(move || {
+      for i in 0..25 {
+        log.put(WriteOptions::new(), t * 100 + i, &[0]).unwrap();
+      }
+    })
+  }).collect();
+  for thread in threads {
+    thread.join().unwrap();
+  }
+  let log = ReplicationLog::new(database).unwrap();
+  assert_eq!(log.last_sequence().unwrap(), 100);
+  let sequences: Vec<u64> = log.read_since(0).map(|e| e.unwrap().sequence).collect();
+  assert_eq!(sequences, (1..101).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_replication_on_ordered_database() {
+  let tmp = tmpdir("replication_ordered");
+  let log = ReplicationLog::new(open_ordered_database(tmp.path())).unwrap();
+  log.put(WriteOptions::new(), 2, &[2]).unwrap();
+  log.put(WriteOptions::new(), 1, &[1]).unwrap();
+  assert_eq!(log.read_since(0).count(), 2);
+  assert_eq!(log.read_since(u64::MAX).count(), 0);
+  let keys: Vec<i32> = log.database().keys_iter(ReadOptions::new()).collect();
+  assert_eq!(keys, vec![1, 2]);
+  assert_eq!(log.trim_to(1).unwrap(), 1);
+  assert_eq!(log.read_since(0).count(), 1);
+}
+
+#[test]
+fn test_replica_on_ordered_database() {
+  let tmp = tmpdir("replica_ordered_leader");
+  let log = ReplicationLog::new(open_ordered_database(tmp.path())).unwrap();
+  log.put(WriteOptions::new(), 2, &[2]).unwrap();
+  log.put(WriteOptions::new(), 1, &[1]).unwrap();
+
+  let follower_dir = tmpdir("replica_ordered_follower");
+  let replica = Replica::new(open_ordered_database(follower_dir.path()));
+  for entry in log.read_since(0) {
+    assert!(replica.apply_entry(&entry.unwrap()).unwrap());
+  }
+  assert_eq!(replica.applied().unwrap(), 2);
+  let keys: Vec<i32> = replica.database().keys_iter(ReadOptions::new()).collect();
+  assert_eq!(keys, vec![1, 2]);
+  assert_eq!(replica.database().last_key(ReadOptions::new()).unwrap(), Some(2));
+}
@@ -0,0 +1,55 @@
+use utils::{db_put_simple,open_database,tmpdir};
+use leveldb::database::Database;
+use leveldb::compaction::Compaction;
+use leveldb::kv::KV;
+use leveldb::options::ReadOptions;
+use leveldb::secondary::SecondaryDatabase;
+
+#[test]
+fn test_secondary_catch_up() {
+  let primary_dir = tmpdir("secondary_primary");
+  let mirrors = tmpdir("secondary_mirrors");
+  let primary: Database<i32> = open_database(primary_dir.path(), true);
+  db_put_simple(&primary, 1, &[1]);
+
+  let mut secondary: SecondaryDatabase<i32> = SecondaryDatabase::open(primary_dir.path(), mirrors.path()).unwrap();
+  assert_eq!(secondary.database().get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+  assert!(!secondary.try_catch_up().unwrap());
+
+  // writes still in the log of the primary
+  db_put_simple(&primary, 2, &[2]);
+  assert_eq!(secondary.database().get(ReadOptions::new(), 2).unwrap(), None);
+  assert!(secondary.try_catch_up().unwrap());
+  assert_eq!(secondary.database().get(ReadOptions::new(), 2).unwrap(), Some(vec![2]));
+
+  // tables written by a compaction
+  let held = secondary.database().clone();
+  for i in 0..100 {
+    db_put_simple(&primary, i, &[i as u8 + 1]);
+  }
+  primary.compact_all();
+  assert!(secondary.try_catch_up().unwrap());
+  for i in 0..100 {
+    assert_eq!(secondary.database().get(ReadOptions::new(), i).unwrap(), Some(vec![i as u8 + 1]));
+  }
+  // a clone keeps its mirror open
+  assert_eq!(held.get(ReadOptions::new(), 2).unwrap(), Some(vec![2]));
+  drop(held);
+  assert!(secondary.try_catch_up().is_ok());
+}
+
+#[test]
+fn test_secondary_removes_old_mirrors() {
+  let primary_dir = tmpdir("secondary_old_primary");
+  let mirrors = tmpdir("secondary_old_mirrors");
+  let primary: Database<i32> = open_database(primary_dir.path(), true);
+  db_put_simple(&primary, 1, &[1]);
+  {
+    let _secondary: SecondaryDatabase<i32> = SecondaryDatabase::open(primary_dir.path(), mirrors.path()).unwrap();
+  }
+  let mut secondary: SecondaryDatabase<i32> = SecondaryDatabase::open(primary_dir.path(), mirrors.path()).unwrap();
+  db_put_simple(&primary, 2, &[2]);
+  assert!(secondary.try_catch_up().unwrap());
+  let count = std::fs::read_dir(mirrors.path()).unwrap().count();
+  assert_eq!(count, 1);
+}
@@ -0,0 +1,74 @@
+use utils::{open_database,tmpdir};
+use leveldb::server::Server;
+use std::io::{Read,Write};
+use std::net::{SocketAddr,TcpStream};
+use std::time::{Duration,Instant};
+
+fn request(address: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, Vec<u8>) {
+  let mut stream = TcpStream::connect(address).unwrap();
+  write!(stream, "{} {} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n", method, path, body.len()).unwrap();
+  stream.write_all(body).unwrap();
+  let mut response = vec![];
+  stream.read_to_end(&mut response).unwrap();
+  let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+  let status = String::from_utf8_lossy(&response[9..12]).parse().unwrap();
+  (status, response[end + 4..].to_vec())
+}
+
+#[test]
+fn test_server() {
+  let tmp = tmpdir("server");
+  let server = Server::new(open_database(tmp.path(), true)).spawn("127.0.0.1:0").unwrap();
+  let address = server.local_addr();
+  assert_eq!(request(address, "PUT", "/keys/6131", b"one"), (204, vec![]));
+  assert_eq!(request(address, "GET", "/keys/6131", b""), (200, b"one".to_vec()));
+  assert_eq!(request(address, "GET", "/keys/6132", b"").0, 404);
+  assert_eq!(request(address, "GET", "/keys/zz", b"").0, 400);
+  assert_eq!(request(address, "POST", "/batch", b"put 6132 74776f\nput 6231 ff\ndelete 6131\n"), (204, vec![]));
+  assert_eq!(request(address, "GET", "/scan?prefix=61", b""), (200, b"6132 74776f\n".to_vec()));
+  assert_eq!(request(address, "GET", "/scan?limit=1&from=6200", b""), (200, b"6231 ff\n".to_vec()));
+  assert_eq!(request(address, "DELETE", "/keys/6132", b""), (204, vec![]));
+  assert_eq!(request(address, "GET", "/keys/6132", b"").0, 404);
+  assert_eq!(request(address, "GET", "/other", b"").0, 404);
+}
+
+#[test]
+fn test_server_access_control() {
+  let tmp = tmpdir("server_acl");
+  let database = open_database(tmp.path(), true);
+  {
+    let server = Server::new(database.clone()).allow_prefix(b"a").spawn("127.0.0.1:0").unwrap();
+    let address = server.local_addr();
+    assert_eq!(request(address, "PUT", "/keys/6131", b"one").0, 204);
+    assert_eq!(request(address, "PUT", "/keys/6231", b"one").0, 403);
+    assert_eq!(request(address, "POST", "/batch", b"put 6132 00\nput 6231 00\n").0, 403);
+    assert_eq!(request(address, "GET", "/keys/6132", b"").0, 404);
+    assert_eq!(request(address, "GET", "/scan", b"").0, 403);
+    assert_eq!(request(address, "GET", "/scan?prefix=6131", b"").0, 200);
+  }
+  let server = Server::new(database).read_only(true).spawn("127.0.0.1:0").unwrap();
+  let address = server.local_addr();
+  assert_eq!(request(address, "GET", "/keys/6131", b""), (200, b"one".to_vec()));
+  assert_eq!(request(address, "PUT", "/keys/6131", b"two").0, 403);
+  assert_eq!(request(address, "DELETE", "/keys/6131", b"").0, 403);
+  assert_eq!(request(address, "POST", "/batch", b"delete 6131\n").0, 403);
+}
+
+#[test]
+fn test_server_limits_connections() {
+  let tmp = tmpdir("server_limits");
+  let server = Server::new(open_database(tmp.path(), true))
+    .max_connections(1)
+    .timeout(Duration::from_millis(200))
+    .spawn("127.0.0.1:0")
+    .unwrap();
+  let address = server.local_addr();
+  // a client that never sends its request takes the only connection
+  let mut stalled = TcpStream::connect(address).unwrap();
+  let start = Instant::now();
+  assert_eq!(request(address, "GET", "/keys/6131", b"").0, 404);
+  assert!(start.elapsed() >= Duration::from_millis(100));
+  let mut response = vec![];
+  stalled.read_to_end(&mut response).unwrap();
+  assert!(response.starts_with(b"HTTP/1.1 400"));
+}
@@ -0,0 +1,75 @@
+use utils::{open_database,tmpdir};
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::database::Database;
+use leveldb::error::ErrorKind;
+use leveldb::iterator::Iterable;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::sharded::{Partitioner,ShardedDatabase};
+use tempdir::TempDir;
+
+fn shards(tmp: &TempDir, count: usize) -> Vec<Database<i32>> {
+  (0..count).map(|i| open_database(&tmp.path().join(i.to_string()), true)).collect()
+}
+
+#[test]
+fn test_hash_sharding() {
+  let tmp = tmpdir("sharded_hash");
+  let database = ShardedDatabase::new(shards(&tmp, 4), Partitioner::Hash).unwrap();
+  for i in 0..100 {
+    database.put(WriteOptions::new(), i, &[i as u8]).unwrap();
+  }
+  database.delete(WriteOptions::new(), 50).unwrap();
+
+  assert_eq!(Some(vec![7]), database.get(ReadOptions::new(), 7).unwrap());
+  assert_eq!(None, database.get(ReadOptions::new(), 50).unwrap());
+  let results = database.multi_get(ReadOptions::new(), &[1, 50, 99]);
+  assert_eq!(Some(vec![1]), *results[0].as_ref().unwrap());
+  assert_eq!(None, *results[1].as_ref().unwrap());
+  assert_eq!(Some(vec![99]), *results[2].as_ref().unwrap());
+
+  // every shard got some keys, and each key only lives in its own shard
+  for shard in database.shards() {
+    let keys: Vec<i32> = shard.keys_iter(ReadOptions::new()).collect();
+    assert!(!keys.is_empty());
+    for key in keys {
+      assert!(::std::ptr::eq(shard, database.shard(&key)));
+    }
+  }
+
+  let keys: Vec<i32> = database.iter(ReadOptions::new()).map(|(key, _)| key).collect();
+  let expected: Vec<i32> = (0..100).filter(|&i| i != 50).collect();
+  assert_eq!(expected, keys);
+}
+
+#[test]
+fn test_range_sharding() {
+  let tmp = tmpdir("sharded_range");
+  let starts = vec![vec![0, 0, 0, 10], vec![0, 0, 0, 20]];
+  let database = ShardedDatabase::new(shards(&tmp, 3), Partitioner::Range(starts)).unwrap();
+  let mut batch = Writebatch::new();
+  for i in 0..30 {
+    batch.put(i, &[i as u8]);
+  }
+  batch.delete(15);
+  database.write(WriteOptions::new(), &batch).unwrap();
+
+  let counts: Vec<usize> = database.shards().iter().map(|shard| shard.iter(ReadOptions::new()).count()).collect();
+  assert_eq!(vec![10, 9, 10], counts);
+  let first: Vec<i32> = database.shards()[1].keys_iter(ReadOptions::new()).take(1).collect();
+  assert_eq!(vec![10], first);
+  let entries: Vec<(i32, Vec<u8>)> = database.iter(ReadOptions::new()).skip(19).take(2).collect();
+  assert_eq!(vec![(20, vec![20]), (21, vec![21])], entries);
+}
+
+#[test]
+fn test_sharding_validation() {
+  let tmp = tmpdir("sharded_invalid");
+  let error = ShardedDatabase::<i32>::new(vec![], Partitioner::Hash).err().unwrap();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
+  let error = ShardedDatabase::new(shards(&tmp, 2), Partitioner::Range(vec![])).err().unwrap();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
+  let starts = vec![vec![2], vec![1]];
+  let error = ShardedDatabase::new(shards(&tmp, 3), Partitioner::Range(starts)).err().unwrap();
+  assert_eq!(&ErrorKind::InvalidArgument, error.kind());
+}
@@ -0,0 +1,120 @@
+use utils::{open_database,tmpdir,db_put_simple};
+use key::Key;
+use leveldb::iterator::{Iterable, LevelDBIterator};
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::sizes::ApproximateSizes;
+use leveldb::snapshots::{OwnedSnapshots,Snapshots};
+use leveldb::compaction::Compaction;
+
+#[test]
+fn test_approximate_sizes() {
+  let tmp = tmpdir("approximate_sizes");
+  let database = &mut open_database(tmp.path(), true);
+  for i in 0..1000 {
+    db_put_simple(database, i, &[0; 128]);
+  }
+  database.compact(&0, &1000);
+
+  let sizes = database.approximate_sizes(&[(0, 1000), (2000, 3000)]);
+  assert_eq!(sizes.len(), 2);
+  assert!(sizes[0] > 0);
+  assert_eq!(sizes[1], 0);
+}
+
+#[test]
+fn test_snapshot_approximate_sizes() {
+  let tmp = tmpdir("snap_approximate_sizes");
+  let database = &mut open_database::<i32>(tmp.path(), true);
+  let snapshot = database.snapshot();
+  let sizes = snapshot.approximate_sizes(&[(0, 10)]);
+  assert_eq!(sizes, vec![0]);
+  let owned = database.owned_snapshot();
+  assert_eq!(owned.approximate_sizes(&[(0, 10)]), vec![0]);
+}
+
+#[test]
+fn test_split_ranges() {
+  let tmp = tmpdir("split_ranges");
+  let database = &mut open_database::<i32>(tmp.path(), true);
+  assert!(database.split_ranges(4).is_empty());
+  for i in 0..10000 {
+    db_put_simple(database, i, &[i as u8; 256]);
+  }
+  database.compact_all();
+
+  let whole = database.split_ranges(1);
+  assert_eq!(whole, vec![(from_i32(0), vec![])]);
+
+  let ranges = database.split_ranges(4);
+  assert_eq!(ranges.len(), 4);
+  assert_eq!(ranges[0].0, from_i32(0));
+  assert!(ranges[3].1.is_empty());
+  let mut total = 0;
+  for (i, (start, end)) in ranges.iter().enumerate() {
+    if i > 0 {
+      assert_eq!(*start, ranges[i - 1].1);
+    }
+    let from: i32 = Key::from_u8(start);
+    let count = if end.is_empty() {
+      database.keys_iter(ReadOptions::new()).from(&from).count()
+    } else {
+      let until: i32 = Key::from_u8(end);
+      database.keys_iter(ReadOptions::new()).from(&from).until(&until).count()
+    };
+    assert!(count > 1500 && count < 3500, "range {} holds {} keys", i, count);
+    total += count;
+  }
+  assert_eq!(total, 10000);
+}
+
+#[test]
+fn test_sample_keys() {
+  let tmp = tmpdir("sample_keys");
+  let database = &mut open_database::<i32>(tmp.path(), true);
+  assert!(database.sample_keys(ReadOptions::new(), 4).is_empty());
+  // in the memtable, sampled from a scan
+  for i in 0..100 {
+    db_put_simple(database, i, &[i as u8]);
+  }
+  database.meta().put(WriteOptions::new(), "sampled", b"no").unwrap();
+  assert_eq!(database.sample_keys(ReadOptions::new(), 4), vec![12, 37, 62, 87]);
+  assert_eq!(database.sample_keys(ReadOptions::new(), 200).len(), 100);
+
+  for i in 0..10000 {
+    db_put_simple(database, i, &[i as u8; 256]);
+  }
+  database.compact_all();
+  let samples = database.sample_keys(ReadOptions::new(), 10);
+  assert_eq!(samples.len(), 10);
+  for (i, &key) in samples.iter().enumerate() {
+    let expected = i as i32 * 1000 + 500;
+    assert!((key - expected).abs() < 500, "sample {} is {}", i, key);
+  }
+}
+
+fn from_i32(key: i32) -> Vec<u8> {
+  key.as_slice(|k| k.to_vec())
+}
+
+#[test]
+fn test_estimate_num_keys() {
+  let tmp = tmpdir("estimate_num_keys");
+  let database = &mut open_database::<i32>(tmp.path(), true);
+  assert!(database.estimate_num_keys().is_exact());
+  assert_eq!(database.estimate_num_keys().estimate, 0);
+  for i in 0..100 {
+    db_put_simple(database, i, &[i as u8; 100]);
+  }
+  let counted = database.estimate_num_keys();
+  assert!(counted.is_exact());
+  assert_eq!(counted.estimate, 100);
+
+  for i in 0..50000 {
+    db_put_simple(database, i, &[i as u8; 100]);
+  }
+  database.compact_all();
+  let estimated = database.estimate_num_keys();
+  assert!(!estimated.is_exact());
+  assert!(estimated.low <= estimated.estimate && estimated.estimate <= estimated.high);
+  assert!(estimated.estimate > 25000 && estimated.estimate < 100000, "{:?}", estimated);
+}
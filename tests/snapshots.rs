@@ -1,6 +1,9 @@
 use utils::{open_database,tmpdir,db_put_simple};
-use leveldb::snapshots::Snapshots;
-use leveldb::options::{ReadOptions};
+use leveldb::snapshots::{Snapshots,OwnedSnapshots,Change,diff};
+use std::sync::Arc;
+use std::thread;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::kv::KV;
 use leveldb::iterator::{Iterable};
 
 #[test]
@@ -30,3 +33,93 @@ fn test_snapshot_iterator() {
   let next = iter.next();
   assert_eq!(None, next);
 }
+
+#[test]
+fn test_snapshot_get_bytes() {
+  let tmp = tmpdir("snap_get_bytes");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1]);
+  let snapshot = database.snapshot();
+  db_put_simple(database, 1, &[2]);
+  let read_opts = ReadOptions::new();
+  let res = snapshot.get_bytes(read_opts, 1).unwrap();
+  assert_eq!(&*res.unwrap(), &[1]);
+}
+
+#[test]
+fn test_owned_snapshot() {
+  let tmp = tmpdir("owned_snapshot");
+  let database = Arc::new(open_database(tmp.path(), true));
+  db_put_simple(&database, 1, &[1]);
+  let snapshot = database.owned_snapshot();
+  db_put_simple(&database, 2, &[2]);
+  drop(database);
+
+  let keys = thread::spawn(move || {
+    assert_eq!(snapshot.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+    assert_eq!(snapshot.get(ReadOptions::new(), 2).unwrap(), None);
+    snapshot.keys_iter(ReadOptions::new()).collect::<Vec<_>>()
+  }).join().unwrap();
+  assert_eq!(keys, vec![1]);
+}
+
+#[test]
+fn test_snapshot_diff() {
+  let tmp = tmpdir("snap_diff");
+  let database = &mut open_database(tmp.path(), true);
+  db_put_simple(database, 1, &[1]);
+  db_put_simple(database, 2, &[2]);
+  db_put_simple(database, 3, &[3]);
+  let older = database.snapshot();
+  db_put_simple(database, 0, &[0]);
+  db_put_simple(database, 2, &[20]);
+  db_put_simple(database, 3, &[3]);
+  database.delete(WriteOptions::new(), 1).unwrap();
+  db_put_simple(database, 4, &[4]);
+  let newer = database.snapshot();
+
+  let changes: Vec<Change<i32>> = diff(&older, &newer, ReadOptions::new()).collect();
+  assert_eq!(changes, vec![Change::Added(0, vec![0]),
+                           Change::Removed(1),
+                           Change::Changed(2, vec![2], vec![20]),
+                           Change::Added(4, vec![4])]);
+
+  let reversed: Vec<Change<i32>> = diff(&newer, &older, ReadOptions::new()).collect();
+  assert_eq!(reversed.len(), 4);
+  assert_eq!(reversed[0], Change::Removed(0));
+  assert_eq!(diff(&newer, &newer, ReadOptions::new()).count(), 0);
+}
+
+#[test]
+fn test_owned_snapshot_without_arc() {
+  struct Holder {
+    database: ::leveldb::database::Database<i32>,
+    snapshot: ::leveldb::snapshots::OwnedSnapshot<i32>,
+  }
+
+  let tmp = tmpdir("owned_snapshot_no_arc");
+  let database = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &[1]);
+  let snapshot = database.owned_snapshot();
+  let holder = Holder { database, snapshot };
+  db_put_simple(&holder.database, 1, &[2]);
+
+  let Holder { database, snapshot } = holder;
+  drop(database);
+  let value = thread::spawn(move || snapshot.get(ReadOptions::new(), 1).unwrap()).join().unwrap();
+  assert_eq!(value, Some(vec![1]));
+}
+
+#[test]
+fn test_contains_key() {
+  let tmp = tmpdir("contains_key");
+  let database = open_database(tmp.path(), true);
+  db_put_simple(&database, 1, &[1; 1000]);
+  let snapshot = database.snapshot();
+  database.delete(WriteOptions::new(), 1).unwrap();
+  db_put_simple(&database, 2, &[2]);
+  assert!(!database.contains_key(ReadOptions::new(), 1).unwrap());
+  assert!(database.contains_key(ReadOptions::new(), 2).unwrap());
+  assert!(snapshot.contains_key(ReadOptions::new(), 1).unwrap());
+  assert!(!snapshot.contains_key(ReadOptions::new(), 2).unwrap());
+}
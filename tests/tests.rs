@@ -1,3 +1,5 @@
+#![allow(clippy::module_inception)]
+
 extern crate db_key as key;
 extern crate leveldb;
 extern crate tempdir;
@@ -8,9 +10,54 @@ mod database;
 mod comparator;
 mod binary;
 mod iterator;
+mod keys;
 mod snapshots;
 mod cache;
+mod filter_policy;
+mod env;
+mod error;
 mod writebatch;
 mod management;
 mod compaction;
-mod concurrent_access;
\ No newline at end of file
+mod concurrent_access;
+mod sizes;
+mod properties;
+mod typed;
+mod ttl;
+mod index;
+mod transaction;
+mod atomic;
+mod keyspace;
+mod backup;
+mod watch;
+mod sharded;
+mod cursor;
+mod compression;
+mod queue;
+mod merge;
+mod chunked;
+mod blob;
+mod backend;
+mod manager;
+mod read_cache;
+mod coalescer;
+mod rate_limit;
+mod migrations;
+mod meta;
+mod hooks;
+mod replication;
+mod consensus;
+mod integrity;
+mod health;
+mod logs;
+mod live_files;
+mod secondary;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "derive")]
+mod derive;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "tracing")]
+mod trace;
+mod asynchronous;
\ No newline at end of file
@@ -0,0 +1,60 @@
+use utils::{open_database,tmpdir};
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::compaction::Compaction;
+use leveldb::database::Database;
+use leveldb::iterator::Iterable;
+use leveldb::keys::BinaryKey;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::snapshots::Snapshots;
+use leveldb::trace::{self,Span,Tracer};
+use std::sync::{Arc,Mutex};
+
+struct Collector(Arc<Mutex<Vec<Span>>>);
+
+impl Tracer for Collector {
+  fn span(&self, span: &Span) {
+    self.0.lock().unwrap().push(span.clone());
+  }
+}
+
+// other tests run concurrently, so spans are told apart by an unusual key length
+const KEY_LENGTH: usize = 37;
+
+#[test]
+fn test_tracer() {
+  let tmp = tmpdir("trace");
+  let database: Database<BinaryKey> = open_database(tmp.path(), true);
+  let key = BinaryKey(vec![7; KEY_LENGTH]);
+  let spans = Arc::new(Mutex::new(vec![]));
+  trace::set_tracer(Collector(spans.clone()));
+
+  database.put(WriteOptions::new(), &key, &[1, 2, 3]).unwrap();
+  database.get(ReadOptions::new(), &key).unwrap();
+  database.delete(WriteOptions::new(), &key).unwrap();
+  database.get(ReadOptions::new(), &key).unwrap();
+  let mut batch = Writebatch::new();
+  batch.put(BinaryKey(vec![1]), &[1]);
+  batch.put(BinaryKey(vec![2]), &[2]);
+  batch.delete(BinaryKey(vec![3]));
+  database.write(WriteOptions::new(), &batch).unwrap();
+  {
+    let snapshot = database.snapshot();
+    assert_eq!(2, snapshot.iter(ReadOptions::new()).count());
+  }
+  database.compact_all();
+  trace::clear_tracer();
+  database.put(WriteOptions::new(), &key, &[1]).unwrap();
+
+  let spans = spans.lock().unwrap();
+  let ours: Vec<(&str, Option<usize>)> = spans.iter()
+    .filter(|span| span.key_length == Some(KEY_LENGTH))
+    .map(|span| (span.name, span.value_length))
+    .collect();
+  assert_eq!(vec![("put", Some(3)), ("get", Some(3)), ("delete", None), ("get", None)], ours);
+  assert!(spans.iter().any(|span| span.name == "write" && span.entries == Some(3)));
+  assert!(spans.iter().any(|span| span.name == "iterator" && span.entries == Some(2)));
+  assert!(spans.iter().any(|span| span.name == "snapshot"));
+  assert!(spans.iter().any(|span| span.name == "compact"));
+  assert!(spans.iter().all(|span| !span.failed));
+}
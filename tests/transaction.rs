@@ -0,0 +1,94 @@
+use utils::{tmpdir,open_database,db_put_simple};
+use leveldb::error::ErrorKind;
+use leveldb::kv::KV;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::transaction::TransactionDatabase;
+
+#[test]
+fn test_transaction_reads_own_writes() {
+  let tmp = tmpdir("txn_overlay");
+  let database = TransactionDatabase::new(open_database(tmp.path(), true));
+  db_put_simple(database.database(), 1, &[1]);
+
+  let mut txn = database.begin();
+  assert_eq!(Some(vec![1]), txn.get(1).unwrap());
+  txn.put(2, &[2]);
+  txn.delete(1);
+  assert_eq!(Some(vec![2]), txn.get(2).unwrap());
+  assert_eq!(None, txn.get(1).unwrap());
+  // nothing is visible before the commit
+  assert_eq!(None, database.database().get(ReadOptions::new(), 2).unwrap());
+  txn.commit(WriteOptions::new()).unwrap();
+
+  assert_eq!(None, database.database().get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(Some(vec![2]), database.database().get(ReadOptions::new(), 2).unwrap());
+}
+
+#[test]
+fn test_transaction_reads_from_snapshot() {
+  let tmp = tmpdir("txn_snapshot");
+  let database = TransactionDatabase::new(open_database(tmp.path(), true));
+  let mut txn = database.begin();
+  db_put_simple(database.database(), 1, &[1]);
+  assert_eq!(None, txn.get(1).unwrap());
+}
+
+#[test]
+fn test_transaction_conflict() {
+  let tmp = tmpdir("txn_conflict");
+  let database = TransactionDatabase::new(open_database(tmp.path(), true));
+  db_put_simple(database.database(), 1, &[0]);
+
+  let mut first = database.begin();
+  let mut second = database.begin();
+  let value = first.get(1).unwrap().unwrap();
+  first.put(1, &[value[0] + 1]);
+  let value = second.get(1).unwrap().unwrap();
+  second.put(1, &[value[0] + 1]);
+
+  first.commit(WriteOptions::new()).unwrap();
+  let error = second.commit(WriteOptions::new()).err().unwrap();
+  assert_eq!(&ErrorKind::Conflict, error.kind());
+  assert_eq!(Some(vec![1]), database.database().get(ReadOptions::new(), 1).unwrap());
+
+  // a retry sees the committed value
+  let mut retry = database.begin();
+  let value = retry.get(1).unwrap().unwrap();
+  retry.put(1, &[value[0] + 1]);
+  retry.commit(WriteOptions::new()).unwrap();
+  assert_eq!(Some(vec![2]), database.database().get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_transaction_without_overlap() {
+  let tmp = tmpdir("txn_disjoint");
+  let database = TransactionDatabase::new(open_database(tmp.path(), true));
+  let mut first = database.begin();
+  let mut second = database.begin();
+  first.get(1).unwrap();
+  first.put(1, &[1]);
+  // blind writes are not validated
+  second.put(1, &[2]);
+  second.get(2).unwrap();
+  second.put(2, &[2]);
+
+  second.commit(WriteOptions::new()).unwrap();
+  let error = first.commit(WriteOptions::new()).err().unwrap();
+  assert_eq!(&ErrorKind::Conflict, error.kind());
+
+  let mut third = database.begin();
+  third.get(1).unwrap();
+  third.put(3, &[3]);
+  drop(database.begin());
+  third.commit(WriteOptions::new()).unwrap();
+}
+
+#[test]
+fn test_transaction_dropped_without_commit() {
+  let tmp = tmpdir("txn_rollback");
+  let database = TransactionDatabase::new(open_database(tmp.path(), true));
+  let mut txn = database.begin();
+  txn.put(1, &[1]);
+  drop(txn);
+  assert_eq!(None, database.database().get(ReadOptions::new(), 1).unwrap());
+}
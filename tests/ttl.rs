@@ -0,0 +1,79 @@
+use utils::{tmpdir,open_database,db_put_simple};
+use leveldb::error::ErrorKind;
+use leveldb::iterator::Iterable;
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::ttl::TtlDatabase;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_ttl_get_hides_expired() {
+  let tmp = tmpdir("ttl_get");
+  let database = TtlDatabase::new(open_database(tmp.path(), true), Duration::from_millis(20));
+  database.put(WriteOptions::new(), 1, &[1]).unwrap();
+  database.put_with_ttl(WriteOptions::new(), 2, &[2], Duration::from_secs(3600)).unwrap();
+  assert_eq!(Some(vec![1]), database.get(ReadOptions::new(), 1).unwrap());
+
+  thread::sleep(Duration::from_millis(40));
+  assert_eq!(None, database.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(Some(vec![2]), database.get(ReadOptions::new(), 2).unwrap());
+  let entries: Vec<(i32, Vec<u8>)> = database.iter(ReadOptions::new()).collect();
+  assert_eq!(vec![(2, vec![2])], entries);
+}
+
+#[test]
+fn test_ttl_purge_expired() {
+  let tmp = tmpdir("ttl_purge");
+  let database = TtlDatabase::new(open_database(tmp.path(), true), Duration::from_millis(0));
+  database.put(WriteOptions::new(), 1, &[1]).unwrap();
+  database.put(WriteOptions::new(), 2, &[2]).unwrap();
+  database.put_with_ttl(WriteOptions::new(), 3, &[3], Duration::from_secs(3600)).unwrap();
+
+  assert_eq!(2, database.purge_expired().unwrap());
+  let keys: Vec<i32> = database.database().keys_iter(ReadOptions::new()).collect();
+  assert_eq!(vec![3], keys);
+  assert_eq!(0, database.purge_expired().unwrap());
+}
+
+#[test]
+fn test_ttl_sweeper() {
+  let tmp = tmpdir("ttl_sweeper");
+  let database = TtlDatabase::new(open_database(tmp.path(), true), Duration::from_millis(0));
+  database.put(WriteOptions::new(), 1, &[1]).unwrap();
+  let sweeper = database.spawn_sweeper(Duration::from_millis(5));
+  let mut remaining = 1;
+  for _ in 0..200 {
+    remaining = database.database().keys_iter(ReadOptions::new()).count();
+    if remaining == 0 {
+      break;
+    }
+    thread::sleep(Duration::from_millis(5));
+  }
+  drop(sweeper);
+  assert_eq!(0, remaining);
+}
+
+#[test]
+fn test_ttl_value_without_expiry() {
+  let tmp = tmpdir("ttl_malformed");
+  let database = TtlDatabase::new(open_database(tmp.path(), true), Duration::from_secs(60));
+  db_put_simple(database.database(), 1, &[1]);
+  let error = database.get(ReadOptions::new(), 1).err().unwrap();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+  assert_eq!(0, database.iter(ReadOptions::new()).count());
+}
+
+#[test]
+fn test_ttl_purge_expired_in_batches() {
+  let tmp = tmpdir("ttl_purge_batches");
+  let database = TtlDatabase::new(open_database(tmp.path(), true), Duration::from_millis(0));
+  // more deletes than fit in one purge batch
+  for i in 0..200_000 {
+    database.put(WriteOptions::new(), i, &[]).unwrap();
+  }
+  database.put_with_ttl(WriteOptions::new(), 200_000, &[1], Duration::from_secs(3600)).unwrap();
+
+  assert_eq!(200_000, database.purge_expired().unwrap());
+  let keys: Vec<i32> = database.database().keys_iter(ReadOptions::new()).collect();
+  assert_eq!(vec![200_000], keys);
+}
@@ -0,0 +1,97 @@
+use utils::{tmpdir,open_database,db_put_simple};
+use leveldb::error::{Error,ErrorKind};
+use leveldb::batch::Writebatch;
+use leveldb::iterator::{Iterable,LevelDBIterator};
+use leveldb::options::{ReadOptions,WriteOptions};
+use leveldb::typed::{Codec,TypedDatabase,TypedIterator,Utf8Codec};
+
+#[derive(Debug,PartialEq)]
+struct Point {
+  x: i16,
+  y: i16,
+}
+
+struct PointCodec;
+
+impl Codec<Point> for PointCodec {
+  fn encode(&self, value: &Point) -> Vec<u8> {
+    let mut bytes = value.x.to_be_bytes().to_vec();
+    bytes.extend_from_slice(&value.y.to_be_bytes());
+    bytes
+  }
+
+  fn decode(&self, bytes: &[u8]) -> Result<Point, Error> {
+    if bytes.len() != 4 {
+      return Err(Error::new("Corruption: bad point".to_string()));
+    }
+    Ok(Point {
+      x: i16::from_be_bytes([bytes[0], bytes[1]]),
+      y: i16::from_be_bytes([bytes[2], bytes[3]]),
+    })
+  }
+}
+
+#[test]
+fn test_typed_put_get_delete() {
+  let tmp = tmpdir("typed_put_get");
+  let database = TypedDatabase::new(open_database(tmp.path(), true), PointCodec);
+  database.put(WriteOptions::new(), 1, &Point { x: 1, y: -1 }).unwrap();
+  assert_eq!(Some(Point { x: 1, y: -1 }), database.get(ReadOptions::new(), 1).unwrap());
+
+  let mut batch = Writebatch::new();
+  database.batch_put(&mut batch, 2, &Point { x: 2, y: 2 });
+  database.write(WriteOptions::new(), &batch).unwrap();
+  assert_eq!(Some(Point { x: 2, y: 2 }), database.get(ReadOptions::new(), 2).unwrap());
+
+  database.delete(WriteOptions::new(), 1).unwrap();
+  assert_eq!(None, database.get(ReadOptions::new(), 1).unwrap());
+}
+
+#[test]
+fn test_typed_decode_errors() {
+  let tmp = tmpdir("typed_decode_errors");
+  let database = TypedDatabase::new(open_database(tmp.path(), true), Utf8Codec);
+  database.put(WriteOptions::new(), 1, &"one".to_string()).unwrap();
+  db_put_simple(database.database(), 2, &[0xff]);
+  database.put(WriteOptions::new(), 3, &"three".to_string()).unwrap();
+
+  let error = database.get(ReadOptions::new(), 2).err().unwrap();
+  assert_eq!(&ErrorKind::Corruption, error.kind());
+
+  let entries: Vec<(i32, Option<String>)> = database.iter(ReadOptions::new())
+    .map(|(k, v)| (k, v.ok()))
+    .collect();
+  assert_eq!(vec![(1, Some("one".to_string())), (2, None), (3, Some("three".to_string()))],
+             entries);
+}
+
+#[test]
+fn test_typed_iterator_with_range() {
+  let tmp = tmpdir("typed_range");
+  let database = TypedDatabase::new(open_database(tmp.path(), true), Utf8Codec);
+  for i in 0..5 {
+    database.put(WriteOptions::new(), i, &i.to_string()).unwrap();
+  }
+  let inner = database.database().iter(ReadOptions::new()).from(&1).to(&2);
+  let values: Vec<String> = TypedIterator::with_iterator(inner, database.codec())
+    .map(|(_, v)| v.unwrap())
+    .collect();
+  assert_eq!(vec!["1".to_string(), "2".to_string()], values);
+}
+
+#[test]
+fn test_typed_writebatch() {
+  let tmp = tmpdir("typed_writebatch");
+  let database = TypedDatabase::new(open_database(tmp.path(), true), PointCodec);
+  database.put(WriteOptions::new(), 3, &Point { x: 3, y: 3 }).unwrap();
+  let mut batch = database.batch();
+  assert!(batch.is_empty());
+  batch.put(1, &Point { x: 1, y: 2 });
+  batch.put(2, &Point { x: -2, y: 0 });
+  batch.delete(3);
+  assert_eq!(batch.len(), 3);
+  batch.write(WriteOptions::new()).unwrap();
+  assert_eq!(Some(Point { x: 1, y: 2 }), database.get(ReadOptions::new(), 1).unwrap());
+  assert_eq!(Some(Point { x: -2, y: 0 }), database.get(ReadOptions::new(), 2).unwrap());
+  assert_eq!(None, database.get(ReadOptions::new(), 3).unwrap());
+}
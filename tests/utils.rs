@@ -1,4 +1,5 @@
 use leveldb::database::Database;
+use leveldb::comparator::OrdComparator;
 use leveldb::database::kv::{KV};
 use leveldb::options::{Options,WriteOptions};
 use std::path::Path;
@@ -14,6 +15,16 @@ pub fn open_database<K: Key + Ord>(path: &Path, create_if_missing: bool) -> Data
   }
 }
 
+// a database whose keys reach a comparator decoding them as `i32`
+pub fn open_ordered_database(path: &Path) -> Database<i32> {
+  let mut opts = Options::new();
+  opts.create_if_missing = true;
+  match Database::open_with_comparator(path, opts, OrdComparator::<i32>::new("ord")) {
+    Ok(db) => { db },
+    Err(e) => { panic!("failed to open database: {:?}", e) }
+  }
+}
+
 pub fn tmpdir(name: &str) -> TempDir {
   TempDir::new(name)
            .unwrap()
@@ -22,7 +33,7 @@ pub fn tmpdir(name: &str) -> TempDir {
 pub fn db_put_simple<K: Key + Ord>(database: &Database<K>, key: K, val: &[u8]) {
   let write_opts = WriteOptions::new();
   match database.put(write_opts, key, val) {
-    Ok(_) => { () },
+    Ok(_) => { },
     Err(e) => { panic!("failed to write to database: {:?}", e) }
   }
 }
@@ -0,0 +1,61 @@
+use utils::{open_database,tmpdir,db_put_simple};
+use leveldb::batch::{Batch,Writebatch};
+use leveldb::database::Database;
+use leveldb::keys::BinaryKey;
+use leveldb::kv::KV;
+use leveldb::options::WriteOptions;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_watch_key() {
+  let tmp = tmpdir("watch_key");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let watcher = database.watch(&1);
+  db_put_simple(&database, 1, &[1]);
+  db_put_simple(&database, 2, &[2]);
+  database.delete(WriteOptions::new(), 1).unwrap();
+
+  let mut batch = Writebatch::new();
+  batch.put(2, &[3]);
+  batch.put(1, &[4]);
+  database.write(WriteOptions::new(), &batch).unwrap();
+
+  assert_eq!(Some((1, Some(vec![1]))), watcher.try_recv());
+  assert_eq!(Some((1, None)), watcher.try_recv());
+  assert_eq!(Some((1, Some(vec![4]))), watcher.try_recv());
+  assert_eq!(None, watcher.try_recv());
+}
+
+#[test]
+fn test_watch_prefix() {
+  let tmp = tmpdir("watch_prefix");
+  let database: Database<BinaryKey> = open_database(tmp.path(), true);
+  let watcher = database.watch_prefix(b"user/");
+  let other = database.clone();
+  let writer = thread::spawn(move || {
+    other.put(WriteOptions::new(), BinaryKey(b"order/1".to_vec()), &[1]).unwrap();
+    other.put(WriteOptions::new(), BinaryKey(b"user/1".to_vec()), &[2]).unwrap();
+    other.delete(WriteOptions::new(), BinaryKey(b"user/2".to_vec())).unwrap();
+  });
+  writer.join().unwrap();
+
+  let timeout = Duration::from_secs(5);
+  assert_eq!(Some((BinaryKey(b"user/1".to_vec()), Some(vec![2]))), watcher.recv_timeout(timeout));
+  assert_eq!(Some((BinaryKey(b"user/2".to_vec()), None)), watcher.recv_timeout(timeout));
+  assert_eq!(None, watcher.try_recv());
+}
+
+#[test]
+fn test_watch_ends_with_database() {
+  let tmp = tmpdir("watch_end");
+  let database: Database<i32> = open_database(tmp.path(), true);
+  let watcher = database.watch(&1);
+  let dropped = database.watch(&1);
+  drop(dropped);
+  db_put_simple(&database, 1, &[1]);
+  drop(database);
+
+  let events: Vec<(i32, Option<Vec<u8>>)> = watcher.collect();
+  assert_eq!(vec![(1, Some(vec![1]))], events);
+}
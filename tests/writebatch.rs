@@ -1,5 +1,7 @@
-use utils::{tmpdir};
+use utils::{tmpdir,open_database};
 use leveldb::database::{Database};
+use leveldb::iterator::Iterable;
+use leveldb::keys::BinaryKey;
 use leveldb::options::{Options,ReadOptions,WriteOptions};
 use leveldb::database::kv::{KV};
 use leveldb::database::batch::{Batch,Writebatch,WritebatchIterator};
@@ -49,12 +51,12 @@ impl WritebatchIterator for Iter {
     fn put(&mut self,
            _key: i32,
            _value: &[u8]) {
-        self.put = self.put + 1;
+        self.put += 1;
     }
 
     fn deleted(&mut self,
                _key: i32) {
-        self.deleted = self.deleted + 1;
+        self.deleted += 1;
     }
 }
 
@@ -78,3 +80,232 @@ fn test_writebatchiter() {
     assert_eq!(iter2.put, 2);
     assert_eq!(iter2.deleted, 1);
 }
+
+#[test]
+fn test_writebatch_append() {
+    let mut opts = Options::new();
+    opts.create_if_missing = true;
+    let tmp = tmpdir("writebatch_append");
+    let database = &mut Database::open(tmp.path(), opts).unwrap();
+    let batch = &mut Writebatch::new();
+    batch.put(1, &[1]);
+    let other = &mut Writebatch::new();
+    other.put(2, &[2]);
+    other.delete(1);
+    batch.append(other);
+
+    let iter = batch.iterate(Box::new(Iter { put: 0, deleted: 0 }));
+    assert_eq!(iter.put, 2);
+    assert_eq!(iter.deleted, 1);
+    // the appended batch is left untouched
+    let iter = other.iterate(Box::new(Iter { put: 0, deleted: 0 }));
+    assert_eq!(iter.put, 1);
+    assert_eq!(iter.deleted, 1);
+
+    // the appended delete comes after the put of the same key
+    let ack = database.write(WriteOptions::new(), batch);
+    assert!(ack.is_ok());
+    assert!(database.get(ReadOptions::new(), 1).unwrap().is_none());
+    assert_eq!(Some(vec![2]), database.get(ReadOptions::new(), 2).unwrap());
+}
+
+#[test]
+fn test_writebatch_len_and_size() {
+    let batch = &mut Writebatch::<i32>::new();
+    assert!(batch.is_empty());
+    assert_eq!(batch.len(), 0);
+    let empty_size = batch.approximate_size_bytes();
+    assert!(empty_size > 0);
+
+    batch.put(1, &[0; 100]);
+    batch.delete(2);
+    assert!(!batch.is_empty());
+    assert_eq!(batch.len(), 2);
+    assert!(batch.approximate_size_bytes() > empty_size + 100);
+
+    let other = &mut Writebatch::new();
+    other.put(3, &[3]);
+    batch.append(other);
+    assert_eq!(batch.len(), 3);
+    assert_eq!(batch.approximate_size_bytes(), batch.to_bytes().len());
+
+    batch.set_savepoint();
+    batch.put(4, &[0; 200]);
+    batch.rollback_to_savepoint().unwrap();
+    assert_eq!(batch.len(), 3);
+    assert_eq!(batch.approximate_size_bytes(), batch.to_bytes().len());
+
+    batch.clear();
+    assert!(batch.is_empty());
+    assert_eq!(batch.approximate_size_bytes(), empty_size);
+}
+
+#[test]
+fn test_writebatch_serialization() {
+    let mut opts = Options::new();
+    opts.create_if_missing = true;
+    let tmp = tmpdir("writebatch_bytes");
+    let database = &mut Database::open(tmp.path(), opts).unwrap();
+    let batch = &mut Writebatch::new();
+    batch.put(1, &[1]);
+    batch.put(2, &[2; 300]);
+    batch.delete(1);
+
+    let bytes = batch.to_bytes();
+    assert_eq!(bytes.len(), batch.approximate_size_bytes());
+    let copy = &mut Writebatch::<i32>::from_bytes(&bytes).unwrap();
+    assert_eq!(copy.len(), 3);
+    assert_eq!(copy.to_bytes(), bytes);
+
+    let ack = database.write(WriteOptions::new(), copy);
+    assert!(ack.is_ok());
+    assert!(database.get(ReadOptions::new(), 1).unwrap().is_none());
+    assert_eq!(Some(vec![2; 300]), database.get(ReadOptions::new(), 2).unwrap());
+}
+
+#[test]
+fn test_writebatch_from_malformed_bytes() {
+    let bytes = Writebatch::<i32>::new().to_bytes();
+    assert!(Writebatch::<i32>::from_bytes(&bytes).unwrap().is_empty());
+    assert!(Writebatch::<i32>::from_bytes(&bytes[..4]).is_err());
+
+    let batch = &mut Writebatch::new();
+    batch.put(1, &[1]);
+    let bytes = batch.to_bytes();
+    assert!(Writebatch::<i32>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut wrong_count = bytes.clone();
+    wrong_count[8] = 2;
+    assert!(Writebatch::<i32>::from_bytes(&wrong_count).is_err());
+
+    let mut wrong_tag = bytes;
+    wrong_tag[12] = 7;
+    assert!(Writebatch::<i32>::from_bytes(&wrong_tag).is_err());
+}
+
+#[test]
+fn test_write_all() {
+    let tmp = tmpdir("write_all");
+    let database: Database<i32> = open_database(tmp.path(), true);
+    let entries = (0..1000).map(|i| (i, vec![i as u8; 100]));
+    let mut options = WriteOptions::new();
+    options.sync = true;
+    assert_eq!(database.write_all(options, entries, 1024).unwrap(), 1000);
+    for i in [0, 500, 999].iter() {
+        assert_eq!(database.get(ReadOptions::new(), *i).unwrap(), Some(vec![*i as u8; 100]));
+    }
+    assert_eq!(database.write_all(WriteOptions::new(), vec![], 1024).unwrap(), 0);
+}
+
+#[test]
+fn test_delete_range() {
+    let tmp = tmpdir("delete_range");
+    let database: Database<i32> = open_database(tmp.path(), true);
+    database.write_all(WriteOptions::new(), (0..100).map(|i| (i, vec![i as u8])), 1 << 20).unwrap();
+    assert_eq!(database.delete_range(WriteOptions::new(), &10, &90, true).unwrap(), 80);
+    let keys: Vec<i32> = database.keys_iter(ReadOptions::new()).collect();
+    assert_eq!(keys, (0..10).chain(90..100).collect::<Vec<_>>());
+    assert_eq!(database.delete_range(WriteOptions::new(), &10, &90, false).unwrap(), 0);
+}
+
+#[test]
+fn test_clear() {
+    let tmp = tmpdir("clear");
+    let database: Database<i32> = open_database(tmp.path(), true);
+    database.write_all(WriteOptions::new(), (0..100).map(|i| (i, vec![i as u8])), 1 << 20).unwrap();
+    assert_eq!(database.clear(WriteOptions::new()).unwrap(), 100);
+    assert_eq!(database.keys_iter(ReadOptions::new()).count(), 0);
+    database.put(WriteOptions::new(), 1, &[1]).unwrap();
+    assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+}
+
+#[test]
+fn test_clear_keeps_metadata() {
+    let tmp = tmpdir("clear_metadata");
+    {
+        let database: Database<BinaryKey> = open_database(tmp.path(), true);
+        database.put(WriteOptions::new(), BinaryKey(vec![1, 2, 3]), &[1]).unwrap();
+    }
+    // the key written above doesn't decode as an i32
+    let database: Database<i32> = open_database(tmp.path(), false);
+    database.put(WriteOptions::new(), 1, &[1]).unwrap();
+    database.meta().put(WriteOptions::new(), "marker", &[1]).unwrap();
+    assert_eq!(database.clear(WriteOptions::new()).unwrap(), 2);
+    assert_eq!(database.keys_iter(ReadOptions::new()).count(), 0);
+    assert_eq!(database.meta().get("marker").unwrap(), Some(vec![1]));
+}
+
+#[test]
+fn test_bulk_load() {
+    let tmp = tmpdir("bulk_load");
+    let database: Database<i32> = open_database(tmp.path(), true);
+    let entries = (0..10000).map(|i| (i, vec![i as u8; 1000]));
+    assert_eq!(database.bulk_load(entries).unwrap(), 10000);
+    for i in [0, 5000, 9999].iter() {
+        assert_eq!(database.get(ReadOptions::new(), *i).unwrap(), Some(vec![*i as u8; 1000]));
+    }
+    assert_eq!(database.keys_iter(ReadOptions::new()).count(), 10000);
+    assert_eq!(database.bulk_load(vec![]).unwrap(), 0);
+}
+
+#[test]
+#[should_panic(expected = "keys not sorted")]
+fn test_bulk_load_unsorted() {
+    let tmp = tmpdir("bulk_load_unsorted");
+    let database: Database<i32> = open_database(tmp.path(), true);
+    let _ = database.bulk_load(vec![(2, vec![2]), (1, vec![1])]);
+}
+
+#[test]
+fn test_writebatch_savepoints() {
+    let tmp = tmpdir("writebatch_savepoints");
+    let database: Database<i32> = open_database(tmp.path(), true);
+    let mut batch = Writebatch::new();
+    assert!(batch.rollback_to_savepoint().is_err());
+    batch.put(1, &[1]);
+    batch.set_savepoint();
+    batch.put(2, &[2]);
+    batch.set_savepoint();
+    batch.delete(1);
+    batch.put(3, &[3]);
+    batch.rollback_to_savepoint().unwrap();
+    assert_eq!(batch.len(), 2);
+    // rolling back without new operations keeps the batch
+    batch.set_savepoint();
+    batch.rollback_to_savepoint().unwrap();
+    assert_eq!(batch.len(), 2);
+    batch.set_savepoint();
+    batch.put(4, &[4]);
+    batch.pop_savepoint().unwrap();
+    batch.rollback_to_savepoint().unwrap();
+    assert_eq!(batch.len(), 1);
+    assert!(batch.pop_savepoint().is_err());
+
+    database.write(WriteOptions::new(), &batch).unwrap();
+    assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![1]));
+    assert_eq!(database.get(ReadOptions::new(), 2).unwrap(), None);
+    assert_eq!(database.get(ReadOptions::new(), 4).unwrap(), None);
+}
+
+#[test]
+fn test_writebatch_dedup() {
+    let tmp = tmpdir("writebatch_dedup");
+    let database: Database<i32> = open_database(tmp.path(), true);
+    database.put(WriteOptions::new(), 3, &[3]).unwrap();
+    let mut batch = Writebatch::new();
+    batch.put(1, &[1]);
+    batch.put(2, &[2]);
+    batch.put(1, &[10]);
+    batch.delete(2);
+    batch.put(3, &[30]);
+    batch.delete(3);
+    batch.put(3, &[31]);
+    assert_eq!(batch.dedup(), 4);
+    assert_eq!(batch.len(), 3);
+    assert_eq!(batch.dedup(), 0);
+
+    database.write(WriteOptions::new(), &batch).unwrap();
+    assert_eq!(database.get(ReadOptions::new(), 1).unwrap(), Some(vec![10]));
+    assert_eq!(database.get(ReadOptions::new(), 2).unwrap(), None);
+    assert_eq!(database.get(ReadOptions::new(), 3).unwrap(), Some(vec![31]));
+}